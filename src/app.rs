@@ -1,18 +1,41 @@
 use yew::prelude::*;
-use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 use futures_util::StreamExt;
+use gloo_timers::callback::Interval;
 use wasm_bindgen_futures::spawn_local;
-use web_sys::console;
+use web_sys::{console, HtmlInputElement};
 
 use crate::models::*;
-use crate::services::{storage::LocalStorage, llm::LlmService, document_service::DocumentService};
-use crate::components::{sidebar::Sidebar, settings::SettingsModal, chat_area::ChatArea};
+use crate::services::{storage::{LocalStorage, StorageRecovery}, llm::{ChoiceRoute, ChoiceRouter, LlmService, RequestInputs}, request::{AbortHandle, RequestConfig}, error::LlmError, document_service::DocumentService, export::ExportService};
+use crate::services::similarity::{SimilarityService, SimilarQuestion};
+use crate::services::sse::SseEvent;
+use crate::services::deployment::DeploymentService;
+use crate::services::migration::{ChatMigration, MigrationService};
+use crate::services::changelog::{ChangelogService, Release, CHANGELOG};
+use crate::services::host_events::{HostEvents, HostCommand, HostCommands, MessageSent, ResponseCompleted, ResponseMetrics, ChatCreated, DocumentUploaded, HostError};
+use crate::components::{sidebar::{Sidebar, BulkAction}, settings::SettingsModal, chat_area::ChatArea, onboarding::Onboarding, chat_export::ChatExport, whats_new::WhatsNew, setup_guide::SetupGuide};
+use crate::utils::{count_tokens, format_absolute, format_count, format_relative_time, history_window_start, inject_styles, summary_end, with_summary, ROOT_CLASS};
 
-const KEY_CHATS: &str = "llm_chats_v2";
 const KEY_SETTINGS: &str = "chat_settings_v1";
+// Set once the server has answered, so the setup guide stops showing
+const KEY_SERVER_CONNECTED: &str = "server_connected";
+// A stream is aborted after this many undecodable chunks in a row
+const MAX_CONSECUTIVE_DECODE_FAILURES: usize = 5;
+// Failed attempts kept on a retried response; older ones are dropped
+const MAX_FAILED_ATTEMPTS: usize = 3;
+// How long a toast stays on screen
+const TOAST_MS: u32 = 4000;
+// How often recurring prompts are checked while the app is open
+const RECURRING_CHECK_MS: u32 = 60_000;
+// How often the server is checked while messages wait for it
+const OFFLINE_PROBE_MS: u32 = 15_000;
+// Narrowest window that fits two chats side by side
+const SPLIT_MIN_WIDTH: f64 = 1100.0;
 
 const GLOBAL_STYLES: &str = r#"
-    :root {
+    .swi-root {
         --bg-app: #ffffff;
         --bg-sidebar: #f9f9f9;
         --bg-user: #f4f4f4;
@@ -23,84 +46,411 @@ const GLOBAL_STYLES: &str = r#"
         --accent-color: #10a37f;
         --accent-hover: #1a7f64;
         --danger-color: #ef4444;
+        --motion-fast: 0.2s;
+        --motion-slow: 0.3s;
+        --motion-flash: 2s;
+        --scroll-behavior: smooth;
     }
+    .swi-root.swi-high-contrast {
+        --bg-sidebar: #ffffff;
+        --bg-user: #ffffff;
+        --border-color: #000000;
+        --text-primary: #000000;
+        --text-secondary: #1f1f1f;
+        --accent-color: #00664f;
+        --accent-hover: #004d3b;
+        --danger-color: #b91c1c;
+    }
+    .swi-root.swi-high-contrast :focus { outline: 3px solid var(--text-primary); outline-offset: 2px; }
+    .swi-root.swi-reduced-motion {
+        --motion-fast: 0s;
+        --motion-slow: 0s;
+        --motion-flash: 0s;
+        --scroll-behavior: auto;
+    }
+    @media (prefers-reduced-motion: reduce) {
+        .swi-root {
+            --motion-fast: 0s;
+            --motion-slow: 0s;
+            --motion-flash: 0s;
+            --scroll-behavior: auto;
+        }
+    }
+
+    .swi-root, .swi-root * { box-sizing: border-box; }
+    /* Inherited text settings are reset so the host page's don't apply */
+    .swi-root { position: relative; height: 100%; font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, Helvetica, Arial, sans-serif; color: var(--text-primary); font-size: medium; font-weight: normal; font-style: normal; line-height: normal; letter-spacing: normal; text-align: left; text-transform: none; }
+
+    .swi-app-container { display: flex; height: 100%; overflow: hidden; }
+    .swi-main-content { flex-grow: 1; display: flex; flex-direction: column; position: relative; background: var(--bg-app); }
+    .swi-header { padding: 10px 20px; border-bottom: 1px solid var(--border-color); display: flex; justify-content: space-between; align-items: center; height: 60px; }
+    .swi-header h2 { font-size: 1rem; margin: 0; font-weight: 600; overflow: hidden; white-space: nowrap; text-overflow: ellipsis; max-width: 500px; cursor: text; border-radius: 4px; padding: 2px 4px; }
+    .swi-header h2:hover { background: rgba(0,0,0,0.04); }
+    .swi-title-input { font-size: 1rem; font-weight: 600; font-family: inherit; width: 500px; max-width: 100%; padding: 2px 4px; border: 1px solid var(--accent-color); border-radius: 4px; outline: none; }
+
+    .swi-btn { cursor: pointer; border: 1px solid var(--border-color); background: white; padding: 8px 12px; border-radius: 6px; font-size: 0.9rem; transition: all var(--motion-fast); color: var(--text-primary); }
+    .swi-btn:hover { background: #f0f0f0; }
+    .swi-btn-primary { background: var(--accent-color); color: white; border-color: transparent; }
+    .swi-btn-primary:hover { background: var(--accent-hover); }
+    .swi-btn-danger { color: var(--danger-color); border-color: var(--danger-color); }
+    .swi-btn-danger:hover { background: #fef2f2; }
+    .swi-btn-icon { border: none; background: transparent; font-size: 1.2rem; padding: 5px; color: var(--text-secondary); }
+    .swi-btn-icon:hover { background: rgba(0,0,0,0.05); color: var(--text-primary); }
+    .swi-language-badge { flex-shrink: 0; font-size: 0.75rem; color: var(--text-secondary); border: 1px solid var(--border-color); border-radius: 10px; padding: 1px 8px; white-space: nowrap; }
+    .swi-header-actions { display: flex; gap: 6px; align-items: center; flex-shrink: 0; }
+    .swi-server-status { width: 8px; height: 8px; border-radius: 50%; background: #d1d5db; margin-right: 4px; cursor: help; }
+    .swi-server-status.swi-ok { background: #27ae60; }
+    .swi-server-status.swi-failed { background: var(--danger-color); }
+    .swi-mini-btn { padding: 4px 8px; font-size: 0.8rem; }
+    .swi-settings-btn { position: relative; }
+    .swi-settings-badge { position: absolute; top: 4px; right: 4px; width: 8px; height: 8px; border-radius: 50%; background: #f59e0b; border: 1px solid white; }
+    .swi-active-toggle { border-color: var(--accent-color); color: var(--accent-color); }
+
+    .swi-form-input, .swi-form-select, .swi-form-textarea { width: 100%; padding: 8px; border: 1px solid var(--border-color); border-radius: 6px; font-family: inherit; margin-bottom: 10px; }
+    .swi-form-input:focus, .swi-form-textarea:focus { outline: 2px solid var(--accent-color); border-color: transparent; }
+
+    .swi-chat-banner { display: flex; align-items: center; gap: 10px; padding: 8px 20px; font-size: 0.85rem; border-bottom: 1px solid var(--border-color); }
+    .swi-chat-banner span { flex-grow: 1; }
+    .swi-chat-banner.swi-warning { background: #fffbeb; color: #92400e; }
+    .swi-chat-banner.swi-error { background: #fef2f2; color: #b91c1c; }
+    .swi-banner-select { padding: 4px; border: 1px solid var(--border-color); border-radius: 4px; font-family: inherit; max-width: 220px; }
+    .swi-banner-close { font-size: 1rem; padding: 0 5px; }
+    .swi-banner-input { flex-grow: 1; padding: 4px 6px; border: 1px solid var(--border-color); border-radius: 4px; font-family: inherit; font-size: 0.85rem; }
+    .swi-chat-banner.swi-info { background: #f0fdf4; color: #166534; }
 
-    * { box-sizing: border-box; }
-    body { margin: 0; font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, Helvetica, Arial, sans-serif; color: var(--text-primary); }
-
-    .app-container { display: flex; height: 100vh; overflow: hidden; }
-    .main-content { flex-grow: 1; display: flex; flex-direction: column; position: relative; background: var(--bg-app); }
-    .header { padding: 10px 20px; border-bottom: 1px solid var(--border-color); display: flex; justify-content: space-between; align-items: center; height: 60px; }
-    .header h2 { font-size: 1rem; margin: 0; font-weight: 600; overflow: hidden; white-space: nowrap; text-overflow: ellipsis; max-width: 500px; }
-
-    .btn { cursor: pointer; border: 1px solid var(--border-color); background: white; padding: 8px 12px; border-radius: 6px; font-size: 0.9rem; transition: all 0.2s; color: var(--text-primary); }
-    .btn:hover { background: #f0f0f0; }
-    .btn-primary { background: var(--accent-color); color: white; border-color: transparent; }
-    .btn-primary:hover { background: var(--accent-hover); }
-    .btn-danger { color: var(--danger-color); border-color: var(--danger-color); }
-    .btn-danger:hover { background: #fef2f2; }
-    .btn-icon { border: none; background: transparent; font-size: 1.2rem; padding: 5px; color: var(--text-secondary); }
-    .btn-icon:hover { background: rgba(0,0,0,0.05); color: var(--text-primary); }
-
-    .form-input, .form-select, .form-textarea { width: 100%; padding: 8px; border: 1px solid var(--border-color); border-radius: 6px; font-family: inherit; margin-bottom: 10px; }
-    .form-input:focus, .form-textarea:focus { outline: 2px solid var(--accent-color); border-color: transparent; }
-
-    .markdown-body { line-height: 1.6; font-size: 1rem; }
-    .markdown-body pre { background: #2d2d2d; color: #fff; padding: 15px; border-radius: 6px; overflow-x: auto; }
-    .markdown-body code { background: #f4f4f4; padding: 2px 4px; border-radius: 4px; font-family: monospace; font-size: 0.9em; }
-    .markdown-body pre code { background: transparent; color: inherit; }
-    .markdown-body p { margin-top: 0; margin-bottom: 1em; }
+    .swi-title-spinner { width: 14px; height: 14px; border: 2px solid var(--border-color); border-top-color: var(--accent-color); border-radius: 50%; animation: swi-spin 0.8s linear infinite; flex-shrink: 0; }
+    @keyframes swi-spin { to { transform: rotate(360deg); } }
+    .swi-split { flex-grow: 1; display: flex; min-height: 0; }
+    .swi-pane { flex: 1; min-width: 0; display: flex; flex-direction: column; position: relative; }
+    .swi-pane + .swi-pane { border-left: 1px solid var(--border-color); }
+    .swi-pane-bar { display: flex; align-items: center; gap: 8px; padding: 6px 12px; border-bottom: 1px solid var(--border-color); border-top: 2px solid transparent; }
+    .swi-pane.swi-focused .swi-pane-bar { border-top-color: var(--accent-color); }
+    .swi-pane-bar select { flex-grow: 1; min-width: 0; }
+    .swi-toast { position: absolute; bottom: 90px; left: 50%; transform: translateX(-50%); background: #333; color: white; padding: 8px 14px; border-radius: 6px; font-size: 0.85rem; z-index: 50; box-shadow: 0 2px 8px rgba(0,0,0,0.2); }
+
+    .swi-markdown-body { line-height: 1.6; font-size: 1rem; }
+    .swi-markdown-body pre { position: relative; background: #2d2d2d; color: #fff; padding: 15px; border-radius: 6px; overflow-x: auto; }
+    .swi-code-copy { position: absolute; top: 6px; right: 6px; border: 1px solid #555; background: #3a3a3a; color: #ddd; border-radius: 4px; padding: 1px 8px; font-size: 0.7rem; cursor: pointer; opacity: 0.7; }
+    .swi-code-copy:hover { opacity: 1; }
+    .swi-markdown-body code { background: #f4f4f4; padding: 2px 4px; border-radius: 4px; font-family: monospace; font-size: 0.9em; }
+    .swi-markdown-body pre code { background: transparent; color: inherit; }
+    .swi-markdown-body p { margin-top: 0; margin-bottom: 1em; }
 "#;
 
+/// A message to send into a chat, which need not be the open one
+struct OutgoingMessage {
+    chat_id: String,
+    content: String,
+    /// Sent by a recurring prompt
+    automated: bool,
+    /// Chunks the user approved in a context review, used instead of retrieval
+    context_chunks: Option<Vec<DocumentChunk>>,
+    /// Response being regenerated, kept on the new one for comparison
+    previous_response: Option<String>,
+    /// Broken partial answers of earlier tries, kept on the new one
+    failed_attempts: Vec<FailedAttempt>,
+}
+
+/// Outcome of the last keep-alive request
+#[derive(Clone, PartialEq)]
+struct KeepAlivePing {
+    at: f64,
+    error: Option<String>,
+}
+
+/// One side of the split view
+#[derive(Clone, Copy, PartialEq)]
+enum Pane {
+    Left,
+    Right,
+}
+
+/// Reverts a chat migration and reloads, so nothing from the migrated data
+/// stays in memory to be saved over the original
+fn restore_chats(prefix: &str, migration: &ChatMigration, show_toast: &Callback<String>) {
+    let confirmed = web_sys::window()
+        .and_then(|w| w.confirm_with_message("Go back to your chats as they were before the migration? Changes made since then are lost.").ok())
+        .unwrap_or(false);
+    if !confirmed {
+        return;
+    }
+    match MigrationService::restore(prefix, migration) {
+        Ok(()) => {
+            if let Some(window) = web_sys::window() {
+                let _ = window.location().reload();
+            }
+        }
+        Err(err) => show_toast.emit(err),
+    }
+}
+
+/// Stores a new chat list and keeps `latest` in sync, so async tasks and
+/// callbacks created before the next render build on this list, not on the
+/// snapshot they captured.
+fn commit_chats(state: &UseStateHandle<Vec<ChatSession>>, latest: &Rc<RefCell<Vec<ChatSession>>>, list: Vec<ChatSession>) {
+    *latest.borrow_mut() = list.clone();
+    state.set(list);
+}
+
+/// A response in flight: the id its answer is stored under and the token that stops only it
+struct Generation {
+    answer_id: String,
+    cancel: AbortHandle,
+}
+
+/// Replaces the messages a chat has sent, keeping its queued ones at the end
+fn set_sent_messages(chat: &mut ChatSession, messages: Vec<Message>) {
+    let queued: Vec<Message> = chat.messages.drain(..).filter(|m| m.queued).collect();
+    chat.messages = messages;
+    chat.messages.extend(queued);
+}
+
+/// Replaces the title taken from a chat's first message with one the model
+/// suggests once the first answer is in. A chat renamed in the meantime keeps
+/// its name, and so does every chat when the request fails.
+async fn generate_chat_title(
+    chats: UseStateHandle<Vec<ChatSession>>,
+    latest: Rc<RefCell<Vec<ChatSession>>>,
+    chat_id: String,
+    provisional: String,
+    settings: AppSettings,
+) {
+    let messages: Vec<Message> = latest.borrow().iter().find(|c| c.id == chat_id).map(|c| c.messages.clone()).unwrap_or_default();
+    let Ok(title) = LlmService::generate_title(&RequestConfig::from(&settings), &settings.selected_model, &messages).await else { return };
+    if title.is_empty() {
+        return;
+    }
+    let mut list = latest.borrow().clone();
+    if let Some(c) = list.iter_mut().find(|c| c.id == chat_id && c.title == provisional) {
+        c.title = title;
+        commit_chats(&chats, &latest, list);
+    }
+}
+
+#[derive(Properties, PartialEq, Default)]
+pub struct AppProps {
+    #[prop_or_default]
+    pub config: MountConfig,
+    /// Element the app was mounted into; host events are dispatched on it
+    #[prop_or_default]
+    pub host: Option<web_sys::Element>,
+    /// Filled with a handler so the mount handle can drive the app
+    #[prop_or_default]
+    pub commands: HostCommands,
+}
+
+/// What a regenerated response starts from, given the messages after its
+/// question: the answer to compare it with, and the failed attempts to keep.
+/// An answer broken by an error becomes a failed attempt; one the user stopped
+/// is compared with like a finished one, and carries its attempts on.
+fn retry_inputs(answers: &[Message], now: f64) -> (Option<String>, Vec<FailedAttempt>) {
+    let answered = |m: &&Message| m.role == "assistant" && !m.queued;
+    match answers.iter().rfind(answered) {
+        Some(last) if last.interrupted => {
+            let mut attempts = last.failed_attempts.clone();
+            let partial = !last.content.trim().is_empty();
+            let previous = if last.errored || !partial { last.previous.clone() } else { Some(last.content.clone()) };
+            if last.errored && partial {
+                attempts.push(FailedAttempt { content: last.content.clone(), at: last.created_at.unwrap_or(now) });
+            }
+            let excess = attempts.len().saturating_sub(MAX_FAILED_ATTEMPTS);
+            attempts.drain(..excess);
+            (previous, attempts)
+        }
+        _ => (answers.iter().filter(answered).rfind(|m| !m.content.trim().is_empty()).map(|m| m.content.clone()), Vec::new()),
+    }
+}
+
 #[function_component(App)]
-pub fn app() -> Html {
-    let settings = use_state(|| LocalStorage::get::<AppSettings>(KEY_SETTINGS).unwrap_or_default());
-    let chats = use_state(|| LocalStorage::get::<Vec<ChatSession>>(KEY_CHATS).unwrap_or_else(|| {
-        vec![ChatSession::new("You are a helpful assistant".to_string())]
-    }));
-    let active_chat_id = use_state(|| chats.first().map(|c| c.id.clone()).unwrap_or_default());
+pub fn app(props: &AppProps) -> Html {
+    // Chats in an older format are migrated before they're read
+    let chat_storage = {
+        let prefix = props.config.storage_key_prefix.clone();
+        use_memo((), move |_| MigrationService::prepare_chats(&prefix))
+    };
+    let key_chats = chat_storage.0.clone();
+    let key_settings = format!("{}{}", props.config.storage_key_prefix, KEY_SETTINGS);
+    let host_events = HostEvents::new(props.host.clone());
+
+    // News since the last version the user saw, judged before anything is stored on a first run
+    let whats_new = {
+        let prefix = props.config.storage_key_prefix.clone();
+        let keys = (key_settings.clone(), key_chats.clone());
+        use_state(move || {
+            let fresh_install = LocalStorage::get_raw(&keys.0).is_none() && LocalStorage::get_raw(&keys.1).is_none();
+            Some(ChangelogService::unseen(&prefix, fresh_install)).filter(|r: &Vec<&'static Release>| !r.is_empty())
+        })
+    };
+
+    // Unreadable stored data is backed up and partially recovered before anything else runs
+    let recoveries = use_mut_ref(Vec::<StorageRecovery>::new);
+    let deployment = use_memo((), |_| DeploymentService::load());
+    let settings = {
+        let recoveries = recoveries.clone();
+        let key = key_settings.clone();
+        let config = props.config.clone();
+        let deployment = deployment.clone();
+        use_state(move || {
+            let (loaded, report) = LocalStorage::get_recovering::<AppSettings>(&key, "settings fields");
+            recoveries.borrow_mut().extend(report);
+            let first_run = loaded.is_none();
+            let mut settings = DeploymentService::apply(&deployment, loaded.unwrap_or_default(), first_run);
+            // A preset server is the setup onboarding would have asked for
+            if first_run && deployment.values.contains_key("base_url") {
+                settings.onboarding_completed = true;
+            }
+            // The host page's configuration wins over what was stored
+            if let Some(url) = config.base_url {
+                settings.base_url = url;
+            }
+            if let Some(model) = config.model {
+                settings.selected_model = model;
+            }
+            settings
+        })
+    };
+    // Locked fields keep the values they started with, whatever the user does
+    let pinned_settings = use_state(|| (*settings).clone());
+    let enforce_locked = {
+        let deployment = deployment.clone();
+        let pinned = pinned_settings.clone();
+        move |s: AppSettings| DeploymentService::keep_locked(&deployment.locked, &pinned, s)
+    };
+    let chats = {
+        let recoveries = recoveries.clone();
+        let key = key_chats.clone();
+        let system_prompt = settings.system_prompt.clone();
+        use_state(move || {
+            let (mut loaded, report) = LocalStorage::get_vec_recovering::<ChatSession>(&key, "chats");
+            recoveries.borrow_mut().extend(report);
+            for message in loaded.iter_mut().flat_map(|c| c.messages.iter_mut()) {
+                message.separate_inline_reasoning();
+                message.separate_typed_text();
+            }
+            recoveries.borrow_mut().extend(DocumentService::recover_storage());
+            // Documents deleted while their undo was still offered never reached the scopes
+            let existing: HashSet<String> = DocumentService::get_documents().into_iter().map(|d| d.id).collect();
+            for chat in loaded.iter_mut() {
+                chat.retain_scoped_documents(|id| existing.contains(id));
+            }
+            if loaded.is_empty() {
+                vec![ChatSession::new(system_prompt)]
+            } else {
+                loaded
+            }
+        })
+    };
+    let chat_migration = use_state(|| chat_storage.1.clone());
+    let storage_notices = {
+        let recoveries = recoveries.clone();
+        use_state(move || recoveries.borrow_mut().drain(..).collect::<Vec<_>>())
+    };
+    let active_chat_id = use_state(|| chats.iter().find(|c| c.is_listed()).map(|c| c.id.clone()).unwrap_or_default());
+
+    let latest_chats = use_mut_ref(Vec::<ChatSession>::new);
+    *latest_chats.borrow_mut() = (*chats).clone();
+
+    // The open chat, for async tasks that outlive the render they started in
+    let viewed_chat = use_mut_ref(String::new);
+    *viewed_chat.borrow_mut() = (*active_chat_id).clone();
 
     let sidebar_open = use_state(|| true);
     let show_settings = use_state(|| false);
-    let is_loading = use_state(|| false);
-    let cancellation_token = use_state(|| Arc::new(AtomicBool::new(false)));
-    let available_models = use_state(Vec::new);
+    // Settings field to bring into view when the settings open
+    let settings_focus = use_state(|| None::<String>);
+    // Chats with a generation in flight; every request gets its own cancellation token
+    let generations = use_mut_ref(HashMap::<String, Generation>::new);
+    let force_update = use_force_update();
+    // When each streaming generation last heard from the server, and those gone quiet too long
+    let last_activity = use_mut_ref(HashMap::<String, f64>::new);
+    let stalled = use_mut_ref(HashSet::<String>::new);
+    // Chats whose request is being retried: the retry and how many there can be
+    let retrying = use_mut_ref(HashMap::<String, (u32, u32)>::new);
+    let available_models = use_state(Vec::<String>::new);
+    let model_notice_dismissed = use_state(|| false);
+    // Chat the last request failed in, and why
+    let request_error = use_state(|| None::<(String, LlmError)>);
+    // Chat whose last response came back empty, offered a retry
+    let empty_reply = use_state(|| None::<String>);
+    // Set when a send or a health check could not reach the server; messages
+    // sent meanwhile wait in their chat until it answers again
+    let server_unreachable = use_state(|| false);
+    // Whether the server ever answered; chats from before this was recorded count as an answer
+    let key_server_connected = format!("{}{}", props.config.storage_key_prefix, KEY_SERVER_CONNECTED);
+    let server_connected = {
+        let key = key_server_connected.clone();
+        let chats = chats.clone();
+        use_state(move || {
+            LocalStorage::get::<bool>(&key).unwrap_or_else(|| {
+                chats.iter().any(|c| c.messages.iter().any(|m| m.role == "assistant" && !m.content.is_empty()))
+            })
+        })
+    };
+    let editing_title = use_state(|| false);
+    let selection_mode = use_state(|| false);
+    let exporting_chat = use_state(|| false);
+    // Document selected in the sidebar; used as context in Manual mode
+    let selected_document = use_state(|| None::<String>);
+    let title_input_ref = use_node_ref();
+    // Chat whose title is being suggested; the ref blocks concurrent requests
+    let suggesting_title = use_state(|| None::<String>);
+    let title_request_in_flight = use_mut_ref(|| false);
+    // (chat id, suggested title) waiting to be accepted, edited or rejected
+    let title_suggestion = use_state(|| None::<(String, String)>);
+    let toast = use_state(|| None::<String>);
+    // Earlier question matching the last one sent, with the chat it was sent in
+    let similar_question = use_state(|| None::<(String, SimilarQuestion)>);
+    let focus_message = use_state(|| None::<String>);
+    // Chat shown in the right pane; the view is split while this is set
+    let split_chat_id = use_state(|| None::<String>);
+    // Pane the sidebar opens chats in
+    let focused_pane = use_state(|| Pane::Left);
+    let toast_seq = use_mut_ref(|| 0u32);
 
     let current_chat = chats.iter().find(|c| c.id == *active_chat_id);
-    let current_messages = current_chat.map(|c| c.messages.clone()).unwrap_or_default();
+
+    // What the system prompt, custom instructions and documents take up before
+    // the conversation, counted from stored token counts
+    let context_overhead = {
+        let system_messages: Vec<Message> = current_chat
+            .map(|c| c.messages.iter().filter(|m| m.role == "system").cloned().collect())
+            .unwrap_or_else(|| vec![Message::new("system", settings.system_prompt.clone())]);
+        let deps = (
+            system_messages,
+            settings.custom_instructions.clone(),
+            settings.document_context_mode.clone(),
+            current_chat.and_then(|c| c.document_scope.clone()),
+            (*selected_document).clone(),
+            settings.context_length_for(current_chat.and_then(|c| c.model_override.as_deref()).unwrap_or(&settings.selected_model)),
+            settings.embedding_model.clone(),
+        );
+        *use_memo(deps, |(system, instructions, mode, scope, selected, context_length, embedding_model)| ContextOverhead {
+            system_tokens: system.iter().map(Message::tokens).sum(),
+            instruction_tokens: count_tokens(instructions.trim()),
+            document_tokens: DocumentService::projected_context_tokens(mode, scope.as_deref(), selected.as_deref(), embedding_model),
+            context_length: *context_length,
+        })
+    };
 
     // --- EFFECTS ---
 
     // Fetch models on startup if base_url is not default
     {
         let models = available_models.clone();
-        let settings = settings.clone();
-        use_effect_with(settings.clone(), move |settings_ref| {
-            let base_url = settings_ref.base_url.clone();
-            if base_url != "http://localhost:8080" {
-                let url = base_url.clone();
+        let dismissed = model_notice_dismissed.clone();
+        let server_connected = server_connected.clone();
+        use_effect_with(RequestConfig::from(&*settings), move |request| {
+            if request.base_url != "http://localhost:8080" {
+                let request = request.clone();
                 let models = models.clone();
-                let settings = settings.clone();
+                let dismissed = dismissed.clone();
                 spawn_local(async move {
-                    match LlmService::fetch_models(&url).await {
-                        Ok(resp) => {
-                            let model_list: Vec<String> = resp.data.into_iter().map(|m| m.id).collect();
-                            models.set(model_list.clone());
-                            // If the saved model exists in the list, keep it; otherwise use the first one
-                            let current_settings: AppSettings = (*settings).clone();
-                            let saved_model = current_settings.selected_model.clone();
-                            if model_list.contains(&saved_model) {
-                                // Keep the saved model
-                            } else if let Some(first_model) = model_list.first().cloned() {
-                                // Update settings with the first available model
-                                let mut new_settings = current_settings.clone();
-                                new_settings.selected_model = first_model;
-                                settings.set(new_settings);
-                            }
-                        }
-                        Err(_) => {
-                            // If fetch fails, keep using the saved model
-                        }
+                    // If fetch fails, keep using the saved model. A saved model missing from
+                    // the list is reported in the chat rather than silently replaced.
+                    if let Ok(entry) = LlmService::refresh_models(&request).await {
+                        server_connected.set(true);
+                        dismissed.set(false);
+                        models.set(entry.models);
                     }
                 });
             }
@@ -108,24 +458,149 @@ pub fn app() -> Html {
     }
 
     // --- EFFECTS ---
+    // Unreadable data that could not be backed up stays stored until its notice is dismissed
+    let unsaved_original = |key: &str| storage_notices.iter().any(|r| r.key == key && r.backup_key.is_none());
     {
         let chats = chats.clone();
-        use_effect_with(chats, |c| LocalStorage::set(KEY_CHATS, &**c));
+        let hold = unsaved_original(&key_chats);
+        use_effect_with((chats, hold), move |(c, hold)| {
+            if !*hold {
+                LocalStorage::set(&key_chats, &**c);
+            }
+        });
     }
     {
         let s = settings.clone();
-        use_effect_with(s, |s| LocalStorage::set(KEY_SETTINGS, &**s));
+        let hold = unsaved_original(&key_settings);
+        use_effect_with((s, hold), move |(s, hold)| {
+            if !*hold {
+                LocalStorage::set(&key_settings, &**s);
+            }
+        });
+    }
+    {
+        let connected = server_connected.clone();
+        use_effect_with(connected, move |c| {
+            if **c {
+                LocalStorage::set(&key_server_connected, &true);
+            }
+        });
+    }
+
+    // Opening a chat marks its responses as read
+    {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        let unread = current_chat.is_some_and(|c| c.unread);
+        use_effect_with(((*active_chat_id).clone(), unread), move |(id, unread)| {
+            if *unread {
+                let mut list = latest.borrow().clone();
+                if let Some(c) = list.iter_mut().find(|c| c.id == *id) {
+                    c.unread = false;
+                }
+                commit_chats(&chats, &latest, list);
+            }
+        });
+    }
+
+    // Messages saved before token counts were stored are counted once, the first
+    // time their chat is opened, and the counts are saved with them
+    {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        use_effect_with((*active_chat_id).clone(), move |id| {
+            let id = id.clone();
+            let missing: Vec<(String, String)> = latest.borrow().iter()
+                .find(|c| c.id == id)
+                .map(|c| c.messages.iter()
+                    .filter(|m| m.token_count.is_none() && !m.content.is_empty())
+                    .map(|m| (m.id.clone(), m.content.clone()))
+                    .collect())
+                .unwrap_or_default();
+            if !missing.is_empty() {
+                spawn_local(async move {
+                    let mut counts = HashMap::new();
+                    for (i, (message_id, content)) in missing.into_iter().enumerate() {
+                        if i % 50 == 0 {
+                            gloo_timers::future::TimeoutFuture::new(0).await;
+                        }
+                        counts.insert(message_id, count_tokens(&content));
+                    }
+                    let mut list = latest.borrow().clone();
+                    if let Some(c) = list.iter_mut().find(|c| c.id == id) {
+                        for m in c.messages.iter_mut().filter(|m| m.token_count.is_none()) {
+                            m.token_count = counts.get(&m.id).copied();
+                        }
+                    }
+                    commit_chats(&chats, &latest, list);
+                });
+            }
+        });
+    }
+
+    // Focus the title input as soon as it appears
+    {
+        let input_ref = title_input_ref.clone();
+        use_effect_with(*editing_title, move |editing| {
+            if *editing {
+                if let Some(input) = input_ref.cast::<HtmlInputElement>() {
+                    let _ = input.focus();
+                    input.select();
+                }
+            }
+        });
     }
 
     // --- ACTIONS ---
 
+    // Stops the generation running in a chat (if any) and marks its partial answer
+    let cancel_generation = {
+        let generations = generations.clone();
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        let force_update = force_update.clone();
+        Callback::from(move |chat_id: String| {
+            let generation = generations.borrow_mut().remove(&chat_id);
+            if let Some(generation) = generation {
+                generation.cancel.abort();
+                let mut list = latest.borrow().clone();
+                if let Some(answer) = list.iter_mut().find(|c| c.id == chat_id).and_then(|c| c.messages.iter_mut().find(|m| m.id == generation.answer_id)) {
+                    answer.interrupted = true;
+                }
+                commit_chats(&chats, &latest, list);
+                force_update.force_update();
+            }
+        })
+    };
+
+    // Stops one response by its answer id; a stop aimed at a request that already ended does nothing
+    let stop_response = {
+        let generations = generations.clone();
+        let cancel_generation = cancel_generation.clone();
+        Callback::from(move |answer_id: String| {
+            let chat_id = generations.borrow().iter().find(|(_, g)| g.answer_id == answer_id).map(|(chat_id, _)| chat_id.clone());
+            if let Some(chat_id) = chat_id {
+                cancel_generation.emit(chat_id);
+            }
+        })
+    };
+
     let on_new_chat = {
         let chats = chats.clone();
+        let latest = latest_chats.clone();
         let active_id = active_chat_id.clone();
         let sys = settings.system_prompt.clone();
+        let selection_mode = selection_mode.clone();
+        let stop_on_switch = settings.stop_on_chat_switch;
+        let cancel_generation = cancel_generation.clone();
+        let host_events = host_events.clone();
         Callback::from(move |_| {
+            selection_mode.set(false);
             let current_id = (*active_id).clone();
-            let mut current_list = (*chats).clone();
+            if stop_on_switch {
+                cancel_generation.emit(current_id.clone());
+            }
+            let mut current_list = latest.borrow().clone();
 
             let current_is_empty = if let Some(curr) = current_list.iter().find(|c| c.id == current_id) {
                 curr.messages.len() == 1 && curr.messages[0].role == "system"
@@ -139,19 +614,30 @@ pub fn app() -> Html {
 
             let new_chat = ChatSession::new(sys.clone());
             current_list.insert(0, new_chat.clone());
-            chats.set(current_list);
+            commit_chats(&chats, &latest, current_list);
+            host_events.emit("chat_created", &ChatCreated { chat_id: new_chat.id.clone() });
             active_id.set(new_chat.id);
         })
     };
 
     let on_select_chat = {
         let chats = chats.clone();
+        let latest = latest_chats.clone();
         let active_id = active_chat_id.clone();
+        let selection_mode = selection_mode.clone();
+        let stop_on_switch = settings.stop_on_chat_switch;
+        let cancel_generation = cancel_generation.clone();
+        let focus_message = focus_message.clone();
         Callback::from(move |target_id: String| {
             let current_id = (*active_id).clone();
             if current_id == target_id { return; }
+            selection_mode.set(false);
+            focus_message.set(None);
+            if stop_on_switch {
+                cancel_generation.emit(current_id.clone());
+            }
 
-            let mut list = (*chats).clone();
+            let mut list = latest.borrow().clone();
             let should_delete_prev = if let Some(prev) = list.iter().find(|c| c.id == current_id) {
                 prev.messages.len() == 1 && prev.messages[0].role == "system"
             } else {
@@ -162,46 +648,480 @@ pub fn app() -> Html {
                 list.retain(|c| c.id != current_id);
             }
 
-            chats.set(list);
+            commit_chats(&chats, &latest, list);
             active_id.set(target_id);
         })
     };
 
     let on_delete_chat = {
         let chats = chats.clone();
+        let latest = latest_chats.clone();
+        let cancel_generation = cancel_generation.clone();
         Callback::from(move |(e, id): (MouseEvent, String)| {
             e.stop_propagation();
-            let mut curr = (*chats).clone();
+            cancel_generation.emit(id.clone());
+            let mut curr = latest.borrow().clone();
             curr.retain(|c| c.id != id);
-            chats.set(curr);
+            commit_chats(&chats, &latest, curr);
+        })
+    };
+
+    let on_bulk_action = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        let active_id = active_chat_id.clone();
+        let generations = generations.clone();
+        let force_update = force_update.clone();
+        let selection_mode = selection_mode.clone();
+        let settings = settings.clone();
+        Callback::from(move |(action, ids): (BulkAction, Vec<String>)| {
+            let mut list = latest.borrow().clone();
+            if matches!(action, BulkAction::Trash | BulkAction::DeleteForever) {
+                // Stop generations inline so everything lands in a single update
+                for c in list.iter_mut().filter(|c| ids.contains(&c.id)) {
+                    c.messages.retain(|m| !m.queued);
+                    if let Some(generation) = generations.borrow_mut().remove(&c.id) {
+                        generation.cancel.abort();
+                        if let Some(answer) = c.messages.iter_mut().find(|m| m.id == generation.answer_id) {
+                            answer.interrupted = true;
+                        }
+                        force_update.force_update();
+                    }
+                }
+            }
+            match &action {
+                BulkAction::DeleteForever => list.retain(|c| !ids.contains(&c.id)),
+                _ => {
+                    let now = js_sys::Date::now();
+                    for c in list.iter_mut().filter(|c| ids.contains(&c.id)) {
+                        match &action {
+                            BulkAction::Trash => c.trashed_at = Some(now),
+                            BulkAction::Archive => c.archived = true,
+                            BulkAction::Restore => {
+                                c.archived = false;
+                                c.trashed_at = None;
+                            }
+                            BulkAction::MoveToFolder(folder) => c.folder = folder.clone(),
+                            BulkAction::SetLabel(label) => c.label = label.clone(),
+                            BulkAction::DeleteForever => {}
+                        }
+                    }
+                }
+            }
+
+            // Move away from the active chat when it left the chat list
+            let active_hidden = !list.iter().any(|c| c.id == *active_id && c.is_listed());
+            if active_hidden && ids.contains(&*active_id) {
+                let next = match list.iter().find(|c| c.is_listed()) {
+                    Some(c) => c.id.clone(),
+                    None => {
+                        let chat = ChatSession::new(settings.system_prompt.clone());
+                        let id = chat.id.clone();
+                        list.insert(0, chat);
+                        id
+                    }
+                };
+                selection_mode.set(false);
+                active_id.set(next);
+            }
+            commit_chats(&chats, &latest, list);
+        })
+    };
+
+    let on_rename_chat = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        Callback::from(move |(id, title): (String, String)| {
+            // An empty title keeps the previous one
+            let title = title.trim();
+            if title.is_empty() { return; }
+
+            let mut list = latest.borrow().clone();
+            if let Some(c) = list.iter_mut().find(|c| c.id == id) {
+                if c.title == title { return; }
+                c.title = title.to_string();
+            }
+            commit_chats(&chats, &latest, list);
+        })
+    };
+
+    let commit_title = {
+        let editing = editing_title.clone();
+        let on_rename = on_rename_chat.clone();
+        let active_id = active_chat_id.clone();
+        Callback::from(move |value: String| {
+            editing.set(false);
+            on_rename.emit(((*active_id).clone(), value));
+        })
+    };
+
+    let on_title_keydown = {
+        let editing = editing_title.clone();
+        let commit = commit_title.clone();
+        let original = current_chat.map(|c| c.title.clone()).unwrap_or_default();
+        Callback::from(move |e: KeyboardEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            match e.key().as_str() {
+                "Enter" => {
+                    e.prevent_default();
+                    commit.emit(input.value());
+                }
+                "Escape" => {
+                    // Reset the value so the blur fired on removal saves nothing
+                    input.set_value(&original);
+                    editing.set(false);
+                }
+                _ => {}
+            }
+        })
+    };
+
+    let on_title_blur = {
+        let commit = commit_title.clone();
+        Callback::from(move |e: FocusEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            commit.emit(input.value());
+        })
+    };
+
+    let show_toast = {
+        let toast = toast.clone();
+        let toast_seq = toast_seq.clone();
+        Callback::from(move |text: String| {
+            let seq = {
+                let mut seq = toast_seq.borrow_mut();
+                *seq += 1;
+                *seq
+            };
+            toast.set(Some(text));
+            let toast = toast.clone();
+            let toast_seq = toast_seq.clone();
+            spawn_local(async move {
+                gloo_timers::future::TimeoutFuture::new(TOAST_MS).await;
+                // A newer toast replaced this one and owns its own timeout
+                if *toast_seq.borrow() == seq {
+                    toast.set(None);
+                }
+            });
+        })
+    };
+
+    // Opens the open chat's neighbour in a second pane, or returns to a single
+    // pane keeping the left one's chat
+    let on_toggle_split = {
+        let split_chat_id = split_chat_id.clone();
+        let focused_pane = focused_pane.clone();
+        let latest = latest_chats.clone();
+        let active_id = active_chat_id.clone();
+        let show_toast = show_toast.clone();
+        Callback::from(move |_| {
+            if split_chat_id.is_some() {
+                split_chat_id.set(None);
+                focused_pane.set(Pane::Left);
+                return;
+            }
+            let width = web_sys::window()
+                .and_then(|w| w.inner_width().ok())
+                .and_then(|w| w.as_f64())
+                .unwrap_or_default();
+            if width < SPLIT_MIN_WIDTH {
+                show_toast.emit("The window is too narrow to show two chats side by side.".to_string());
+                return;
+            }
+            let list = latest.borrow();
+            let other = list
+                .iter()
+                .find(|c| c.is_listed() && c.id != *active_id)
+                .map(|c| c.id.clone());
+            split_chat_id.set(Some(other.unwrap_or_else(|| (*active_id).clone())));
+            focused_pane.set(Pane::Right);
+        })
+    };
+
+    let on_select_right_chat = {
+        let split_chat_id = split_chat_id.clone();
+        let stop_on_switch = settings.stop_on_chat_switch;
+        let cancel_generation = cancel_generation.clone();
+        Callback::from(move |target_id: String| {
+            let Some(current_id) = (*split_chat_id).clone() else { return };
+            if current_id == target_id { return; }
+            if stop_on_switch {
+                cancel_generation.emit(current_id);
+            }
+            split_chat_id.set(Some(target_id));
+        })
+    };
+
+    // Sidebar clicks open the chat in whichever pane has focus
+    let on_sidebar_select = {
+        let on_select_chat = on_select_chat.clone();
+        let on_select_right_chat = on_select_right_chat.clone();
+        let focused_pane = focused_pane.clone();
+        let split = split_chat_id.is_some();
+        Callback::from(move |id: String| match *focused_pane {
+            Pane::Right if split => on_select_right_chat.emit(id),
+            _ => on_select_chat.emit(id),
+        })
+    };
+
+    let on_suggest_title = {
+        let latest = latest_chats.clone();
+        let active_id = active_chat_id.clone();
+        let settings = settings.clone();
+        let suggesting = suggesting_title.clone();
+        let in_flight = title_request_in_flight.clone();
+        let suggestion = title_suggestion.clone();
+        let show_toast = show_toast.clone();
+        Callback::from(move |_: MouseEvent| {
+            if *in_flight.borrow() { return; }
+            let id = (*active_id).clone();
+            let messages: Vec<Message> = latest.borrow().iter()
+                .find(|c| c.id == id)
+                .map(|c| c.messages.clone())
+                .unwrap_or_default();
+            if !messages.iter().any(|m| m.role == "user") {
+                show_toast.emit("Send a message before asking for a title.".to_string());
+                return;
+            }
+
+            *in_flight.borrow_mut() = true;
+            suggesting.set(Some(id.clone()));
+            let request = RequestConfig::from(&*settings);
+            let model = settings.selected_model.clone();
+            let in_flight = in_flight.clone();
+            let suggesting = suggesting.clone();
+            let suggestion = suggestion.clone();
+            let show_toast = show_toast.clone();
+            spawn_local(async move {
+                let result = LlmService::generate_title(&request, &model, &messages).await;
+                *in_flight.borrow_mut() = false;
+                suggesting.set(None);
+                match result {
+                    Ok(title) if !title.is_empty() => suggestion.set(Some((id, title))),
+                    _ => show_toast.emit("Couldn't suggest a title right now.".to_string()),
+                }
+            });
+        })
+    };
+
+    let accept_title_suggestion = {
+        let suggestion = title_suggestion.clone();
+        let on_rename = on_rename_chat.clone();
+        Callback::from(move |_: ()| {
+            if let Some((id, title)) = (*suggestion).clone() {
+                on_rename.emit((id, title));
+            }
+            suggestion.set(None);
+        })
+    };
+
+    let on_delete_messages = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        Callback::from(move |(chat_id, ids): (String, Vec<String>)| {
+            let mut list = latest.borrow().clone();
+            if let Some(c) = list.iter_mut().find(|c| c.id == chat_id) {
+                c.invalidate_summary(&ids);
+                c.messages.retain(|m| !ids.contains(&m.id));
+            }
+            commit_chats(&chats, &latest, list);
+        })
+    };
+
+    let on_toggle_excluded = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        Callback::from(move |(chat_id, message_id): (String, String)| {
+            let mut list = latest.borrow().clone();
+            if let Some(c) = list.iter_mut().find(|c| c.id == chat_id) {
+                c.invalidate_summary(std::slice::from_ref(&message_id));
+                if let Some(m) = c.messages.iter_mut().find(|m| m.id == message_id) {
+                    m.excluded = !m.excluded;
+                }
+            }
+            commit_chats(&chats, &latest, list);
+        })
+    };
+
+    let on_exit_selection = {
+        let selection_mode = selection_mode.clone();
+        Callback::from(move |_| selection_mode.set(false))
+    };
+
+    let on_quick_pick_model = {
+        let settings = settings.clone();
+        let enforce_locked = enforce_locked.clone();
+        Callback::from(move |e: Event| {
+            let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+            let mut s = (*settings).clone();
+            s.selected_model = select.value();
+            settings.set(enforce_locked(s));
+        })
+    };
+
+    let model_missing = !available_models.is_empty() && !available_models.contains(&settings.selected_model);
+
+    let on_history_window_change = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        Callback::from(move |(chat_id, window): (String, Option<usize>)| {
+            let mut list = latest.borrow().clone();
+            if let Some(c) = list.iter_mut().find(|c| c.id == chat_id) {
+                c.history_window = window;
+            }
+            commit_chats(&chats, &latest, list);
+        })
+    };
+
+    // Documents made in the chat (e.g. from a long paste) count as uploads
+    let documents_added = use_state(Vec::<String>::new);
+    let on_document_uploaded = {
+        let host_events = host_events.clone();
+        Callback::from(move |doc: Document| host_events.emit("document_uploaded", &DocumentUploaded {
+            document_id: doc.id,
+            filename: doc.filename,
+            chunk_count: doc.chunk_count,
+        }))
+    };
+    let on_document_created = {
+        let documents_added = documents_added.clone();
+        let on_document_uploaded = on_document_uploaded.clone();
+        Callback::from(move |doc: Document| {
+            documents_added.set([(*documents_added).clone(), vec![doc.id.clone()]].concat());
+            on_document_uploaded.emit(doc);
+        })
+    };
+
+    let on_response_language_change = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        Callback::from(move |(chat_id, language): (String, Option<String>)| {
+            let mut list = latest.borrow().clone();
+            if let Some(c) = list.iter_mut().find(|c| c.id == chat_id) {
+                c.response_language = language;
+            }
+            commit_chats(&chats, &latest, list);
+        })
+    };
+
+    // None goes back to the default from the settings
+    let on_response_length_change = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        Callback::from(move |(chat_id, length): (String, Option<ResponseLength>)| {
+            let mut list = latest.borrow().clone();
+            if let Some(c) = list.iter_mut().find(|c| c.id == chat_id) {
+                c.response_length = length;
+            }
+            commit_chats(&chats, &latest, list);
+        })
+    };
+
+    let on_model_override_change = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        Callback::from(move |(chat_id, model): (String, Option<String>)| {
+            let mut list = latest.borrow().clone();
+            if let Some(c) = list.iter_mut().find(|c| c.id == chat_id) {
+                c.model_override = model;
+            }
+            commit_chats(&chats, &latest, list);
+        })
+    };
+
+    let on_temperature_override_change = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        Callback::from(move |(chat_id, temperature): (String, Option<f32>)| {
+            let mut list = latest.borrow().clone();
+            if let Some(c) = list.iter_mut().find(|c| c.id == chat_id) {
+                c.temperature_override = temperature;
+            }
+            commit_chats(&chats, &latest, list);
+        })
+    };
+
+    let on_prefill_change = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        Callback::from(move |(chat_id, prefill): (String, Option<Prefill>)| {
+            let mut list = latest.borrow().clone();
+            if let Some(c) = list.iter_mut().find(|c| c.id == chat_id) {
+                c.prefill = prefill;
+            }
+            commit_chats(&chats, &latest, list);
+        })
+    };
+
+    let on_review_context_change = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        Callback::from(move |(chat_id, review): (String, bool)| {
+            let mut list = latest.borrow().clone();
+            if let Some(c) = list.iter_mut().find(|c| c.id == chat_id) {
+                c.review_context = review;
+            }
+            commit_chats(&chats, &latest, list);
+        })
+    };
+
+    let on_document_scope_change = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        Callback::from(move |(chat_id, scope): (String, Option<Vec<String>>)| {
+            let mut list = latest.borrow().clone();
+            if let Some(c) = list.iter_mut().find(|c| c.id == chat_id) {
+                c.document_scope = scope;
+            }
+            commit_chats(&chats, &latest, list);
+        })
+    };
+
+    // Deleted documents are dropped from every chat that was limited to them
+    let on_documents_deleted = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        Callback::from(move |ids: Vec<String>| {
+            let mut list = latest.borrow().clone();
+            let mut changed = false;
+            for chat in list.iter_mut() {
+                changed |= chat.retain_scoped_documents(|id| !ids.iter().any(|deleted| deleted == id));
+            }
+            if changed {
+                commit_chats(&chats, &latest, list);
+            }
         })
     };
 
     let on_settings_save = {
         let s = settings.clone();
         let chats = chats.clone();
+        let latest = latest_chats.clone();
         let active = active_chat_id.clone();
+        let enforce_locked = enforce_locked.clone();
 
         Callback::from(move |new_settings: AppSettings| {
+            let new_settings = enforce_locked(new_settings);
             let prompt_changed = new_settings.system_prompt != s.system_prompt;
             s.set(new_settings.clone());
 
             if prompt_changed {
                 let current_id = (*active).clone();
-                let mut list = (*chats).clone();
+                let mut list = latest.borrow().clone();
                 let mut handled = false;
                 if let Some(curr) = list.iter_mut().find(|c| c.id == current_id) {
                     if curr.messages.len() == 1 && curr.messages[0].role == "system" {
                         curr.messages[0].content = new_settings.system_prompt.clone();
+                        curr.messages[0].token_count = None;
                         handled = true;
                     }
                 }
                 if handled {
-                    chats.set(list);
+                    commit_chats(&chats, &latest, list);
                 } else {
                     let new_chat = ChatSession::new(new_settings.system_prompt);
                     list.insert(0, new_chat.clone());
-                    chats.set(list);
+                    commit_chats(&chats, &latest, list);
                     active.set(new_chat.id);
                 }
             }
@@ -209,24 +1129,105 @@ pub fn app() -> Html {
     };
 
     // --- MAIN CHAT LOGIC ---
-    let run_chat = {
+    let send_message = {
         let chats = chats.clone();
-        let active_id = active_chat_id.clone();
-        let loading = is_loading.clone();
+        let latest = latest_chats.clone();
+        let generations = generations.clone();
+        let force_update = force_update.clone();
         let settings = settings.clone();
-        let token = cancellation_token.clone();
+        let request_error = request_error.clone();
+        let empty_reply = empty_reply.clone();
+        let selected_document = selected_document.clone();
+        let similar_question = similar_question.clone();
+        let host_events = host_events.clone();
+        let last_activity = last_activity.clone();
+        let stalled = stalled.clone();
+        let retrying = retrying.clone();
+        let viewed_chat = viewed_chat.clone();
+        let server_unreachable = server_unreachable.clone();
+        let server_connected = server_connected.clone();
 
-        Callback::from(move |msg_content: String| {
-            let current_id = (*active_id).clone();
-            loading.set(true);
-            token.store(false, Ordering::Relaxed);
+        Callback::from(move |outgoing: OutgoingMessage| {
+            let OutgoingMessage { chat_id: current_id, content: msg_content, automated, context_chunks, previous_response, failed_attempts } = outgoing;
+            // Retries fail as usual; there is no earlier answer to keep while they wait.
+            // Neither are reviewed chunks, which a queued message has no place for
+            let can_wait = previous_response.is_none() && failed_attempts.is_empty() && context_chunks.is_none();
+            let offline = *server_unreachable && can_wait;
+            // One response at a time per chat; later messages wait their turn
+            if generations.borrow().contains_key(&current_id) || offline {
+                let mut queued = Message::new("user", msg_content);
+                queued.automated = automated;
+                queued.queued = true;
+                queued.awaiting_server = offline;
+                let mut list = latest.borrow().clone();
+                if let Some(c) = list.iter_mut().find(|c| c.id == current_id) {
+                    c.messages.push(queued);
+                }
+                commit_chats(&chats, &latest, list);
+                return;
+            }
+            if !automated {
+                request_error.set(None);
+                similar_question.set(None);
+            }
+            if empty_reply.as_deref() == Some(current_id.as_str()) {
+                empty_reply.set(None);
+            }
 
-            let mut history = chats.iter().find(|c| c.id == current_id).map(|c| c.messages.clone()).unwrap_or_default();
-            history.push(Message { role: "user".into(), content: msg_content.clone() });
+            // Look for the same question in the background; the send doesn't wait for it
+            if settings.similar_question_hints && !automated {
+                let previous = latest.borrow().clone();
+                let similar_question = similar_question.clone();
+                let text = msg_content.clone();
+                let cid = current_id.clone();
+                spawn_local(async move {
+                    if let Some(found) = SimilarityService::find_similar(previous, text).await {
+                        similar_question.set(Some((cid, found)));
+                    }
+                });
+            }
+
+            // Each request gets its own token so stopping it leaves other responses running
+            let cancel = AbortHandle::new();
+            let answer_id = new_message_id();
+            generations.borrow_mut().insert(current_id.clone(), Generation { answer_id: answer_id.clone(), cancel: cancel.clone() });
+            force_update.force_update();
+
+            let mut all_chats = latest.borrow().clone();
+            let mut history: Vec<Message> = all_chats
+                .iter()
+                .find(|c| c.id == current_id)
+                .map(|c| c.messages.iter().filter(|m| !m.queued).cloned().collect())
+                .unwrap_or_default();
+            let window = all_chats.iter().find(|c| c.id == current_id).and_then(|c| c.history_window);
+            let scope = all_chats.iter().find(|c| c.id == current_id).and_then(|c| c.document_scope.clone());
+            let language = all_chats.iter().find(|c| c.id == current_id).and_then(|c| c.response_language.clone());
+            let length = all_chats.iter().find(|c| c.id == current_id).and_then(|c| c.response_length).unwrap_or(settings.response_length);
+            let model_override = all_chats.iter().find(|c| c.id == current_id).and_then(|c| c.model_override.clone());
+            let temperature_override = all_chats.iter().find(|c| c.id == current_id).and_then(|c| c.temperature_override);
+            // Kept while another strategy is chosen, but only sent under Summarize
+            let summary = all_chats
+                .iter()
+                .find(|c| c.id == current_id)
+                .and_then(|c| Some((c.summary.clone()?, c.summary_until.clone()?)))
+                .filter(|_| settings.context_strategy == ContextStrategy::Summarize);
+            // Recurring prompts answer without the prefill the user set up
+            let prefill = all_chats
+                .iter()
+                .find(|c| c.id == current_id)
+                .and_then(|c| c.prefill.clone())
+                .filter(|_| !automated);
+            let one_shot_prefill = prefill.clone().filter(|p| !p.pinned);
+            let selected_doc = (*selected_document).clone();
+            let mut user_message = Message::new("user", msg_content.clone());
+            user_message.automated = automated;
+            let user_message_id = user_message.id.clone();
+            history.push(user_message);
 
             // 1. Calculate Title if needed
             let mut new_title_opt = None;
-            if history.len() == 2 {
+            // Automated chats keep the recurring prompt's name
+            if history.len() == 2 && !automated {
                 let first_line = msg_content.lines().next().unwrap_or("New Chat");
                 let mut t: String = first_line.chars().take(40).collect();
                 if first_line.chars().count() > 40 { t.push_str("..."); }
@@ -234,51 +1235,86 @@ pub fn app() -> Html {
             }
 
             // 2. Update Immediate UI (so user sees it instantly)
-            let mut all_chats = (*chats).clone();
             if let Some(c) = all_chats.iter_mut().find(|c| c.id == current_id) {
                 if let Some(t) = &new_title_opt {
                     c.title = t.clone();
                 }
-                c.messages = history.clone();
+                // An unpinned prefill is for this message only
+                if one_shot_prefill.is_some() {
+                    c.prefill = None;
+                }
+                set_sent_messages(c, history.clone());
             }
-            chats.set(all_chats);
+            commit_chats(&chats, &latest, all_chats);
+            host_events.emit("message_sent", &MessageSent {
+                chat_id: current_id.clone(),
+                message_id: user_message_id,
+                content: msg_content.clone(),
+            });
 
             // 3. Prepare for Async
             let chats_state = chats.clone();
-            let loading_state = loading.clone();
+            let latest = latest.clone();
+            let generations = generations.clone();
+            let force_update = force_update.clone();
             let set = settings.clone();
-            let cancel = token.clone();
+            let request = RequestConfig::from(&*set);
             let cid = current_id.clone();
-            let title_override = new_title_opt.clone(); // <--- Pass the new title into the async block
+            let request_error = request_error.clone();
+            let empty_reply = empty_reply.clone();
+            let host_events = host_events.clone();
+            let last_activity = last_activity.clone();
+            let stalled = stalled.clone();
+            let retrying = retrying.clone();
+            let viewed_chat = viewed_chat.clone();
+            let server_unreachable = server_unreachable.clone();
+            let server_connected = server_connected.clone();
 
             // Spawn async task with document context
             spawn_local(async move {
                 // Get document context based on mode
-                let service = DocumentService::default();
+                let service = DocumentService;
                 
+                // Overlap left out where retrieved chunks were merged
+                let mut deduplicated_tokens = 0;
                 // For manual mode, we need both the context for LLM and clean display
-                let (doc_context, display_message) = if set.document_context_mode == DocumentContextMode::Manual {
-                    service.build_manual_context_with_display(&msg_content).await
-                } else {
-                    // For RAG mode, use the original context builder
-                    let ctx = service.build_context(&msg_content, 3).await;
-                    (ctx.clone(), ctx + "User message:\n" + &msg_content)
+                let (doc_context, display_message, used_documents) = match set.document_context_mode {
+                    DocumentContextMode::Manual => {
+                        service.build_manual_context_with_display(&msg_content, scope.as_deref(), selected_doc.as_deref()).await
+                    }
+                    DocumentContextMode::Off => (String::new(), msg_content.clone(), Vec::new()),
+                    DocumentContextMode::RAG => {
+                        let context = match &context_chunks {
+                            Some(chunks) => DocumentService::context_from_chunks(chunks),
+                            None => DocumentService::rag_context(&msg_content, scope.as_deref(), &request, &set.embedding_model).await,
+                        };
+                        deduplicated_tokens = context.deduplicated_tokens;
+                        (context.text.clone(), context.text + "User message:\n" + &msg_content, context.documents)
+                    }
                 };
 
                 // DEBUG: Log what's being sent to the model
-                console::log_1(&format!("--- Chat Request Debug ---").into());
+                console::log_1(&"--- Chat Request Debug ---".into());
                 console::log_1(&format!("Original message: {}", msg_content).into());
                 console::log_1(&format!("Document context mode: {:?}", set.document_context_mode).into());
                 if !doc_context.is_empty() {
                     console::log_1(&format!("Document context ({} chars): {}...", doc_context.len(), &doc_context[..std::cmp::min(200, doc_context.len())]).into());
                 }
                 console::log_1(&format!("Display message: {}...", &display_message[..std::cmp::min(300, display_message.len())]).into());
-                console::log_1(&format!("--- End Debug ---").into());
+                console::log_1(&"--- End Debug ---".into());
 
                 // Update history with the display message (clean version)
                 if let Some(last_msg) = history.last_mut() {
                     if last_msg.role == "user" {
+                        if display_message != msg_content {
+                            last_msg.typed = Some(msg_content.clone());
+                        }
                         last_msg.content = display_message.clone();
+                        last_msg.token_count = Some(count_tokens(&last_msg.content));
+                        last_msg.context_documents = used_documents;
+                        if set.document_context_mode == DocumentContextMode::Manual {
+                            last_msg.document_refs = DocumentService::document_refs_in(&msg_content);
+                        }
                     }
                 }
 
@@ -287,104 +1323,835 @@ pub fn app() -> Html {
                     console::log_1(&format!("  [{}] Role: {}, Content ({} chars): {}...", i, msg.role, msg.content.len(), &msg.content[..std::cmp::min(100, msg.content.len())]).into());
                 }
 
-                // Create messages with full context for LLM
-                let mut llm_messages = history.clone();
-                if !doc_context.is_empty() {
-                    // For LLM, prepend document context and keep original message
-                    let llm_user_content = format!("{}User message:\n{}", doc_context, msg_content);
-                    llm_messages.pop();
-                    llm_messages.push(Message {
-                        role: "user".to_string(),
-                        content: llm_user_content
+                let context_docs: Vec<Document> = DocumentService::get_documents()
+                    .into_iter()
+                    .filter(|d| history.last().is_some_and(|m| m.context_documents.contains(&d.id)))
+                    .collect();
+                let document_list = DocumentService::request_document_list(&set, scope.as_deref());
+                // Mentions go out as placeholders, as they are stored; the context names the documents
+                let outgoing = if set.document_context_mode == DocumentContextMode::Manual { display_message.clone() } else { msg_content.clone() };
+                let prefill = prefill.map(|p| p.text).unwrap_or_default();
+                // The chat's overrides also pick the context length the history is fitted to
+                let request_settings = AppSettings {
+                    selected_model: model_override.unwrap_or_else(|| set.selected_model.clone()),
+                    temperature: temperature_override.unwrap_or(set.temperature),
+                    ..(*set).clone()
+                };
+                let compose = |summary: Option<&(String, String)>| {
+                    let messages = match summary {
+                        Some((text, until)) => with_summary(&history, text, until),
+                        None => history.clone(),
+                    };
+                    LlmService::compose_request(
+                        &request_settings,
+                        &RequestInputs {
+                            history: &messages,
+                            window,
+                            language: language.as_deref(),
+                            prefill: Some(&prefill),
+                            length,
+                            doc_context: &doc_context,
+                            document_list: &document_list,
+                            message: &outgoing,
+                            documents: &context_docs,
+                        },
+                    )
+                };
+                let (mut req, mut composition) = compose(summary.as_ref());
+                // What didn't fit is folded into the chat's summary, which takes its place
+                let mut new_summary = None;
+                if request_settings.context_strategy == ContextStrategy::Summarize && composition.omitted_messages > 0 {
+                    let covered = summary.as_ref().and_then(|(_, until)| summary_end(&history, until)).unwrap_or(0);
+                    let start = covered.max(history_window_start(&history, window));
+                    let chunk: Vec<Message> = history[start..]
+                        .iter()
+                        .filter(|m| m.role != "system" && !m.excluded)
+                        .take(composition.omitted_messages)
+                        .cloned()
+                        .collect();
+                    let previous = summary.as_ref().filter(|_| covered > 0).map(|(text, _)| text.as_str());
+                    match cancel.run(LlmService::summarize(&request, &req.model, previous, &chunk, &cancel)).await {
+                        Ok(Ok(text)) if !text.is_empty() => {
+                            let fresh = (text, chunk.last().map(|m| m.id.clone()).unwrap_or_default());
+                            (req, composition) = compose(Some(&fresh));
+                            new_summary = Some(fresh);
+                        }
+                        // The oldest messages are still left out, as with the sliding window
+                        result => console::debug_1(&format!("Could not summarize the earlier conversation: {:?}", result).into()),
+                    }
+                }
+                composition.deduplicated_tokens = deduplicated_tokens;
+                if let Some(last_msg) = history.last_mut() {
+                    last_msg.composition = Some(composition);
+                }
+
+                let title_target = (chats_state.clone(), latest.clone());
+                // Writes into the latest chat list so other chats' updates are never lost
+                let edit_chat = move |edit: &dyn Fn(&mut ChatSession)| {
+                    let mut all = latest.borrow().clone();
+                    if let Some(c) = all.iter_mut().find(|c| c.id == cid) {
+                        edit(c);
+                    }
+                    commit_chats(&chats_state, &latest, all);
+                };
+                let update = |msgs: Vec<Message>| edit_chat(&|c| set_sent_messages(c, msgs.clone()));
+                if let Some((text, until)) = new_summary {
+                    edit_chat(&|c| {
+                        c.summary = Some(text.clone());
+                        c.summary_until = Some(until.clone());
                     });
                 }
+                // Store the cleaned message and its composition even if the request fails
+                update(history.clone());
+
+                let started_at = js_sys::Date::now();
+                let report_error = |error: LlmError| {
+                    // Stopping is not a failure; the canceller marks the partial answer
+                    if error == LlmError::Aborted {
+                        return;
+                    }
+                    host_events.emit("error", &HostError { chat_id: Some(current_id.clone()), message: error.to_string() });
+                    request_error.set(Some((current_id.clone(), error)));
+                };
+                // Counted in the chat's usage and offered for a retry
+                let report_empty = || {
+                    edit_chat(&|c| c.empty_responses += 1);
+                    host_events.emit("error", &HostError {
+                        chat_id: Some(current_id.clone()),
+                        message: "The model returned an empty response".to_string(),
+                    });
+                    empty_reply.set(Some(current_id.clone()));
+                };
+                let report_completed = |message: &Message| {
+                    if *viewed_chat.borrow() != current_id {
+                        edit_chat(&|c| c.unread = true);
+                    }
+                    // Runs once the answer is stored, so the title is based on it too
+                    if let Some(provisional) = new_title_opt.clone() {
+                        let (chats, latest) = title_target.clone();
+                        spawn_local(generate_chat_title(chats, latest, current_id.clone(), provisional, (*set).clone()));
+                    }
+                    host_events.emit("response_completed", &ResponseCompleted {
+                        chat_id: current_id.clone(),
+                        message_id: message.id.clone(),
+                        content: message.content.clone(),
+                        metrics: ResponseMetrics {
+                            model: message.model.clone(),
+                            tokens: message.token_count,
+                            duration_ms: js_sys::Date::now() - started_at,
+                        },
+                    });
+                };
 
-                let req = ChatRequest {
-                    messages: llm_messages,
-                    model: "/root/models/Strand-Rust-Coder-14B-v1".to_string(),//set.selected_model.clone(),
-                    temperature: 0.7,
-                    stream: set.stream_enabled,
+                // Resets the stall watchdog and clears its warning
+                let heard_from_server = || {
+                    last_activity.borrow_mut().insert(current_id.clone(), js_sys::Date::now());
+                    if stalled.borrow_mut().remove(&current_id) {
+                        force_update.force_update();
+                    }
                 };
 
-                // Define update closure that preserves the title
-                let update = move |msgs: Vec<Message>| {
-                    let mut all = (*chats_state).clone(); // <--- This handle might still hold the old "New Chat" title
-                    if let Some(c) = all.iter_mut().find(|c| c.id == cid) {
-                        c.messages = msgs;
-                        // FORCE the title back if we changed it in this session
-                        if let Some(t) = &title_override {
-                            c.title = t.clone();
-                        }
+                // A retry that fails before answering keeps the earlier attempts
+                // on an empty, stopped answer so the next retry carries them on
+                let keep_failed_attempts = |history: &mut Vec<Message>| {
+                    if !failed_attempts.is_empty() && !cancel.is_aborted() {
+                        let mut holder = Message::new("assistant", "");
+                        holder.interrupted = true;
+                        holder.errored = true;
+                        holder.previous = previous_response.clone();
+                        holder.failed_attempts = failed_attempts.clone();
+                        history.push(holder);
+                        update(history.clone());
                     }
-                    chats_state.set(all);
                 };
 
-                if let Ok(resp) = LlmService::chat_completion_request(&set.base_url, &req).await {
-                    if set.stream_enabled {
-                        history.push(Message { role: "assistant".into(), content: "".into() });
+                heard_from_server();
+                let on_retry = {
+                    let retrying = retrying.clone();
+                    let force_update = force_update.clone();
+                    let chat_id = current_id.clone();
+                    move |retry: u32, retries: u32| {
+                        retrying.borrow_mut().insert(chat_id.clone(), (retry, retries));
+                        force_update.force_update();
+                    }
+                };
+                let response = LlmService::chat_completion_request(&request, &req, &cancel, on_retry).await;
+                if retrying.borrow_mut().remove(&current_id).is_some() {
+                    force_update.force_update();
+                }
+                if let Err(err) = &response {
+                    let error = LlmService::diagnose(&request, err.clone()).await;
+                    if matches!(error, LlmError::Network { .. }) && can_wait && !cancel.is_aborted() {
+                        // Parked ahead of messages queued behind it, as typed
+                        server_unreachable.set(true);
+                        history.pop();
                         update(history.clone());
+                        let content = msg_content.clone();
+                        edit_chat(&|c| {
+                            // The one-shot prefill goes back so the message gets it when it is sent
+                            if c.prefill.is_none() {
+                                c.prefill = one_shot_prefill.clone();
+                            }
+                            let mut parked = Message::new("user", content.clone());
+                            parked.automated = automated;
+                            parked.queued = true;
+                            parked.awaiting_server = true;
+                            let at = c.messages.iter().position(|m| m.queued).unwrap_or(c.messages.len());
+                            c.messages.insert(at, parked);
+                        });
+                    } else {
+                        report_error(error);
+                        keep_failed_attempts(&mut history);
+                    }
+                }
+                if let Ok(resp) = response {
+                    server_unreachable.set(false);
+                    let status = resp.status();
+                    if status.is_success() && !*server_connected {
+                        server_connected.set(true);
+                    }
+                    if !status.is_success() {
+                        report_error(LlmError::from_status(status.as_u16(), cancel.run(resp.text()).await.ok().and_then(|t| t.ok()).unwrap_or_default()));
+                        keep_failed_attempts(&mut history);
+                    } else if LlmService::is_event_stream(&resp, set.stream_enabled) {
+                        if !set.stream_enabled {
+                            console::debug_1(&"Server streamed a response to a non-streaming request".into());
+                        }
+                        let mut placeholder = Message::new("assistant", prefill.clone());
+                        placeholder.id = answer_id.clone();
+                        placeholder.model = Some(req.model.clone());
+                        placeholder.previous = previous_response.clone();
+                        placeholder.failed_attempts = failed_attempts.clone();
+                        history.push(placeholder);
+                        if !cancel.is_aborted() {
+                            update(history.clone());
+                        }
                         let mut stream = resp.bytes_stream();
-                        let mut buffer = String::new();
-                        while let Some(item) = stream.next().await {
-                            if cancel.load(Ordering::Relaxed) { break; }
-                            if let Ok(chunk) = item {
-                                buffer.push_str(&String::from_utf8_lossy(&chunk));
-                                while let Some(pos) = buffer.find('\n') {
-                                    let line = buffer[..pos].trim().to_string();
-                                    buffer.drain(..pos+1);
-                                    if line.starts_with("data: ") && line != "data: [DONE]" {
-                                        if let Ok(json) = serde_json::from_str::<StreamResponse>(&line[6..]) {
-                                            if let Some(txt) = json.choices[0].delta.content.as_ref() {
-                                                if let Some(last) = history.last_mut() { last.content.push_str(txt); }
-                                                update(history.clone());
+                        let mut parser = LlmService::stream_parser(request.backend);
+                        // Isolated bad chunks are skipped, a run of them aborts the stream
+                        let mut undecoded: Vec<String> = Vec::new();
+                        let mut consecutive_failures = 0;
+                        let mut router = ChoiceRouter::default();
+                        let mut first_token_at: Option<f64> = None;
+                        // What the model sent so far, reasoning included
+                        let mut output = String::new();
+                        let mut in_reasoning = false;
+                        let mut usage: Option<Usage> = None;
+                        // Raw body, kept until the first SSE line proves this really is a stream
+                        let mut raw_body = Some(String::new());
+                        'stream: loop {
+                            let (events, ended) = match cancel.run(stream.next()).await {
+                                // The canceller already marked the partial answer as interrupted
+                                Err(_) => break 'stream,
+                                Ok(Some(item)) => {
+                                    heard_from_server();
+                                    // The connection dropped mid-answer; the partial text stays for a retry
+                                    let chunk = match item {
+                                        Ok(chunk) => chunk,
+                                        Err(err) => {
+                                            report_error(LlmError::from(err));
+                                            if let Some(last) = history.last_mut() { last.interrupted = true; last.errored = true; }
+                                            break 'stream;
+                                        }
+                                    };
+                                    if let Some(raw) = raw_body.as_mut() {
+                                        raw.push_str(&String::from_utf8_lossy(&chunk));
+                                    }
+                                    (parser.push(&chunk), false)
+                                }
+                                // The last event may lack the blank line that ends it
+                                Ok(None) => (parser.finish(), true),
+                            };
+                            for event in events {
+                                let data = match event {
+                                    SseEvent::Data { data, .. } => data,
+                                    SseEvent::Done => break 'stream,
+                                };
+                                raw_body = None;
+                                if let Some(message) = LlmService::stream_error(&data) {
+                                    report_error(LlmError::Stream(message));
+                                    if let Some(last) = history.last_mut() { last.interrupted = true; last.errored = true; }
+                                    break 'stream;
+                                }
+                                match LlmService::parse_stream_chunk(request.backend, &data) {
+                                    Ok(json) => {
+                                        consecutive_failures = 0;
+                                        usage = json.usage.or(usage);
+                                        for choice in &json.choices {
+                                            if let ChoiceRoute::Ignored { first, primary } = router.route(choice.index) {
+                                                if first {
+                                                    console::debug_1(&format!(
+                                                        "Ignoring stream chunks for choice {} (showing choice {})",
+                                                        choice.index, primary
+                                                    ).into());
+                                                }
+                                                continue;
                                             }
+                                            if let (Some(tokens), Some(last)) = (parse_logprobs(&choice.logprobs), history.last_mut()) {
+                                                last.logprobs.get_or_insert_with(Vec::new).extend(tokens);
+                                            }
+                                            if let Some(last) = history.last_mut() {
+                                                if choice.delta.append_to(&mut output, &mut in_reasoning) {
+                                                    last.set_output(&output, &prefill);
+                                                    first_token_at.get_or_insert_with(js_sys::Date::now);
+                                                    update(history.clone());
+                                                }
+                                            }
+                                        }
+                                    }
+                                    Err(_) => {
+                                        undecoded.push(data);
+                                        consecutive_failures += 1;
+                                        if consecutive_failures >= MAX_CONSECUTIVE_DECODE_FAILURES {
+                                            report_error(LlmError::Parse(format!(
+                                                "the stream was aborted after {} chunks in a row could not be decoded",
+                                                consecutive_failures
+                                            )));
+                                            if let Some(last) = history.last_mut() { last.interrupted = true; last.errored = true; }
+                                            break 'stream;
                                         }
                                     }
                                 }
                             }
+                            if ended { break; }
                         }
-                    } else {
-                        if let Ok(json) = resp.json::<ChatResponse>().await {
-                            if let Some(choice) = json.choices.first() {
-                                history.push(choice.message.clone());
-                                update(history);
+                        // No SSE at all: some servers answer with a plain JSON body anyway
+                        if let Some(raw) = raw_body {
+                            if let Some(json) = LlmService::single_json_body(request.backend, &raw) {
+                                console::debug_1(&"Streaming was requested but the server sent a single JSON response".into());
+                                if let (Some(mut message), Some(last)) = (LlmService::message_from_response(&json, &req.model, &prefill), history.last_mut()) {
+                                    message.id = last.id.clone();
+                                    message.previous = last.previous.take();
+                                    message.failed_attempts = std::mem::take(&mut last.failed_attempts);
+                                    usage = json.usage;
+                                    *last = message;
+                                }
+                            }
+                        }
+                        // A cancelled message was already finalized by the canceller
+                        if !cancel.is_aborted() {
+                            if let Some(last) = history.last_mut() {
+                                last.token_count = Some(count_tokens(&last.content));
+                                if !last.interrupted {
+                                    last.metrics = Some(MessageMetrics::measure(&last.content, started_at, first_token_at, usage.as_ref()));
+                                }
+                                if !undecoded.is_empty() {
+                                    last.undecoded_chunks = undecoded;
+                                }
+                            }
+                            let empty = history.last().is_some_and(|m| m.role == "assistant" && !m.interrupted && m.content.trim().is_empty());
+                            // An empty retry still holds the earlier attempts
+                            if empty && set.remove_empty_responses && failed_attempts.is_empty() {
+                                history.pop();
+                            }
+                            update(history.clone());
+                            if empty {
+                                report_empty();
+                            } else if let Some(last) = history.last().filter(|m| !m.interrupted) {
+                                report_completed(last);
                             }
                         }
+                    } else if let Ok(Ok(body)) = cancel.run(resp.text()).await {
+                        let (message, usage) = match LlmService::parse_response(request.backend, &body) {
+                            Ok(json) => (LlmService::message_from_response(&json, &req.model, &prefill), json.usage),
+                            // Streamed without saying so; join the deltas instead
+                            Err(_) => (LlmService::message_from_sse(request.backend, &body, &req.model, &prefill), None),
+                        };
+                        if !cancel.is_aborted() {
+                            match message.filter(|m| !m.content.trim().is_empty()) {
+                                Some(mut message) => {
+                                    message.metrics = Some(MessageMetrics::measure(&message.content, started_at, None, usage.as_ref()));
+                                    message.previous = previous_response.clone();
+                                    message.failed_attempts = failed_attempts.clone();
+                                    report_completed(&message);
+                                    history.push(message);
+                                    update(history);
+                                }
+                                None => {
+                                    if !set.remove_empty_responses || !failed_attempts.is_empty() {
+                                        let mut message = Message::new("assistant", "");
+                                        message.model = Some(req.model.clone());
+                                        message.failed_attempts = failed_attempts.clone();
+                                        history.push(message);
+                                        update(history);
+                                    }
+                                    report_empty();
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // Only clear our own entry; a newer generation may own this chat by now
+                {
+                    let mut gens = generations.borrow_mut();
+                    if gens.get(&current_id).is_some_and(|g| g.cancel == cancel) {
+                        gens.remove(&current_id);
+                        last_activity.borrow_mut().remove(&current_id);
+                        stalled.borrow_mut().remove(&current_id);
                     }
                 }
-                loading_state.set(false);
+                force_update.force_update();
             });
         })
     };
+    let run_chat = {
+        let send_message = send_message.clone();
+        let active_id = active_chat_id.clone();
+        Callback::from(move |content: String| send_message.emit(OutgoingMessage {
+            chat_id: (*active_id).clone(),
+            content,
+            automated: false,
+            context_chunks: None,
+            previous_response: None,
+            failed_attempts: Vec::new(),
+        }))
+    };
+
+    // A queued message goes out as soon as its chat has no response running
+    {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        let generations = generations.clone();
+        let send_message = send_message.clone();
+        let offline = *server_unreachable;
+        use_effect(move || {
+            // Messages waiting for the server hold back the ones queued after them
+            let ready = |c: &ChatSession| {
+                c.trashed_at.is_none()
+                    && !generations.borrow().contains_key(&c.id)
+                    && c.messages.iter().find(|m| m.queued).is_some_and(|m| !(offline && m.awaiting_server))
+            };
+            if !latest.borrow().iter().any(ready) {
+                return;
+            }
+            let mut list = latest.borrow().clone();
+            let mut next = Vec::new();
+            for c in list.iter_mut().filter(|c| ready(c)) {
+                if let Some(pos) = c.messages.iter().position(|m| m.queued) {
+                    let message = c.messages.remove(pos);
+                    next.push(OutgoingMessage { chat_id: c.id.clone(), content: message.content, automated: message.automated, context_chunks: None, previous_response: None, failed_attempts: Vec::new() });
+                }
+            }
+            commit_chats(&chats, &latest, list);
+            for outgoing in next {
+                send_message.emit(outgoing);
+            }
+        });
+    }
+
+    // Stops every running response; queued messages are dropped so none start in their place
+    let on_stop_all = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        let generations = generations.clone();
+        let cancel_generation = cancel_generation.clone();
+        Callback::from(move |_: ()| {
+            let mut list = latest.borrow().clone();
+            for c in list.iter_mut() {
+                c.messages.retain(|m| !m.queued);
+            }
+            commit_chats(&chats, &latest, list);
+            let running: Vec<String> = generations.borrow().keys().cloned().collect();
+            for chat_id in running {
+                cancel_generation.emit(chat_id);
+            }
+        })
+    };
+
+    let on_remove_queued = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        Callback::from(move |(chat_id, message_id): (String, String)| {
+            let mut list = latest.borrow().clone();
+            if let Some(c) = list.iter_mut().find(|c| c.id == chat_id) {
+                c.messages.retain(|m| !(m.queued && m.id == message_id));
+            }
+            commit_chats(&chats, &latest, list);
+        })
+    };
+
+    // Sends the question behind an empty or failed response again, in place of
+    // the old attempt
+    let retry_last = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        let empty_reply = empty_reply.clone();
+        let request_error = request_error.clone();
+        let send_message = send_message.clone();
+        Callback::from(move |chat_id: String| {
+            empty_reply.set(None);
+            request_error.set(None);
+            let mut list = latest.borrow().clone();
+            let Some(c) = list.iter_mut().find(|c| c.id == chat_id) else { return };
+            let Some(pos) = c.messages.iter().rposition(|m| m.role == "user" && !m.queued) else { return };
+            let question = c.messages[pos].clone();
+            let (previous, failed_attempts) = retry_inputs(&c.messages[pos + 1..], js_sys::Date::now());
+            let kept = c.messages[..pos].to_vec();
+            set_sent_messages(c, kept);
+            commit_chats(&chats, &latest, list);
+            send_message.emit(OutgoingMessage {
+                chat_id,
+                content: question.typed_text(),
+                automated: question.automated,
+                context_chunks: None,
+                previous_response: previous,
+                failed_attempts,
+            });
+        })
+    };
+    // Sends an edited question in place of the original; everything after it is dropped
+    let on_edit_message = {
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        let generations = generations.clone();
+        let empty_reply = empty_reply.clone();
+        let request_error = request_error.clone();
+        let send_message = send_message.clone();
+        Callback::from(move |(chat_id, (message_id, text)): (String, (String, String))| {
+            if generations.borrow().contains_key(&chat_id) || text.trim().is_empty() {
+                return;
+            }
+            let mut list = latest.borrow().clone();
+            let Some(c) = list.iter_mut().find(|c| c.id == chat_id) else { return };
+            let Some(pos) = c.messages.iter().position(|m| m.id == message_id && m.role == "user" && !m.queued) else { return };
+            let automated = c.messages[pos].automated;
+            let edited: Vec<String> = c.messages[pos..].iter().map(|m| m.id.clone()).collect();
+            c.invalidate_summary(&edited);
+            let kept = c.messages[..pos].to_vec();
+            set_sent_messages(c, kept);
+            commit_chats(&chats, &latest, list);
+            if empty_reply.as_deref() == Some(chat_id.as_str()) {
+                empty_reply.set(None);
+            }
+            request_error.set(None);
+            send_message.emit(OutgoingMessage {
+                chat_id,
+                content: text,
+                automated,
+                context_chunks: None,
+                previous_response: None,
+                failed_attempts: Vec::new(),
+            });
+        })
+    };
+
+    // Re-rolls the last answer. Does nothing while the chat is answering, or when
+    // the last message is a question whose request failed.
+    let on_regenerate = {
+        let latest = latest_chats.clone();
+        let generations = generations.clone();
+        let retry_last = retry_last.clone();
+        Callback::from(move |chat_id: String| {
+            if generations.borrow().contains_key(&chat_id) {
+                return;
+            }
+            let answered = latest
+                .borrow()
+                .iter()
+                .find(|c| c.id == chat_id)
+                .and_then(|c| c.messages.iter().rev().find(|m| !m.queued))
+                .is_some_and(|m| m.role == "assistant");
+            if answered {
+                retry_last.emit(chat_id);
+            }
+        })
+    };
     // -------------------------
 
-    let on_stop = {
-        let token = cancellation_token.clone();
-        let loading = is_loading.clone();
+    // Recurring prompts are checked on open and then on a timer
+    let clock = use_state(js_sys::Date::now);
+    {
+        let clock = clock.clone();
+        use_effect_with((), move |_| {
+            let timer = Interval::new(RECURRING_CHECK_MS, move || clock.set(js_sys::Date::now()));
+            move || drop(timer)
+        });
+    }
+    // Keeps the model loaded while the user is away from the chat. Skipped while
+    // the tab is hidden, while anything is generating and while a ping is out.
+    let keep_alive_ping = use_state(|| None::<KeepAlivePing>);
+    let ping_in_flight = use_mut_ref(|| false);
+    {
+        let generations = generations.clone();
+        let keep_alive_ping = keep_alive_ping.clone();
+        let server_unreachable = server_unreachable.clone();
+        let deps = (settings.keep_alive, settings.keep_alive_minutes, RequestConfig::from(&*settings), settings.selected_model.clone(), !chats.is_empty());
+        use_effect_with(deps, move |(enabled, minutes, request, model, has_chats)| {
+            let timer = (*enabled && *has_chats && *minutes > 0).then(|| {
+                let (minutes, request, model) = (*minutes, request.clone(), model.clone());
+                Interval::new(minutes.saturating_mul(60_000), move || {
+                    let hidden = web_sys::window().and_then(|w| w.document()).is_some_and(|d| d.hidden());
+                    if hidden || !generations.borrow().is_empty() || *ping_in_flight.borrow() {
+                        return;
+                    }
+                    *ping_in_flight.borrow_mut() = true;
+                    let ping_in_flight = ping_in_flight.clone();
+                    let keep_alive_ping = keep_alive_ping.clone();
+                    let server_unreachable = server_unreachable.clone();
+                    let (request, model) = (request.clone(), model.clone());
+                    spawn_local(async move {
+                        let result = LlmService::keep_alive(&request, &model, minutes).await;
+                        *ping_in_flight.borrow_mut() = false;
+                        match &result {
+                            Ok(()) => server_unreachable.set(false),
+                            Err(LlmError::Network { .. }) => server_unreachable.set(true),
+                            Err(_) => {}
+                        }
+                        keep_alive_ping.set(Some(KeepAlivePing { at: js_sys::Date::now(), error: result.err().map(|e| e.to_string()) }));
+                    });
+                })
+            });
+            move || drop(timer)
+        });
+    }
+
+    // Checks on the server while messages wait for it, so they go out once it is back
+    let probe_in_flight = use_mut_ref(|| false);
+    {
+        let server_unreachable = server_unreachable.clone();
+        let waiting = chats.iter().any(|c| c.messages.iter().any(|m| m.awaiting_server));
+        use_effect_with((*server_unreachable, waiting, RequestConfig::from(&*settings)), move |(unreachable, waiting, request)| {
+            let timer = (*unreachable && *waiting).then(|| {
+                let request = request.clone();
+                Interval::new(OFFLINE_PROBE_MS, move || {
+                    if *probe_in_flight.borrow() {
+                        return;
+                    }
+                    *probe_in_flight.borrow_mut() = true;
+                    let probe_in_flight = probe_in_flight.clone();
+                    let server_unreachable = server_unreachable.clone();
+                    let request = request.clone();
+                    spawn_local(async move {
+                        let reachable = LlmService::test_connection(&request).await.is_ok();
+                        *probe_in_flight.borrow_mut() = false;
+                        if reachable {
+                            server_unreachable.set(false);
+                        }
+                    });
+                })
+            });
+            move || drop(timer)
+        });
+    }
+    // Another server may well be reachable, so waiting messages get a try there
+    {
+        let server_unreachable = server_unreachable.clone();
+        use_effect_with(settings.base_url.clone(), move |_| {
+            if *server_unreachable {
+                server_unreachable.set(false);
+            }
+        });
+    }
+    // "Retry now" on a waiting message sends every waiting message
+    let on_retry_waiting = {
+        let server_unreachable = server_unreachable.clone();
+        Callback::from(move |_: ()| server_unreachable.set(false))
+    };
+
+    // Flags streaming generations the server has gone quiet on, e.g. while it loads
+    // the model. Only warns; stopping is left to the user. Non-streamed answers
+    // arrive all at once, so silence is expected there.
+    {
+        let generations = generations.clone();
+        let last_activity = last_activity.clone();
+        let stalled = stalled.clone();
+        let force_update = force_update.clone();
+        use_effect_with((settings.stall_warning_seconds, settings.stream_enabled), move |(seconds, streaming)| {
+            let timer = (*streaming && *seconds > 0).then(|| {
+                let limit = f64::from(*seconds) * 1000.0;
+                Interval::new(1000, move || {
+                    let now = js_sys::Date::now();
+                    let quiet: HashSet<String> = {
+                        let activity = last_activity.borrow();
+                        generations
+                            .borrow()
+                            .keys()
+                            .filter(|id| activity.get(*id).is_some_and(|at| now - at >= limit))
+                            .cloned()
+                            .collect()
+                    };
+                    if *stalled.borrow() != quiet {
+                        *stalled.borrow_mut() = quiet;
+                        force_update.force_update();
+                    }
+                })
+            });
+            move || drop(timer)
+        });
+    }
+    let on_keep_waiting = {
+        let last_activity = last_activity.clone();
+        let stalled = stalled.clone();
+        let force_update = force_update.clone();
+        Callback::from(move |chat_id: String| {
+            last_activity.borrow_mut().insert(chat_id.clone(), js_sys::Date::now());
+            stalled.borrow_mut().remove(&chat_id);
+            force_update.force_update();
+        })
+    };
+
+    // "On app open" prompts already sent since this page loaded
+    let opened_runs = use_mut_ref(HashSet::<String>::new);
+    {
+        let settings = settings.clone();
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        let generations = generations.clone();
+        let send_message = send_message.clone();
+        use_effect_with(*clock, move |now| {
+            let now = *now;
+            let mut next = (*settings).clone();
+            let mut changed = false;
+            for recurring in next.recurring_prompts.iter_mut().filter(|r| !r.paused) {
+                let due = match recurring.interval.period_ms() {
+                    Some(_) => recurring.next_run_at <= now,
+                    None => !opened_runs.borrow().contains(&recurring.id),
+                };
+                let Some(prompt) = next.saved_prompts.iter().find(|p| p.id == recurring.prompt_id) else { continue };
+                if !due {
+                    continue;
+                }
+
+                let chat_id = match recurring.chat_id.clone().filter(|id| latest.borrow().iter().any(|c| &c.id == id && c.trashed_at.is_none())) {
+                    Some(id) => id,
+                    None => {
+                        let mut chat = ChatSession::new(next.system_prompt.clone());
+                        chat.title = prompt.name.clone();
+                        let mut list = latest.borrow().clone();
+                        list.insert(0, chat.clone());
+                        commit_chats(&chats, &latest, list);
+                        recurring.chat_id = Some(chat.id.clone());
+                        changed = true;
+                        chat.id
+                    }
+                };
+                // Tried again on the next check
+                if generations.borrow().contains_key(&chat_id) {
+                    continue;
+                }
+
+                send_message.emit(OutgoingMessage { chat_id, content: prompt.content.clone(), automated: true, context_chunks: None, previous_response: None, failed_attempts: Vec::new() });
+                match recurring.interval.period_ms() {
+                    // Skipped runs aren't made up, but the time of day is kept
+                    Some(period) => while recurring.next_run_at <= now {
+                        recurring.next_run_at += period;
+                    },
+                    None => {
+                        opened_runs.borrow_mut().insert(recurring.id.clone());
+                    }
+                }
+                changed = true;
+            }
+            if changed {
+                settings.set(next);
+            }
+        });
+    }
+
+    // Commands from the mount handle act on whatever the app currently shows
+    {
+        let commands = props.commands.clone();
+        let handler = {
+            let run_chat = run_chat.clone();
+            let on_select_chat = on_select_chat.clone();
+            let on_settings_save = on_settings_save.clone();
+            let settings = settings.clone();
+            let latest = latest_chats.clone();
+            let active_id = active_chat_id.clone();
+            let generations = generations.clone();
+            let deployment = deployment.clone();
+            Callback::from(move |command: HostCommand| match command {
+                HostCommand::SendMessage(text) => {
+                    if text.trim().is_empty() {
+                        return Err("The message is empty".to_string());
+                    }
+                    if generations.borrow().contains_key(&*active_id) {
+                        return Err("A response is still being generated in this chat".to_string());
+                    }
+                    run_chat.emit(text);
+                    Ok(())
+                }
+                HostCommand::SetSystemPrompt(text) => {
+                    if deployment.locked.iter().any(|f| f == "system_prompt") {
+                        return Err("The system prompt is locked by the deployment".to_string());
+                    }
+                    let mut s = (*settings).clone();
+                    s.system_prompt = text;
+                    on_settings_save.emit(s);
+                    Ok(())
+                }
+                HostCommand::OpenChat(id) => {
+                    if !latest.borrow().iter().any(|c| c.id == id && c.trashed_at.is_none()) {
+                        return Err(format!("No chat with id '{}'", id));
+                    }
+                    on_select_chat.emit(id);
+                    Ok(())
+                }
+            })
+        };
+        use_effect(move || {
+            commands.set_handler(Some(handler));
+            move || commands.set_handler(None)
+        });
+    }
+
+    let on_onboarding_finish = {
+        let settings = settings.clone();
+        let chats = chats.clone();
+        let latest = latest_chats.clone();
+        let active_id = active_chat_id.clone();
+        let enforce_locked = enforce_locked.clone();
+        Callback::from(move |new_settings: AppSettings| {
+            let new_settings = enforce_locked(new_settings);
+            // Drop the untouched placeholder chat and start fresh with the chosen prompt
+            let mut list = latest.borrow().clone();
+            list.retain(|c| !(c.messages.len() == 1 && c.messages[0].role == "system"));
+            let new_chat = ChatSession::new(new_settings.system_prompt.clone());
+            list.insert(0, new_chat.clone());
+            commit_chats(&chats, &latest, list);
+            active_id.set(new_chat.id);
+            settings.set(new_settings);
+        })
+    };
+
+    let on_onboarding_skip = {
+        let settings = settings.clone();
         Callback::from(move |_| {
-            token.store(true, Ordering::Relaxed);
-            loading.set(false);
+            let mut s = (*settings).clone();
+            s.onboarding_completed = true;
+            settings.set(s);
         })
     };
 
     let on_reset_settings = {
         let settings = settings.clone();
+        let enforce_locked = enforce_locked.clone();
+        // Confirmed in the settings panel's danger zone
         Callback::from(move |_| {
-            if web_sys::window().unwrap().confirm_with_message("Reset all settings to default?").unwrap_or(false) {
-                settings.set(AppSettings::default());
-            }
+            // Resetting must not send the user through onboarding again, nor unlock anything
+            settings.set(enforce_locked(AppSettings { onboarding_completed: true, ..AppSettings::default() }));
         })
     };
 
     let on_clear_all_chats = {
         let chats = chats.clone();
+        let latest = latest_chats.clone();
         let active_chat_id = active_chat_id.clone();
         let settings = settings.clone();
+        // Confirmed in the settings panel's danger zone
+        Callback::from(move |_| {
+            let new_chat = ChatSession::new(settings.system_prompt.clone());
+            commit_chats(&chats, &latest, vec![new_chat.clone()]);
+            active_chat_id.set(new_chat.id);
+        })
+    };
+
+    let on_backup_chats = {
+        let latest = latest_chats.clone();
         Callback::from(move |_| {
-            if web_sys::window().unwrap().confirm_with_message("Irreversibly delete ALL chat history?").unwrap_or(false) {
-                let new_chat = ChatSession::new(settings.system_prompt.clone());
-                chats.set(vec![new_chat.clone()]);
-                active_chat_id.set(new_chat.id);
+            if let Ok(json) = serde_json::to_string_pretty(&*latest.borrow()) {
+                let _ = ExportService::download("chats-backup.json", &json, "application/json");
             }
         })
     };
@@ -394,53 +2161,611 @@ pub fn app() -> Html {
         Callback::from(move |_| show_settings.set(false))
     };
 
-    let toggle_settings = show_settings.clone();
+    let toggle_settings = {
+        let show_settings = show_settings.clone();
+        let settings_focus = settings_focus.clone();
+        Callback::from(move |_: MouseEvent| {
+            settings_focus.set(None);
+            show_settings.set(!*show_settings);
+        })
+    };
+
+    // Opens the settings at one field
+    let open_setting = |field: &'static str| {
+        let show_settings = show_settings.clone();
+        let settings_focus = settings_focus.clone();
+        Callback::from(move |_: MouseEvent| {
+            settings_focus.set(Some(field.to_string()));
+            show_settings.set(true);
+        })
+    };
     let toggle_sidebar = sidebar_open.clone();
 
-    html! {
-        <>
-            <style>{ GLOBAL_STYLES }</style>
-            <div class="app-container">
-                <Sidebar
-                    open={*sidebar_open}
-                    chats={(*chats).clone()}
-                    active_chat_id={(*active_chat_id).clone()}
-                    on_select={on_select_chat}
-                    on_new={on_new_chat}
-                    on_delete={on_delete_chat}
-                />
-
-                <div class="main-content">
-                    <div class="header">
-                        <div style="display: flex; gap: 10px; align-items: center; min-width: 0;">
-                            <button class="btn-icon" onclick={Callback::from(move |_| toggle_sidebar.set(!*toggle_sidebar))} title="Toggle Menu">
-                                <svg width="24" height="24" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><line x1="3" y1="12" x2="21" y2="12"></line><line x1="3" y1="6" x2="21" y2="6"></line><line x1="3" y1="18" x2="21" y2="18"></line></svg>
-                            </button>
-                            <h2>{ if let Some(c) = &current_chat { &c.title } else { "Local LLM" } }</h2>
-                        </div>
-                        <button class="btn-icon" onclick={Callback::from(move |_| toggle_settings.set(!*toggle_settings))} title="Settings">
-                            <svg width="24" height="24" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><circle cx="12" cy="12" r="3"></circle><path d="M19.4 15a1.65 1.65 0 0 0 .33 1.82l.06.06a2 2 0 0 1 0 2.83 2 2 0 0 1-2.83 0l-.06-.06a1.65 1.65 0 0 0-1.82-.33 1.65 1.65 0 0 0-1 1.51V21a2 2 0 0 1-2 2 2 2 0 0 1-2-2v-.09A1.65 1.65 0 0 0 9 19.4a1.65 1.65 0 0 0-1.82.33l-.06-.06a2 2 0 0 1-2.83 0 2 2 0 0 1 0-2.83l.06-.06a1.65 1.65 0 0 0 .33-1.82 1.65 1.65 0 0 0-1.51-1H3a2 2 0 0 1-2-2 2 2 0 0 1 2-2h.09A1.65 1.65 0 0 0 4.6 9a1.65 1.65 0 0 0-.33-1.82l-.06-.06a2 2 0 0 1 0-2.83 2 2 0 0 1 2.83 0l.06-.06a1.65 1.65 0 0 0 1.82.33H9a1.65 1.65 0 0 0 1-1.51V3a2 2 0 0 1 2-2 2 2 0 0 1 2 2v.09a1.65 1.65 0 0 0 1 1.51 1.65 1.65 0 0 0 1.82-.33l.06-.06a2 2 0 0 1 2.83 0 2 2 0 0 1 0 2.83l-.06-.06a1.65 1.65 0 0 0-.33 1.82V9a1.65 1.65 0 0 0 1.51 1H21a2 2 0 0 1 2 2 2 2 0 0 1-2 2h-.09a1.65 1.65 0 0 0-1.51 1z"></path></svg>
-                        </button>
-                    </div>
+    // Setup guide actions
+    let on_guide_base_url = {
+        let settings = settings.clone();
+        let enforce_locked = enforce_locked.clone();
+        Callback::from(move |url: String| {
+            let mut s = (*settings).clone();
+            s.base_url = url;
+            settings.set(enforce_locked(s));
+        })
+    };
+    let on_guide_connected = {
+        let server_connected = server_connected.clone();
+        Callback::from(move |_| server_connected.set(true))
+    };
+    let on_guide_open_settings = {
+        let show_settings = show_settings.clone();
+        let settings_focus = settings_focus.clone();
+        Callback::from(move |_| {
+            settings_focus.set(Some("base_url".to_string()));
+            show_settings.set(true);
+        })
+    };
 
-                    if *show_settings {
-                        <SettingsModal
-                            settings={(*settings).clone()}
-                            on_save={on_settings_save}
-                            on_close={close_settings}
-                            on_reset={on_reset_settings}
-                            on_clear_chats={on_clear_all_chats}
+    // `primary` is the single or left pane, which selection and jumping to a
+    // message apply to
+    // Dialogs over the chat keep the message input from taking focus
+    let dialog_open = *show_settings || *exporting_chat || whats_new.is_some() || !settings.onboarding_completed;
+    let chat_area = |chat_id: String, primary: bool| -> Html {
+        let chat = chats.iter().find(|c| c.id == chat_id);
+        fn bind<T>(chat_id: &str) -> impl Fn(T) -> (String, T) {
+            let chat_id = chat_id.to_string();
+            move |value| (chat_id.clone(), value)
+        }
+        html! {
+            <ChatArea
+                chat_id={chat_id.clone()}
+                messages={chat.map(|c| c.messages.clone()).unwrap_or_default()}
+                history_window={chat.and_then(|c| c.history_window)}
+                on_history_window_change={on_history_window_change.reform(bind(&chat_id))}
+                response_language={chat.and_then(|c| c.response_language.clone())}
+                on_response_language_change={on_response_language_change.reform(bind(&chat_id))}
+                response_length={chat.and_then(|c| c.response_length)}
+                default_response_length={settings.response_length}
+                on_response_length_change={on_response_length_change.reform(bind(&chat_id))}
+                prefill={chat.and_then(|c| c.prefill.clone())}
+                empty_state={if *server_connected {
+                    html! {}
+                } else {
+                    html! {
+                        <SetupGuide
+                            request={RequestConfig::from(&*settings)}
+                            last_error={request_error.as_ref().filter(|(id, _)| *id == chat_id).map(|(_, e)| e.clone())}
+                            url_locked={deployment.locked.iter().any(|f| f == "base_url")}
+                            on_base_url_change={on_guide_base_url.clone()}
+                            on_connected={on_guide_connected.clone()}
+                            on_open_settings={on_guide_open_settings.clone()}
                         />
                     }
+                }}
+                on_prefill_change={on_prefill_change.reform(bind(&chat_id))}
+                model_override={chat.and_then(|c| c.model_override.clone())}
+                default_model={settings.selected_model.clone()}
+                available_models={(*available_models).clone()}
+                on_model_override_change={on_model_override_change.reform(bind(&chat_id))}
+                temperature_override={chat.and_then(|c| c.temperature_override)}
+                default_temperature={settings.temperature}
+                on_temperature_override_change={on_temperature_override_change.reform(bind(&chat_id))}
+                is_loading={generations.borrow().contains_key(&chat_id)}
+                on_send={{
+                    let chat_id = chat_id.clone();
+                    send_message.reform(move |content| OutgoingMessage {
+                        chat_id: chat_id.clone(),
+                        content,
+                        automated: false,
+                        context_chunks: None,
+                        previous_response: None,
+                        failed_attempts: Vec::new(),
+                    })
+                }}
+                on_send_reviewed={{
+                    let chat_id = chat_id.clone();
+                    send_message.reform(move |(content, chunks)| OutgoingMessage {
+                        chat_id: chat_id.clone(),
+                        content,
+                        automated: false,
+                        context_chunks: Some(chunks),
+                        previous_response: None,
+                        failed_attempts: Vec::new(),
+                    })
+                }}
+                review_context={chat.is_some_and(|c| c.review_context)}
+                embedding_model={settings.embedding_model.clone()}
+                request={RequestConfig::from(&*settings)}
+                on_review_context_change={on_review_context_change.reform(bind(&chat_id))}
+                on_stop={{
+                    let answer_id = generations.borrow().get(&chat_id).map(|g| g.answer_id.clone()).unwrap_or_default();
+                    stop_response.reform(move |_| answer_id.clone())
+                }}
+                stall_warning={stalled.borrow().contains(&chat_id).then_some(settings.stall_warning_seconds)}
+                retrying={retrying.borrow().get(&chat_id).copied()}
+                summary={chat.and_then(|c| c.summary.clone()).filter(|_| settings.context_strategy == ContextStrategy::Summarize)}
+                summary_until={chat.and_then(|c| c.summary_until.clone())}
+                saved_prompts={settings.saved_prompts.clone()}
+                on_keep_waiting={{
+                    let chat_id = chat_id.clone();
+                    on_keep_waiting.reform(move |_| chat_id.clone())
+                }}
+                active_generations={generations.borrow().len()}
+                on_stop_all={on_stop_all.clone()}
+                on_remove_queued={on_remove_queued.reform(bind(&chat_id))}
+                on_edit={on_edit_message.reform(bind(&chat_id))}
+                on_regenerate={{
+                    let chat_id = chat_id.clone();
+                    on_regenerate.reform(move |_| chat_id.clone())
+                }}
+                on_retry_waiting={on_retry_waiting.clone()}
+                chat_title={chat.map(|c| c.title.clone()).unwrap_or_default()}
+                selection_mode={primary && *selection_mode}
+                on_exit_selection={on_exit_selection.clone()}
+                on_delete_messages={on_delete_messages.reform(bind(&chat_id))}
+                on_toggle_excluded={on_toggle_excluded.reform(bind(&chat_id))}
+                context_mode={settings.document_context_mode.clone()}
+                document_scope={chat.and_then(|c| c.document_scope.clone())}
+                on_document_scope_change={on_document_scope_change.reform(bind(&chat_id))}
+                prompt_warning_tokens={settings.prompt_warning_tokens}
+                context_length={settings.context_length_for(chat.and_then(|c| c.model_override.as_deref()).unwrap_or(&settings.selected_model))}
+                custom_instructions={settings.custom_instructions.clone()}
+                selected_document_id={(*selected_document).clone()}
+                on_clear_selected_document={{
+                    let selected_document = selected_document.clone();
+                    Callback::from(move |_| selected_document.set(None))
+                }}
+                document_suggestions={settings.document_suggestions}
+                similar_question={similar_question.as_ref().filter(|(cid, _)| *cid == chat_id).map(|(_, q)| q.clone())}
+                on_view_similar={{
+                    let similar_question = similar_question.clone();
+                    let focus_message = focus_message.clone();
+                    let on_select_chat = on_select_chat.clone();
+                    Callback::from(move |q: SimilarQuestion| {
+                        similar_question.set(None);
+                        on_select_chat.emit(q.chat_id);
+                        focus_message.set(Some(q.message_id));
+                    })
+                }}
+                on_dismiss_similar={{
+                    let similar_question = similar_question.clone();
+                    Callback::from(move |_| similar_question.set(None))
+                }}
+                focus_message={if primary { (*focus_message).clone() } else { None }}
+                empty_response={empty_reply.as_deref() == Some(chat_id.as_str())}
+                request_error={request_error.as_ref().filter(|(id, _)| *id == chat_id).map(|(_, e)| e.clone())}
+                on_dismiss_error={{
+                    let request_error = request_error.clone();
+                    Callback::from(move |_| request_error.set(None))
+                }}
+                on_open_setting={{
+                    let show_settings = show_settings.clone();
+                    let settings_focus = settings_focus.clone();
+                    Callback::from(move |field: String| {
+                        settings_focus.set(Some(field));
+                        show_settings.set(true);
+                    })
+                }}
+                on_retry={{
+                    let chat_id = chat_id.clone();
+                    retry_last.reform(move |_| chat_id.clone())
+                }}
+                on_dismiss_empty={{
+                    let empty_reply = empty_reply.clone();
+                    Callback::from(move |_| empty_reply.set(None))
+                }}
+                count_unit={settings.count_unit}
+                take_focus={primary && !dialog_open}
+                on_document_created={on_document_created.clone()}
+            />
+        }
+    };
+
+    let chat_pane = |pane: Pane, chat_id: String| -> Html {
+        let on_change = match pane {
+            Pane::Left => on_select_chat.clone(),
+            Pane::Right => on_select_right_chat.clone(),
+        };
+        let exists = chats.iter().any(|c| c.id == chat_id);
+        html! {
+            <div
+                class={classes!("swi-pane", (*focused_pane == pane).then_some("swi-focused"))}
+                onfocusin={{
+                    let focused_pane = focused_pane.clone();
+                    Callback::from(move |_| focused_pane.set(pane))
+                }}
+                onmousedown={{
+                    let focused_pane = focused_pane.clone();
+                    Callback::from(move |_| focused_pane.set(pane))
+                }}
+            >
+                <div class="swi-pane-bar">
+                    <select
+                        class="swi-banner-select"
+                        aria-label={if pane == Pane::Left { "Left pane chat" } else { "Right pane chat" }}
+                        onchange={Callback::from(move |e: Event| {
+                            let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+                            on_change.emit(select.value());
+                        })}
+                    >
+                        if !exists {
+                            <option value="" selected=true disabled=true>{ "Choose a chat..." }</option>
+                        }
+                        { for chats.iter().filter(|c| c.is_listed() || c.id == chat_id).map(|c| html! {
+                            <option value={c.id.clone()} selected={c.id == chat_id}>{ &c.title }</option>
+                        }) }
+                    </select>
+                </div>
+                if exists {
+                    { chat_area(chat_id.clone(), pane == Pane::Left) }
+                }
+            </div>
+        }
+    };
+
+    inject_styles("app", GLOBAL_STYLES);
 
-                    <ChatArea
-                        messages={current_messages}
-                        is_loading={*is_loading}
-                        on_send={run_chat}
-                        on_stop={on_stop}
+    html! {
+        <>
+            <div
+                class={classes!(ROOT_CLASS, settings.high_contrast.then_some("swi-high-contrast"), settings.reduced_motion.then_some("swi-reduced-motion"))}
+                style={props.config.theme_style()}
+            >
+                <div class="swi-app-container">
+                    <Sidebar
+                        open={*sidebar_open}
+                        chats={(*chats).clone()}
+                        active_chat_id={match (*focused_pane, &*split_chat_id) {
+                            (Pane::Right, Some(id)) => id.clone(),
+                            _ => (*active_chat_id).clone(),
+                        }}
+                        on_select={on_sidebar_select}
+                        on_new={on_new_chat}
+                        on_delete={on_delete_chat}
+                        on_bulk_action={on_bulk_action}
+                        folder_order={settings.folder_order.clone()}
+                        request={RequestConfig::from(&*settings)}
+                        embedding_model={settings.embedding_model.clone()}
+                        on_documents_deleted={on_documents_deleted.clone()}
+                        on_open_setting={{
+                            let show_settings = show_settings.clone();
+                            let settings_focus = settings_focus.clone();
+                            Callback::from(move |field: String| {
+                                settings_focus.set(Some(field));
+                                show_settings.set(true);
+                            })
+                        }}
+                        on_reorder_folders={{
+                            let settings = settings.clone();
+                            Callback::from(move |order: Vec<String>| {
+                                let mut s = (*settings).clone();
+                                s.folder_order = order;
+                                settings.set(s);
+                            })
+                        }}
+                        selected_document_id={(*selected_document).clone()}
+                        on_document_selected={{
+                            let selected_document = selected_document.clone();
+                            Callback::from(move |id: Option<String>| selected_document.set(id))
+                        }}
+                        on_document_uploaded={on_document_uploaded.clone()}
+                        documents_added={(*documents_added).clone()}
+                        count_unit={settings.count_unit}
                     />
+
+                    <div class="swi-main-content">
+                        <div class="swi-header">
+                            <div style="display: flex; gap: 10px; align-items: center; min-width: 0;">
+                                <button class="swi-btn-icon" onclick={Callback::from(move |_| toggle_sidebar.set(!*toggle_sidebar))} title="Toggle Menu">
+                                    <svg width="24" height="24" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><line x1="3" y1="12" x2="21" y2="12"></line><line x1="3" y1="6" x2="21" y2="6"></line><line x1="3" y1="18" x2="21" y2="18"></line></svg>
+                                </button>
+                                if let (true, Some(c)) = (*editing_title, &current_chat) {
+                                    <input
+                                        class="swi-title-input"
+                                        ref={title_input_ref}
+                                        value={c.title.clone()}
+                                        onkeydown={on_title_keydown}
+                                        onblur={on_title_blur}
+                                    />
+                                } else {
+                                    <h2
+                                        title="Click to rename"
+                                        onclick={{
+                                            let editing = editing_title.clone();
+                                            let has_chat = current_chat.is_some();
+                                            Callback::from(move |_| if has_chat { editing.set(true) })
+                                        }}
+                                    >
+                                        { if let Some(c) = &current_chat { &c.title } else { "Local LLM" } }
+                                    </h2>
+                                }
+                                if let Some(language) = current_chat.and_then(|c| c.response_language.as_ref()) {
+                                    <span class="swi-language-badge" title={format!("Answers in this chat are requested in {}", language)}>{ format!("🌐 {}", language) }</span>
+                                }
+                                if suggesting_title.as_deref() == Some(active_chat_id.as_str()) {
+                                    <span class="swi-title-spinner" title="Suggesting a title..."></span>
+                                }
+                            </div>
+                            <div class="swi-header-actions">
+                                if settings.keep_alive {
+                                    <span
+                                        class={classes!("swi-server-status", keep_alive_ping.as_ref().map(|p| if p.error.is_some() { "swi-failed" } else { "swi-ok" }))}
+                                        title={match &*keep_alive_ping {
+                                            None => format!("Keep-alive on: the model is pinged every {} minutes while this tab is open", settings.keep_alive_minutes),
+                                            Some(KeepAlivePing { at, error: None }) => format!("Keep-alive: last ping {} succeeded", format_relative_time(*at, js_sys::Date::now())),
+                                            Some(KeepAlivePing { at, error: Some(e) }) => format!("Keep-alive: last ping {} failed. {}", format_relative_time(*at, js_sys::Date::now()), e),
+                                        }}
+                                    ></span>
+                                } else if *server_unreachable {
+                                    <span class="swi-server-status swi-failed" title="The server could not be reached; waiting messages are sent once it answers"></span>
+                                }
+                                <button
+                                    class="swi-btn swi-mini-btn"
+                                    disabled={suggesting_title.is_some()}
+                                    onclick={on_suggest_title}
+                                    title="Ask the model for a title based on the conversation so far"
+                                >
+                                    { "Suggest title" }
+                                </button>
+                                <button
+                                    class={if *selection_mode { "swi-btn swi-mini-btn swi-active-toggle" } else { "swi-btn swi-mini-btn" }}
+                                    onclick={{
+                                        let selection_mode = selection_mode.clone();
+                                        Callback::from(move |_| selection_mode.set(!*selection_mode))
+                                    }}
+                                    title="Select messages to copy, export or delete"
+                                >
+                                    { if *selection_mode { "Done" } else { "Select messages" } }
+                                </button>
+                                <button
+                                    class="swi-btn swi-mini-btn"
+                                    disabled={current_chat.is_none()}
+                                    onclick={{
+                                        let exporting_chat = exporting_chat.clone();
+                                        Callback::from(move |_| exporting_chat.set(true))
+                                    }}
+                                    title="Export this chat in a prompt format such as ChatML"
+                                >
+                                    { "Export" }
+                                </button>
+                                <button
+                                    class={if split_chat_id.is_some() { "swi-btn swi-mini-btn swi-active-toggle" } else { "swi-btn swi-mini-btn" }}
+                                    onclick={on_toggle_split}
+                                    title="Show two chats side by side"
+                                >
+                                    { if split_chat_id.is_some() { "Single view" } else { "Split view" } }
+                                </button>
+                                <button
+                                    class="swi-btn-icon swi-settings-btn"
+                                    onclick={toggle_settings}
+                                    title={if context_overhead.is_tight() { "Settings — little room is left for the conversation" } else { "Settings" }}
+                                >
+                                    if context_overhead.is_tight() {
+                                        <span class="swi-settings-badge"></span>
+                                    }
+                                    <svg width="24" height="24" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><circle cx="12" cy="12" r="3"></circle><path d="M19.4 15a1.65 1.65 0 0 0 .33 1.82l.06.06a2 2 0 0 1 0 2.83 2 2 0 0 1-2.83 0l-.06-.06a1.65 1.65 0 0 0-1.82-.33 1.65 1.65 0 0 0-1 1.51V21a2 2 0 0 1-2 2 2 2 0 0 1-2-2v-.09A1.65 1.65 0 0 0 9 19.4a1.65 1.65 0 0 0-1.82.33l-.06-.06a2 2 0 0 1-2.83 0 2 2 0 0 1 0-2.83l.06-.06a1.65 1.65 0 0 0 .33-1.82 1.65 1.65 0 0 0-1.51-1H3a2 2 0 0 1-2-2 2 2 0 0 1 2-2h.09A1.65 1.65 0 0 0 4.6 9a1.65 1.65 0 0 0-.33-1.82l-.06-.06a2 2 0 0 1 0-2.83 2 2 0 0 1 2.83 0l.06-.06a1.65 1.65 0 0 0 1.82.33H9a1.65 1.65 0 0 0 1-1.51V3a2 2 0 0 1 2-2 2 2 0 0 1 2 2v.09a1.65 1.65 0 0 0 1 1.51 1.65 1.65 0 0 0 1.82-.33l.06-.06a2 2 0 0 1 2.83 0 2 2 0 0 1 0 2.83l-.06-.06a1.65 1.65 0 0 0-.33 1.82V9a1.65 1.65 0 0 0 1.51 1H21a2 2 0 0 1 2 2 2 2 0 0 1-2 2h-.09a1.65 1.65 0 0 0-1.51 1z"></path></svg>
+                                </button>
+                            </div>
+                        </div>
+
+                        if *show_settings {
+                            <SettingsModal
+                                settings={(*settings).clone()}
+                                on_save={on_settings_save}
+                                on_close={close_settings}
+                                on_reset={on_reset_settings}
+                                locked={deployment.locked.clone()}
+                                focus_field={(*settings_focus).clone()}
+                                on_clear_chats={on_clear_all_chats}
+                                on_backup_chats={on_backup_chats}
+                                chats={(*chats).clone()}
+                                on_whats_new={{
+                                    let whats_new = whats_new.clone();
+                                    let show_settings = show_settings.clone();
+                                    Callback::from(move |_| {
+                                        show_settings.set(false);
+                                        whats_new.set(Some(CHANGELOG.iter().collect()));
+                                    })
+                                }}
+                            />
+                        }
+
+                        if let Some(releases) = (*whats_new).clone().filter(|_| settings.onboarding_completed) {
+                            <WhatsNew
+                                releases={releases}
+                                on_close={{
+                                    let whats_new = whats_new.clone();
+                                    let prefix = props.config.storage_key_prefix.clone();
+                                    Callback::from(move |_| {
+                                        ChangelogService::mark_seen(&prefix);
+                                        whats_new.set(None);
+                                    })
+                                }}
+                                on_open_setting={{
+                                    let whats_new = whats_new.clone();
+                                    let show_settings = show_settings.clone();
+                                    let settings_focus = settings_focus.clone();
+                                    let prefix = props.config.storage_key_prefix.clone();
+                                    Callback::from(move |field: String| {
+                                        ChangelogService::mark_seen(&prefix);
+                                        whats_new.set(None);
+                                        settings_focus.set(Some(field));
+                                        show_settings.set(true);
+                                    })
+                                }}
+                            />
+                        }
+
+                        if let Some(chat) = current_chat.filter(|_| *exporting_chat) {
+                            <ChatExport
+                                chat={chat.clone()}
+                                settings={(*settings).clone()}
+                                on_close={{
+                                    let exporting_chat = exporting_chat.clone();
+                                    Callback::from(move |_| exporting_chat.set(false))
+                                }}
+                            />
+                        }
+
+                        if model_missing && !*model_notice_dismissed {
+                            <div class="swi-chat-banner swi-warning">
+                                <span>{ format!("Model '{}' not found on this server — pick a model:", settings.selected_model) }</span>
+                                <select class="swi-banner-select" onchange={on_quick_pick_model}>
+                                    <option value="" selected=true disabled=true>{ "Choose..." }</option>
+                                    { for available_models.iter().map(|m| html! { <option value={m.clone()}>{ m }</option> }) }
+                                </select>
+                                <button class="swi-btn-icon swi-banner-close" title="Dismiss" onclick={{
+                                    let dismissed = model_notice_dismissed.clone();
+                                    Callback::from(move |_| dismissed.set(true))
+                                }}>{ "×" }</button>
+                            </div>
+                        }
+                        if let Some(migration) = &*chat_migration {
+                            <div class="swi-chat-banner swi-info">
+                                <span>{ format!("Your {} chats were migrated to a new storage format. Does everything look right?", migration.chats) }</span>
+                                <button class="swi-btn swi-mini-btn" onclick={{
+                                    let chat_migration = chat_migration.clone();
+                                    let migration = migration.clone();
+                                    let prefix = props.config.storage_key_prefix.clone();
+                                    Callback::from(move |_| {
+                                        MigrationService::confirm(&prefix, &migration);
+                                        chat_migration.set(None);
+                                    })
+                                }}>{ "Yes, clean up" }</button>
+                                <button class="swi-btn swi-mini-btn" onclick={{
+                                    let migration = migration.clone();
+                                    let prefix = props.config.storage_key_prefix.clone();
+                                    let show_toast = show_toast.clone();
+                                    Callback::from(move |_| restore_chats(&prefix, &migration, &show_toast))
+                                }}>{ "Restore old data" }</button>
+                            </div>
+                        }
+                        { for storage_notices.iter().enumerate().map(|(i, r)| {
+                            let raw = r.raw.clone();
+                            let file_name = format!("{}.json", r.backup_key.clone().unwrap_or_else(|| format!("corrupt_{}", r.backed_up_at as u64)));
+                            let notices = storage_notices.clone();
+                            let kept = match &r.backup_key {
+                                Some(key) => format!("the original data was kept as '{}' ({})", key, format_absolute(r.backed_up_at)),
+                                None => "the original data could not be backed up and is left as it was until this notice is dismissed, so download it first".to_string(),
+                            };
+                            html! {
+                                <div class="swi-chat-banner swi-error">
+                                    <span>{ format!("Saved {} could not be read. Recovered {} of {}; {}.", r.label, r.recovered, r.total, kept) }</span>
+                                    <button class="swi-btn swi-mini-btn" onclick={Callback::from(move |_| {
+                                        let _ = ExportService::download(&file_name, &raw, "application/json");
+                                    })}>{ "Download raw data" }</button>
+                                    <button class="swi-btn-icon swi-banner-close" title="Dismiss" onclick={Callback::from(move |_| {
+                                        let mut list = (*notices).clone();
+                                        list.remove(i);
+                                        notices.set(list);
+                                    })}>{ "×" }</button>
+                                </div>
+                            }
+                        })}
+                        if let Some((_, suggested)) = title_suggestion.as_ref().filter(|(id, _)| *id == *active_chat_id) {
+                            <div class="swi-chat-banner swi-info">
+                                { "Suggested title:" }
+                                <input
+                                    class="swi-banner-input"
+                                    value={suggested.clone()}
+                                    oninput={{
+                                        let suggestion = title_suggestion.clone();
+                                        let id = (*active_chat_id).clone();
+                                        Callback::from(move |e: InputEvent| {
+                                            let input: HtmlInputElement = e.target_unchecked_into();
+                                            suggestion.set(Some((id.clone(), input.value())));
+                                        })
+                                    }}
+                                    onkeydown={{
+                                        let accept = accept_title_suggestion.clone();
+                                        Callback::from(move |e: KeyboardEvent| if e.key() == "Enter" { accept.emit(()) })
+                                    }}
+                                />
+                                <button class="swi-btn swi-mini-btn" onclick={accept_title_suggestion.reform(|_| ())}>{ "Use title" }</button>
+                                <button class="swi-btn-icon swi-banner-close" title="Keep the current title" onclick={{
+                                    let suggestion = title_suggestion.clone();
+                                    Callback::from(move |_| suggestion.set(None))
+                                }}>{ "×" }</button>
+                            </div>
+                        }
+                        if context_overhead.is_tight() {
+                            <div class="swi-chat-banner swi-warning">
+                                <span>{ format!(
+                                    "The system prompt ({}), custom instructions ({}) and documents ({}) leave {} of {} tokens for the conversation. Longer messages may be rejected by the server.",
+                                    format_count(context_overhead.system_tokens),
+                                    format_count(context_overhead.instruction_tokens),
+                                    format_count(context_overhead.document_tokens),
+                                    format_count(context_overhead.remaining()),
+                                    format_count(context_overhead.context_length),
+                                ) }</span>
+                                <button class="swi-btn swi-mini-btn" onclick={open_setting("system_prompt")}>{ "System prompt" }</button>
+                                if context_overhead.instruction_tokens > 0 {
+                                    <button class="swi-btn swi-mini-btn" onclick={open_setting("custom_instructions")}>{ "Custom instructions" }</button>
+                                }
+                                if context_overhead.document_tokens > 0 {
+                                    <button class="swi-btn swi-mini-btn" onclick={open_setting("document_context_mode")}>{ "Document context" }</button>
+                                }
+                                <button class="swi-btn swi-mini-btn" onclick={open_setting("context_length")}>{ "Context length" }</button>
+                            </div>
+                        }
+                        if let Some(right_id) = &*split_chat_id {
+                            <div class="swi-split">
+                                { chat_pane(Pane::Left, (*active_chat_id).clone()) }
+                                { chat_pane(Pane::Right, right_id.clone()) }
+                            </div>
+                        } else {
+                            { chat_area((*active_chat_id).clone(), true) }
+                        }
+                        if let Some(text) = &*toast {
+                            <div class="swi-toast" role="status">{ text }</div>
+                        }
+                    </div>
                 </div>
+                // Covers the whole app, sidebar included
+                if !settings.onboarding_completed {
+                    <Onboarding
+                        settings={(*settings).clone()}
+                        on_finish={on_onboarding_finish}
+                        on_skip={on_onboarding_skip}
+                    />
+                }
             </div>
         </>
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(content: &str, interrupted: bool, errored: bool) -> Message {
+        Message { role: "assistant".to_string(), content: content.to_string(), interrupted, errored, created_at: Some(1.0), ..Default::default() }
+    }
+
+    #[test]
+    fn retries_compare_with_a_finished_answer() {
+        let (previous, attempts) = retry_inputs(&[answer("done", false, false)], 5.0);
+        assert_eq!(previous.as_deref(), Some("done"));
+        assert!(attempts.is_empty());
+    }
+
+    #[test]
+    fn answers_broken_by_errors_become_failed_attempts() {
+        let mut broken = answer("half an", true, true);
+        broken.previous = Some("older".to_string());
+        broken.failed_attempts = (0..MAX_FAILED_ATTEMPTS).map(|i| FailedAttempt { content: i.to_string(), at: 0.0 }).collect();
+        let (previous, attempts) = retry_inputs(&[broken], 5.0);
+        assert_eq!(previous.as_deref(), Some("older"));
+        assert_eq!(attempts.len(), MAX_FAILED_ATTEMPTS);
+        assert_eq!(attempts.last().map(|a| (a.content.as_str(), a.at)), Some(("half an", 1.0)));
+        assert_eq!(attempts[0].content, "1");
+    }
+
+    #[test]
+    fn answers_the_user_stopped_are_not_failed_attempts() {
+        let mut stopped = answer("enough", true, false);
+        stopped.failed_attempts = vec![FailedAttempt { content: "broken".to_string(), at: 0.0 }];
+        let (previous, attempts) = retry_inputs(&[stopped], 5.0);
+        assert_eq!(previous.as_deref(), Some("enough"));
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].content, "broken");
+    }
+}
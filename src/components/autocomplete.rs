@@ -0,0 +1,85 @@
+use yew::prelude::*;
+use web_sys::{Element, HtmlElement};
+use crate::utils::inject_styles;
+
+/// One suggestion of the list
+#[derive(Clone, PartialEq)]
+pub struct AutocompleteItem {
+    pub label: String,
+    pub detail: String,
+}
+
+#[derive(Properties, PartialEq)]
+pub struct AutocompleteProps {
+    pub items: Vec<AutocompleteItem>,
+    /// Index of the highlighted item, moved with the arrow keys by the owner
+    pub active: usize,
+    /// Element the list opens above, usually the input being typed in
+    pub anchor: NodeRef,
+    pub on_select: Callback<usize>,
+    /// Shown when nothing matches
+    #[prop_or_default]
+    pub empty_text: AttrValue,
+}
+
+/// Suggestion list shown above an input while typing a trigger such as `/`.
+/// Keyboard handling stays with the input so focus never moves here.
+#[function_component(Autocomplete)]
+pub fn autocomplete(props: &AutocompleteProps) -> Html {
+    let list_ref = use_node_ref();
+
+    // Keep the highlighted item in view while moving through a long list
+    {
+        let list_ref = list_ref.clone();
+        use_effect_with(props.active, move |_| {
+            let item = list_ref.cast::<Element>().and_then(|list| list.query_selector(".swi-active").ok().flatten());
+            if let Some(item) = item {
+                item.scroll_into_view_with_bool(false);
+            }
+        });
+    }
+
+    let css = r#"
+        .swi-autocomplete { position: fixed; background: white; border: 1px solid var(--border-color); border-radius: 8px; box-shadow: 0 4px 12px rgba(0, 0, 0, 0.15); width: 320px; max-height: 240px; overflow-y: auto; z-index: 100; }
+        .swi-autocomplete-item { padding: 8px 12px; cursor: pointer; display: flex; flex-direction: column; gap: 2px; border-bottom: 1px solid #f0f0f0; }
+        .swi-autocomplete-item:last-child { border-bottom: none; }
+        .swi-autocomplete-item.swi-active { background: #f0f4ff; }
+        .swi-autocomplete-label { font-size: 0.9rem; font-weight: 500; color: var(--text-primary); }
+        .swi-autocomplete-detail { font-size: 0.75rem; color: var(--text-secondary); white-space: nowrap; overflow: hidden; text-overflow: ellipsis; }
+        .swi-autocomplete-empty { padding: 10px 12px; color: var(--text-secondary); text-align: center; font-size: 0.85rem; }
+    "#;
+    inject_styles("autocomplete", css);
+
+    // Opens upward from the top edge of the anchor, in viewport coordinates
+    let style = props
+        .anchor
+        .cast::<HtmlElement>()
+        .and_then(|el| {
+            let rect = el.get_bounding_client_rect();
+            let vh = web_sys::window()?.inner_height().ok()?.as_f64()?;
+            Some(format!("left: {}px; bottom: {}px;", rect.left().round(), (vh - rect.top() + 6.0).round()))
+        })
+        .unwrap_or_default();
+
+    // Clicking an item must not take focus from the input
+    let keep_focus = Callback::from(|e: MouseEvent| e.prevent_default());
+
+    html! {
+        <div class="swi-autocomplete" style={style} ref={list_ref} onmousedown={keep_focus}>
+            if props.items.is_empty() {
+                <div class="swi-autocomplete-empty">{ &props.empty_text }</div>
+            }
+            { for props.items.iter().enumerate().map(|(i, item)| {
+                let class = classes!("swi-autocomplete-item", (i == props.active).then_some("swi-active"));
+                html! {
+                    <div class={class} onclick={props.on_select.reform(move |_| i)}>
+                        <span class="swi-autocomplete-label">{ &item.label }</span>
+                        if !item.detail.is_empty() {
+                            <span class="swi-autocomplete-detail">{ &item.detail }</span>
+                        }
+                    </div>
+                }
+            }) }
+        </div>
+    }
+}
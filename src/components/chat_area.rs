@@ -1,31 +1,439 @@
 use yew::prelude::*;
+use std::collections::{BTreeSet, HashMap};
 use wasm_bindgen::JsCast;
-use web_sys::{HtmlElement, HtmlTextAreaElement, Element};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{HtmlElement, HtmlSelectElement, HtmlTextAreaElement, Element};
 
-use crate::models::Message;
+use crate::models::{CountUnit, Prefill, ResponseLength, RESPONSE_LANGUAGES, Document, DocumentChunk, DocumentContextMode, DocumentRef, Message, SavedPrompt, TokenLogprob};
+use crate::components::autocomplete::{Autocomplete, AutocompleteItem};
+use crate::components::document_viewer::DocumentViewer;
+use crate::components::context_inspector::ContextInspector;
+use crate::components::diff_view::DiffView;
 use crate::services::document_service::DocumentService;
-use crate::utils::render_markdown;
+use crate::services::error::{DocumentError, LlmError};
+use crate::services::export::ExportService;
+use crate::services::request::RequestConfig;
+use crate::services::similarity::SimilarQuestion;
+use crate::utils::{render_markdown, render_markdown_with_chips, chip_marker, strip_chip_marks, history_window_start, summary_end, format_count, format_compact, count_tokens, TextCounts, format_absolute, format_date, format_relative_time, looks_like_code, guess_code_language, inject_styles};
+
+const HISTORY_WINDOW_OPTIONS: [usize; 6] = [1, 2, 3, 5, 10, 20];
+
+#[derive(Clone, Copy, PartialEq)]
+enum ToolbarPopover {
+    History,
+    Language,
+    Length,
+    Model,
+    Prefill,
+}
+// Long chats are rendered from the end, older messages are added in steps
+const INITIAL_VISIBLE_MESSAGES: usize = 50;
+const LOAD_MORE_STEP: usize = 50;
+// Quiet time after scrolling before the position is remembered
+const SCROLL_SAVE_DELAY_MS: u32 = 150;
+// Offered when fencing a pasted snippet; "" is a fence without a language
+const CODE_LANGUAGES: &[&str] = &[
+    "", "rust", "python", "javascript", "typescript", "go", "c", "cpp", "java",
+    "json", "html", "css", "bash", "sql", "yaml", "toml",
+];
+
+/// Where the user left a chat, remembered for this session
+#[derive(Clone, Copy)]
+struct SavedScroll {
+    // Distance of the top of the view from the end of the content
+    from_bottom: i32,
+    at_bottom: bool,
+    visible_count: usize,
+    // Messages in the chat at the time, so new ones can be shown instead
+    message_count: usize,
+}
+
+/// A paste that looked like code, waiting for the user to wrap or dismiss it
+#[derive(Clone, PartialEq)]
+struct PastedCode {
+    // Insertion point as reported by the textarea (UTF-16 units)
+    start: u32,
+    text: String,
+    language: String,
+}
+
+/// What a picker in the input offers
+#[derive(Clone, Copy, PartialEq)]
+enum PickerKind {
+    /// Saved prompts, opened by `/`
+    Prompts,
+    /// Uploaded documents, opened by `@`
+    Documents,
+}
+
+/// Picker opened by typing a trigger character in the input
+#[derive(Clone, PartialEq)]
+struct InputPicker {
+    kind: PickerKind,
+    // Byte offset of the trigger and the text typed after it
+    start: usize,
+    query: String,
+    active: usize,
+}
+
+/// A message held back until the user has vetted the chunks it would be sent with
+#[derive(Clone, PartialEq)]
+struct ContextReview {
+    text: String,
+    chunks: Vec<DocumentChunk>,
+    // Ids of the checked chunks
+    approved: BTreeSet<String>,
+}
+
+/// Warnings the user chose to send past; each prompt before sending has its own
+#[derive(Clone, Copy, Default, PartialEq)]
+struct SendOverrides {
+    /// The message alone is too large for the model's context
+    oversize: bool,
+    /// The whole request is over the size the user asked to be warned about
+    size_warning: bool,
+    /// Mentioned documents were deleted before sending
+    missing_mentions: bool,
+}
 
 #[derive(Properties, PartialEq)]
 pub struct ChatAreaProps {
+    pub chat_id: String,
     pub messages: Vec<Message>,
+    pub history_window: Option<usize>,
+    pub on_history_window_change: Callback<Option<usize>>,
+    /// None = auto
+    #[prop_or_default]
+    pub response_language: Option<String>,
+    #[prop_or_default]
+    pub on_response_language_change: Callback<Option<String>>,
+    /// None = `default_response_length`
+    #[prop_or_default]
+    pub response_length: Option<ResponseLength>,
+    #[prop_or_default]
+    pub default_response_length: ResponseLength,
+    #[prop_or_default]
+    pub on_response_length_change: Callback<Option<ResponseLength>>,
+    /// None = `default_model`
+    #[prop_or_default]
+    pub model_override: Option<String>,
+    /// The model selected in the settings
+    #[prop_or_default]
+    pub default_model: String,
+    /// Models the server offers
+    #[prop_or_default]
+    pub available_models: Vec<String>,
+    #[prop_or_default]
+    pub on_model_override_change: Callback<Option<String>>,
+    /// None = `default_temperature`
+    #[prop_or_default]
+    pub temperature_override: Option<f32>,
+    #[prop_or_default]
+    pub default_temperature: f32,
+    #[prop_or_default]
+    pub on_temperature_override_change: Callback<Option<f32>>,
+    /// Text the next answer starts with
+    #[prop_or_default]
+    pub prefill: Option<Prefill>,
+    #[prop_or_default]
+    pub on_prefill_change: Callback<Option<Prefill>>,
     pub is_loading: bool,
     pub on_send: Callback<String>,
+    /// A document made from text in the input, e.g. a paste too large to send
+    #[prop_or_default]
+    pub on_document_created: Callback<Document>,
+    /// Sends a message with exactly these chunks as document context
+    #[prop_or_default]
+    pub on_send_reviewed: Callback<(String, Vec<DocumentChunk>)>,
+    /// Whether RAG context is shown for approval before sending
+    #[prop_or_default]
+    pub review_context: bool,
+    #[prop_or_default]
+    pub on_review_context_change: Callback<bool>,
+    /// Model RAG searches document indexes with; empty when it sends whole documents
+    #[prop_or_default]
+    pub embedding_model: String,
+    /// Server the chunks to review are retrieved with
+    #[prop_or_default]
+    pub request: RequestConfig,
     pub on_stop: Callback<()>,
+    /// Responses being generated across all chats
+    #[prop_or_default]
+    pub active_generations: usize,
+    #[prop_or_default]
+    pub on_stop_all: Callback<()>,
+    /// Removes a queued message before it is sent
+    #[prop_or_default]
+    pub on_remove_queued: Callback<String>,
+    /// (message id, new text): sends an edited question again, dropping what came after it
+    #[prop_or_default]
+    pub on_edit: Callback<(String, String)>,
+    /// Replaces the last answer with a new one
+    #[prop_or_default]
+    pub on_regenerate: Callback<()>,
+    /// Sends the messages waiting for an unreachable server right away
+    #[prop_or_default]
+    pub on_retry_waiting: Callback<()>,
+    pub chat_title: String,
+    pub selection_mode: bool,
+    pub on_exit_selection: Callback<()>,
+    pub on_delete_messages: Callback<Vec<String>>,
+    pub on_toggle_excluded: Callback<String>,
+    pub context_mode: DocumentContextMode,
+    pub document_scope: Option<Vec<String>>,
+    pub on_document_scope_change: Callback<Option<Vec<String>>>,
+    pub prompt_warning_tokens: usize,
+    pub context_length: usize,
+    pub custom_instructions: String,
+    pub selected_document_id: Option<String>,
+    pub on_clear_selected_document: Callback<()>,
+    /// Earlier question matching the one just sent
+    pub similar_question: Option<SimilarQuestion>,
+    pub on_view_similar: Callback<SimilarQuestion>,
+    pub on_dismiss_similar: Callback<()>,
+    /// Offer documents the typed message looks related to as context
+    #[prop_or_default]
+    pub document_suggestions: bool,
+    /// Message to scroll to and highlight
+    pub focus_message: Option<String>,
+    /// The last response came back empty
+    #[prop_or_default]
+    pub empty_response: bool,
+    /// Why the last request in this chat failed
+    #[prop_or_default]
+    pub request_error: Option<LlmError>,
+    #[prop_or_default]
+    pub on_dismiss_error: Callback<()>,
+    /// Opens the settings at a field
+    #[prop_or_default]
+    pub on_open_setting: Callback<String>,
+    /// Sends the last user message again, after an empty or failed response
+    #[prop_or_default]
+    pub on_retry: Callback<()>,
+    #[prop_or_default]
+    pub on_dismiss_empty: Callback<()>,
+    /// Seconds the running generation has gone without hearing from the server
+    #[prop_or_default]
+    pub stall_warning: Option<u32>,
+    /// Retry of the request in flight and how many there can be, after a
+    /// connection error or a busy server
+    #[prop_or_default]
+    pub retrying: Option<(u32, u32)>,
+    /// Summary sent in place of the earlier messages, and the last message it covers
+    #[prop_or_default]
+    pub summary: Option<String>,
+    #[prop_or_default]
+    pub summary_until: Option<String>,
+    /// Prompts offered when typing `/` in the input
+    #[prop_or_default]
+    pub saved_prompts: Vec<SavedPrompt>,
+    /// Hides the stall warning and restarts its timer
+    #[prop_or_default]
+    pub on_keep_waiting: Callback<()>,
+    /// Unit of the count shown next to the input
+    #[prop_or_default]
+    pub count_unit: CountUnit,
+    /// Shown below the messages until the chat has an answer
+    #[prop_or_default]
+    pub empty_state: Html,
+    /// Whether the input may take focus; off while a dialog covers the chat
+    #[prop_or(true)]
+    pub take_focus: bool,
+}
+
+/// Tokens a single message may take: the context minus room for the answer,
+/// the same quarter (at most 1024 tokens) the context warning keeps free
+fn message_token_limit(context_length: usize) -> usize {
+    context_length.saturating_sub((context_length / 4).min(1024))
+}
+
+/// The beginning and end of `text`, cut to at most `max_tokens` in total
+fn trim_middle(text: &str, max_tokens: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let tokens = count_tokens(text).max(1);
+    // Tokens per character vary along the text, so aim a little lower and
+    // keep less until the result fits
+    let mut keep = (chars.len() as f64 * max_tokens as f64 / tokens as f64 * 0.9) as usize;
+    loop {
+        let half = (keep / 2).min(chars.len() / 2);
+        let head: String = chars[..half].iter().collect();
+        let tail: String = chars[chars.len() - half..].iter().collect();
+        let trimmed = format!("{}\n\n[… trimmed …]\n\n{}", head.trim_end(), tail.trim_start());
+        if half == 0 || count_tokens(&trimmed) <= max_tokens {
+            return trimmed;
+        }
+        keep = (keep * 9 / 10).min(half * 2 - 1);
+    }
+}
+
+/// Runs of at least two consecutive system or automated messages among the
+/// shown ones, as message id → (id of the run's first message, run length).
+/// A run ends at `break_at`, so the history divider stays visible.
+fn event_runs<'a>(messages: impl Iterator<Item = (usize, &'a Message)>, break_at: Option<usize>) -> HashMap<String, (String, usize)> {
+    let mut runs: Vec<Vec<&Message>> = Vec::new();
+    let mut current: Vec<&Message> = Vec::new();
+    for (idx, msg) in messages {
+        if Some(idx) == break_at || !(msg.role == "system" || msg.automated) {
+            runs.push(std::mem::take(&mut current));
+        }
+        if msg.role == "system" || msg.automated {
+            current.push(msg);
+        }
+    }
+    runs.push(current);
+    runs.into_iter()
+        .filter(|run| run.len() > 1)
+        .flat_map(|run| {
+            let first = run[0].id.clone();
+            let len = run.len();
+            run.into_iter().map(move |m| (m.id.clone(), (first.clone(), len)))
+        })
+        .collect()
+}
+
+/// Moves focus back to the message input, unless the user is typing in
+/// another field such as the sidebar search
+fn focus_composer(input: &NodeRef) {
+    let Some(textarea) = input.cast::<HtmlTextAreaElement>() else { return };
+    let active = web_sys::window().and_then(|w| w.document()).and_then(|d| d.active_element());
+    let typing_elsewhere = active.is_some_and(|el| {
+        !el.is_same_node(Some(&textarea)) && matches!(el.tag_name().as_str(), "INPUT" | "TEXTAREA" | "SELECT")
+    });
+    if !typing_elsewhere {
+        let _ = textarea.focus();
+    }
 }
 
 #[function_component(ChatArea)]
 pub fn chat_area(props: &ChatAreaProps) -> Html {
     let input_text = use_state(String::new);
-    let documents = use_state(|| vec![]);
+    let pasted_code = use_state(|| None::<PastedCode>);
+    let documents = use_state(Vec::<Document>::new);
     let scroll_ref = use_node_ref();
+    let input_ref = use_node_ref();
 
     // Track if the user is currently at the bottom of the chat
     let is_at_bottom = use_state(|| true);
 
-    // @ mention dropdown state
-    let mention_position = use_state(|| None::<(i32, i32)>); // Some((x, y)) in viewport coords
-    let mention_query = use_state(|| String::new());
+    // Prompt or document picker of the input
+    let picker = use_state(|| None::<InputPicker>);
+    // Documents mentioned in the input, shown there as `@filename` and sent as `@id`
+    let mentions = use_state(Vec::<DocumentRef>::new);
+    // Mentioned documents deleted before the message was sent
+    let missing_mentions = use_state(Vec::<String>::new);
+    // Warnings already sent past when the next one came up, so its "send anyway" keeps them
+    let send_overrides = use_state(SendOverrides::default);
+
+    // History window popover
+    // Popovers of the input toolbar share a spot, so only one is open at a time
+    let toolbar_popover = use_state(|| None::<ToolbarPopover>);
+
+    // Per-chat document scope picker
+    let show_scope_picker = use_state(|| false);
+    let context_review = use_state(|| None::<ContextReview>);
+
+    // Assistant messages shown as token probabilities instead of markdown
+    let inspecting = use_state(BTreeSet::<String>::new);
+    // Regenerated messages shown as a diff against the response they replaced
+    let comparing = use_state(BTreeSet::<String>::new);
+    // Runs of system events opened by the user, by chat and first message of the run
+    let expanded_events = use_state(HashMap::<String, BTreeSet<String>>::new);
+    // User message being edited, with the draft of its new text
+    let editing = use_state(|| None::<(String, String)>);
+    // Document opened from a reference chip
+    let viewing_document = use_state(|| None::<String>);
+    // User message whose request composition is shown
+    let inspecting_context = use_state(|| None::<String>);
+
+    // Estimated size of the next request and the large-request confirmation
+    // Tokens of the system prompt and of the history messages that are sent
+    let system_tokens = use_state(|| 0usize);
+    let history_tokens = use_state(|| 0usize);
+    let input_counts = use_state(TextCounts::default);
+    // Document the typed message looks related to, and those the user turned down
+    let doc_suggestion = use_state(|| None::<Document>);
+    let dismissed_suggestions = use_state(BTreeSet::<String>::new);
+    let estimate_seq = use_mut_ref(|| 0u32);
+    let confirm_send = use_state(|| false);
+    // The typed message alone doesn't fit the context; sending waits for a choice
+    let oversized = use_state(|| false);
+    let converting = use_state(|| false);
+    let convert_error = use_state(|| None::<DocumentError>);
+    // Text of the latest paste, offered for turning into a document
+    let last_paste = use_mut_ref(String::new);
+
+    // The input gets focus back when the chat changes, a response finishes or
+    // is stopped, and a dialog closes
+    {
+        let input_ref = input_ref.clone();
+        let can_focus = props.take_focus && inspecting_context.is_none() && viewing_document.is_none() && context_review.is_none() && !*oversized;
+        use_effect_with((props.chat_id.clone(), props.is_loading, can_focus), move |(_, _, can_focus)| {
+            if *can_focus {
+                focus_composer(&input_ref);
+            }
+        });
+    }
+
+    // Ids of the selected messages
+    let selected = use_state(BTreeSet::<String>::new);
+    let copy_feedback = use_state(|| false);
+    // Reasoning blocks shown open, by message id and whether the answer had
+    // started; opening one mid-thought lasts until the answer starts
+    let expanded_reasoning = use_state(BTreeSet::<(String, bool)>::new);
+    // Message whose text was just copied
+    let copied_message = use_state(|| None::<String>);
+
+    // Leaving (or entering) selection mode always starts from a clean slate
+    {
+        let selected = selected.clone();
+        use_effect_with(props.selection_mode, move |_| {
+            selected.set(BTreeSet::new());
+        });
+    }
+
+    // Progressive loading: only the newest `visible_count` messages are rendered
+    let visible_count = use_state(|| INITIAL_VISIBLE_MESSAGES);
+    // Distance from the bottom before older messages were prepended
+    let scroll_anchor = use_mut_ref(|| None::<i32>);
+    let first_visible = props.messages.len().saturating_sub(*visible_count);
+
+    // Scroll positions per chat id; the latest one is kept pending until scrolling settles
+    let scroll_positions = use_mut_ref(HashMap::<String, SavedScroll>::new);
+    let pending_scroll = use_mut_ref(|| None::<(String, SavedScroll)>);
+    let scroll_seq = use_mut_ref(|| 0u32);
+
+    // Keep the same messages in view after older ones were prepended above them
+    {
+        let div_ref = scroll_ref.clone();
+        let anchor = scroll_anchor.clone();
+        use_effect_with(*visible_count, move |_| {
+            if let Some(from_bottom) = anchor.borrow_mut().take() {
+                if let Some(div) = div_ref.cast::<HtmlElement>() {
+                    // Jump instantly instead of using the container's smooth scrolling
+                    let style = div.style();
+                    let _ = style.set_property("scroll-behavior", "auto");
+                    div.set_scroll_top(div.scroll_height() - from_bottom);
+                    let _ = style.remove_property("scroll-behavior");
+                }
+            }
+        });
+    }
+
+    let load_earlier = {
+        let div_ref = scroll_ref.clone();
+        let anchor = scroll_anchor.clone();
+        let visible_count = visible_count.clone();
+        Callback::from(move |_: ()| {
+            // Ignore repeated requests until the previous batch has rendered
+            if first_visible == 0 || anchor.borrow().is_some() {
+                return;
+            }
+            if let Some(div) = div_ref.cast::<HtmlElement>() {
+                *anchor.borrow_mut() = Some(div.scroll_height() - div.scroll_top());
+            }
+            visible_count.set(*visible_count + LOAD_MORE_STEP);
+        })
+    };
 
     // Auto-scroll effect
     {
@@ -43,191 +451,1384 @@ pub fn chat_area(props: &ChatAreaProps) -> Html {
         });
     }
 
+    // An edit left open belongs to the chat it was started in
+    {
+        let editing = editing.clone();
+        use_effect_with(props.chat_id.clone(), move |_| editing.set(None));
+    }
+
+    // A review left open when switching chats goes back to the input unsent
+    {
+        let context_review = context_review.clone();
+        let input_text = input_text.clone();
+        use_effect_with(props.chat_id.clone(), move |_| {
+            if let Some(review) = (*context_review).clone() {
+                input_text.set(review.text);
+                context_review.set(None);
+            }
+        });
+    }
+
+    // Back to where the chat was left, unless it is new this session or has new messages.
+    // Declared after auto-scroll so it decides the position when the chat changes.
+    {
+        let div_ref = scroll_ref.clone();
+        let positions = scroll_positions.clone();
+        let pending = pending_scroll.clone();
+        let anchor = scroll_anchor.clone();
+        let visible_count = visible_count.clone();
+        let is_at_bottom = is_at_bottom.clone();
+        let message_count = props.messages.len();
+        use_effect_with(props.chat_id.clone(), move |chat_id| {
+            // Positions not yet saved belong to the chat that was just left
+            if let Some((id, saved)) = pending.borrow_mut().take() {
+                positions.borrow_mut().insert(id, saved);
+            }
+            let saved = positions.borrow().get(chat_id).copied().filter(|s| s.message_count == message_count && !s.at_bottom);
+            match saved {
+                Some(saved) => {
+                    is_at_bottom.set(false);
+                    *anchor.borrow_mut() = Some(saved.from_bottom);
+                    if *visible_count == saved.visible_count {
+                        if let Some(div) = div_ref.cast::<HtmlElement>() {
+                            let style = div.style();
+                            let _ = style.set_property("scroll-behavior", "auto");
+                            div.set_scroll_top(div.scroll_height() - saved.from_bottom);
+                            let _ = style.remove_property("scroll-behavior");
+                        }
+                        anchor.borrow_mut().take();
+                    } else {
+                        // Restored by the anchor effect once these messages are rendered
+                        visible_count.set(saved.visible_count);
+                    }
+                }
+                None => {
+                    is_at_bottom.set(true);
+                    visible_count.set(INITIAL_VISIBLE_MESSAGES);
+                    if let Some(div) = div_ref.cast::<HtmlElement>() {
+                        div.set_scroll_top(div.scroll_height());
+                    }
+                }
+            }
+        });
+    }
+
+    // Bring a linked message into view, loading older messages if needed
+    {
+        let visible_count = visible_count.clone();
+        let index = props.focus_message.as_ref().and_then(|id| props.messages.iter().position(|m| m.id == *id));
+        let needed = props.messages.len() - index.unwrap_or(props.messages.len());
+        use_effect_with((props.chat_id.clone(), props.focus_message.clone()), move |(_, focus)| {
+            if let (Some(id), Some(_)) = (focus.clone(), index) {
+                if needed > *visible_count {
+                    visible_count.set(needed);
+                }
+                // After the messages above have rendered
+                spawn_local(async move {
+                    gloo_timers::future::TimeoutFuture::new(0).await;
+                    if let Some(el) = web_sys::window().and_then(|w| w.document()).and_then(|d| d.get_element_by_id(&format!("msg-{}", id))) {
+                        el.scroll_into_view_with_bool(true);
+                    }
+                });
+            }
+        });
+    }
+
     // Scroll Event Handler
     let on_scroll = {
         let is_at_bottom = is_at_bottom.clone();
+        let load_earlier = load_earlier.clone();
+        let positions = scroll_positions.clone();
+        let pending = pending_scroll.clone();
+        let seq = scroll_seq.clone();
+        let chat_id = props.chat_id.clone();
+        let visible = *visible_count;
+        let message_count = props.messages.len();
         Callback::from(move |e: Event| {
             let div: HtmlElement = e.target_unchecked_into();
+            if div.scroll_top() < 40 {
+                load_earlier.emit(());
+            }
             let distance_from_bottom = div.scroll_height() - div.scroll_top() - div.client_height();
             let currently_at_bottom = distance_from_bottom < 35;
 
             if *is_at_bottom != currently_at_bottom {
                 is_at_bottom.set(currently_at_bottom);
             }
+
+            *pending.borrow_mut() = Some((chat_id.clone(), SavedScroll {
+                from_bottom: div.scroll_height() - div.scroll_top(),
+                at_bottom: currently_at_bottom,
+                visible_count: visible,
+                message_count,
+            }));
+            *seq.borrow_mut() += 1;
+            let current = *seq.borrow();
+            let positions = positions.clone();
+            let pending = pending.clone();
+            let seq = seq.clone();
+            spawn_local(async move {
+                gloo_timers::future::TimeoutFuture::new(SCROLL_SAVE_DELAY_MS).await;
+                if *seq.borrow() == current {
+                    if let Some((id, saved)) = pending.borrow_mut().take() {
+                        positions.borrow_mut().insert(id, saved);
+                    }
+                }
+            });
         })
     };
 
-    let on_submit = {
+    // Documents that would be added to the next request
+    let scoped_documents: Vec<Document> = match (&props.context_mode, &props.document_scope) {
+        (DocumentContextMode::Off, _) => Vec::new(),
+        (_, Some(ids)) => documents.iter().filter(|d| d.enabled && ids.contains(&d.id)).cloned().collect(),
+        (DocumentContextMode::RAG, None) => documents.iter().filter(|d| d.enabled).cloned().collect(),
+        // Without a scope, Manual mode only uses @-mentioned documents
+        (DocumentContextMode::Manual, None) => Vec::new(),
+    };
+
+    // The input as it will be sent, with mentions pointing at document ids
+    let outgoing_text = expand_mentions(&input_text, &mentions);
+    // Documents @-mentioned in the input are sent as well in Manual mode
+    let mentioned_doc_tokens: usize = if props.context_mode == DocumentContextMode::Manual {
+        documents
+            .iter()
+            .filter(|d| d.enabled && !scoped_documents.iter().any(|s| s.id == d.id))
+            .filter(|d| outgoing_text.contains(&format!("@{}", d.id)))
+            .map(|d| d.total_tokens)
+            .sum()
+    } else {
+        0
+    };
+    // The document selected in the sidebar is used as context in Manual mode
+    let selected_document: Option<Document> = if props.context_mode == DocumentContextMode::Manual {
+        props.selected_document_id.as_ref().and_then(|id| documents.iter().find(|d| d.id == *id && d.enabled)).cloned()
+    } else {
+        None
+    };
+    let selected_doc_tokens = selected_document
+        .as_ref()
+        .filter(|d| !scoped_documents.iter().any(|s| s.id == d.id) && !outgoing_text.contains(&format!("@{}", d.id)))
+        .map_or(0, |d| d.total_tokens);
+    // RAG sends the best matching chunks of the scoped documents, not all of them
+    let rag_tokens = *use_memo(
+        (props.context_mode.clone(), props.document_scope.clone(), props.embedding_model.clone(), (*documents).clone()),
+        |(mode, scope, embedding_model, _)| match mode {
+            DocumentContextMode::RAG => DocumentService::projected_context_tokens(mode, scope.as_deref(), None, embedding_model),
+            _ => 0,
+        },
+    );
+    let scoped_doc_tokens = match props.context_mode {
+        DocumentContextMode::RAG => rag_tokens,
+        _ => scoped_documents.iter().map(|d| d.total_tokens).sum(),
+    };
+    let doc_tokens = scoped_doc_tokens + mentioned_doc_tokens + selected_doc_tokens;
+
+    // History size is recounted off the render path whenever the chat changes
+    {
+        let system_tokens = system_tokens.clone();
+        let history_tokens = history_tokens.clone();
+        let deps = (props.messages.clone(), props.history_window, props.custom_instructions.clone());
+        use_effect_with(deps, move |(messages, window, instructions)| {
+            let messages = messages.clone();
+            let window = *window;
+            let instructions = instructions.clone();
+            spawn_local(async move {
+                gloo_timers::future::TimeoutFuture::new(0).await;
+                let start = history_window_start(&messages, window);
+                let system: usize = messages.iter().filter(|m| m.role == "system").map(Message::tokens).sum::<usize>()
+                    + count_tokens(instructions.trim());
+                let history = messages
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, m)| *i >= start && m.role != "system" && !m.excluded)
+                    .map(|(_, m)| m.tokens())
+                    .sum();
+                system_tokens.set(system);
+                history_tokens.set(history);
+            });
+        });
+    }
+
+    // The typed input is counted once typing pauses, in every unit at once, and
+    // matched against documents that aren't used as context yet
+    {
+        let input_counts = input_counts.clone();
+        let seq = estimate_seq.clone();
+        let confirm = confirm_send.clone();
+        let doc_suggestion = doc_suggestion.clone();
+        let documents = documents.clone();
+        let dismissed = dismissed_suggestions.clone();
+        let suggest = props.document_suggestions;
+        let mode = props.context_mode.clone();
+        let scope = props.document_scope.clone();
+        let missing_mentions = missing_mentions.clone();
+        let mentions = (*mentions).clone();
+        use_effect_with((*input_text).clone(), move |text| {
+            confirm.set(false);
+            missing_mentions.set(Vec::new());
+            *seq.borrow_mut() += 1;
+            let current = *seq.borrow();
+            let typed = text.clone();
+            let text = expand_mentions(text, &mentions);
+            spawn_local(async move {
+                gloo_timers::future::TimeoutFuture::new(250).await;
+                if *seq.borrow() == current {
+                    input_counts.set(TextCounts::of(&typed));
+                    let unused = |d: &&Document| {
+                        d.enabled
+                            && !dismissed.contains(&d.id)
+                            && !scope.as_ref().is_some_and(|ids| ids.contains(&d.id))
+                            && !text.contains(&format!("@{}", d.id))
+                    };
+                    // RAG without a scope already uses every document; Off uses none
+                    let candidates: Vec<Document> = match (&mode, &scope) {
+                        (DocumentContextMode::Manual, _) | (DocumentContextMode::RAG, Some(_)) if suggest => {
+                            documents.iter().filter(unused).cloned().collect()
+                        }
+                        _ => Vec::new(),
+                    };
+                    doc_suggestion.set(DocumentService::related_document(&text, &candidates).cloned());
+                }
+            });
+        });
+    }
+
+    // Mentions added as `@id`, e.g. by attaching a suggested document, are shown by name
+    {
+        let input_text = input_text.clone();
+        let mentions = mentions.clone();
+        let documents = documents.clone();
+        use_effect_with((*input_text).clone(), move |text| {
+            let mut found: Vec<DocumentRef> = Vec::new();
+            for d in documents.iter().filter(|d| text.contains(&format!("@{}", d.id))) {
+                // A name stands for one document, so a second one keeps its id
+                if !found.iter().any(|f| f.filename == d.filename) {
+                    found.push(DocumentRef { id: d.id.clone(), filename: d.filename.clone() });
+                }
+            }
+            if !found.is_empty() {
+                let shown = found.iter().fold(text.clone(), |text, d| text.replace(&format!("@{}", d.id), &d.mention()));
+                let mut list: Vec<DocumentRef> = mentions.iter().filter(|m| !found.iter().any(|f| f.filename == m.filename)).cloned().collect();
+                list.extend(found);
+                mentions.set(list);
+                input_text.set(shown);
+            }
+        });
+    }
+
+    // Sends the input, asking first when the request would be unusually large
+    let send_message = {
         let text = input_text.clone();
         let on_send = props.on_send.clone();
         let is_at_bottom = is_at_bottom.clone();
-        let mention_pos = mention_position.clone();
-        let mention_q = mention_query.clone();
+        let picker = picker.clone();
+        let mentions = mentions.clone();
+        let missing_mentions = missing_mentions.clone();
+        let confirm = confirm_send.clone();
+        let threshold = props.prompt_warning_tokens;
+        let base_tokens = *system_tokens + *history_tokens + doc_tokens;
+        let review = context_review.clone();
+        let review_scope = (props.review_context && props.context_mode == DocumentContextMode::RAG)
+            .then(|| props.document_scope.clone());
+        let request = props.request.clone();
+        let embedding_model = props.embedding_model.clone();
+        let oversized = oversized.clone();
+        let message_limit = message_token_limit(props.context_length);
+        let send_overrides = send_overrides.clone();
 
-        Callback::from(move |e: SubmitEvent| {
-            e.prevent_default();
-            if !text.is_empty() {
-                // Clear mention state before sending
-                mention_pos.set(None);
-                mention_q.set(String::new());
+        Callback::from(move |overrides: SendOverrides| {
+            if text.is_empty() {
+                return;
+            }
+            if !overrides.oversize && message_limit > 0 && count_tokens(&text) > message_limit {
+                send_overrides.set(overrides);
+                oversized.set(true);
+                return;
+            }
+            if !overrides.size_warning && threshold > 0 && base_tokens + count_tokens(&text) > threshold {
+                send_overrides.set(overrides);
+                confirm.set(true);
+                return;
+            }
+            // A document can be deleted between mentioning it and sending
+            let existing = DocumentService::get_documents();
+            let (kept, missing): (Vec<DocumentRef>, Vec<DocumentRef>) = mentions
+                .iter()
+                .filter(|m| !mention_positions(&text, &m.mention()).is_empty())
+                .cloned()
+                .partition(|m| existing.iter().any(|d| d.id == m.id));
+            if !overrides.missing_mentions && !missing.is_empty() {
+                send_overrides.set(overrides);
+                missing_mentions.set(missing.into_iter().map(|m| m.filename).collect());
+                return;
+            }
+            let message = expand_mentions(&text, &kept);
+            picker.set(None);
+            mentions.set(Vec::new());
+            missing_mentions.set(Vec::new());
 
-                on_send.emit((*text).clone());
+            confirm.set(false);
+            if let Some(scope) = review_scope.clone() {
                 text.set(String::new());
-                is_at_bottom.set(true);
+                let (review, on_send, is_at_bottom) = (review.clone(), on_send.clone(), is_at_bottom.clone());
+                let (request, embedding_model) = (request.clone(), embedding_model.clone());
+                wasm_bindgen_futures::spawn_local(async move {
+                    let chunks = DocumentService::retrieve_chunks(&message, scope.as_deref(), &request, &embedding_model).await;
+                    // With nothing retrieved there is nothing to review
+                    if chunks.is_empty() {
+                        on_send.emit(message);
+                        is_at_bottom.set(true);
+                    } else {
+                        review.set(Some(ContextReview {
+                            text: message,
+                            approved: chunks.iter().map(|c| c.id.clone()).collect(),
+                            chunks,
+                        }));
+                    }
+                });
+                return;
             }
+            on_send.emit(message);
+            text.set(String::new());
+            is_at_bottom.set(true);
+        })
+    };
+
+    let on_submit = {
+        let send_message = send_message.clone();
+        Callback::from(move |e: SubmitEvent| {
+            e.prevent_default();
+            send_message.emit(SendOverrides::default());
         })
     };
 
-    // Load documents on mount
+    // Load documents on mount, whenever another chat is opened or a document is selected
     {
         let docs = documents.clone();
-        use_effect_with(() as (), move |_| {
+        use_effect_with((props.chat_id.clone(), props.selected_document_id.clone()), move |_| {
             let loaded_docs = DocumentService::get_documents();
             docs.set(loaded_docs);
         });
     }
 
-    let on_keydown = {
-        let text = input_text.clone();
-        let on_send = props.on_send.clone();
-        let is_at_bottom = is_at_bottom.clone();
-        let mention_pos = mention_position.clone();
-        let mention_q = mention_query.clone();
+    // Replaces the trigger and query with the picked prompt or document mention
+    let on_pick = {
+        let picker = picker.clone();
+        let prompts = props.saved_prompts.clone();
+        let documents = documents.clone();
+        let mentions = mentions.clone();
+        let input_text = input_text.clone();
+        let input_ref = input_ref.clone();
+        Callback::from(move |index: usize| {
+            let Some(open) = (*picker).clone() else { return };
+            picker.set(None);
+            let insert = match open.kind {
+                PickerKind::Prompts => {
+                    let Some(prompt) = matching_prompts(&prompts, &open.query).get(index).copied() else { return };
+                    prompt.content.clone()
+                }
+                PickerKind::Documents => {
+                    let Some(doc) = matching_documents(&documents, &open.query).get(index).copied() else { return };
+                    let doc = DocumentRef { id: doc.id.clone(), filename: doc.filename.clone() };
+                    let insert = format!("{} ", doc.mention());
+                    // A name stands for one document; the latest mention of it wins
+                    let mut list: Vec<DocumentRef> = mentions.iter().filter(|m| m.filename != doc.filename).cloned().collect();
+                    list.push(doc);
+                    mentions.set(list);
+                    insert
+                }
+            };
+            let value = (*input_text).clone();
+            let end = open.start + 1 + open.query.len();
+            let (Some(before), Some(after)) = (value.get(..open.start), value.get(end..)) else { return };
+            let text = format!("{}{}{}", before, insert, after);
+            // Put the cursor right after the inserted text
+            let cursor = (before.encode_utf16().count() + insert.encode_utf16().count()) as u32;
+            if let Some(textarea) = input_ref.cast::<HtmlTextAreaElement>() {
+                textarea.set_value(&text);
+                let _ = textarea.set_selection_range(cursor, cursor);
+            }
+            input_text.set(text);
+            focus_composer(&input_ref);
+        })
+    };
 
+    let on_keydown = {
+        let send_message = send_message.clone();
+        let picker = picker.clone();
+        let prompts = props.saved_prompts.clone();
+        let documents = documents.clone();
+        let mentions = mentions.clone();
+        let input_text = input_text.clone();
+        let on_pick = on_pick.clone();
         Callback::from(move |e: KeyboardEvent| {
+            // While a picker is open, the arrows, Enter and Tab belong to it
+            if let Some(open) = (*picker).clone() {
+                let count = match open.kind {
+                    PickerKind::Prompts => matching_prompts(&prompts, &open.query).len(),
+                    PickerKind::Documents => matching_documents(&documents, &open.query).len(),
+                };
+                match e.key().as_str() {
+                    "ArrowDown" | "ArrowUp" if count > 0 => {
+                        e.prevent_default();
+                        let active = if e.key() == "ArrowDown" { (open.active + 1) % count } else { (open.active + count - 1) % count };
+                        picker.set(Some(InputPicker { active, ..open }));
+                        return;
+                    }
+                    "Enter" | "Tab" if count > 0 && !e.shift_key() => {
+                        e.prevent_default();
+                        on_pick.emit(open.active);
+                        return;
+                    }
+                    "Escape" => {
+                        e.prevent_default();
+                        picker.set(None);
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+            // Backspace right after a mention removes all of it
+            if e.key() == "Backspace" && !e.ctrl_key() && !e.alt_key() && !e.meta_key() {
+                let textarea: HtmlTextAreaElement = e.target_unchecked_into();
+                let start = textarea.selection_start().ok().flatten();
+                if start.is_some() && start == textarea.selection_end().ok().flatten() {
+                    let value = textarea.value();
+                    let cursor = utf16_to_byte_index(&value, start.unwrap_or(0) as usize);
+                    if let Some(from) = mention_before(&value, cursor, &mentions) {
+                        e.prevent_default();
+                        let text = format!("{}{}", &value[..from], &value[cursor..]);
+                        let at = value[..from].encode_utf16().count() as u32;
+                        textarea.set_value(&text);
+                        let _ = textarea.set_selection_range(at, at);
+                        input_text.set(text);
+                        picker.set(None);
+                        return;
+                    }
+                }
+            }
             if e.key() == "Enter" && !e.shift_key() {
+                picker.set(None);
                 e.prevent_default();
-                if !text.is_empty() {
-                    // Clear mention state before sending
-                    mention_pos.set(None);
-                    mention_q.set(String::new());
+                send_message.emit(SendOverrides::default());
+            }
+        })
+    };
 
-                    on_send.emit((*text).clone());
-                    text.set(String::new());
-                    is_at_bottom.set(true);
+    let on_paste = {
+        let pasted = pasted_code.clone();
+        let last_paste = last_paste.clone();
+        Callback::from(move |e: Event| {
+            let text = e
+                .dyn_ref::<web_sys::ClipboardEvent>()
+                .and_then(|e| e.clipboard_data())
+                .and_then(|data| data.get_data("text/plain").ok())
+                .unwrap_or_default();
+            // The textarea stores line breaks as \n, so match that
+            let text = text.replace("\r\n", "\n").replace('\r', "\n");
+            *last_paste.borrow_mut() = text.clone();
+            if !looks_like_code(&text) {
+                pasted.set(None);
+                return;
+            }
+            let textarea: HtmlTextAreaElement = e.target_unchecked_into();
+            let start = textarea.selection_start().ok().flatten().unwrap_or(0);
+            let language = guess_code_language(&text).to_string();
+            pasted.set(Some(PastedCode { start, text, language }));
+        })
+    };
+
+    let on_wrap_paste = {
+        let pasted = pasted_code.clone();
+        let input_text = input_text.clone();
+        Callback::from(move |_: MouseEvent| {
+            let Some(p) = (*pasted).clone() else { return };
+            let value = (*input_text).clone();
+            // Prefer the recorded position; fall back to searching if the text moved
+            let at_start = utf16_to_byte_index(&value, p.start as usize);
+            let start = match value.get(at_start..at_start + p.text.len()) {
+                Some(region) if region == p.text => Some(at_start),
+                _ => value.find(&p.text),
+            };
+            if let Some(start) = start {
+                let (before, rest) = value.split_at(start);
+                let after = &rest[p.text.len()..];
+                let mut fenced = String::new();
+                if !before.is_empty() && !before.ends_with('\n') {
+                    fenced.push('\n');
                 }
+                fenced.push_str(&format!("```{}\n", p.language));
+                fenced.push_str(&p.text);
+                if !p.text.ends_with('\n') {
+                    fenced.push('\n');
+                }
+                fenced.push_str("```");
+                if !after.is_empty() && !after.starts_with('\n') {
+                    fenced.push('\n');
+                }
+                input_text.set(format!("{}{}{}", before, fenced, after));
             }
+            pasted.set(None);
         })
     };
 
+    let paste_prompt = match &*pasted_code {
+        // Hidden once the pasted text is no longer in the input
+        Some(p) if input_text.contains(&p.text) => {
+            let on_language = {
+                let pasted = pasted_code.clone();
+                Callback::from(move |e: Event| {
+                    let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+                    if let Some(mut p) = (*pasted).clone() {
+                        p.language = select.value();
+                        pasted.set(Some(p));
+                    }
+                })
+            };
+            let on_dismiss = {
+                let pasted = pasted_code.clone();
+                Callback::from(move |_: MouseEvent| pasted.set(None))
+            };
+            html! {
+                <div class="swi-selection-bar swi-paste-prompt">
+                    <span>{ "Wrap pasted content in a code block?" }</span>
+                    <select class="swi-paste-language" onchange={on_language}>
+                        { for CODE_LANGUAGES.iter().map(|lang| html! {
+                            <option value={*lang} selected={p.language == *lang}>{ if lang.is_empty() { "plain" } else { lang } }</option>
+                        }) }
+                    </select>
+                    <button class="swi-btn swi-mini-btn swi-btn-primary" onclick={on_wrap_paste}>{ "Wrap" }</button>
+                    <button class="swi-btn swi-mini-btn" onclick={on_dismiss}>{ "Dismiss" }</button>
+                </div>
+            }
+        }
+        _ => html! {},
+    };
+
     let on_input = {
         let text = input_text.clone();
-        let mention_pos = mention_position.clone();
-        let mention_q = mention_query.clone();
-        let documents_for_set = documents.clone();
+        let documents = documents.clone();
+        let picker = picker.clone();
+        let has_prompts = !props.saved_prompts.is_empty();
 
         Callback::from(move |e: InputEvent| {
             let i: HtmlTextAreaElement = e.target_unchecked_into();
             let val = i.value();
             text.set(val.clone());
 
-            // Check for @ mention
-            if let Some(pos) = val.rfind('@') {
-                let after_at = &val[pos + 1..];
-
-                // Only treat as mention if no whitespace/newline after '@'
-                if !after_at.contains(' ') && !after_at.contains('\n') {
-                    // Update mention query
-                    let query = after_at.to_string();
-                    mention_q.set(query.clone());
-
-                    // Refresh docs from localStorage
-                    let loaded_docs = DocumentService::get_documents();
-
-                    // Count filtered docs for positioning/height decisions
-                    let after_at_lc = after_at.to_lowercase();
-                    let filtered_count = loaded_docs
-                        .iter()
-                        .filter(|d| d.filename.to_lowercase().contains(&after_at_lc))
-                        .count();
-
-                    // Update state for rendering (avoid reading localStorage again in render)
-                    documents_for_set.set(loaded_docs);
-
-                    // Calculate position for dropdown (viewport coordinates)
-                    let element: Element = i.unchecked_into();
-                    let rect = Element::get_bounding_client_rect(&element);
-
-                    // Sizing parameters (match your CSS/item layout)
-                    let row_h: i32 = 44; // approx item height
-                    let padding: i32 = 8;
-                    let max_visible_rows: usize = 5;
-
-                    let visible_rows = filtered_count.min(max_visible_rows) as i32;
-                    let dropdown_h = (visible_rows * row_h) + padding;
-
-                    let textarea_bottom = (rect.top() + rect.height()) as i32;
-                    let x = rect.left() as i32;
-
-                    // - 0/1 result: show below
-                    // - >1 results: grow upward
-                    let gap: i32 = 5;
-
-                    // % of viewport height
-                    let window = web_sys::window().unwrap();
-                    let vh = window
-                        .inner_height()
-                        .ok()
-                        .and_then(|v| v.as_f64())
-                        .unwrap_or(800.0);
-
-                    let lift_pct: f64 = 0.10; // 10% of viewport height
-                    let lift_px: i32 = (vh * lift_pct).round() as i32;
-
-                    let mut y = if filtered_count <= 1 {
-                        (textarea_bottom + gap) - lift_px
-                    } else {
-                        ((textarea_bottom + gap) - dropdown_h) - lift_px
-                    };
+            // `/` opens the saved prompt picker and `@` the document picker
+            let cursor = utf16_to_byte_index(&val, i.selection_start().ok().flatten().unwrap_or(0) as usize);
+            let typed = trigger_query(&val, cursor, '/')
+                .filter(|_| has_prompts)
+                .map(|q| (PickerKind::Prompts, q))
+                .or_else(|| trigger_query(&val, cursor, '@').map(|q| (PickerKind::Documents, q)));
+            let next = typed.map(|(kind, (start, query))| {
+                let current = (*picker).clone().filter(|p| p.kind == kind && p.start == start);
+                if kind == PickerKind::Documents && current.is_none() {
+                    // Pick up documents uploaded since the list was loaded
+                    documents.set(DocumentService::get_documents());
+                }
+                // Keep the highlight while the query is unchanged
+                let active = current.filter(|p| p.query == query).map_or(0, |p| p.active);
+                InputPicker { kind, start, query, active }
+            });
+            picker.set(next);
+        })
+    };
 
-                    // Clamp to viewport top a bit
-                    if y < 5 {
-                        y = 5;
-                    }
+    let selected_messages = {
+        let selected = (*selected).clone();
+        let messages = props.messages.clone();
+        move || -> Vec<Message> {
+            // Kept in conversation order; queued messages have not been sent yet
+            messages.iter().filter(|m| !m.queued && selected.contains(&m.id)).cloned().collect()
+        }
+    };
 
-                    mention_pos.set(Some((x, y)));
-                    return;
+    let on_copy_selected = {
+        let selected_messages = selected_messages.clone();
+        let title = props.chat_title.clone();
+        let feedback = copy_feedback.clone();
+        Callback::from(move |_: MouseEvent| {
+            let markdown = ExportService::to_markdown(&title, &selected_messages());
+            let feedback = feedback.clone();
+            spawn_local(async move {
+                if ExportService::copy_to_clipboard(&markdown).await.is_ok() {
+                    feedback.set(true);
+                    gloo_timers::future::TimeoutFuture::new(1500).await;
+                    feedback.set(false);
                 }
-            }
+            });
+        })
+    };
 
-            // Clear mention state if not valid
-            mention_pos.set(None);
-            mention_q.set(String::new());
+    // Copy buttons of code blocks are plain markup inside the rendered Markdown
+    let on_code_copy = Callback::from(|e: MouseEvent| {
+        let button = e.target_dyn_into::<Element>().and_then(|el| el.closest("[data-copy-code]").ok().flatten());
+        if let Some(button) = button {
+            let code = button.parent_element().and_then(|pre| pre.query_selector("code").ok().flatten()).and_then(|code| code.text_content()).unwrap_or_default();
+            spawn_local(async move {
+                if ExportService::copy_to_clipboard(&code).await.is_ok() {
+                    button.set_text_content(Some("Copied!"));
+                    gloo_timers::future::TimeoutFuture::new(1500).await;
+                    button.set_text_content(Some("Copy"));
+                }
+            });
+        }
+    });
+
+    let on_export_selected = {
+        let selected_messages = selected_messages.clone();
+        let title = props.chat_title.clone();
+        Callback::from(move |_: MouseEvent| {
+            let markdown = ExportService::to_markdown(&title, &selected_messages());
+            let _ = ExportService::download(&ExportService::file_name(&title, "md"), &markdown, "text/markdown");
         })
     };
 
-    let on_select_document = {
-        let text = input_text.clone();
-        let mention_pos = mention_position.clone();
-        let mention_query_handle = mention_query.clone();
+    let on_delete_selected = {
+        let selected = selected.clone();
+        let on_delete = props.on_delete_messages.clone();
+        Callback::from(move |_: MouseEvent| {
+            let count = selected.len();
+            let msg = format!("Delete {} selected message{}?", count, if count == 1 { "" } else { "s" });
+            if web_sys::window().unwrap().confirm_with_message(&msg).unwrap_or(false) {
+                on_delete.emit(selected.iter().cloned().collect());
+                selected.set(BTreeSet::new());
+            }
+        })
+    };
+
+    let selection_bar = if props.selection_mode {
+        let count = selected.len();
+        html! {
+            <div class="swi-selection-bar">
+                <span class="swi-selection-count">{ format!("{} selected", count) }</span>
+                <button class="swi-btn swi-mini-btn" disabled={count == 0} onclick={on_copy_selected}>
+                    { if *copy_feedback { "Copied!" } else { "Copy as Markdown" } }
+                </button>
+                <button class="swi-btn swi-mini-btn" disabled={count == 0} onclick={on_export_selected}>{ "Export" }</button>
+                <button class="swi-btn swi-mini-btn swi-btn-danger" disabled={count == 0} onclick={on_delete_selected}>{ "Delete selected" }</button>
+                <button class="swi-btn-icon" title="Exit selection mode" onclick={props.on_exit_selection.reform(|_| ())}>{ "×" }</button>
+            </div>
+        }
+    } else {
+        html! {}
+    };
+
+    let toggle_popover = |popover: ToolbarPopover| {
+        let open = toolbar_popover.clone();
+        Callback::from(move |_: MouseEvent| open.set(if *open == Some(popover) { None } else { Some(popover) }))
+    };
 
-        Callback::from(move |doc_id: String| {
-            let current_text = text.to_string();
-            if let Some(pos) = current_text.rfind('@') {
-                let before_at = current_text[..pos].to_string();
+    let history_label = match props.history_window {
+        None => "History: All".to_string(),
+        Some(1) => "History: last exchange".to_string(),
+        Some(n) => format!("History: last {} exchanges", n),
+    };
 
-                // IMPORTANT: get the actual query string from the state handle
-                let current_query = (*mention_query_handle).clone();
+    let history_popover = if *toolbar_popover == Some(ToolbarPopover::History) {
+        let option = |window: Option<usize>, label: String| {
+            let on_change = props.on_history_window_change.clone();
+            let show = toolbar_popover.clone();
+            let active = if props.history_window == window { "swi-history-option swi-active" } else { "swi-history-option" };
+            html! {
+                <button type="button" class={active} onclick={Callback::from(move |_| {
+                    on_change.emit(window);
+                    show.set(None);
+                })}>{ label }</button>
+            }
+        };
+        html! {
+            <div class="swi-history-popover">
+                <div class="swi-history-popover-title">{ "Send to model" }</div>
+                { option(None, "All messages".to_string()) }
+                { for HISTORY_WINDOW_OPTIONS.iter().map(|n| option(Some(*n), format!("Last {}", n))) }
+            </div>
+        }
+    } else {
+        html! {}
+    };
 
-                // Safely slice after query
-                let start = pos + 1 + current_query.len();
-                let after_query = if start <= current_text.len() {
-                    &current_text[start..]
+    let language_popover = if *toolbar_popover == Some(ToolbarPopover::Language) {
+        let option = |language: Option<String>, label: String| {
+            let on_change = props.on_response_language_change.clone();
+            let show = toolbar_popover.clone();
+            let active = if props.response_language == language { "swi-history-option swi-active" } else { "swi-history-option" };
+            html! {
+                <button type="button" class={active} onclick={Callback::from(move |_| {
+                    on_change.emit(language.clone());
+                    show.set(None);
+                })}>{ label }</button>
+            }
+        };
+        html! {
+            <div class="swi-history-popover">
+                <div class="swi-history-popover-title">{ "Respond in" }</div>
+                { option(None, "Auto".to_string()) }
+                { for RESPONSE_LANGUAGES.iter().map(|l| option(Some(l.to_string()), l.to_string())) }
+            </div>
+        }
+    } else {
+        html! {}
+    };
+
+    let response_length = props.response_length.unwrap_or(props.default_response_length);
+    let length_popover = if *toolbar_popover == Some(ToolbarPopover::Length) {
+        let option = |length: Option<ResponseLength>, label: String| {
+            let on_change = props.on_response_length_change.clone();
+            let show = toolbar_popover.clone();
+            let active = if props.response_length == length { "swi-history-option swi-active" } else { "swi-history-option" };
+            html! {
+                <button type="button" class={active} onclick={Callback::from(move |_| {
+                    on_change.emit(length);
+                    show.set(None);
+                })}>{ label }</button>
+            }
+        };
+        let custom_tokens = match props.response_length {
+            Some(ResponseLength::Custom(tokens)) => Some(tokens),
+            _ => None,
+        };
+        // Half-typed values keep the last valid limit until the number is complete
+        let on_custom_input = {
+            let on_change = props.on_response_length_change.clone();
+            Callback::from(move |e: InputEvent| {
+                let value = e.target_unchecked_into::<web_sys::HtmlInputElement>().value();
+                if let Some(tokens) = custom_max_tokens(&value) {
+                    on_change.emit(Some(ResponseLength::Custom(tokens)));
+                }
+            })
+        };
+        html! {
+            <div class="swi-history-popover">
+                <div class="swi-history-popover-title">{ "Response length" }</div>
+                { option(None, format!("Default ({})", props.default_response_length.describe())) }
+                { for ResponseLength::PRESETS.iter().map(|l| option(Some(*l), l.describe())) }
+                if let Some(tokens) = custom_tokens {
+                    <label class="swi-scope-option">
+                        { "Max tokens" }
+                        <input type="number" class="swi-length-custom" min="1" value={tokens.to_string()} oninput={on_custom_input} />
+                    </label>
                 } else {
-                    ""
-                };
+                    { option(Some(ResponseLength::Custom(response_length.max_tokens().unwrap_or(1024))), "Custom…".to_string()) }
+                }
+            </div>
+        }
+    } else {
+        html! {}
+    };
+
+    let temperature = props.temperature_override.unwrap_or(props.default_temperature);
+    let model_popover = if *toolbar_popover == Some(ToolbarPopover::Model) {
+        let on_model = props.on_model_override_change.reform(|e: Event| {
+            let value = e.target_unchecked_into::<HtmlSelectElement>().value();
+            (!value.is_empty()).then_some(value)
+        });
+        let on_temperature = props.on_temperature_override_change.reform(|e: InputEvent| {
+            let value = e.target_unchecked_into::<web_sys::HtmlInputElement>().value();
+            value.parse::<f32>().ok().map(|t| t.clamp(0.0, 2.0))
+        });
+        // A chosen model stays listed while the server doesn't offer it
+        let mut models = props.available_models.clone();
+        if let Some(model) = props.model_override.as_ref().filter(|m| !models.contains(m)) {
+            models.push(model.clone());
+        }
+        html! {
+            <div class="swi-history-popover">
+                <div class="swi-history-popover-title">{ "Model for this chat" }</div>
+                <select class="swi-model-override" onchange={on_model}>
+                    <option value="" selected={props.model_override.is_none()}>{ format!("Default ({})", props.default_model) }</option>
+                    { for models.iter().map(|m| html! {
+                        <option value={m.clone()} selected={props.model_override.as_ref() == Some(m)}>{ m }</option>
+                    }) }
+                </select>
+                <div class="swi-history-popover-title">{ format!("Temperature: {:.1}", temperature) }</div>
+                <input type="range" class="swi-temperature-override" min="0" max="2" step="0.1" value={temperature.to_string()} oninput={on_temperature} />
+                if props.temperature_override.is_some() {
+                    <button type="button" class="swi-history-option" onclick={props.on_temperature_override_change.reform(|_| None)}>
+                        { format!("Use default ({:.1})", props.default_temperature) }
+                    </button>
+                }
+            </div>
+        }
+    } else {
+        html! {}
+    };
 
-                let new_text = format!("{}@{}{}", before_at, doc_id, after_query);
-                text.set(new_text);
+    let prefill_popover = if *toolbar_popover == Some(ToolbarPopover::Prefill) {
+        let pinned = props.prefill.as_ref().is_some_and(|p| p.pinned);
+        let text = props.prefill.as_ref().map(|p| p.text.clone()).unwrap_or_default();
+        let on_text_input = props.on_prefill_change.reform(move |e: InputEvent| {
+            let text = e.target_unchecked_into::<HtmlTextAreaElement>().value();
+            (!text.is_empty()).then_some(Prefill { text, pinned })
+        });
+        let on_pinned_change = {
+            let text = text.clone();
+            props.on_prefill_change.reform(move |e: Event| {
+                let pinned = e.target_unchecked_into::<web_sys::HtmlInputElement>().checked();
+                (!text.is_empty()).then(|| Prefill { text: text.clone(), pinned })
+            })
+        };
+        html! {
+            <div class="swi-history-popover swi-prefill-popover">
+                <div class="swi-history-popover-title">{ "Start the answer with" }</div>
+                <textarea
+                    class="swi-prefill-input"
+                    rows="3"
+                    placeholder={"```json\n{"}
+                    value={text}
+                    oninput={on_text_input}
+                />
+                <label class="swi-scope-option" title="Otherwise the prefill is used for the next message only">
+                    <input type="checkbox" checked={pinned} onchange={on_pinned_change} />
+                    { "Keep for every message in this chat" }
+                </label>
+                if props.prefill.is_some() {
+                    <button type="button" class="swi-history-option" onclick={props.on_prefill_change.reform(|_| None)}>{ "Clear" }</button>
+                }
+            </div>
+        }
+    } else {
+        html! {}
+    };
 
-                mention_pos.set(None);
-                mention_query_handle.set(String::new());
+    let toggle_scope_picker = {
+        let show = show_scope_picker.clone();
+        let docs = documents.clone();
+        Callback::from(move |_: MouseEvent| {
+            // Pick up documents uploaded since the chat was opened
+            if !*show {
+                docs.set(DocumentService::get_documents());
             }
+            show.set(!*show);
         })
     };
 
+    let context_pill = if props.context_mode != DocumentContextMode::Off && !documents.is_empty() {
+        let scope_ids: Vec<String> = scoped_documents.iter().map(|d| d.id.clone()).collect();
+        let ctx_tokens: usize = scoped_documents.iter().map(|d| d.total_tokens).sum();
+        let pill_label = if scoped_documents.is_empty() {
+            "📄 No documents in scope · @-mention to reference one".to_string()
+        } else {
+            format!(
+                "📄 {} document{} · up to {} ctx tokens",
+                scoped_documents.len(),
+                if scoped_documents.len() == 1 { "" } else { "s" },
+                format_count(ctx_tokens)
+            )
+        };
+        // Documents without a usable index are matched by keywords instead
+        let keyword_only = scoped_documents.iter().filter(|d| !d.index.is_current(&props.embedding_model)).count();
+        let mut mode_label = match props.context_mode {
+            DocumentContextMode::RAG if !props.embedding_model.is_empty() && keyword_only > 0 => format!("RAG · {} by keyword", keyword_only),
+            DocumentContextMode::RAG => "RAG".to_string(),
+            _ => "Manual".to_string(),
+        };
+        // Overlap merged away in the latest request's context
+        let deduplicated = props
+            .messages
+            .iter()
+            .rev()
+            .find_map(|m| m.composition.as_ref())
+            .map_or(0, |c| c.deduplicated_tokens);
+        if deduplicated > 0 {
+            mode_label.push_str(&format!(" · {} overlap tokens saved", format_count(deduplicated)));
+        }
+        let remove_chip = |doc_id: String| {
+            let on_change = props.on_document_scope_change.clone();
+            let remaining: Vec<String> = scope_ids.iter().filter(|id| **id != doc_id).cloned().collect();
+            Callback::from(move |e: MouseEvent| {
+                e.stop_propagation();
+                on_change.emit(Some(remaining.clone()));
+            })
+        };
+        let picker = if *show_scope_picker {
+            let toggle_doc = |doc_id: String, in_scope: bool| {
+                let on_change = props.on_document_scope_change.clone();
+                let mut next = scope_ids.clone();
+                if in_scope {
+                    next.retain(|id| *id != doc_id);
+                } else {
+                    next.push(doc_id);
+                }
+                Callback::from(move |_: Event| on_change.emit(Some(next.clone())))
+            };
+            html! {
+                <div class="swi-history-popover swi-scope-picker">
+                    <div class="swi-history-popover-title">{ "Documents in this chat" }</div>
+                    { for documents.iter().filter(|d| d.enabled).map(|d| {
+                        let in_scope = scope_ids.contains(&d.id);
+                        html! {
+                            <label key={d.id.clone()} class="swi-scope-option">
+                                <input type="checkbox" checked={in_scope} onchange={toggle_doc(d.id.clone(), in_scope)} />
+                                { &d.filename }
+                            </label>
+                        }
+                    })}
+                    if props.document_scope.is_some() {
+                        <button type="button" class="swi-history-option" onclick={props.on_document_scope_change.reform(|_| None)}>
+                            { "Use all documents" }
+                        </button>
+                    }
+                </div>
+            }
+        } else {
+            html! {}
+        };
+        html! {
+            <div class="swi-context-pill-row">
+                <button type="button" class="swi-context-pill" onclick={toggle_scope_picker.clone()} title="Choose the documents used in this chat">
+                    { format!("{} · {}", pill_label, mode_label) }
+                </button>
+                if props.context_mode == DocumentContextMode::RAG {
+                    <label class="swi-review-toggle" title="Show the retrieved chunks for approval before each message in this chat is sent">
+                        <input
+                            type="checkbox"
+                            checked={props.review_context}
+                            onchange={props.on_review_context_change.reform(|e: Event| e.target_unchecked_into::<web_sys::HtmlInputElement>().checked())}
+                        />
+                        { "Review before sending" }
+                    </label>
+                }
+                { for scoped_documents.iter().map(|d| html! {
+                    <span key={d.id.clone()} class="swi-context-chip">
+                        { &d.filename }
+                        <button type="button" class="swi-context-chip-remove" title="Remove from this chat" onclick={remove_chip(d.id.clone())}>{ "×" }</button>
+                    </span>
+                })}
+                { picker }
+            </div>
+        }
+    } else {
+        html! {}
+    };
+
+    let review_panel = match &*context_review {
+        Some(review) => {
+            let filename = |id: &str| documents.iter().find(|d| d.id == id).map(|d| d.filename.clone()).unwrap_or_default();
+            let toggle_chunk = |chunk_id: String| {
+                let context_review = context_review.clone();
+                let review = review.clone();
+                Callback::from(move |_: Event| {
+                    let mut next = review.clone();
+                    if !next.approved.remove(&chunk_id) {
+                        next.approved.insert(chunk_id.clone());
+                    }
+                    context_review.set(Some(next));
+                })
+            };
+            let on_add_document = {
+                let context_review = context_review.clone();
+                let review = review.clone();
+                Callback::from(move |e: Event| {
+                    let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+                    let mut chunks = DocumentService::get_document_chunks(&select.value());
+                    chunks.sort_by_key(|c| c.chunk_index);
+                    let mut next = review.clone();
+                    next.approved.extend(chunks.iter().map(|c| c.id.clone()));
+                    next.chunks.extend(chunks);
+                    context_review.set(Some(next));
+                })
+            };
+            let on_confirm = {
+                let context_review = context_review.clone();
+                let review = review.clone();
+                let on_send = props.on_send_reviewed.clone();
+                let is_at_bottom = is_at_bottom.clone();
+                Callback::from(move |_: MouseEvent| {
+                    let approved = review.chunks.iter().filter(|c| review.approved.contains(&c.id)).cloned().collect();
+                    on_send.emit((review.text.clone(), approved));
+                    context_review.set(None);
+                    is_at_bottom.set(true);
+                })
+            };
+            let on_cancel = {
+                let context_review = context_review.clone();
+                let input_text = input_text.clone();
+                let text = review.text.clone();
+                Callback::from(move |_: MouseEvent| {
+                    input_text.set(text.clone());
+                    context_review.set(None);
+                })
+            };
+            let approved_tokens: usize = review.chunks.iter().filter(|c| review.approved.contains(&c.id)).map(|c| c.token_count).sum();
+            html! {
+                <div class="swi-context-review" role="dialog" aria-label="Review document context">
+                    <div class="swi-context-review-title">
+                        { format!("Context for this message: {} of {} chunks, ~{} tokens", review.approved.len(), review.chunks.len(), format_count(approved_tokens)) }
+                    </div>
+                    <div class="swi-context-review-list">
+                        { for review.chunks.iter().enumerate().map(|(i, chunk)| {
+                            let first_of_document = i == 0 || review.chunks[i - 1].document_id != chunk.document_id;
+                            let preview: String = chunk.content.chars().take(240).collect();
+                            html! {
+                                <key={chunk.id.clone()}>
+                                if first_of_document {
+                                    <div class="swi-context-review-doc">{ filename(&chunk.document_id) }</div>
+                                }
+                                <label class="swi-context-review-chunk" title={chunk.content.clone()}>
+                                    <input type="checkbox" checked={review.approved.contains(&chunk.id)} onchange={toggle_chunk(chunk.id.clone())} />
+                                    <span class="swi-context-review-meta">{ format!("#{} · {} tokens", chunk.chunk_index + 1, format_count(chunk.token_count)) }</span>
+                                    <span class="swi-context-review-text">{ preview }</span>
+                                </label>
+                                </>
+                            }
+                        }) }
+                    </div>
+                    <div class="swi-context-review-actions">
+                        <select class="swi-banner-select" onchange={on_add_document}>
+                            <option value="" selected=true disabled=true>{ "Add a document..." }</option>
+                            { for documents.iter().filter(|d| d.enabled && !review.chunks.iter().any(|c| c.document_id == d.id)).map(|d| html! {
+                                <option key={d.id.clone()} value={d.id.clone()}>{ &d.filename }</option>
+                            }) }
+                        </select>
+                        <button class="swi-btn swi-mini-btn" onclick={on_cancel}>{ "Cancel" }</button>
+                        <button class="swi-btn swi-mini-btn swi-btn-primary" onclick={on_confirm}>
+                            { if review.approved.is_empty() { "Send without documents".to_string() } else { format!("Send with {} chunk{}", review.approved.len(), if review.approved.len() == 1 { "" } else { "s" }) } }
+                        </button>
+                    </div>
+                </div>
+            }
+        }
+        None => html! {},
+    };
+
+    let similar_hint = match &props.similar_question {
+        Some(similar) if !*confirm_send && pasted_code.is_none() && !props.selection_mode => {
+            let on_view = props.on_view_similar.reform({
+                let similar = similar.clone();
+                move |_: MouseEvent| similar.clone()
+            });
+            html! {
+                <div class="swi-selection-bar swi-similar-hint" role="status">
+                    <span class="swi-similar-text">
+                        { format!("You asked something similar in '{}' on {}", similar.chat_title, format_date(similar.asked_at)) }
+                    </span>
+                    <button class="swi-btn swi-mini-btn swi-btn-primary" onclick={on_view}>{ "View answer" }</button>
+                    <button class="swi-btn swi-mini-btn" title="Dismiss" onclick={props.on_dismiss_similar.reform(|_| ())}>{ "×" }</button>
+                </div>
+            }
+        }
+        _ => html! {},
+    };
+
+    let document_hint = match &*doc_suggestion {
+        Some(doc) if !*confirm_send && !props.selection_mode && props.similar_question.is_none() => {
+            let on_include = {
+                let doc_suggestion = doc_suggestion.clone();
+                let input_text = input_text.clone();
+                let on_scope_change = props.on_document_scope_change.clone();
+                let scope = props.document_scope.clone();
+                let manual = props.context_mode == DocumentContextMode::Manual;
+                let id = doc.id.clone();
+                Callback::from(move |_: MouseEvent| {
+                    if manual {
+                        input_text.set(format!("{} @{} ", input_text.trim_end(), id));
+                    } else {
+                        let mut ids = scope.clone().unwrap_or_default();
+                        ids.push(id.clone());
+                        on_scope_change.emit(Some(ids));
+                    }
+                    doc_suggestion.set(None);
+                })
+            };
+            let on_dismiss = {
+                let doc_suggestion = doc_suggestion.clone();
+                let dismissed = dismissed_suggestions.clone();
+                let id = doc.id.clone();
+                Callback::from(move |_: MouseEvent| {
+                    let mut set = (*dismissed).clone();
+                    set.insert(id.clone());
+                    dismissed.set(set);
+                    doc_suggestion.set(None);
+                })
+            };
+            html! {
+                <div class="swi-selection-bar swi-similar-hint" role="status">
+                    <span class="swi-similar-text">
+                        { format!("This looks related to {} — include it as context?", doc.filename) }
+                    </span>
+                    <button class="swi-btn swi-mini-btn swi-btn-primary" onclick={on_include}>{ "Include" }</button>
+                    <button class="swi-btn swi-mini-btn" onclick={on_dismiss}>{ "Dismiss" }</button>
+                </div>
+            }
+        }
+        _ => html! {},
+    };
+
+    let oversize_dialog = if *oversized {
+        let tokens = count_tokens(&input_text);
+        let limit = message_token_limit(props.context_length);
+        let close = {
+            let oversized = oversized.clone();
+            let convert_error = convert_error.clone();
+            Callback::from(move |_: MouseEvent| {
+                oversized.set(false);
+                convert_error.set(None);
+            })
+        };
+        let on_send_anyway = {
+            let oversized = oversized.clone();
+            let send_message = send_message.clone();
+            let overrides = SendOverrides { oversize: true, ..*send_overrides };
+            Callback::from(move |_: MouseEvent| {
+                oversized.set(false);
+                send_message.emit(overrides);
+            })
+        };
+        let on_trim = {
+            let oversized = oversized.clone();
+            let input_text = input_text.clone();
+            Callback::from(move |_: MouseEvent| {
+                input_text.set(trim_middle(&input_text, limit));
+                oversized.set(false);
+            })
+        };
+        // The latest paste becomes the document when it is most of the message,
+        // otherwise the whole message does
+        let on_convert = {
+            let oversized = oversized.clone();
+            let converting = converting.clone();
+            let convert_error = convert_error.clone();
+            let input_text = input_text.clone();
+            let last_paste = last_paste.clone();
+            let on_created = props.on_document_created.clone();
+            let documents = documents.clone();
+            Callback::from(move |_: MouseEvent| {
+                let message = (*input_text).clone();
+                let paste = last_paste.borrow().clone();
+                let bulk = if !paste.is_empty() && message.contains(&paste) && paste.len() * 2 >= message.len() { paste } else { message.clone() };
+                let oversized = oversized.clone();
+                let converting = converting.clone();
+                let convert_error = convert_error.clone();
+                let input_text = input_text.clone();
+                let on_created = on_created.clone();
+                let documents = documents.clone();
+                converting.set(true);
+                spawn_local(async move {
+                    let name = format!("Pasted text {}.txt", format_date(js_sys::Date::now()));
+                    let result = DocumentService::process_document(&name, bulk.as_bytes(), |_, _| {}).await;
+                    converting.set(false);
+                    match result {
+                        Ok(document) => {
+                            let rest = message.replacen(&bulk, "", 1);
+                            let note = format!("The pasted text was too long to send, so it is attached as a document: @{}", document.id);
+                            documents.set(DocumentService::get_documents());
+                            input_text.set(if rest.trim().is_empty() { note } else { format!("{}\n\n{}", rest.trim_end(), note) });
+                            on_created.emit(document);
+                            oversized.set(false);
+                        }
+                        Err(err) => convert_error.set(Some(err)),
+                    }
+                });
+            })
+        };
+        html! {
+            <>
+                <div class="swi-oversize-backdrop"></div>
+                <div class="swi-oversize-panel" role="dialog">
+                    <h3>{ "This message is too long for the model" }</h3>
+                    <p>
+                        { format!(
+                            "It is ~{} tokens on its own, but the context of {} tokens leaves ~{} for a message once room for the answer is kept. The server would most likely reject it.",
+                            format_count(tokens), format_count(props.context_length), format_count(limit)
+                        ) }
+                    </p>
+                    if let Some(err) = &*convert_error {
+                        <p class="swi-oversize-error">{ err.to_string() }</p>
+                    }
+                    <div class="swi-oversize-actions">
+                        <button class="swi-btn swi-btn-primary" disabled={*converting} onclick={on_convert} title="Store the pasted text as a document and reference it with @ instead">
+                            { if *converting { "Attaching..." } else { "Attach as document" } }
+                        </button>
+                        <button class="swi-btn" disabled={*converting} onclick={on_trim} title="Keep the beginning and the end, cutting the middle">{ "Trim to fit" }</button>
+                        <button class="swi-btn" disabled={*converting} onclick={on_send_anyway}>{ "Send anyway" }</button>
+                        <button class="swi-btn" disabled={*converting} onclick={close}>{ "Cancel" }</button>
+                    </div>
+                </div>
+            </>
+        }
+    } else {
+        html! {}
+    };
+
+    let missing_notice = if missing_mentions.is_empty() {
+        html! {}
+    } else {
+        let overrides = SendOverrides { missing_mentions: true, ..*send_overrides };
+        let on_send_anyway = send_message.reform(move |_: MouseEvent| overrides);
+        let (text, button) = if missing_mentions.len() == 1 {
+            ("was deleted after it was mentioned, so it can't be sent with the message", "Send without it")
+        } else {
+            ("were deleted after they were mentioned, so they can't be sent with the message", "Send without them")
+        };
+        html! {
+            <div class="swi-selection-bar swi-send-confirm">
+                <span>{ format!("@{} {}.", missing_mentions.join(", @"), text) }</span>
+                <button class="swi-btn swi-mini-btn swi-btn-primary" onclick={on_send_anyway}>{ button }</button>
+            </div>
+        }
+    };
+
+    let send_confirmation = if *confirm_send {
+        let total = *system_tokens + *history_tokens + doc_tokens + input_counts.tokens;
+        let overrides = SendOverrides { size_warning: true, ..*send_overrides };
+        let on_send_anyway = send_message.reform(move |_: MouseEvent| overrides);
+        let on_trim = {
+            let confirm = confirm_send.clone();
+            let show_scope = show_scope_picker.clone();
+            let popover = toolbar_popover.clone();
+            Callback::from(move |_: MouseEvent| {
+                confirm.set(false);
+                // Documents are usually the bulk; otherwise offer the history window
+                if doc_tokens > 0 {
+                    show_scope.set(true);
+                } else {
+                    popover.set(Some(ToolbarPopover::History));
+                }
+            })
+        };
+        html! {
+            <div class="swi-selection-bar swi-send-confirm">
+                <span>
+                    { format!("This message will send ~{} tokens", format_count(total)) }
+                    if doc_tokens > 0 {
+                        { format!(" (incl. {} from documents)", format_count(doc_tokens)) }
+                    }
+                    { ". Send anyway?" }
+                </span>
+                <button class="swi-btn swi-mini-btn swi-btn-primary" onclick={on_send_anyway}>{ "Send" }</button>
+                <button class="swi-btn swi-mini-btn" onclick={on_trim}>{ "Trim context" }</button>
+            </div>
+        }
+    } else {
+        html! {}
+    };
+
+    // Times are shown relative to this render
+    let now = js_sys::Date::now();
+    // Everything before this index (apart from system messages) is not sent
+    let first_non_system = props.messages.iter().position(|m| m.role != "system").unwrap_or(props.messages.len());
+    let window_start = history_window_start(&props.messages, props.history_window);
+    let divider_at = if window_start > first_non_system { Some(window_start) } else { None };
+    // Shown after the last message the chat's summary covers
+    let summary_at = props.summary_until.as_deref().and_then(|until| summary_end(&props.messages, until));
+    let summary_marker = match &props.summary {
+        Some(summary) => html! {
+            <details class="swi-summary-marker">
+                <summary>{ "Earlier messages summarized" }</summary>
+                <div class="swi-summary-text">{ summary }</div>
+                <div class="swi-summary-note">{ "The model gets this summary instead of the messages above, which are kept here as they are." }</div>
+            </details>
+        },
+        None => html! {},
+    };
+    // The answer being streamed carries the typing state and its own stop button
+    let streaming_id = props
+        .messages
+        .iter()
+        .rev()
+        .find(|m| !m.queued)
+        .filter(|m| props.is_loading && m.role == "assistant")
+        .map(|m| m.id.clone());
+    let queued: Vec<&Message> = props.messages.iter().filter(|m| m.queued).collect();
+    // Only the last answer can be regenerated, and not after a failed request
+    let last_answer_id = props.messages.iter().rev().find(|m| !m.queued).filter(|m| m.role == "assistant").map(|m| m.id.clone());
+    // Only for display: every message is still stored, exported and sent as before
+    let events = event_runs(
+        props.messages.iter().enumerate().skip(first_visible).filter(|(_, m)| !m.queued && streaming_id.as_deref() != Some(m.id.as_str())),
+        divider_at,
+    );
+    let expanded_here = expanded_events.get(&props.chat_id).cloned().unwrap_or_default();
+    let toggle_events = {
+        let expanded_events = expanded_events.clone();
+        let chat_id = props.chat_id.clone();
+        move |first: String| {
+            let expanded_events = expanded_events.clone();
+            let chat_id = chat_id.clone();
+            Callback::from(move |_: MouseEvent| {
+                let mut all = (*expanded_events).clone();
+                let runs = all.entry(chat_id.clone()).or_default();
+                if !runs.remove(&first) {
+                    runs.insert(first.clone());
+                }
+                expanded_events.set(all);
+            })
+        }
+    };
+    let stop_label = if props.active_generations > 1 { "Stop all" } else { "Stop" };
+    let on_stop_button = if props.active_generations > 1 { props.on_stop_all.clone() } else { props.on_stop.clone() };
+
+    // Conversation size against the context length, by section
+    let budget_bar = if props.context_length > 0 {
+        let used = *system_tokens + doc_tokens + *history_tokens;
+        let scale = props.context_length.max(used) as f64;
+        let width = |tokens: usize| format!("width: {:.2}%;", tokens as f64 * 100.0 / scale);
+        let over = used > props.context_length;
+        let title = format!(
+            "System prompt: {} · Documents: {} · History: {} — {} of {} tokens. Click to change how much history is sent.",
+            format_count(*system_tokens), format_count(doc_tokens), format_count(*history_tokens),
+            format_count(used), format_count(props.context_length)
+        );
+        let popover = toolbar_popover.clone();
+        html! {
+            <div class={classes!("swi-budget-bar", over.then_some("swi-over"))} title={title} onclick={Callback::from(move |_| popover.set(Some(ToolbarPopover::History)))}>
+                <div class="swi-budget-seg swi-system" style={width(*system_tokens)}></div>
+                <div class="swi-budget-seg swi-documents" style={width(doc_tokens)}></div>
+                <div class="swi-budget-seg swi-history" style={width(*history_tokens)}></div>
+            </div>
+        }
+    } else {
+        html! {}
+    };
+
+    // What the next request would take, typed message included, next to the input
+    let context_meter = if props.context_length > 0 {
+        let used = *system_tokens + doc_tokens + *history_tokens + input_counts.tokens;
+        let share = used as f64 / props.context_length as f64;
+        let level = if share > 1.0 {
+            Some("swi-over")
+        } else if share > 0.8 {
+            Some("swi-near")
+        } else {
+            None
+        };
+        let title = format!(
+            "System prompt: {} · Documents: {} · History: {} · Message: {} — {} of {} tokens",
+            format_count(*system_tokens), format_count(doc_tokens), format_count(*history_tokens), format_count(input_counts.tokens),
+            format_count(used), format_count(props.context_length)
+        );
+        html! {
+            <span class={classes!("swi-context-meter", level)} title={title}>
+                <span class="swi-context-meter-track">
+                    <span class="swi-context-meter-fill" style={format!("width: {:.1}%;", share.min(1.0) * 100.0)}></span>
+                </span>
+                { format!("{} / {} tokens", format_compact(used), format_compact(props.context_length)) }
+            </span>
+        }
+    } else {
+        html! {}
+    };
+
     let css = r#"
-        .messages-container {
+        .swi-context-meter { margin-left: auto; display: inline-flex; align-items: center; gap: 6px; font-size: 0.75rem; color: var(--text-secondary); font-variant-numeric: tabular-nums; }
+        .swi-input-count + .swi-context-meter { margin-left: 10px; }
+        .swi-context-meter-track { width: 48px; height: 4px; border-radius: 2px; background: #e5e7eb; overflow: hidden; }
+        .swi-context-meter-fill { display: block; height: 100%; background: var(--accent-color); }
+        .swi-context-meter.swi-near { color: #c2410c; }
+        .swi-context-meter.swi-near .swi-context-meter-fill { background: #f97316; }
+        .swi-context-meter.swi-over { color: var(--danger-color); }
+        .swi-context-meter.swi-over .swi-context-meter-fill { background: var(--danger-color); }
+        .swi-budget-bar { display: flex; height: 4px; background: #f0f0f0; cursor: pointer; flex-shrink: 0; }
+        .swi-budget-bar:hover { height: 8px; }
+        .swi-budget-bar.swi-over { background: #fecaca; }
+        .swi-budget-seg.swi-system { background: #a78bfa; }
+        .swi-budget-seg.swi-documents { background: #60a5fa; }
+        .swi-budget-seg.swi-history { background: var(--accent-color); }
+        .swi-budget-bar.swi-over .swi-budget-seg { background: var(--danger-color); }
+        .swi-messages-container {
             flex-grow: 1;
             overflow-y: auto;
             padding: 20px;
@@ -235,26 +1836,26 @@ pub fn chat_area(props: &ChatAreaProps) -> Html {
             flex-direction: column;
             gap: 15px;
             background-color: #ffffff;
-            scroll-behavior: smooth;
+            scroll-behavior: var(--scroll-behavior);
         }
 
         /* Row Layout */
-        .message-row { display: flex; width: 100%; }
-        .message-row.user { justify-content: flex-end; }
-        .message-row.assistant { justify-content: flex-start; }
-        .message-row.system { justify-content: center; margin: 10px 0; }
+        .swi-message-row { display: flex; width: 100%; }
+        .swi-message-row.swi-user { justify-content: flex-end; }
+        .swi-message-row.swi-assistant { justify-content: flex-start; }
+        .swi-message-row.swi-system { justify-content: center; margin: 10px 0; }
 
         /* Bubble Container */
-        .bubble-group { display: flex; gap: 10px; max-width: 85%; align-items: flex-end; }
-        .message-row.user .bubble-group { flex-direction: row-reverse; }
+        .swi-bubble-group { display: flex; gap: 10px; max-width: 85%; align-items: flex-end; }
+        .swi-message-row.swi-user .swi-bubble-group { flex-direction: row-reverse; }
 
         /* Avatars */
-        .avatar { width: 32px; height: 32px; border-radius: 50%; display: flex; align-items: center; justify-content: center; flex-shrink: 0; box-shadow: 0 2px 4px rgba(0,0,0,0.1); }
-        .avatar.user { background: #555; color: white; }
-        .avatar.assistant { background: var(--accent-color); color: white; }
+        .swi-avatar { width: 32px; height: 32px; border-radius: 50%; display: flex; align-items: center; justify-content: center; flex-shrink: 0; box-shadow: 0 2px 4px rgba(0,0,0,0.1); }
+        .swi-avatar.swi-user { background: #555; color: white; }
+        .swi-avatar.swi-assistant { background: var(--accent-color); color: white; }
 
         /* Text Bubble */
-        .msg-bubble {
+        .swi-msg-bubble {
             padding: 10px 15px;
             border-radius: 12px;
             font-size: 0.95rem;
@@ -266,11 +1867,11 @@ pub fn chat_area(props: &ChatAreaProps) -> Html {
             max-width: 100%;
         }
 
-        .message-row.user .msg-bubble { background-color: #e3f2fd; color: #1565c0; border-bottom-right-radius: 2px; }
-        .message-row.assistant .msg-bubble { background-color: #f5f5f5; color: #333; border-bottom-left-radius: 2px; }
+        .swi-message-row.swi-user .swi-msg-bubble { background-color: #e3f2fd; color: #1565c0; border-bottom-right-radius: 2px; }
+        .swi-message-row.swi-assistant .swi-msg-bubble { background-color: #f5f5f5; color: #333; border-bottom-left-radius: 2px; }
 
         /* SYSTEM MESSAGE STYLE */
-        .system-bubble {
+        .swi-system-bubble {
             background-color: #fff3cd;
             color: #666;
             padding: 8px 16px;
@@ -282,59 +1883,133 @@ pub fn chat_area(props: &ChatAreaProps) -> Html {
             overflow-wrap: anywhere;
         }
 
+        .swi-event-group { border: 1px dashed #ccc; background: #fffbea; color: #666; border-radius: 20px; padding: 4px 14px; font-size: 0.8em; cursor: pointer; }
+        .swi-event-group:hover { background: #fff3cd; }
+
         /* Input Area Styles */
-        .input-wrapper { border-top: 1px solid var(--border-color); padding: 20px; display: flex; justify-content: center; background: white; position: relative; }
-        .input-container { width: 100%; max-width: 900px; position: relative; display: flex; flex-direction: column; }
-        .chat-input { width: 100%; padding: 12px; padding-right: 45px; border: 1px solid var(--border-color); border-radius: 8px; box-shadow: 0 2px 5px rgba(0,0,0,0.05); resize: none; font-family: inherit; outline: none; transition: border 0.2s; }
-        .chat-input:focus { border-color: var(--accent-color); box-shadow: 0 0 0 2px rgba(16, 163, 127, 0.1); }
-        .send-btn { position: absolute; right: 8px; bottom: 8px; background: var(--accent-color); color: white; border: none; border-radius: 4px; padding: 6px 10px; cursor: pointer; transition: opacity 0.2s; }
-        .send-btn:disabled { background: #ccc; cursor: default; }
-        .send-btn:hover:not(:disabled) { background: var(--accent-hover); }
-
-        /* Document Mention Dropdown */
-        .document-mention-dropdown {
-            position: fixed; /* IMPORTANT: use viewport coordinates */
-            background: white;
-            border: 1px solid var(--border-color);
-            border-radius: 8px;
-            box-shadow: 0 4px 12px rgba(0, 0, 0, 0.15);
-            overflow: hidden;
-            width: 250px;
-            z-index: 100;
-        }
-        .document-mention-dropdown.scrollable {
-            max-height: 228px;
-            overflow-y: auto;
-        }
-        .document-mention-dropdown.no-scrollbar {
-            max-height: 228px;
-            overflow-y: hidden;
-        }
-        .document-mention-item {
-            padding: 10px 12px;
-            cursor: pointer;
-            display: flex;
-            flex-direction: column;
-            gap: 4px;
-            border-bottom: 1px solid #f0f0f0;
-        }
-        .document-mention-item:last-child { border-bottom: none; }
-        .document-mention-item:hover { background: #f5f5f5; }
-        .document-mention-name {
-            font-size: 0.9rem;
-            font-weight: 500;
-            color: var(--text-primary);
-        }
-        .document-mention-meta {
-            font-size: 0.75rem;
-            color: var(--text-secondary);
-        }
-        .document-mention-no-results {
-            padding: 10px 12px;
-            color: var(--text-secondary);
-            text-align: center;
-        }
+        .swi-input-wrapper { border-top: 1px solid var(--border-color); padding: 20px; display: flex; justify-content: center; background: white; position: relative; }
+        .swi-input-container { width: 100%; max-width: 900px; position: relative; display: flex; flex-direction: column; }
+        .swi-chat-input { width: 100%; padding: 12px; padding-right: 45px; border: 1px solid var(--border-color); border-radius: 8px; box-shadow: 0 2px 5px rgba(0,0,0,0.05); resize: none; font-family: inherit; outline: none; transition: border var(--motion-fast); }
+        .swi-chat-input:focus { border-color: var(--accent-color); box-shadow: 0 0 0 2px rgba(16, 163, 127, 0.1); }
+        .swi-send-btn { position: absolute; right: 8px; bottom: 8px; background: var(--accent-color); color: white; border: none; border-radius: 4px; padding: 6px 10px; cursor: pointer; transition: opacity var(--motion-fast); }
+        .swi-send-btn:disabled { background: #ccc; cursor: default; }
+        .swi-send-btn:hover:not(:disabled) { background: var(--accent-hover); }
+
+        /* Input Toolbar */
+        .swi-input-toolbar { display: flex; gap: 6px; align-items: center; margin-bottom: 6px; position: relative; }
+        .swi-input-count { margin-left: auto; font-size: 0.75rem; color: var(--text-secondary); }
+        .swi-toolbar-btn { border: 1px solid var(--border-color); background: white; border-radius: 12px; padding: 2px 10px; font-size: 0.75rem; color: var(--text-secondary); cursor: pointer; }
+        .swi-toolbar-btn:hover { background: #f0f0f0; color: var(--text-primary); }
+        .swi-toolbar-btn.swi-active { border-color: var(--accent-color); color: var(--accent-color); }
+
+        /* Document Context Pill */
+        .swi-review-toggle { display: flex; align-items: center; gap: 4px; font-size: 0.75rem; color: var(--text-secondary); cursor: pointer; }
+        .swi-context-review { border: 1px solid var(--border-color); border-radius: 8px; background: white; margin-bottom: 8px; font-size: 0.85rem; }
+        .swi-context-review-title { padding: 8px 12px; font-weight: 600; border-bottom: 1px solid var(--border-color); }
+        .swi-context-review-list { max-height: 260px; overflow-y: auto; padding: 4px 0; }
+        .swi-context-review-doc { padding: 6px 12px 2px; font-size: 0.75rem; font-weight: 600; color: var(--text-secondary); }
+        .swi-context-review-chunk { display: flex; align-items: flex-start; gap: 8px; padding: 4px 12px; cursor: pointer; }
+        .swi-context-review-chunk:hover { background: #f5f5f5; }
+        .swi-context-review-meta { flex-shrink: 0; font-size: 0.75rem; color: var(--text-secondary); white-space: nowrap; }
+        .swi-context-review-text { overflow: hidden; display: -webkit-box; -webkit-line-clamp: 2; -webkit-box-orient: vertical; word-break: break-word; }
+        .swi-context-review-actions { display: flex; gap: 6px; justify-content: flex-end; align-items: center; padding: 8px 12px; border-top: 1px solid var(--border-color); }
+        .swi-context-review-actions select { margin-right: auto; }
+        .swi-context-pill-row { display: flex; flex-wrap: wrap; gap: 6px; align-items: center; margin-bottom: 6px; position: relative; }
+        .swi-context-pill { border: 1px solid var(--accent-color); background: #f0fdf9; color: var(--accent-color); border-radius: 12px; padding: 2px 10px; font-size: 0.75rem; cursor: pointer; }
+        .swi-context-chip { display: inline-flex; align-items: center; gap: 4px; background: #f4f4f4; border-radius: 12px; padding: 2px 4px 2px 10px; font-size: 0.75rem; color: var(--text-secondary); max-width: 200px; white-space: nowrap; overflow: hidden; text-overflow: ellipsis; }
+        .swi-selected-doc-chip { background: #f0fdf9; color: var(--accent-color); border: 1px solid var(--accent-color); }
+        .swi-context-chip-remove { border: none; background: transparent; cursor: pointer; color: var(--text-secondary); font-size: 0.9rem; line-height: 1; padding: 0 4px; }
+        .swi-context-chip-remove:hover { color: var(--danger-color); }
+        .swi-scope-option { display: flex; align-items: center; gap: 8px; padding: 6px 8px; font-size: 0.85rem; cursor: pointer; white-space: nowrap; }
+        .swi-scope-option:hover { background: #f5f5f5; border-radius: 4px; }
+
+        /* History Window */
+        .swi-history-popover { position: absolute; bottom: 100%; left: 0; margin-bottom: 4px; background: white; border: 1px solid var(--border-color); border-radius: 8px; box-shadow: 0 4px 12px rgba(0, 0, 0, 0.15); padding: 6px; display: flex; flex-direction: column; min-width: 160px; z-index: 100; }
+        .swi-prefill-popover { min-width: 260px; gap: 4px; }
+        .swi-prefill-input { font-family: monospace; font-size: 0.8rem; border: 1px solid var(--border-color); border-radius: 6px; padding: 6px; resize: vertical; }
+        .swi-model-override { max-width: 220px; padding: 4px 6px; border: 1px solid var(--border-color); border-radius: 4px; font-size: 0.8rem; }
+        .swi-temperature-override { width: 100%; }
+        .swi-length-custom { width: 90px; margin-left: 6px; padding: 2px 4px; border: 1px solid var(--border-color); border-radius: 4px; }
+        .swi-history-popover-title { font-size: 0.75rem; font-weight: 600; color: var(--text-secondary); padding: 4px 8px; }
+        .swi-history-option { border: none; background: transparent; text-align: left; padding: 6px 8px; border-radius: 4px; font-size: 0.85rem; cursor: pointer; color: var(--text-primary); }
+        .swi-history-option:hover { background: #f5f5f5; }
+        .swi-history-option.swi-active { color: var(--accent-color); font-weight: 600; }
+        .swi-summary-marker { font-size: 0.8rem; color: var(--text-secondary); border: 1px dashed var(--border-color); border-radius: 6px; padding: 4px 10px; }
+        .swi-summary-marker summary { cursor: pointer; }
+        .swi-summary-text { white-space: pre-wrap; margin-top: 6px; color: var(--text-primary); }
+        .swi-summary-note { margin-top: 6px; font-style: italic; }
+        .swi-history-divider { display: flex; align-items: center; gap: 10px; color: var(--text-secondary); font-size: 0.75rem; }
+        .swi-history-divider::before, .swi-history-divider::after { content: ""; flex: 1; border-top: 1px dashed #ccc; }
+
+        .swi-load-earlier-btn { align-self: center; border: 1px solid var(--border-color); background: white; border-radius: 14px; padding: 4px 14px; font-size: 0.8rem; color: var(--text-secondary); cursor: pointer; }
+        .swi-load-earlier-btn:hover { background: #f5f5f5; color: var(--text-primary); }
+        .swi-decode-warning { font-size: 0.75rem; color: #b45309; margin-top: 4px; }
+        .swi-decode-warning summary { cursor: pointer; }
+        .swi-failed-attempts { font-size: 0.75rem; color: var(--text-secondary); margin-top: 4px; }
+        .swi-failed-attempts summary { cursor: pointer; }
+        .swi-failed-attempt { border: 1px dashed var(--border-color); border-radius: 4px; padding: 6px; margin-top: 4px; }
+        .swi-failed-attempt-time { font-style: italic; margin-bottom: 2px; }
+        .swi-failed-attempt-text { white-space: pre-wrap; word-break: break-word; max-height: 200px; overflow-y: auto; color: var(--text-primary); }
+        .swi-decode-warning pre { white-space: pre-wrap; word-break: break-all; max-height: 200px; overflow-y: auto; background: #fffbeb; border: 1px solid #fde68a; border-radius: 4px; padding: 6px; margin: 4px 0 0; }
+        .swi-doc-chip { display: inline-flex; align-items: center; gap: 4px; padding: 0 6px; border-radius: 10px; background: white; border: 1px solid #90caf9; font-size: 0.85em; cursor: pointer; }
+        .swi-doc-chip:hover { background: #f5faff; }
+        .swi-doc-chip.swi-doc-attach { border-style: dashed; }
+        .swi-doc-chip.swi-missing { cursor: default; color: #999; border-color: var(--border-color); text-decoration: line-through; }
+        .swi-doc-chip-type { font-size: 0.7em; font-weight: 700; color: #95a5a6; }
+        .swi-doc-chip-type.swi-pdf { color: #e74c3c; }
+        .swi-doc-chip-type.swi-txt { color: #3498db; }
+        .swi-doc-chip-type.swi-md { color: #27ae60; }
+        .swi-msg-time { font-size: 0.7rem; color: var(--text-secondary); margin-top: 4px; }
+        .swi-msg-metrics { font-size: 0.7rem; color: var(--text-secondary); margin-top: 4px; font-variant-numeric: tabular-nums; }
+        .swi-msg-actions { display: flex; gap: 6px; margin-top: 6px; }
+        .swi-msg-action { border: 1px solid var(--border-color); background: white; border-radius: 10px; padding: 1px 8px; font-size: 0.7rem; color: var(--text-secondary); cursor: pointer; }
+        .swi-message-row:not(:hover) .swi-msg-action.swi-hover-only { visibility: hidden; }
+        .swi-edit-message { display: flex; flex-direction: column; gap: 6px; min-width: min(480px, 70vw); }
+        .swi-edit-input { font-family: inherit; font-size: inherit; border: 1px solid var(--border-color); border-radius: 6px; padding: 6px; resize: vertical; }
+        .swi-edit-actions { display: flex; gap: 6px; justify-content: flex-end; }
+        .swi-message-row.swi-excluded .swi-msg-bubble { opacity: 0.55; }
+        .swi-message-row.swi-excluded .swi-markdown-body { text-decoration: line-through; }
+        .swi-msg-stop { align-self: flex-end; border: 1px solid var(--border-color); background: white; color: var(--text-secondary); border-radius: 50%; width: 24px; height: 24px; padding: 0; font-size: 0.7rem; cursor: pointer; flex-shrink: 0; }
+        .swi-msg-stop:hover { border-color: var(--danger-color); color: var(--danger-color); }
+        .swi-message-row.swi-queued .swi-msg-bubble { opacity: 0.6; }
+        .swi-avatar.swi-typing { animation: swi-typing var(--motion-flash) ease-in-out infinite; }
+        @keyframes swi-typing { 50% { opacity: 0.35; } }
+        .swi-reasoning { margin-bottom: 8px; }
+        .swi-reasoning-toggle { display: inline-flex; align-items: center; gap: 6px; border: 1px solid var(--border-color); background: white; border-radius: 10px; padding: 2px 10px; font-size: 0.75rem; color: var(--text-secondary); cursor: pointer; }
+        .swi-reasoning-toggle:hover { border-color: var(--accent-color); color: var(--accent-color); }
+        .swi-reasoning-spinner { width: 10px; height: 10px; border: 2px solid var(--border-color); border-top-color: var(--accent-color); border-radius: 50%; animation: swi-spin 0.8s linear infinite; }
+        .swi-reasoning-body { margin-top: 6px; padding: 4px 0 4px 10px; border-left: 3px solid var(--border-color); color: var(--text-secondary); font-size: 0.9em; }
+        .swi-excluded-badge { display: inline-block; font-size: 0.7rem; color: var(--text-secondary); border: 1px dashed var(--border-color); border-radius: 10px; padding: 0 6px; margin-top: 4px; }
+        .swi-msg-action:hover, .swi-msg-action.swi-active { border-color: var(--accent-color); color: var(--accent-color); }
+        .swi-logprob-view { white-space: pre-wrap; font-family: monospace; font-size: 0.9rem; line-height: 1.7; }
+        .swi-logprob-token { border-radius: 2px; cursor: help; }
+        .swi-interrupted-note { font-size: 0.75rem; color: var(--text-secondary); font-style: italic; margin-top: 4px; }
+
+        /* Message Selection */
+        .swi-select-box { align-self: center; width: 16px; height: 16px; cursor: pointer; flex-shrink: 0; }
+        .swi-message-row.swi-selected .swi-msg-bubble { outline: 2px solid var(--accent-color); }
+        .swi-selection-bar { position: absolute; bottom: 100%; left: 50%; transform: translateX(-50%); margin-bottom: 10px; display: flex; gap: 8px; align-items: center; background: white; border: 1px solid var(--border-color); border-radius: 10px; box-shadow: 0 4px 12px rgba(0, 0, 0, 0.15); padding: 8px 12px; z-index: 50; white-space: nowrap; }
+        .swi-send-confirm { font-size: 0.85rem; }
+        .swi-oversize-backdrop { position: absolute; top: 0; left: 0; width: 100%; height: 100%; background: rgba(255,255,255,0.6); backdrop-filter: blur(2px); z-index: 99; }
+        .swi-oversize-panel { position: absolute; top: 80px; left: 50%; transform: translateX(-50%); width: min(480px, 90%); background: white; border: 1px solid var(--border-color); border-radius: 8px; box-shadow: 0 10px 15px -3px rgba(0, 0, 0, 0.1); padding: 20px; z-index: 100; font-size: 0.9rem; }
+        .swi-oversize-panel h3 { margin: 0 0 8px; font-size: 1.05rem; }
+        .swi-oversize-error { color: #d32f2f; }
+        .swi-oversize-actions { display: flex; flex-wrap: wrap; gap: 8px; margin-top: 12px; }
+        .swi-paste-prompt { font-size: 0.85rem; }
+        .swi-similar-hint { font-size: 0.85rem; max-width: 90%; }
+        .swi-stall-warning { display: flex; align-items: center; flex-wrap: wrap; gap: 8px; margin: -8px 0 12px 44px; padding: 8px 12px; border: 1px solid #f5d08a; background: #fff8e6; border-radius: 8px; font-size: 0.85rem; color: #7a5200; align-self: flex-start; }
+        .swi-message-row.swi-assistant .swi-msg-bubble.swi-request-error { position: relative; display: flex; flex-direction: column; gap: 8px; padding-right: 32px; background: #fef2f2; color: #b91c1c; border: 1px solid #fecaca; }
+        .swi-request-error span { white-space: pre-wrap; word-break: break-word; }
+        .swi-request-error-actions { display: flex; gap: 6px; }
+        .swi-request-error-close { position: absolute; top: 6px; right: 6px; }
+        .swi-empty-response { display: flex; align-items: center; gap: 8px; color: var(--text-secondary); font-style: italic; }
+        .swi-similar-text { overflow: hidden; text-overflow: ellipsis; }
+        .swi-message-row.swi-focused .swi-msg-bubble { animation: swi-focus-flash var(--motion-flash) ease-out; }
+        @keyframes swi-focus-flash { from { box-shadow: 0 0 0 3px var(--accent-color); } to { box-shadow: 0 0 0 3px transparent; } }
+        .swi-paste-language { padding: 3px; border: 1px solid var(--border-color); border-radius: 4px; font-family: inherit; font-size: 0.8rem; }
+        .swi-selection-count { font-size: 0.85rem; font-weight: 600; margin-right: 4px; }
     "#;
+    inject_styles("chat_area", css);
 
     let user_icon = html! {
         <svg width="20" height="20" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round">
@@ -352,130 +2027,983 @@ pub fn chat_area(props: &ChatAreaProps) -> Html {
         </svg>
     };
 
-    // Document mention dropdown (use documents state, not localStorage each render)
-    let mention_dropdown = {
-        let mention_pos = *mention_position;
-        let query = (*mention_query).clone();
-        let docs = (*documents).clone();
-        let on_select_document = on_select_document.clone();
-
-        if let Some((x, y)) = mention_pos {
-            let query_lc = query.to_lowercase();
-            let filtered_docs: Vec<_> = docs
-                .iter()
-                .filter(|d| d.filename.to_lowercase().contains(&query_lc))
-                .collect();
-
-            let style_val = format!("left: {}px; top: {}px;", x, y);
-
-            if !filtered_docs.is_empty() {
-                let scrollbar_class = if filtered_docs.len() > 5 { "scrollable" } else { "no-scrollbar" };
-
-                html! {
-                    <div class={format!("document-mention-dropdown {}", scrollbar_class)} style={style_val}>
-                        { for filtered_docs.iter().map(|doc| {
-                            let doc_id = doc.id.clone();
-                            let doc_name = doc.filename.clone();
-                            let chunk_count = doc.chunk_count;
-                            let on_select = on_select_document.clone();
-
-                            html! {
-                                <div
-                                    class="document-mention-item"
-                                    onclick={Callback::from(move |_| on_select.emit(doc_id.clone()))}
-                                >
-                                    <div class="document-mention-name">{ &doc_name }</div>
-                                    <div class="document-mention-meta">{ format!("{} chunks", chunk_count) }</div>
-                                </div>
-                            }
-                        }) }
-                    </div>
-                }
-            } else {
-                html! {
-                    <div class="document-mention-dropdown no-scrollbar" style={style_val}>
-                        <div class="document-mention-no-results">{ "No documents found" }</div>
-                    </div>
-                }
+    let picker_popup = match &*picker {
+        Some(open) => {
+            let (items, empty_text): (Vec<AutocompleteItem>, &str) = match open.kind {
+                PickerKind::Prompts => (
+                    matching_prompts(&props.saved_prompts, &open.query)
+                        .into_iter()
+                        .map(|p| AutocompleteItem {
+                            label: p.name.clone(),
+                            detail: p.content.lines().find(|l| !l.trim().is_empty()).unwrap_or_default().to_string(),
+                        })
+                        .collect(),
+                    "No saved prompt matches",
+                ),
+                PickerKind::Documents => (
+                    matching_documents(&documents, &open.query)
+                        .into_iter()
+                        .map(|d| AutocompleteItem { label: d.filename.clone(), detail: format!("{} chunks", d.chunk_count) })
+                        .collect(),
+                    "No documents found",
+                ),
+            };
+            html! {
+                <Autocomplete
+                    items={items}
+                    active={open.active}
+                    anchor={input_ref.clone()}
+                    on_select={on_pick.clone()}
+                    empty_text={empty_text}
+                />
             }
-        } else {
-            html! { <></> }
         }
+        None => html! {},
+    };
+
+    let on_input_blur = {
+        let picker = picker.clone();
+        Callback::from(move |_: FocusEvent| picker.set(None))
     };
 
     html! {
         <>
-            <style>{ css }</style>
+            { budget_bar }
+
+            <div class="swi-messages-container" ref={scroll_ref} onscroll={on_scroll}>
+                if first_visible > 0 {
+                    <button class="swi-load-earlier-btn" onclick={load_earlier.reform(|_| ())}>
+                        { format!("Load earlier messages ({} hidden)", first_visible) }
+                    </button>
+                }
+                // Queued messages are shown below the response they wait for
+                { for props.messages.iter().enumerate().skip(first_visible).filter(|(_, m)| !m.queued).map(|(idx, msg)| {
+                    let divider = html! {
+                        <>
+                            if summary_at == Some(idx) {
+                                { summary_marker.clone() }
+                            }
+                            if divider_at == Some(idx) {
+                                <div class="swi-history-divider">{ "Messages above are not sent to the model" }</div>
+                            }
+                        </>
+                    };
+
+                    let event_toggle = match events.get(&msg.id) {
+                        Some((first, count)) if !expanded_here.contains(first) => {
+                            // Collapsed: the first message of the run stands for all of them
+                            return if *first == msg.id {
+                                html! {
+                                    <key={msg.id.clone()}>
+                                        { divider }
+                                        <div class="swi-message-row swi-system">
+                                            <button class="swi-event-group" aria-expanded="false" onclick={toggle_events(first.clone())}>
+                                                { format!("▸ {} system events", count) }
+                                            </button>
+                                        </div>
+                                    </>
+                                }
+                            } else {
+                                html! { <key={msg.id.clone()}></> }
+                            };
+                        }
+                        Some((first, count)) if *first == msg.id => html! {
+                            <div class="swi-message-row swi-system">
+                                <button class="swi-event-group" aria-expanded="true" onclick={toggle_events(first.clone())}>
+                                    { format!("▾ Hide {} system events", count) }
+                                </button>
+                            </div>
+                        },
+                        _ => html! {},
+                    };
 
-            <div class="messages-container" ref={scroll_ref} onscroll={on_scroll}>
-                { for props.messages.iter().map(|msg| {
                     if msg.role == "system" {
                         html! {
-                            <div class="message-row system">
-                                <div class="system-bubble">{ &msg.content }</div>
-                            </div>
+                            <key={msg.id.clone()}>
+                                { event_toggle }
+                                <div class="swi-message-row swi-system">
+                                    <div class="swi-system-bubble">{ &msg.content }</div>
+                                </div>
+                            </>
                         }
                     } else {
-                        let role_cls = msg.role.clone();
+                        let role_cls = format!("swi-{}", msg.role);
                         let (avatar_cls, icon) = if msg.role == "user" {
-                            ("user", user_icon.clone())
+                            ("swi-user", user_icon.clone())
+                        } else {
+                            ("swi-assistant", bot_icon.clone())
+                        };
+
+                        let logprobs_toggle = if msg.logprobs.as_ref().is_some_and(|t| !t.is_empty()) {
+                            let inspecting = inspecting.clone();
+                            let active = inspecting.contains(&msg.id);
+                            let id = msg.id.clone();
+                            html! {
+                                <button class={classes!("swi-msg-action", active.then_some("swi-active"))} onclick={Callback::from(move |_| {
+                                    let mut set = (*inspecting).clone();
+                                    if !set.remove(&id) {
+                                        set.insert(id.clone());
+                                    }
+                                    inspecting.set(set);
+                                })}>
+                                    { if active { "Hide probabilities" } else { "Token probabilities" } }
+                                </button>
+                            }
+                        } else {
+                            html! {}
+                        };
+                        let compare_toggle = if msg.previous.is_some() {
+                            let comparing = comparing.clone();
+                            let id = msg.id.clone();
+                            let active = comparing.contains(&id);
+                            html! {
+                                <button class={classes!("swi-msg-action", active.then_some("swi-active"))} onclick={Callback::from(move |_| {
+                                    let mut set = (*comparing).clone();
+                                    if !set.remove(&id) {
+                                        set.insert(id.clone());
+                                    }
+                                    comparing.set(set);
+                                })}>
+                                    { if active { "Hide comparison" } else { "Compare with previous" } }
+                                </button>
+                            }
+                        } else {
+                            html! {}
+                        };
+                        let context_action = if msg.composition.is_some() {
+                            let inspecting_context = inspecting_context.clone();
+                            let id = msg.id.clone();
+                            html! {
+                                <button class="swi-msg-action" onclick={Callback::from(move |_| inspecting_context.set(Some(id.clone())))}>
+                                    { "Inspect context" }
+                                </button>
+                            }
+                        } else {
+                            html! {}
+                        };
+                        let edit_action = if msg.role == "user" {
+                            let editing = editing.clone();
+                            let id = msg.id.clone();
+                            let text = msg.typed_text();
+                            html! {
+                                <button
+                                    class="swi-msg-action swi-hover-only"
+                                    disabled={props.is_loading}
+                                    title="Change this message and send it again; later messages are removed"
+                                    onclick={Callback::from(move |_| editing.set(Some((id.clone(), text.clone()))))}
+                                >
+                                    { "✎ Edit" }
+                                </button>
+                            }
+                        } else {
+                            html! {}
+                        };
+                        let copy_action = {
+                            let copied_message = copied_message.clone();
+                            let id = msg.id.clone();
+                            let text = msg.typed_text();
+                            let copied = copied_message.as_deref() == Some(msg.id.as_str());
+                            html! {
+                                <button class="swi-msg-action swi-hover-only" title="Copy the text of this message" onclick={Callback::from(move |_| {
+                                    let copied_message = copied_message.clone();
+                                    let id = id.clone();
+                                    let text = text.clone();
+                                    spawn_local(async move {
+                                        if ExportService::copy_to_clipboard(&text).await.is_ok() {
+                                            copied_message.set(Some(id.clone()));
+                                            gloo_timers::future::TimeoutFuture::new(1500).await;
+                                            copied_message.set(None);
+                                        }
+                                    });
+                                })}>
+                                    { if copied { "Copied!" } else { "⧉ Copy" } }
+                                </button>
+                            }
+                        };
+                        let edit_draft = editing.as_ref().filter(|(id, _)| *id == msg.id).map(|(_, draft)| draft.clone());
+                        let regenerate_action = if last_answer_id.as_deref() == Some(msg.id.as_str()) {
+                            html! {
+                                <button
+                                    class="swi-msg-action"
+                                    disabled={props.is_loading}
+                                    title="Ask the model again; the current answer is kept for comparison"
+                                    onclick={props.on_regenerate.reform(|_| ())}
+                                >
+                                    { "Regenerate" }
+                                </button>
+                            }
+                        } else {
+                            html! {}
+                        };
+                        let exclude_toggle = {
+                            let on_toggle = props.on_toggle_excluded.clone();
+                            let id = msg.id.clone();
+                            html! {
+                                <button
+                                    class={classes!("swi-msg-action", (!msg.excluded).then_some("swi-hover-only"))}
+                                    title="Keep the message but leave it out of what is sent to the model"
+                                    onclick={Callback::from(move |_| on_toggle.emit(id.clone()))}
+                                >
+                                    { if msg.excluded { "Include in context" } else { "Exclude from context" } }
+                                </button>
+                            }
+                        };
+
+                        let is_streaming = streaming_id.as_deref() == Some(msg.id.as_str());
+                        let reasoning_view = match &msg.reasoning {
+                            Some(reasoning) => {
+                                let key = (msg.id.clone(), reasoning.finished);
+                                let expanded = expanded_reasoning.contains(&key);
+                                let thinking_now = is_streaming && !reasoning.finished;
+                                let label = if thinking_now {
+                                    format!("Thinking… {} characters", format_count(reasoning.text.chars().count()))
+                                } else if !reasoning.finished {
+                                    "Reasoning (unfinished)".to_string()
+                                } else {
+                                    "Reasoning".to_string()
+                                };
+                                let expanded_reasoning = expanded_reasoning.clone();
+                                html! {
+                                    <div class="swi-reasoning">
+                                        <button class="swi-reasoning-toggle" aria-expanded={expanded.to_string()} onclick={Callback::from(move |_| {
+                                            let mut set = (*expanded_reasoning).clone();
+                                            if !set.remove(&key) {
+                                                set.insert(key.clone());
+                                            }
+                                            expanded_reasoning.set(set);
+                                        })}>
+                                            if thinking_now {
+                                                <span class="swi-reasoning-spinner"></span>
+                                            }
+                                            { if expanded { "▾ " } else { "▸ " } }
+                                            { label }
+                                        </button>
+                                        if expanded {
+                                            <div class="swi-reasoning-body">{ render_markdown(&reasoning.text) }</div>
+                                        }
+                                    </div>
+                                }
+                            }
+                            None => html! {},
+                        };
+                        let answer = msg.content.as_str();
+                        let is_selected = selected.contains(&msg.id);
+                        let select_box = if props.selection_mode {
+                            let selected = selected.clone();
+                            let id = msg.id.clone();
+                            html! {
+                                <input type="checkbox" class="swi-select-box" checked={is_selected} onchange={Callback::from(move |_| {
+                                    let mut set = (*selected).clone();
+                                    if !set.remove(&id) {
+                                        set.insert(id.clone());
+                                    }
+                                    selected.set(set);
+                                })} />
+                            }
                         } else {
-                            ("assistant", bot_icon.clone())
+                            html! {}
                         };
 
                         html! {
-                            <div class={format!("message-row {}", role_cls)}>
-                                <div class="bubble-group">
-                                    <div class={format!("avatar {}", avatar_cls)}>{ icon }</div>
-                                    <div class="msg-bubble">{ render_markdown(&msg.content) }</div>
+                            <key={msg.id.clone()}>
+                                { divider }
+                                { event_toggle }
+                                <div
+                                    id={format!("msg-{}", msg.id)}
+                                    class={classes!(
+                                        "swi-message-row",
+                                        role_cls,
+                                        is_selected.then_some("swi-selected"),
+                                        msg.excluded.then_some("swi-excluded"),
+                                        (props.focus_message.as_deref() == Some(msg.id.as_str())).then_some("swi-focused")
+                                    )}
+                                >
+                                    <div class="swi-bubble-group">
+                                        { select_box }
+                                        <div class={classes!("swi-avatar", avatar_cls, is_streaming.then_some("swi-typing"))}>{ icon }</div>
+                                        <div class="swi-msg-bubble" onclick={on_code_copy.clone()}>
+                                            { reasoning_view }
+                                            if let Some(draft) = edit_draft {
+                                                <div class="swi-edit-message">
+                                                    <textarea
+                                                        class="swi-edit-input"
+                                                        rows="4"
+                                                        value={draft.clone()}
+                                                        oninput={{
+                                                            let editing = editing.clone();
+                                                            let id = msg.id.clone();
+                                                            Callback::from(move |e: InputEvent| {
+                                                                let value = e.target_unchecked_into::<HtmlTextAreaElement>().value();
+                                                                editing.set(Some((id.clone(), value)));
+                                                            })
+                                                        }}
+                                                    />
+                                                    <div class="swi-edit-actions">
+                                                        <button class="swi-btn swi-mini-btn" onclick={{
+                                                            let editing = editing.clone();
+                                                            Callback::from(move |_| editing.set(None))
+                                                        }}>{ "Cancel" }</button>
+                                                        <button
+                                                            class="swi-btn swi-mini-btn swi-btn-primary"
+                                                            disabled={draft.trim().is_empty() || props.is_loading}
+                                                            onclick={{
+                                                                let editing = editing.clone();
+                                                                let on_edit = props.on_edit.clone();
+                                                                let id = msg.id.clone();
+                                                                Callback::from(move |_| {
+                                                                    on_edit.emit((id.clone(), draft.clone()));
+                                                                    editing.set(None);
+                                                                })
+                                                            }}
+                                                        >{ "Save & send" }</button>
+                                                    </div>
+                                                </div>
+                                            } else if let Some(previous) = msg.previous.as_ref().filter(|_| comparing.contains(&msg.id) && !is_streaming) {
+                                                <DiffView previous={previous.clone()} current={msg.content.clone()} />
+                                            } else if let Some(tokens) = msg.logprobs.as_ref().filter(|_| inspecting.contains(&msg.id)) {
+                                                { render_logprobs(tokens) }
+                                            } else if !msg.document_refs.is_empty() {
+                                                <div onclick={{
+                                                    // Chips are plain markup inside the rendered Markdown
+                                                    let viewing = viewing_document.clone();
+                                                    Callback::from(move |e: MouseEvent| {
+                                                        let chip = e.target_dyn_into::<Element>().and_then(|el| el.closest("[data-document-id]").ok().flatten());
+                                                        if let Some(id) = chip.and_then(|c| c.get_attribute("data-document-id")) {
+                                                            viewing.set(Some(id));
+                                                        }
+                                                    })
+                                                }}>
+                                                    {{
+                                                        let (content, chips) = with_document_chips(&msg.content, &msg.document_refs, &documents);
+                                                        render_markdown_with_chips(&content, &chips)
+                                                    }}
+                                                </div>
+                                            } else if let Some((content, chips)) = (msg.role == "assistant").then(|| with_reference_chips(answer, &documents)).flatten() {
+                                                <div onclick={{
+                                                    // Attaches the document the model asked for to the next message
+                                                    let input_text = input_text.clone();
+                                                    Callback::from(move |e: MouseEvent| {
+                                                        let chip = e.target_dyn_into::<Element>().and_then(|el| el.closest("[data-attach-document-id]").ok().flatten());
+                                                        if let Some(id) = chip.and_then(|c| c.get_attribute("data-attach-document-id")) {
+                                                            let mention = format!("@{}", id);
+                                                            if !input_text.contains(&mention) {
+                                                                input_text.set(format!("{} {} ", input_text.trim_end(), mention).trim_start().to_string());
+                                                            }
+                                                        }
+                                                    })
+                                                }}>
+                                                    { render_markdown_with_chips(&content, &chips) }
+                                                </div>
+                                            } else {
+                                                { render_markdown(answer) }
+                                            }
+                                            if msg.interrupted {
+                                                <div class="swi-interrupted-note">{ "Generation stopped" }</div>
+                                            }
+                                            if !msg.undecoded_chunks.is_empty() {
+                                                <details class="swi-decode-warning">
+                                                    <summary>
+                                                        { format!(
+                                                            "{} chunk{} could not be decoded — the response may be incomplete",
+                                                            msg.undecoded_chunks.len(),
+                                                            if msg.undecoded_chunks.len() == 1 { "" } else { "s" }
+                                                        ) }
+                                                    </summary>
+                                                    <pre>{ msg.undecoded_chunks.join("\n") }</pre>
+                                                </details>
+                                            }
+                                            if !msg.failed_attempts.is_empty() {
+                                                <details class="swi-failed-attempts">
+                                                    <summary>
+                                                        { format!(
+                                                            "{} failed attempt{}",
+                                                            msg.failed_attempts.len(),
+                                                            if msg.failed_attempts.len() == 1 { "" } else { "s" }
+                                                        ) }
+                                                    </summary>
+                                                    { for msg.failed_attempts.iter().rev().map(|attempt| html! {
+                                                        <div class="swi-failed-attempt">
+                                                            <div class="swi-failed-attempt-time" title={format_absolute(attempt.at)}>{ format_relative_time(attempt.at, now) }</div>
+                                                            <div class="swi-failed-attempt-text">{ &attempt.content }</div>
+                                                        </div>
+                                                    }) }
+                                                </details>
+                                            }
+                                            if msg.excluded {
+                                                <div class="swi-excluded-badge">{ "Not sent to model" }</div>
+                                            }
+                                            if let Some(omitted) = msg.composition.as_ref().map(|c| c.omitted_messages).filter(|n| *n > 0) {
+                                                <div class="swi-excluded-badge" title="The conversation no longer fits the model's context. Change how it is fitted in the settings.">
+                                                    { format!("{} older message{} left out", omitted, if omitted == 1 { "" } else { "s" }) }
+                                                </div>
+                                            }
+                                            if msg.automated {
+                                                <div class="swi-excluded-badge" title="Sent by a recurring prompt">{ "Automated" }</div>
+                                            }
+                                            <div class="swi-msg-actions">
+                                                { logprobs_toggle }
+                                                { compare_toggle }
+                                                { context_action }
+                                                { copy_action }
+                                                { edit_action }
+                                                { regenerate_action }
+                                                { exclude_toggle }
+                                            </div>
+                                            if let Some(metrics) = msg.metrics.filter(|_| msg.role == "assistant" && !is_streaming) {
+                                                <div class="swi-msg-metrics" title={metrics.details()}>{ metrics.summary() }</div>
+                                            }
+                                            if let Some(at) = msg.created_at {
+                                                <div class="swi-msg-time" title={format_absolute(at)}>{ format_relative_time(at, now) }</div>
+                                            }
+                                        </div>
+                                        if is_streaming {
+                                            <button class="swi-msg-stop" title="Stop this response" onclick={props.on_stop.reform(|_| ())}>{ "■" }</button>
+                                        }
+                                    </div>
                                 </div>
-                            </div>
+                            </>
                         }
                     }
                 })}
 
-                if props.is_loading {
-                    <div class="message-row assistant">
-                        <div class="bubble-group">
-                            <div class="avatar assistant">{ bot_icon.clone() }</div>
-                            <div class="msg-bubble" style="color: #888; font-style: italic;">
-                                { "Thinking..." }
+                if !props.is_loading && !props.messages.iter().any(|m| m.role == "assistant") {
+                    { props.empty_state.clone() }
+                }
+                if props.is_loading && streaming_id.is_none() {
+                    <div class="swi-message-row swi-assistant">
+                        <div class="swi-bubble-group">
+                            <div class="swi-avatar swi-assistant swi-typing">{ bot_icon.clone() }</div>
+                            <div class="swi-msg-bubble" style="color: #888; font-style: italic;">
+                                if let Some((retry, retries)) = props.retrying {
+                                    { format!("Retrying ({}/{})...", retry, retries) }
+                                } else {
+                                    { "Thinking..." }
+                                }
+                            </div>
+                            <button class="swi-msg-stop" title="Stop this response" onclick={props.on_stop.reform(|_| ())}>{ "■" }</button>
+                        </div>
+                    </div>
+                }
+                if let Some(seconds) = props.stall_warning.filter(|_| props.is_loading) {
+                    <div class="swi-stall-warning" role="status">
+                        <span>{ format!("No response from the server for {}s — it may be loading the model.", seconds) }</span>
+                        <button class="swi-btn swi-mini-btn" onclick={props.on_keep_waiting.reform(|_| ())}>{ "Keep waiting" }</button>
+                        <button class="swi-btn swi-mini-btn" onclick={props.on_stop.reform(|_| ())}>{ "Cancel" }</button>
+                    </div>
+                }
+                if props.empty_response && !props.is_loading {
+                    <div class="swi-message-row swi-assistant">
+                        <div class="swi-bubble-group">
+                            <div class="swi-avatar swi-assistant">{ bot_icon.clone() }</div>
+                            <div class="swi-msg-bubble swi-empty-response" role="status">
+                                <span>{ "The model returned an empty response" }</span>
+                                <button class="swi-btn swi-mini-btn" onclick={props.on_retry.reform(|_| ())}>{ "Retry" }</button>
+                                <button class="swi-btn-icon" title="Dismiss" onclick={props.on_dismiss_empty.reform(|_| ())}>{ "×" }</button>
                             </div>
                         </div>
                     </div>
                 }
+                // After the partial answer of a stream that broke off
+                if let Some(error) = props.request_error.as_ref().filter(|_| !props.is_loading) {
+                    <div class="swi-message-row swi-assistant">
+                        <div class="swi-bubble-group">
+                            <div class="swi-avatar swi-assistant">{ bot_icon.clone() }</div>
+                            <div class="swi-msg-bubble swi-request-error" role="alert">
+                                <span>{ error.to_string() }</span>
+                                <div class="swi-request-error-actions">
+                                    <button
+                                        class="swi-btn swi-mini-btn"
+                                        title={(!error.is_retryable()).then_some("Sent as it is, the request will most likely fail again")}
+                                        onclick={props.on_retry.reform(|_| ())}
+                                    >{ "Retry" }</button>
+                                    if let Some(field) = error.settings_field() {
+                                        <button class="swi-btn swi-mini-btn" onclick={props.on_open_setting.reform(move |_| field.to_string())}>{ "Open settings" }</button>
+                                    }
+                                </div>
+                                <button class="swi-btn-icon swi-request-error-close" title="Dismiss" onclick={props.on_dismiss_error.reform(|_| ())}>{ "×" }</button>
+                            </div>
+                        </div>
+                    </div>
+                }
+                { for queued.iter().map(|msg| {
+                    let on_remove = props.on_remove_queued.clone();
+                    let id = msg.id.clone();
+                    html! {
+                        <div key={msg.id.clone()} class="swi-message-row swi-user swi-queued">
+                            <div class="swi-bubble-group">
+                                <div class="swi-avatar swi-user">{ user_icon.clone() }</div>
+                                <div class="swi-msg-bubble">
+                                    { render_markdown(&msg.content) }
+                                    if msg.awaiting_server {
+                                        <div class="swi-excluded-badge" title="Sent once the server answers again">{ "Queued — server unreachable" }</div>
+                                        <button class="swi-btn swi-mini-btn" onclick={props.on_retry_waiting.reform(|_| ())}>{ "Retry now" }</button>
+                                    } else {
+                                        <div class="swi-excluded-badge" title="Sent once the response above is finished">{ "Queued" }</div>
+                                    }
+                                </div>
+                                <button class="swi-msg-stop" title="Remove from the queue" onclick={Callback::from(move |_| on_remove.emit(id.clone()))}>{ "✕" }</button>
+                            </div>
+                        </div>
+                    }
+                })}
             </div>
 
-            <div class="input-wrapper">
-                <form class="input-container" onsubmit={on_submit}>
+            <div class="swi-input-wrapper">
+                { selection_bar }
+                { send_confirmation }
+                { missing_notice }
+                { review_panel }
+                { paste_prompt }
+                { similar_hint }
+                { document_hint }
+                <form class="swi-input-container" onsubmit={on_submit}>
+                    { context_pill }
+                    if let Some(doc) = &selected_document {
+                        <div class="swi-context-pill-row">
+                            <span class="swi-context-chip swi-selected-doc-chip">
+                                { format!("Using {} for context", doc.filename) }
+                                <button type="button" class="swi-context-chip-remove" title="Stop using this document" onclick={props.on_clear_selected_document.reform(|_| ())}>{ "×" }</button>
+                            </span>
+                        </div>
+                    }
+                    <div class="swi-input-toolbar">
+                        <button
+                            type="button"
+                            class={if props.history_window.is_some() { "swi-toolbar-btn swi-active" } else { "swi-toolbar-btn" }}
+                            onclick={toggle_popover(ToolbarPopover::History)}
+                            title="Choose how much of this chat is sent to the model"
+                        >
+                            { history_label }
+                        </button>
+                        { history_popover }
+                        <button
+                            type="button"
+                            class={if props.response_language.is_some() { "swi-toolbar-btn swi-active" } else { "swi-toolbar-btn" }}
+                            onclick={toggle_popover(ToolbarPopover::Language)}
+                            title="Language the model is asked to answer in"
+                        >
+                            { format!("🌐 {}", props.response_language.as_deref().unwrap_or("Auto")) }
+                        </button>
+                        { language_popover }
+                        <button
+                            type="button"
+                            class={if props.response_length.is_some() { "swi-toolbar-btn swi-active" } else { "swi-toolbar-btn" }}
+                            onclick={toggle_popover(ToolbarPopover::Length)}
+                            title={format!("How long answers should be: {}", response_length.describe())}
+                        >
+                            { format!("Length: {}", response_length.label()) }
+                        </button>
+                        { length_popover }
+                        <button
+                            type="button"
+                            class={if props.model_override.is_some() || props.temperature_override.is_some() { "swi-toolbar-btn swi-active" } else { "swi-toolbar-btn" }}
+                            onclick={toggle_popover(ToolbarPopover::Model)}
+                            title={format!(
+                                "Model and temperature for this chat: {}, {:.1}",
+                                props.model_override.as_deref().unwrap_or(&props.default_model), temperature
+                            )}
+                        >
+                            { format!("Model: {}", props.model_override.as_deref().unwrap_or("Default")) }
+                        </button>
+                        { model_popover }
+                        <button
+                            type="button"
+                            class={if props.prefill.is_some() { "swi-toolbar-btn swi-active" } else { "swi-toolbar-btn" }}
+                            onclick={toggle_popover(ToolbarPopover::Prefill)}
+                            title="Text the answer has to start with, e.g. to force a format"
+                        >
+                            { match &props.prefill {
+                                Some(p) if p.pinned => "Prefill: pinned",
+                                Some(_) => "Prefill: next message",
+                                None => "Prefill",
+                            } }
+                        </button>
+                        { prefill_popover }
+                        if !input_text.is_empty() {
+                            <span class="swi-input-count" title={format!(
+                                "~{} tokens · {} words · {} characters",
+                                format_count(input_counts.tokens), format_count(input_counts.words), format_count(input_counts.chars)
+                            )}>
+                                { match props.count_unit {
+                                    CountUnit::Tokens => format!("~{} tokens", format_count(input_counts.tokens)),
+                                    CountUnit::Words => format!("{} words · {} characters", format_count(input_counts.words), format_count(input_counts.chars)),
+                                } }
+                            </span>
+                        }
+                        { context_meter }
+                    </div>
                     <textarea
-                        class="chat-input"
+                        ref={input_ref.clone()}
+                        class="swi-chat-input"
                         rows="1"
                         placeholder="Message Local LLM..."
                         value={(*input_text).clone()}
                         oninput={on_input}
+                        onpaste={on_paste}
                         onkeydown={on_keydown}
-                        disabled={props.is_loading}
+                        onblur={on_input_blur}
+                        disabled={context_review.is_some()}
                         style="height: 50px; overflow-y: hidden;"
                     />
-                    { mention_dropdown }
+                    { picker_popup }
 
-                    if props.is_loading {
+                    if props.is_loading && input_text.is_empty() {
                         <button
                             type="button"
-                            class="send-btn"
+                            class="swi-send-btn"
                             style="background: var(--danger-color);"
-                            onclick={props.on_stop.reform(|_| ())}
+                            title={if props.active_generations > 1 { "Stop the responses being generated in every chat and drop queued messages" } else { "Stop the response" }}
+                            onclick={on_stop_button.reform(|_| ())}
                         >
-                            { "Stop" }
+                            { stop_label }
                         </button>
                     } else {
-                        <button type="submit" class="send-btn" disabled={input_text.is_empty()}>
-                            { "Send" }
+                        <button type="submit" class="swi-send-btn" disabled={input_text.is_empty()} title={props.is_loading.then_some("Send once the current response is finished")}>
+                            { if props.is_loading { "Queue" } else { "Send" } }
                         </button>
                     }
                 </form>
             </div>
+            { oversize_dialog }
+            if let Some(composition) = inspecting_context.as_ref().and_then(|id| props.messages.iter().find(|m| m.id == *id)).and_then(|m| m.composition.clone()) {
+                <ContextInspector
+                    composition={composition}
+                    on_close={{
+                        let inspecting_context = inspecting_context.clone();
+                        Callback::from(move |_| inspecting_context.set(None))
+                    }}
+                />
+            }
+            if let Some(doc) = viewing_document.as_ref().and_then(|id| documents.iter().find(|d| d.id == *id)) {
+                <DocumentViewer
+                    document={doc.clone()}
+                    embedding_model={props.embedding_model.clone()}
+                    on_close={{
+                        let viewing = viewing_document.clone();
+                        Callback::from(move |_| viewing.set(None))
+                    }}
+                />
+            }
         </>
     }
 }
+
+/// Replaces each reference placeholder with a marker for an inline chip,
+/// returned alongside for `render_markdown_with_chips`. References to
+/// documents that no longer exist get a struck-through chip instead.
+/// References are in order of first mention, which tells apart documents
+/// with the same name; a later mention is the same document again.
+fn with_document_chips(content: &str, refs: &[DocumentRef], documents: &[Document]) -> (String, Vec<String>) {
+    let chips: Vec<String> = refs.iter().map(|r| document_chip(r, documents)).collect();
+    let content = strip_chip_marks(content);
+    let mut out = String::new();
+    let mut rest = content.as_str();
+    for (i, r) in refs.iter().enumerate() {
+        let placeholder = r.placeholder();
+        let Some(pos) = rest.find(&placeholder) else { continue };
+        out.push_str(&rest[..pos]);
+        out.push_str(&chip_marker(i));
+        rest = &rest[pos + placeholder.len()..];
+    }
+    out.push_str(rest);
+    for (i, r) in refs.iter().enumerate().rev() {
+        out = out.replace(&r.placeholder(), &chip_marker(i));
+    }
+    (out, chips)
+}
+
+fn document_chip(r: &DocumentRef, documents: &[Document]) -> String {
+    let name = escape_html(&r.filename);
+    match documents.iter().find(|d| d.id == r.id) {
+        Some(doc) => format!(
+            r#"<span class="swi-doc-chip" data-document-id="{}" title="Open {}"><span class="swi-doc-chip-type swi-{}">{}</span>{}</span>"#,
+            escape_html(&r.id), name, doc.file_type.to_lowercase(), escape_html(&doc.file_type), name
+        ),
+        None => format!(
+            r#"<span class="swi-doc-chip swi-missing" title="This document has been deleted">{}</span>"#,
+            name
+        ),
+    }
+}
+
+/// `@file name` and `@id` mentions of existing documents in an answer, as
+/// markers for chips that attach the document to the next message; `None`
+/// when there are none
+fn with_reference_chips(content: &str, documents: &[Document]) -> Option<(String, Vec<String>)> {
+    let mut out = strip_chip_marks(content);
+    let mut chips = Vec::new();
+    // Longest names first, so "@notes.md" doesn't claim part of "@notes.md.txt"
+    let mut candidates: Vec<&Document> = documents.iter().filter(|d| d.enabled).collect();
+    candidates.sort_by_key(|d| std::cmp::Reverse(d.filename.len()));
+    for doc in candidates {
+        let name = escape_html(&doc.filename);
+        let chip = format!(
+            r#"<span class="swi-doc-chip swi-doc-attach" data-attach-document-id="{}" title="Attach {} to your next message"><span class="swi-doc-chip-type swi-{}">{}</span>{}</span>"#,
+            escape_html(&doc.id), name, doc.file_type.to_lowercase(), escape_html(&doc.file_type), name
+        );
+        let marker = chip_marker(chips.len());
+        let mut found = false;
+        for mention in [format!("@{}", doc.filename), format!("@{}", doc.id)] {
+            if out.contains(&mention) {
+                out = out.replace(&mention, &marker);
+                found = true;
+            }
+        }
+        if found {
+            chips.push(chip);
+        }
+    }
+    (!chips.is_empty()).then_some((out, chips))
+}
+
+/// The trigger and query typed right before the cursor, as the byte offset
+/// of the trigger and the query. The trigger must start the input or follow
+/// whitespace, so paths and email addresses don't open a picker.
+fn trigger_query(text: &str, cursor: usize, trigger: char) -> Option<(usize, String)> {
+    let before = text.get(..cursor)?;
+    let start = before.rfind(trigger)?;
+    let query = &before[start + 1..];
+    let at_word_start = before[..start].chars().next_back().is_none_or(char::is_whitespace);
+    if !at_word_start || query.chars().any(char::is_whitespace) {
+        return None;
+    }
+    Some((start, query.to_string()))
+}
+
+/// Saved prompts whose name contains the query, those starting with it first
+fn matching_prompts<'a>(prompts: &'a [SavedPrompt], query: &str) -> Vec<&'a SavedPrompt> {
+    let query = query.to_lowercase();
+    let mut matches: Vec<&SavedPrompt> = prompts.iter().filter(|p| p.name.to_lowercase().contains(&query)).collect();
+    matches.sort_by_key(|p| !p.name.to_lowercase().starts_with(&query));
+    matches
+}
+
+/// Documents whose name contains the query, those starting with it first
+fn matching_documents<'a>(documents: &'a [Document], query: &str) -> Vec<&'a Document> {
+    let query = query.to_lowercase();
+    let mut matches: Vec<&Document> = documents.iter().filter(|d| d.filename.to_lowercase().contains(&query)).collect();
+    matches.sort_by_key(|d| !d.filename.to_lowercase().starts_with(&query));
+    matches
+}
+
+/// Replaces the `@filename` mentions of the input with the `@id` ones the
+/// document context is built from. Longer names go first, so `@notes.md`
+/// doesn't claim part of `@notes.md.txt`.
+fn expand_mentions(text: &str, mentions: &[DocumentRef]) -> String {
+    let mut sorted: Vec<&DocumentRef> = mentions.iter().collect();
+    sorted.sort_by_key(|m| std::cmp::Reverse(m.filename.len()));
+    sorted.iter().fold(text.to_string(), |text, m| {
+        let mention = m.mention();
+        let mut out = String::with_capacity(text.len());
+        let mut rest = 0;
+        for start in mention_positions(&text, &mention) {
+            out.push_str(&text[rest..start]);
+            out.push('@');
+            out.push_str(&m.id);
+            rest = start + mention.len();
+        }
+        out.push_str(&text[rest..]);
+        out
+    })
+}
+
+/// Where `mention` stands in `text` as a token of its own: at the start or
+/// after whitespace, and not followed by more of a file name, so `@notes.md`
+/// is found in "see @notes.md." but not in `@notes.md.txt` or `x@notes.md`.
+fn mention_positions(text: &str, mention: &str) -> Vec<usize> {
+    let name_char = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-');
+    text.match_indices(mention)
+        .map(|(start, _)| start)
+        .filter(|&start| {
+            let mut after = text[start + mention.len()..].chars();
+            let ends = match after.next() {
+                Some('.') => after.next().is_none_or(|c| !name_char(c) && c != '.'),
+                Some(c) => !name_char(c),
+                None => true,
+            };
+            ends && text[..start].chars().next_back().is_none_or(char::is_whitespace)
+        })
+        .collect()
+}
+
+/// Start of the mention that ends at the cursor, if there is one
+fn mention_before(text: &str, cursor: usize, mentions: &[DocumentRef]) -> Option<usize> {
+    let before = text.get(..cursor)?;
+    mentions
+        .iter()
+        .map(|m| m.mention())
+        .filter(|mention| before.ends_with(mention.as_str()))
+        .map(|mention| cursor - mention.len())
+        .filter(|start| before[..*start].chars().next_back().is_none_or(char::is_whitespace))
+        .min()
+}
+
+/// Byte index in `text` of a UTF-16 offset, as reported by DOM selection APIs
+fn utf16_to_byte_index(text: &str, offset: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in text.char_indices() {
+        if units >= offset {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+/// A typed custom token limit, if it is a usable one
+fn custom_max_tokens(value: &str) -> Option<u32> {
+    value.trim().parse().ok().filter(|tokens| *tokens > 0)
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// Tokens colored from red (unlikely) to green (certain), alternatives in the tooltip
+fn render_logprobs(tokens: &[TokenLogprob]) -> Html {
+    html! {
+        <div class="swi-logprob-view">
+            { for tokens.iter().map(|t| {
+                let p = t.logprob.exp();
+                let style = format!("background: hsla({:.0}, 80%, 60%, 0.35);", p * 120.0);
+                let mut title = format!("{:?}: {:.1}%", t.token, p * 100.0);
+                for alt in &t.top_logprobs {
+                    title.push_str(&format!("\n  {:?}: {:.1}%", alt.token, alt.logprob.exp() * 100.0));
+                }
+                html! { <span class="swi-logprob-token" style={style} title={title}>{ &t.token }</span> }
+            })}
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::markdown_html;
+
+    #[test]
+    fn trigger_query_needs_a_word_start() {
+        assert_eq!(trigger_query("/sum", 4, '/'), Some((0, "sum".to_string())));
+        assert_eq!(trigger_query("see /sum more", 8, '/'), Some((4, "sum".to_string())));
+        assert_eq!(trigger_query("see /", 5, '/'), Some((4, String::new())));
+        // Paths, emails and finished words don't open a picker
+        assert_eq!(trigger_query("src/main", 8, '/'), None);
+        assert_eq!(trigger_query("me@example.com", 14, '@'), None);
+        assert_eq!(trigger_query("/sum done", 9, '/'), None);
+        assert_eq!(trigger_query("/sum", 9, '/'), None);
+    }
+
+    #[test]
+    fn trigger_query_handles_multibyte_text() {
+        let text = "héllo @nö";
+        assert_eq!(trigger_query(text, text.len(), '@'), Some((7, "nö".to_string())));
+        assert_eq!(utf16_to_byte_index(text, 9), text.len());
+        assert_eq!(utf16_to_byte_index(text, 2), 3);
+    }
+
+    #[test]
+    fn matching_prompts_puts_prefix_matches_first() {
+        let prompt = |name: &str| SavedPrompt { id: name.to_string(), name: name.to_string(), content: String::new() };
+        let prompts = [prompt("Email summary"), prompt("Summarize"), prompt("Translate")];
+        let names = |query: &str| matching_prompts(&prompts, query).iter().map(|p| p.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names("sum"), ["Summarize", "Email summary"]);
+        assert_eq!(names(""), ["Email summary", "Summarize", "Translate"]);
+        assert!(names("xyz").is_empty());
+    }
+
+    fn mention(id: &str, filename: &str) -> DocumentRef {
+        DocumentRef { id: id.to_string(), filename: filename.to_string() }
+    }
+
+    #[test]
+    fn expand_mentions_matches_whole_mentions_only() {
+        let mentions = [mention("a1", "notes.md"), mention("b2", "notes.md.txt")];
+        assert_eq!(expand_mentions("see @notes.md and @notes.md.txt", &mentions), "see @a1 and @b2");
+        assert_eq!(expand_mentions("@notes.md, then @notes.md.", &mentions), "@a1, then @a1.");
+        assert_eq!(expand_mentions("mail me@notes.md or @notes.mdx", &mentions[..1]), "mail me@notes.md or @notes.mdx");
+        assert_eq!(expand_mentions("(@notes.md)", &mentions[..1]), "(@notes.md)");
+    }
+
+    #[test]
+    fn mention_before_finds_the_mention_ending_at_the_cursor() {
+        let mentions = [mention("a1", "notes.md"), mention("b2", "md")];
+        let text = "read @notes.md now";
+        assert_eq!(mention_before(text, 14, &mentions), Some(5));
+        assert_eq!(mention_before(text, 13, &mentions), None);
+        assert_eq!(mention_before("x@notes.md", 10, &mentions), None);
+        assert_eq!(mention_before("@md", 3, &mentions), Some(0));
+    }
+
+    #[test]
+    fn trimmed_text_fits_the_limit() {
+        // Dense text at the start and sparse text at the end throw off the first guess
+        let text = format!("{}{}", "数据库连接失败，请重试。".repeat(200), " the end of a long paste".repeat(400));
+        for limit in [40, 300, 2000] {
+            let trimmed = trim_middle(&text, limit);
+            assert!(count_tokens(&trimmed) <= limit, "{} tokens over a limit of {}", count_tokens(&trimmed), limit);
+            assert!(trimmed.contains("[… trimmed …]"));
+        }
+        let trimmed = trim_middle(&text, 300);
+        assert!(trimmed.starts_with("数据库"));
+        assert!(trimmed.ends_with("long paste"));
+    }
+
+    #[test]
+    fn custom_max_tokens_ignores_unfinished_input() {
+        assert_eq!(custom_max_tokens(" 512 "), Some(512));
+        assert_eq!(custom_max_tokens(""), None);
+        assert_eq!(custom_max_tokens("0"), None);
+        assert_eq!(custom_max_tokens("-"), None);
+        assert_eq!(custom_max_tokens("1e3"), None);
+    }
+
+    fn doc(id: &str, filename: &str) -> Document {
+        Document { id: id.to_string(), filename: filename.to_string(), file_type: "pdf".to_string(), ..Default::default() }
+    }
+
+    fn reference(d: &Document) -> DocumentRef {
+        DocumentRef { id: d.id.clone(), filename: d.filename.clone() }
+    }
+
+    #[test]
+    fn same_named_documents_get_their_own_chips() {
+        let (a, b) = (doc("a1", "report.pdf"), doc("b2", "report.pdf"));
+        // In order of first mention
+        let refs = [reference(&b), reference(&a)];
+        let content = format!("Compare {} with {}", refs[0].placeholder(), refs[1].placeholder());
+        assert!(!content.contains("b2"), "ids stay out of the message");
+        let (content, chips) = with_document_chips(&content, &refs, &[a, b]);
+        let html = markdown_html(&content, &chips);
+        let first = html.find(r#"data-document-id="b2""#).unwrap();
+        let second = html.find(r#"data-document-id="a1""#).unwrap();
+        assert!(first < second);
+    }
+
+    #[test]
+    fn a_document_mentioned_twice_gets_a_chip_each_time() {
+        let a = doc("a1", "notes.pdf");
+        let content = format!("{0} and again {0}", reference(&a).placeholder());
+        let (content, chips) = with_document_chips(&content, &[reference(&a)], &[a]);
+        let html = markdown_html(&content, &chips);
+        assert_eq!(html.matches(r#"data-document-id="a1""#).count(), 2);
+    }
+
+    #[test]
+    fn name_only_placeholders_still_become_chips() {
+        let a = doc("a1", "notes.pdf");
+        let (content, chips) = with_document_chips("See [Document: notes.pdf]", &[reference(&a)], &[a]);
+        let html = markdown_html(&content, &chips);
+        assert!(html.contains(r#"data-document-id="a1""#));
+        assert!(!html.contains("[Document:"));
+    }
+
+    #[test]
+    fn chips_in_the_content_are_not_trusted() {
+        let a = Document { enabled: true, ..doc("a1", "notes.pdf") };
+        let forged = r#"<span class="swi-doc-chip" data-attach-document-id="x">x</span> and a marker \u{E000}0\u{E000} @notes.pdf"#;
+        let (content, chips) = with_reference_chips(forged, &[a]).unwrap();
+        let html = markdown_html(&content, &chips);
+        assert!(!html.contains(r#"data-attach-document-id="x""#));
+        assert_eq!(html.matches("data-attach-document-id").count(), 1);
+    }
+}
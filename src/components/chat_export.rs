@@ -0,0 +1,103 @@
+use yew::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+use crate::models::{AppSettings, ChatSession};
+use crate::services::export::{ChatFormat, ExportService};
+use crate::utils::inject_styles;
+
+// Lines of the export shown before copying or downloading
+const PREVIEW_LINES: usize = 40;
+
+#[derive(Properties, PartialEq)]
+pub struct ChatExportProps {
+    pub chat: ChatSession,
+    pub settings: AppSettings,
+    pub on_close: Callback<()>,
+}
+
+/// Exports one chat in a model's prompt format, as it would be sent
+#[function_component(ChatExport)]
+pub fn chat_export(props: &ChatExportProps) -> Html {
+    let format = use_state(|| ChatFormat::ChatMl);
+    let copied = use_state(|| None::<bool>);
+
+    let content = ExportService::chat_to_format(&props.chat, &props.settings, *format);
+    let line_count = content.lines().count();
+    let preview = content.lines().take(PREVIEW_LINES).collect::<Vec<_>>().join("\n");
+
+    let on_format_change = {
+        let format = format.clone();
+        let copied = copied.clone();
+        Callback::from(move |e: Event| {
+            let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+            let index = select.value().parse::<usize>().unwrap_or(0);
+            format.set(ChatFormat::ALL.get(index).copied().unwrap_or(ChatFormat::ChatMl));
+            copied.set(None);
+        })
+    };
+
+    let on_copy = {
+        let content = content.clone();
+        let copied = copied.clone();
+        Callback::from(move |_: MouseEvent| {
+            let content = content.clone();
+            let copied = copied.clone();
+            spawn_local(async move {
+                copied.set(Some(ExportService::copy_to_clipboard(&content).await.is_ok()));
+            });
+        })
+    };
+
+    let on_download = {
+        let content = content.clone();
+        let format = *format;
+        let title = props.chat.title.clone();
+        Callback::from(move |_: MouseEvent| {
+            let _ = ExportService::download(&ExportService::file_name(&title, format.extension()), &content, format.mime());
+        })
+    };
+
+    let css = r#"
+        .swi-export-backdrop { position: absolute; top: 0; left: 0; width: 100%; height: 100%; background: rgba(255,255,255,0.6); backdrop-filter: blur(2px); z-index: 99; cursor: pointer; }
+        .swi-export-panel { position: absolute; top: 60px; left: 50%; transform: translateX(-50%); width: min(720px, 90%); max-height: 80vh; background: white; border: 1px solid var(--border-color); border-radius: 8px; box-shadow: 0 10px 15px -3px rgba(0, 0, 0, 0.1); padding: 20px; z-index: 100; display: flex; flex-direction: column; gap: 10px; font-size: 0.85rem; }
+        .swi-export-header { display: flex; justify-content: space-between; align-items: center; gap: 10px; }
+        .swi-export-header h3 { margin: 0; font-size: 1.05rem; }
+        .swi-export-note { color: var(--text-secondary); font-size: 0.8rem; margin: 0; }
+        .swi-export-preview { flex: 1; overflow-y: auto; white-space: pre-wrap; word-break: break-word; background: #fafafa; border: 1px solid var(--border-color); border-radius: 6px; padding: 10px; margin: 0; font-size: 0.8rem; }
+        .swi-export-actions { display: flex; align-items: center; gap: 8px; }
+        .swi-export-actions select { margin-right: auto; }
+    "#;
+    inject_styles("chat_export", css);
+
+    html! {
+        <>
+            <div class="swi-export-backdrop" onclick={props.on_close.reform(|_| ())}></div>
+            <div class="swi-export-panel">
+                <div class="swi-export-header">
+                    <h3>{ format!("Export \"{}\"", props.chat.title) }</h3>
+                    <button class="swi-btn-icon" title="Close" onclick={props.on_close.reform(|_| ())}>{ "×" }</button>
+                </div>
+                <p class="swi-export-note">
+                    { "Exported as the model receives the chat: custom instructions and the history window apply, excluded messages are left out. Document context is only attached to the message being sent, so it isn't part of the export." }
+                </p>
+                <pre class="swi-export-preview">
+                    { preview }
+                    if line_count > PREVIEW_LINES {
+                        { format!("\n… {} more lines", line_count - PREVIEW_LINES) }
+                    }
+                </pre>
+                <div class="swi-export-actions">
+                    <select class="swi-form-select" title="Export format" onchange={on_format_change}>
+                        { for ChatFormat::ALL.iter().enumerate().map(|(i, f)| html! {
+                            <option value={i.to_string()} selected={*f == *format}>{ f.label() }</option>
+                        })}
+                    </select>
+                    if let Some(ok) = *copied {
+                        <span class="swi-export-note">{ if ok { "Copied" } else { "Could not copy" } }</span>
+                    }
+                    <button class="swi-btn" onclick={on_copy}>{ "Copy" }</button>
+                    <button class="swi-btn" onclick={on_download}>{ "Download" }</button>
+                </div>
+            </div>
+        </>
+    }
+}
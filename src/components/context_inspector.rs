@@ -0,0 +1,90 @@
+use yew::prelude::*;
+use crate::models::RequestComposition;
+use crate::utils::{format_count, inject_styles};
+
+#[derive(Properties, PartialEq)]
+pub struct ContextInspectorProps {
+    pub composition: RequestComposition,
+    pub on_close: Callback<()>,
+}
+
+/// Breakdown of what the model saw for one user message
+#[function_component(ContextInspector)]
+pub fn context_inspector(props: &ContextInspectorProps) -> Html {
+    let c = &props.composition;
+    let total = c.total_tokens.max(1);
+
+    let css = r#"
+        .swi-inspector-backdrop { position: absolute; top: 0; left: 0; width: 100%; height: 100%; background: rgba(255,255,255,0.6); backdrop-filter: blur(2px); z-index: 99; cursor: pointer; }
+        .swi-inspector-panel { position: absolute; top: 60px; left: 50%; transform: translateX(-50%); width: min(560px, 90%); max-height: 80vh; overflow-y: auto; background: white; border: 1px solid var(--border-color); border-radius: 8px; box-shadow: 0 10px 15px -3px rgba(0, 0, 0, 0.1); padding: 20px; z-index: 100; display: flex; flex-direction: column; gap: 12px; font-size: 0.85rem; }
+        .swi-inspector-header { display: flex; justify-content: space-between; align-items: center; }
+        .swi-inspector-header h3 { margin: 0; font-size: 1.05rem; }
+        .swi-inspector-row { display: grid; grid-template-columns: 160px 1fr 70px; gap: 8px; align-items: center; }
+        .swi-inspector-bar { height: 8px; background: #f0f0f0; border-radius: 4px; overflow: hidden; }
+        .swi-inspector-bar div { height: 100%; background: var(--accent-color); }
+        .swi-inspector-tokens { text-align: right; color: var(--text-secondary); }
+        .swi-inspector-label { font-weight: 600; color: var(--text-secondary); margin-bottom: 4px; }
+        .swi-inspector-panel pre { white-space: pre-wrap; word-break: break-word; background: #fafafa; border: 1px solid var(--border-color); border-radius: 6px; padding: 8px; margin: 0; max-height: 200px; overflow-y: auto; }
+        .swi-inspector-panel ul { margin: 0; padding-left: 18px; }
+    "#;
+    inject_styles("context_inspector", css);
+
+    html! {
+        <>
+            <div class="swi-inspector-backdrop" onclick={props.on_close.reform(|_| ())}></div>
+            <div class="swi-inspector-panel">
+                <div class="swi-inspector-header">
+                    <h3>{ "Context sent with this message" }</h3>
+                    <button class="swi-btn-icon" title="Close" onclick={props.on_close.reform(|_| ())}>{ "×" }</button>
+                </div>
+                <div>
+                    { format!("~{} tokens to {}", format_count(c.total_tokens), c.model) }
+                </div>
+                <div>
+                    { for c.sections.iter().map(|s| html! {
+                        <div class="swi-inspector-row">
+                            <span>{ &s.label }</span>
+                            <div class="swi-inspector-bar">
+                                <div style={format!("width: {:.1}%;", s.tokens as f64 * 100.0 / total as f64)}></div>
+                            </div>
+                            <span class="swi-inspector-tokens">{ format_count(s.tokens) }</span>
+                        </div>
+                    }) }
+                </div>
+                if !c.documents.is_empty() {
+                    <div>
+                        <div class="swi-inspector-label">{ "Documents" }</div>
+                        <ul>{ for c.documents.iter().map(|d| html! { <li>{ d }</li> }) }</ul>
+                        if c.deduplicated_tokens > 0 {
+                            <div class="swi-inspector-tokens" style="text-align: left;">
+                                { format!("~{} tokens of overlap between neighbouring chunks left out", format_count(c.deduplicated_tokens)) }
+                            </div>
+                        }
+                    </div>
+                }
+                <div>
+                    <div class="swi-inspector-label">{ "System prompt" }</div>
+                    <pre>{ &c.system_prompt }</pre>
+                </div>
+                if let Some(length) = &c.response_length {
+                    <div>
+                        <div class="swi-inspector-label">{ "Response length" }</div>
+                        <div>{ length }</div>
+                    </div>
+                }
+                if let Some(prefill) = &c.prefill {
+                    <div>
+                        <div class="swi-inspector-label">{ "Response prefill" }</div>
+                        <pre>{ prefill }</pre>
+                    </div>
+                }
+                if let Some(payload) = &c.payload {
+                    <details>
+                        <summary>{ "Full request (debug mode)" }</summary>
+                        <pre>{ payload }</pre>
+                    </details>
+                }
+            </div>
+        </>
+    }
+}
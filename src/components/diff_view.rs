@@ -0,0 +1,43 @@
+use yew::prelude::*;
+use crate::utils::{inject_styles, word_diff, DiffPart};
+
+#[derive(Properties, PartialEq)]
+pub struct DiffViewProps {
+    pub previous: String,
+    pub current: String,
+}
+
+/// Word diff of a regenerated response against the one it replaced; texts too
+/// large to diff cheaply are shown side by side instead. The diff is only
+/// recomputed when either text changes, not on every render of the chat.
+#[function_component(DiffView)]
+pub fn diff_view(props: &DiffViewProps) -> Html {
+    let css = r#"
+        .swi-diff-view { white-space: pre-wrap; line-height: 1.6; }
+        .swi-diff-added { background: #dcfce7; color: #166534; text-decoration: none; }
+        .swi-diff-removed { background: #fee2e2; color: #991b1b; text-decoration: line-through; }
+        .swi-diff-columns { display: grid; grid-template-columns: 1fr 1fr; gap: 10px; }
+        .swi-diff-columns > div { white-space: pre-wrap; font-size: 0.85rem; overflow-x: auto; }
+        .swi-diff-label { font-size: 0.75rem; font-weight: 600; color: var(--text-secondary); margin-bottom: 4px; }
+    "#;
+    inject_styles("diff_view", css);
+
+    let parts = use_memo((props.previous.clone(), props.current.clone()), |(previous, current)| word_diff(previous, current));
+    match parts.as_ref() {
+        Some(parts) => html! {
+            <div class="swi-diff-view">
+                { for parts.iter().map(|part| match part {
+                    DiffPart::Same(text) => html! { { text } },
+                    DiffPart::Added(text) => html! { <ins class="swi-diff-added">{ text }</ins> },
+                    DiffPart::Removed(text) => html! { <del class="swi-diff-removed">{ text }</del> },
+                })}
+            </div>
+        },
+        None => html! {
+            <div class="swi-diff-columns">
+                <div><div class="swi-diff-label">{ "Previous" }</div>{ &props.previous }</div>
+                <div><div class="swi-diff-label">{ "Current" }</div>{ &props.current }</div>
+            </div>
+        },
+    }
+}
@@ -0,0 +1,59 @@
+use yew::prelude::*;
+use crate::models::Document;
+use crate::services::document_service::DocumentService;
+use crate::utils::{count_chars, format_absolute, format_bytes, format_count, inject_styles};
+
+#[derive(Properties, PartialEq)]
+pub struct DocumentViewerProps {
+    pub document: Document,
+    pub on_close: Callback<()>,
+    /// Shows the index status when documents are embedded
+    #[prop_or_default]
+    pub embedding_model: String,
+}
+
+/// Read-only view of an uploaded document's text
+#[function_component(DocumentViewer)]
+pub fn document_viewer(props: &DocumentViewerProps) -> Html {
+    let doc = &props.document;
+    let size = DocumentService::storage_sizes(std::slice::from_ref(doc)).get(&doc.id).copied().unwrap_or(0);
+
+    let css = r#"
+        .swi-viewer-backdrop { position: absolute; top: 0; left: 0; width: 100%; height: 100%; background: rgba(255,255,255,0.6); backdrop-filter: blur(2px); z-index: 99; cursor: pointer; }
+        .swi-viewer-panel { position: absolute; top: 60px; left: 50%; transform: translateX(-50%); width: min(720px, 90%); max-height: 80vh; background: white; border: 1px solid var(--border-color); border-radius: 8px; box-shadow: 0 10px 15px -3px rgba(0, 0, 0, 0.1); padding: 20px; z-index: 100; display: flex; flex-direction: column; gap: 10px; }
+        .swi-viewer-header { display: flex; justify-content: space-between; align-items: center; gap: 10px; }
+        .swi-viewer-header h3 { margin: 0; font-size: 1.05rem; overflow: hidden; text-overflow: ellipsis; white-space: nowrap; }
+        .swi-viewer-meta { font-size: 0.8rem; color: var(--text-secondary); }
+        .swi-viewer-content { flex: 1; overflow-y: auto; white-space: pre-wrap; word-break: break-word; font-size: 0.85rem; background: #fafafa; border: 1px solid var(--border-color); border-radius: 6px; padding: 10px; margin: 0; }
+    "#;
+    inject_styles("document_viewer", css);
+
+    html! {
+        <>
+            <div class="swi-viewer-backdrop" onclick={props.on_close.reform(|_| ())}></div>
+            <div class="swi-viewer-panel">
+                <div class="swi-viewer-header">
+                    <h3 title={doc.filename.clone()}>{ &doc.filename }</h3>
+                    <button class="swi-btn-icon" title="Close" onclick={props.on_close.reform(|_| ())}>{ "×" }</button>
+                </div>
+                <div class="swi-viewer-meta">
+                    { format!(
+                        "{} · {} tokens · {} words · {} characters · {} chunks · {} stored · uploaded {}{}",
+                        doc.file_type,
+                        format_count(doc.total_tokens),
+                        format_count(doc.words()),
+                        format_count(count_chars(&doc.full_content)),
+                        doc.chunk_count,
+                        format_bytes(size),
+                        format_absolute(doc.upload_date),
+                        if doc.enabled { "" } else { " · disabled" }
+                    ) }
+                    if !props.embedding_model.is_empty() {
+                        <span title={doc.index.detail()}>{ format!(" · {}", doc.index.label(&props.embedding_model)) }</span>
+                    }
+                </div>
+                <pre class="swi-viewer-content">{ &doc.full_content }</pre>
+            </div>
+        </>
+    }
+}
@@ -3,31 +3,139 @@ use wasm_bindgen::closure:: Closure;
 use wasm_bindgen::{JsValue, JsCast};
 use web_sys::{window, HtmlInputElement, Event, FileReader, console};
 
-use crate::services::document_service::DocumentService;
+use crate::models::{CountUnit, Document, IndexStatus};
+use crate::services::document_service::{DocumentService, RemovedDocuments};
+use crate::services::error::DocumentError;
+use crate::services::request::RequestConfig;
+use crate::utils::{format_absolute, format_bytes, format_count, format_relative_time};
+
+// How long deleted documents can be brought back
+const UNDO_MS: u32 = 8000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum DocumentSort {
+    Uploaded,
+    Name,
+    Newest,
+    Largest,
+    Storage,
+}
 
 #[derive(Properties, PartialEq)]
 pub struct DocumentsProps {
-    pub on_document_selected: Callback<String>,
+    pub selected_document_id: Option<String>,
+    /// Emits the clicked document, or `None` when the selected one is clicked again
+    pub on_document_selected: Callback<Option<String>>,
+    /// Emits each document once it has been processed and stored
+    #[prop_or_default]
+    pub on_uploaded: Callback<Document>,
+    /// Server documents are indexed with
+    #[prop_or_default]
+    pub request: RequestConfig,
+    /// Model documents are embedded with; empty turns indexing off
+    #[prop_or_default]
+    pub embedding_model: String,
+    /// Emits the ids of deleted documents once their deletion can no longer be undone
+    #[prop_or_default]
+    pub on_deleted: Callback<Vec<String>>,
+    /// Opens the settings at a field, e.g. storage when it is full
+    #[prop_or_default]
+    pub on_open_setting: Callback<String>,
+    /// Ids of the documents added outside this list, to reload it and index them
+    #[prop_or_default]
+    pub added_elsewhere: Vec<String>,
+    /// Whether document sizes are shown in tokens or words
+    #[prop_or_default]
+    pub count_unit: CountUnit,
 }
 
 #[function_component(Documents)]
 pub fn documents(props: &DocumentsProps) -> Html {
-    let documents = use_state(|| vec![]);
-    let selected_doc_id = use_state(|| String::new());
+    let documents = use_state(Vec::<Document>::new);
+    let sort = use_state(|| DocumentSort::Uploaded);
     let is_expanded = use_state(|| false);
+    // (chunks done, total chunks) of the upload being processed
+    let upload_progress = use_state(|| None::<(usize, usize)>);
+    let upload_error = use_state(|| None::<DocumentError>);
+    // Ids of the documents being indexed by this session, in order
+    let index_queue = use_mut_ref(Vec::<String>::new);
+    let filter = use_state(String::new);
+    // Ids of the shown documents waiting for the delete to be confirmed
+    let confirm_delete = use_state(|| None::<Vec<String>>);
+    // The last bulk delete, until its undo expires
+    let undo = use_state(|| None::<RemovedDocuments>);
+    let undo_seq = use_mut_ref(|| 0u32);
+
+
+    // Indexes the documents one at a time; ids already queued are skipped
+    let on_index = {
+        let docs = documents.clone();
+        let index_queue = index_queue.clone();
+        let request = props.request.clone();
+        let model = props.embedding_model.clone();
+        Callback::from(move |ids: Vec<String>| {
+            if model.is_empty() {
+                return;
+            }
+            let idle = {
+                let mut queue = index_queue.borrow_mut();
+                let idle = queue.is_empty();
+                for id in ids {
+                    if !queue.contains(&id) {
+                        queue.push(id);
+                    }
+                }
+                idle
+            };
+            if !idle {
+                return;
+            }
+            let docs = docs.clone();
+            let index_queue = index_queue.clone();
+            let request = request.clone();
+            let model = model.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                loop {
+                    let Some(id) = index_queue.borrow().first().cloned() else { break };
+                    let refresh = {
+                        let docs = docs.clone();
+                        move || docs.set(DocumentService::get_documents())
+                    };
+                    DocumentService::index_document(&id, &request, &model, refresh).await;
+                    index_queue.borrow_mut().retain(|queued| *queued != id);
+                }
+                docs.set(DocumentService::get_documents());
+            });
+        })
+    };
 
-    // Load documents on mount
+    // Load documents on mount and when they were added elsewhere; those are
+    // indexed like uploads, unless indexing them was already tried
     {
         let docs = documents.clone();
-        use_effect_with(() as (), move |_| {
+        let on_index = on_index.clone();
+        use_effect_with(props.added_elsewhere.clone(), move |added| {
             let loaded_docs = DocumentService::get_documents();
+            let unindexed: Vec<String> = added
+                .iter()
+                .filter(|id| loaded_docs.iter().any(|d| d.id == **id && d.index == IndexStatus::NotIndexed))
+                .cloned()
+                .collect();
             docs.set(loaded_docs);
+            if !unindexed.is_empty() {
+                on_index.emit(unindexed);
+            }
         });
     }
 
     let on_file_change = {
+        let on_index = on_index.clone();
         let docs = documents.clone();
+        let upload_progress = upload_progress.clone();
+        let upload_error = upload_error.clone();
+        let on_uploaded = props.on_uploaded.clone();
         Callback::from(move |e: Event| {
+            upload_error.set(None);
             let input: HtmlInputElement = e.target_unchecked_into();
             let files = input.files();
             
@@ -40,6 +148,10 @@ pub fn documents(props: &DocumentsProps) -> Html {
                     
                     // Clone Rc for the async task
                     let docs_clone = docs.clone();
+                    let progress_clone = upload_progress.clone();
+                    let error_clone = upload_error.clone();
+                    let on_uploaded = on_uploaded.clone();
+                    let on_index = on_index.clone();
                     let file_clone = file.clone();
                     
                     // Create a FileReader
@@ -67,7 +179,7 @@ pub fn documents(props: &DocumentsProps) -> Html {
                                             if let Some(array_buffer) = result.dyn_ref::<js_sys::ArrayBuffer>() {
                                                 console::log_1(&format!("Array buffer length: {}", array_buffer.byte_length()).into());
                                                 // Create a Uint8Array view over the ArrayBuffer
-                                                let uint8_array = js_sys::Uint8Array::new(&array_buffer);
+                                                let uint8_array = js_sys::Uint8Array::new(array_buffer);
                                                 console::log_1(&format!("Uint8Array length: {}", uint8_array.length()).into());
                                                 let mut bytes = vec![0; uint8_array.length() as usize];
                                                 uint8_array.copy_to(&mut bytes[..]);
@@ -76,21 +188,32 @@ pub fn documents(props: &DocumentsProps) -> Html {
                                                 // Clone the name again for the async task
                                                 let process_name = name_clone.clone();
                                                 let process_docs = docs_clone.clone();
+                                                let progress = progress_clone.clone();
+                                                let upload_error = error_clone.clone();
+                                                let on_uploaded = on_uploaded.clone();
+                                                let on_index = on_index.clone();
                                                 
                                                 wasm_bindgen_futures::spawn_local(async move {
                                                     console::log_1(&"Starting document processing".into());
-                                                    match DocumentService::process_document(&process_name, &bytes).await {
-                                                        Ok(_) => {
+                                                    progress.set(Some((0, 0)));
+                                                    let report = {
+                                                        let progress = progress.clone();
+                                                        move |done: usize, total: usize| progress.set(Some((done, total)))
+                                                    };
+                                                    let result = DocumentService::process_document(&process_name, &bytes, report).await;
+                                                    progress.set(None);
+                                                    match result {
+                                                        Ok(document) => {
                                                             console::log_1(&"Document processed successfully".into());
                                                             let loaded_docs = DocumentService::get_documents();
                                                             console::log_1(&format!("Loaded docs count: {}", loaded_docs.len()).into());
                                                             process_docs.set(loaded_docs);
+                                                            on_index.emit(vec![document.id.clone()]);
+                                                            on_uploaded.emit(document);
                                                         }
                                                         Err(err) => {
                                                             console::log_1(&format!("Error processing document: {}", err).into());
-                                                            if let Some(window) = window() {
-                                                                window.alert_with_message(&format!("Error processing document: {}", err)).ok();
-                                                            }
+                                                            upload_error.set(Some(err));
                                                         }
                                                     }
                                                 });
@@ -146,13 +269,110 @@ pub fn documents(props: &DocumentsProps) -> Html {
 
     let on_delete_document = {
         let docs = documents.clone();
+        let selected = props.selected_document_id.clone();
+        let on_selected = props.on_document_selected.clone();
+        let on_deleted = props.on_deleted.clone();
         Callback::from(move |doc_id: String| {
             DocumentService::delete_document(&doc_id);
+            if selected.as_deref() == Some(doc_id.as_str()) {
+                on_selected.emit(None);
+            }
+            on_deleted.emit(vec![doc_id]);
             let loaded_docs = DocumentService::get_documents();
             docs.set(loaded_docs);
         })
     };
 
+    let on_delete_shown = {
+        let docs = documents.clone();
+        let confirm_delete = confirm_delete.clone();
+        let undo = undo.clone();
+        let undo_seq = undo_seq.clone();
+        let index_queue = index_queue.clone();
+        let selected = props.selected_document_id.clone();
+        let on_selected = props.on_document_selected.clone();
+        let on_deleted = props.on_deleted.clone();
+        Callback::from(move |_: MouseEvent| {
+            let Some(ids) = (*confirm_delete).clone() else { return };
+            confirm_delete.set(None);
+            // The previous delete can't be undone once this one replaces it
+            if let Some(previous) = &*undo {
+                on_deleted.emit(previous.documents.iter().map(|d| d.id.clone()).collect());
+            }
+            let removed = DocumentService::delete_documents(&ids);
+            index_queue.borrow_mut().retain(|id| !ids.contains(id));
+            if selected.as_ref().is_some_and(|id| ids.contains(id)) {
+                on_selected.emit(None);
+            }
+            docs.set(DocumentService::get_documents());
+            undo.set(Some(removed));
+
+            let seq = {
+                let mut seq = undo_seq.borrow_mut();
+                *seq += 1;
+                *seq
+            };
+            let undo = undo.clone();
+            let undo_seq = undo_seq.clone();
+            let on_deleted = on_deleted.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                gloo_timers::future::TimeoutFuture::new(UNDO_MS).await;
+                // Undone, or replaced by a newer delete that owns its own timeout
+                if *undo_seq.borrow() == seq {
+                    undo.set(None);
+                    on_deleted.emit(ids);
+                }
+            });
+        })
+    };
+
+    let on_undo_delete = {
+        let docs = documents.clone();
+        let undo = undo.clone();
+        let undo_seq = undo_seq.clone();
+        let upload_error = upload_error.clone();
+        Callback::from(move |_: MouseEvent| {
+            let Some(removed) = (*undo).clone() else { return };
+            match DocumentService::restore_documents(removed) {
+                Ok(()) => {
+                    *undo_seq.borrow_mut() += 1;
+                    docs.set(DocumentService::get_documents());
+                    undo.set(None);
+                }
+                // Undo stays offered, so it can be tried again after making room
+                Err(err) => upload_error.set(Some(err)),
+            }
+        })
+    };
+
+    let on_filter_input = {
+        let filter = filter.clone();
+        let confirm_delete = confirm_delete.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            filter.set(input.value());
+            confirm_delete.set(None);
+        })
+    };
+
+    let on_toggle_enabled = {
+        let docs = documents.clone();
+        Callback::from(move |(doc_id, enabled): (String, bool)| {
+            DocumentService::set_document_enabled(&doc_id, enabled);
+            docs.set(DocumentService::get_documents());
+        })
+    };
+
+    let on_set_all_enabled = {
+        let docs = documents.clone();
+        Callback::from(move |(e, enabled): (MouseEvent, bool)| {
+            // The header itself toggles the section
+            e.stop_propagation();
+            DocumentService::set_all_enabled(enabled);
+            docs.set(DocumentService::get_documents());
+        })
+    };
+
     let get_file_type_icon = |file_type: &str| -> Html {
         match file_type.to_uppercase().as_str() {
             "PDF" => html! {
@@ -193,35 +413,135 @@ pub fn documents(props: &DocumentsProps) -> Html {
         }
     };
 
+    let on_sort_change = {
+        let sort = sort.clone();
+        Callback::from(move |e: Event| {
+            let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+            sort.set(match select.value().as_str() {
+                "name" => DocumentSort::Name,
+                "newest" => DocumentSort::Newest,
+                "largest" => DocumentSort::Largest,
+                "storage" => DocumentSort::Storage,
+                _ => DocumentSort::Uploaded,
+            });
+        })
+    };
+
+    // Only measured while the list is open; cached until a document changes
+    let sizes = if *is_expanded { DocumentService::storage_sizes(&documents) } else { Default::default() };
+    let query = filter.trim().to_lowercase();
+    let mut sorted: Vec<&Document> = documents
+        .iter()
+        .filter(|d| query.is_empty() || d.filename.to_lowercase().contains(&query) || d.file_type.to_lowercase() == query)
+        .collect();
+    match *sort {
+        DocumentSort::Uploaded => {}
+        DocumentSort::Name => sorted.sort_by_key(|d| d.filename.to_lowercase()),
+        DocumentSort::Newest => sorted.sort_by(|a, b| b.upload_date.total_cmp(&a.upload_date)),
+        DocumentSort::Largest => sorted.sort_by_key(|d| std::cmp::Reverse(d.total_tokens)),
+        DocumentSort::Storage => sorted.sort_by_key(|d| std::cmp::Reverse(sizes.get(&d.id).copied().unwrap_or(0))),
+    }
+
     let documents_list = {
         let on_doc_selected = props.on_document_selected.clone();
         let on_del = on_delete_document.clone();
+        let on_toggle = on_toggle_enabled.clone();
+        let model = props.embedding_model.clone();
         
-        (*documents).iter().map(|doc| {
-            let is_selected = (*selected_doc_id) == doc.id;
-            let select_class = if is_selected { "document-item selected" } else { "document-item" };
+        sorted.iter().map(|doc| {
+            // Documents sharing a name are told apart by their upload time
+            let duplicate_name = documents.iter().filter(|d| d.filename == doc.filename).count() > 1;
+            let is_selected = props.selected_document_id.as_deref() == Some(doc.id.as_str());
+            let select_class = classes!("swi-document-item", is_selected.then_some("swi-selected"), (!doc.enabled).then_some("swi-disabled"));
             let doc_id = doc.id.clone();
             let on_sel = on_doc_selected.clone();
             let on_del = on_del.clone();
             let file_type = doc.file_type.clone();
 
             let doc_id_for_click = doc_id.clone();
+            let on_toggle_click = {
+                let on_toggle = on_toggle.clone();
+                let doc_id = doc_id.clone();
+                let enabled = doc.enabled;
+                Callback::from(move |e: MouseEvent| {
+                    e.stop_propagation();
+                    on_toggle.emit((doc_id.clone(), !enabled));
+                })
+            };
+            let queued = index_queue.borrow().contains(&doc.id);
+            let on_reindex = {
+                let on_index = on_index.clone();
+                let doc_id = doc_id.clone();
+                Callback::from(move |e: MouseEvent| {
+                    e.stop_propagation();
+                    on_index.emit(vec![doc_id.clone()]);
+                })
+            };
+            let index_class = match &doc.index {
+                IndexStatus::Indexed { .. } if doc.index.is_current(&model) => "swi-index-ok",
+                IndexStatus::Failed { .. } => "swi-index-failed",
+                IndexStatus::Indexing { .. } if queued => "swi-index-busy",
+                _ => "swi-index-pending",
+            };
+            // A status stored as indexing by an earlier session never finished
+            let index_label = match &doc.index {
+                IndexStatus::Indexing { .. } if !queued => "Not indexed".to_string(),
+                status => status.label(&model),
+            };
             html! {
-                <div class={select_class} onclick={Callback::from(move |_| {
-                    let _ = on_sel.emit(doc_id_for_click.clone());
+                <div key={doc.id.clone()} class={select_class} onclick={Callback::from(move |_| {
+                    on_sel.emit(if is_selected { None } else { Some(doc_id_for_click.clone()) });
                 })}>
-                    <div class="document-content">
+                    <div class="swi-document-content">
                         { get_file_type_icon(&file_type) }
-                        <div class="document-info">
-                            <span class="document-name">{ &doc.filename }</span>
-                            <div class="document-meta">
-                                <span class="document-chunks">{ doc.chunk_count } { "chunks" }</span>
-                                <span class="document-separator">{ "•" }</span>
-                                <span class="document-tokens">{ format_tokens(doc.total_tokens) }</span>
+                        <div class="swi-document-info">
+                            <span class="swi-document-name" title={format_absolute(doc.upload_date)}>
+                                { &doc.filename }
+                                if duplicate_name {
+                                    <span class="swi-document-date-suffix">{ format!(" · {}", format_absolute(doc.upload_date)) }</span>
+                                }
+                            </span>
+                            <div class="swi-document-meta">
+                                <span class="swi-document-chunks">{ doc.chunk_count } { "chunks" }</span>
+                                <span class="swi-document-separator">{ "•" }</span>
+                                <span class="swi-document-tokens">{ match props.count_unit {
+                                    CountUnit::Tokens => format!("{} tokens", format_short_count(doc.total_tokens)),
+                                    CountUnit::Words => format!("{} words", format_count(doc.words())),
+                                } }</span>
+                                if let Some(size) = sizes.get(&doc.id) {
+                                    <span class="swi-document-separator">{ "•" }</span>
+                                    <span class="swi-document-size" title="Space used in browser storage">{ format_bytes(*size) }</span>
+                                }
+                                <span class="swi-document-separator">{ "•" }</span>
+                                <span class="swi-document-date">{ format_relative_time(doc.upload_date, js_sys::Date::now()) }</span>
                             </div>
+                            if !model.is_empty() {
+                                <div class="swi-document-index">
+                                    <span class={classes!("swi-index-badge", index_class)} title={doc.index.detail()}>{ index_label }</span>
+                                    if !queued {
+                                        <button class="swi-index-btn" onclick={on_reindex} title={format!("Embed this document again with {}", model)}>
+                                            { "↻ Re-index" }
+                                        </button>
+                                    }
+                                </div>
+                            }
                         </div>
                     </div>
-                    <button class="document-delete-btn" onclick={Callback::from(move |_| on_del.emit(doc_id.clone()))} title="Delete document">
+                    <button
+                        class={classes!("swi-document-toggle-btn", (!doc.enabled).then_some("swi-off"))}
+                        onclick={on_toggle_click}
+                        title={if doc.enabled { "Exclude from context" } else { "Use as context again" }}
+                    >
+                        if doc.enabled {
+                            <svg width="16" height="16" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><path d="M1 12s4-8 11-8 11 8 11 8-4 8-11 8-11-8-11-8z"></path><circle cx="12" cy="12" r="3"></circle></svg>
+                        } else {
+                            <svg width="16" height="16" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><path d="M17.94 17.94A10.07 10.07 0 0 1 12 20c-7 0-11-8-11-8a18.45 18.45 0 0 1 5.06-5.94"></path><path d="M9.9 4.24A9.12 9.12 0 0 1 12 4c7 0 11 8 11 8a18.5 18.5 0 0 1-2.16 3.19"></path><line x1="1" y1="1" x2="23" y2="23"></line></svg>
+                        }
+                    </button>
+                    <button class="swi-document-delete-btn" onclick={Callback::from(move |e: MouseEvent| {
+                        e.stop_propagation();
+                        on_del.emit(doc_id.clone());
+                    })} title="Delete document">
                         <svg width="16" height="16" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><path d="M3 6h18"></path><path d="M19 6v14c0 1-1 2-2 2H7c-1 0-2-1-2-2V6"></path><path d="M8 6V4c0-1 1-2 2-2h4c1 0 2 1 2 2v2"></path></svg>
                     </button>
                 </div>
@@ -229,12 +549,68 @@ pub fn documents(props: &DocumentsProps) -> Html {
         }).collect::<Vec<_>>()
     };
 
+    // Ids of the documents the filter leaves, for "Delete shown"
+    let shown: Vec<String> = if query.is_empty() { Vec::new() } else { sorted.iter().map(|d| d.id.clone()).collect() };
+    let delete_confirmation = match &*confirm_delete {
+        Some(ids) => {
+            let targets: Vec<&Document> = documents.iter().filter(|d| ids.contains(&d.id)).collect();
+            let tokens: usize = targets.iter().map(|d| d.total_tokens).sum();
+            let bytes: usize = targets.iter().filter_map(|d| sizes.get(&d.id)).sum();
+            let confirm_delete = confirm_delete.clone();
+            html! {
+                <div class="swi-documents-confirm" role="alertdialog">
+                    <div>{ format!("Delete {} document{}? This frees {} tokens and {} of storage.", targets.len(), if targets.len() == 1 { "" } else { "s" }, format_count(tokens), format_bytes(bytes)) }</div>
+                    <ul>
+                        { for targets.iter().map(|d| html! { <li key={d.id.clone()}>{ &d.filename }</li> }) }
+                    </ul>
+                    <div class="swi-documents-confirm-actions">
+                        <button class="swi-btn" onclick={Callback::from(move |_| confirm_delete.set(None))}>{ "Cancel" }</button>
+                        <button class="swi-btn swi-btn-danger" onclick={on_delete_shown}>{ "Delete" }</button>
+                    </div>
+                </div>
+            }
+        }
+        None => html! {},
+    };
+
+    let all_enabled = documents.iter().all(|d| d.enabled);
+    // Documents whose index can't be searched with the current model
+    let unindexed: Vec<String> = documents
+        .iter()
+        .filter(|d| !d.index.is_current(&props.embedding_model) && !index_queue.borrow().contains(&d.id))
+        .map(|d| d.id.clone())
+        .collect();
+    let stale = documents.iter().any(|d| matches!(&d.index, IndexStatus::Indexed { .. }) && !d.index.is_current(&props.embedding_model));
+
     html! {
-        <div class="documents-section">
-            <div class="documents-header" onclick={toggle_expand}>
+        <div class="swi-documents-section">
+            <div class="swi-documents-header" onclick={toggle_expand}>
                 <h3>{ "Documents" }</h3>
-                <div class="expand-icon-wrapper">
-                    <svg class={if *is_expanded { "expand-icon rotated" } else { "expand-icon" }} width="16" height="16" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round">
+                if *is_expanded && documents.len() > 1 {
+                    <select class="swi-documents-sort" title="Sort documents" onclick={Callback::from(|e: MouseEvent| e.stop_propagation())} onchange={on_sort_change}>
+                        <option value="uploaded" selected={*sort == DocumentSort::Uploaded}>{ "Upload order" }</option>
+                        <option value="name" selected={*sort == DocumentSort::Name}>{ "Name" }</option>
+                        <option value="newest" selected={*sort == DocumentSort::Newest}>{ "Newest" }</option>
+                        <option value="largest" selected={*sort == DocumentSort::Largest}>{ "Largest" }</option>
+                        <option value="storage" selected={*sort == DocumentSort::Storage}>{ "Storage used" }</option>
+                    </select>
+                }
+                if *is_expanded && !documents.is_empty() {
+                    <button class="swi-documents-bulk-btn" onclick={on_set_all_enabled.reform(move |e| (e, !all_enabled))}>
+                        { if all_enabled { "Disable all" } else { "Enable all" } }
+                    </button>
+                }
+                if *is_expanded && !props.embedding_model.is_empty() && !unindexed.is_empty() {
+                    <button
+                        class="swi-documents-bulk-btn"
+                        title={if stale { "The embedding model changed; indexes built with the old one aren't searched" } else { "Embed every document that isn't indexed yet" }}
+                        onclick={on_index.reform(move |e: MouseEvent| { e.stop_propagation(); unindexed.clone() })}
+                    >
+                        { "Re-index all" }
+                    </button>
+                }
+                <div class="swi-expand-icon-wrapper">
+                    <svg class={if *is_expanded { "swi-expand-icon swi-rotated" } else { "swi-expand-icon" }} width="16" height="16" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round">
                         <polyline points="6 9 12 15 18 9"></polyline>
                     </svg>
                 </div>
@@ -242,7 +618,7 @@ pub fn documents(props: &DocumentsProps) -> Html {
             
             if *is_expanded {
                 <>
-                    <div class="document-upload">
+                    <div class="swi-document-upload">
                         <input
                             type="file"
                             accept=".pdf,.txt,.md"
@@ -250,19 +626,61 @@ pub fn documents(props: &DocumentsProps) -> Html {
                             style="display: none;"
                             id="document-upload-input"
                         />
-                        <label for="document-upload-input" class="upload-btn">
+                        <label for="document-upload-input" class="swi-upload-btn">
                             <svg width="16" height="16" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"><line x1="12" y1="5" x2="12" y2="19"></line><line x1="5" y1="12" x2="19" y2="12"></line></svg>
                             <span>{ "Upload Document" }</span>
                         </label>
+                        if let Some((done, total)) = *upload_progress {
+                            <div class="swi-upload-progress">
+                                if total == 0 {
+                                    { "Processing document..." }
+                                } else {
+                                    { format!("Counting tokens: {} / {} chunks", done, total) }
+                                }
+                            </div>
+                        }
+                        if let Some(err) = &*upload_error {
+                            <div class="swi-upload-error" role="alert">
+                                { err.to_string() }
+                                if err.needs_storage() {
+                                    <button class="swi-documents-bulk-btn" onclick={props.on_open_setting.reform(|_| "storage".to_string())}>{ "Manage storage" }</button>
+                                }
+                            </div>
+                        }
                     </div>
                     
-                    <div class="documents-list">
+                    if documents.len() > 1 || !query.is_empty() {
+                        <div class="swi-documents-filter">
+                            <input class="swi-documents-search" type="text" placeholder="Filter documents..." value={(*filter).clone()} oninput={on_filter_input} />
+                            if !shown.is_empty() && confirm_delete.is_none() {
+                                <button class="swi-documents-bulk-btn" onclick={{
+                                    let confirm_delete = confirm_delete.clone();
+                                    let shown = shown.clone();
+                                    Callback::from(move |_| confirm_delete.set(Some(shown.clone())))
+                                }}>
+                                    { format!("Delete shown ({})", shown.len()) }
+                                </button>
+                            }
+                        </div>
+                    }
+                    { delete_confirmation }
+                    if let Some(removed) = &*undo {
+                        <div class="swi-documents-undo" role="status">
+                            <span>{ format!("Deleted {} document{}", removed.documents.len(), if removed.documents.len() == 1 { "" } else { "s" }) }</span>
+                            <button class="swi-documents-bulk-btn" onclick={on_undo_delete}>{ "Undo" }</button>
+                        </div>
+                    }
+
+                    <div class="swi-documents-list">
                         { for documents_list }
                     </div>
+                    if sorted.is_empty() && !documents.is_empty() {
+                        <div class="swi-chat-list-empty">{ "No documents match the filter" }</div>
+                    }
                     
                     if documents.is_empty() {
-                        <div class="no-documents">
-                            <div class="no-documents-icon">
+                        <div class="swi-no-documents">
+                            <div class="swi-no-documents-icon">
                                 <svg width="32" height="32" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="1.5" stroke-linecap="round" stroke-linejoin="round">
                                     <path d="M14 2H6a2 2 0 0 0-2 2v16a2 2 0 0 0 2 2h12a2 2 0 0 0 2-2V8z"></path>
                                     <polyline points="14 2 14 8 20 8"></polyline>
@@ -272,7 +690,7 @@ pub fn documents(props: &DocumentsProps) -> Html {
                                 </svg>
                             </div>
                             <p>{ "No documents uploaded yet." }</p>
-                            <p class="hint">{ "Upload PDF, TXT, or MD files to use as context." }</p>
+                            <p class="swi-hint">{ "Upload PDF, TXT, or MD files to use as context." }</p>
                         </div>
                     }
                 </>
@@ -281,11 +699,14 @@ pub fn documents(props: &DocumentsProps) -> Html {
     }
 }
 
-fn format_tokens(tokens: usize) -> String {
-    if tokens >= 1000 {
-        format!("{}k", tokens / 1000)
+fn format_short_count(count: usize) -> String {
+    if count >= 10_000 {
+        format!("{}k", count / 1000)
+    } else if count >= 1000 {
+        // One decimal, truncated so 1,999 never reads as "2.0k"
+        format!("{:.1}k", (count / 100) as f64 / 10.0)
     } else {
-        format!("{} tokens", tokens)
+        count.to_string()
     }
 }
 
@@ -1,4 +1,15 @@
 pub mod sidebar;
 pub mod settings;
 pub mod chat_area;
-pub mod documents;
\ No newline at end of file
+pub mod documents;
+pub mod onboarding;
+pub mod usage;
+pub mod document_viewer;
+pub mod context_inspector;
+pub mod storage_usage;
+pub mod chat_export;
+pub mod whats_new;
+pub mod setup_guide;
+pub mod autocomplete;
+pub mod diff_view;
+pub mod reorder;
@@ -0,0 +1,248 @@
+use yew::prelude::*;
+use web_sys::{HtmlInputElement, HtmlTextAreaElement, HtmlSelectElement};
+use wasm_bindgen_futures::spawn_local;
+use crate::services::llm::LlmService;
+use crate::services::request::RequestConfig;
+use crate::models::{AppSettings, PROVIDER_PRESETS};
+use crate::utils::inject_styles;
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct OnboardingProps {
+    pub settings: AppSettings,
+    pub on_finish: Callback<AppSettings>,
+    pub on_skip: Callback<()>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    Connect,
+    Model,
+    Prompt,
+}
+
+#[function_component(Onboarding)]
+pub fn onboarding(props: &OnboardingProps) -> Html {
+    let step = use_state(|| Step::Connect);
+    let draft = use_state(|| props.settings.clone());
+    // The draft as last rendered, for the connection test to read once it returns
+    let latest_draft = use_mut_ref(AppSettings::default);
+    *latest_draft.borrow_mut() = (*draft).clone();
+    let models = use_state(Vec::<String>::new);
+    let error_msg = use_state(String::new);
+    let testing = use_state(|| false);
+
+    let on_preset = {
+        let draft = draft.clone();
+        let models = models.clone();
+        Callback::from(move |url: String| {
+            let mut d = (*draft).clone();
+            d.base_url = url;
+            draft.set(d);
+            models.set(Vec::new());
+        })
+    };
+
+    let on_url_input = {
+        let draft = draft.clone();
+        let models = models.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut d = (*draft).clone();
+            d.base_url = input.value();
+            draft.set(d);
+            models.set(Vec::new());
+        })
+    };
+
+    let on_test = {
+        let draft = draft.clone();
+        let latest_draft = latest_draft.clone();
+        let models = models.clone();
+        let err = error_msg.clone();
+        let testing = testing.clone();
+        Callback::from(move |_| {
+            let request = RequestConfig::from(&*draft);
+            let url = request.base_url.clone();
+            let draft = draft.clone();
+            let latest_draft = latest_draft.clone();
+            let models = models.clone();
+            let err = err.clone();
+            let testing = testing.clone();
+            testing.set(true);
+            spawn_local(async move {
+                let result = LlmService::test_connection(&request).await;
+                // Edits made while waiting are kept; a result for a replaced URL is dropped
+                let mut current = latest_draft.borrow().clone();
+                if current.base_url != url {
+                    testing.set(false);
+                    return;
+                }
+                match result {
+                    Ok(list) => {
+                        err.set(String::new());
+                        // Pre-select the first model unless the current one is served
+                        if !list.contains(&current.selected_model) {
+                            if let Some(first) = list.first() {
+                                current.selected_model = first.clone();
+                                draft.set(current);
+                            }
+                        }
+                        models.set(list);
+                    }
+                    Err(e) => {
+                        models.set(Vec::new());
+                        err.set(LlmService::diagnose(&request, e).await.to_string());
+                    }
+                }
+                testing.set(false);
+            });
+        })
+    };
+
+    let on_model_change = {
+        let draft = draft.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            let mut d = (*draft).clone();
+            d.selected_model = select.value();
+            draft.set(d);
+        })
+    };
+
+    let on_prompt_input = {
+        let draft = draft.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlTextAreaElement = e.target_unchecked_into();
+            let mut d = (*draft).clone();
+            d.system_prompt = input.value();
+            draft.set(d);
+        })
+    };
+
+    let go_to = |target: Step| {
+        let step = step.clone();
+        Callback::from(move |_: MouseEvent| step.set(target))
+    };
+
+    let on_finish = {
+        let draft = draft.clone();
+        let on_finish = props.on_finish.clone();
+        Callback::from(move |_: MouseEvent| {
+            let mut d = (*draft).clone();
+            d.onboarding_completed = true;
+            on_finish.emit(d);
+        })
+    };
+
+    let css = r#"
+        .swi-onboarding-backdrop { position: absolute; inset: 0; background: rgba(255,255,255,0.85); backdrop-filter: blur(3px); z-index: 200; display: flex; align-items: center; justify-content: center; }
+        .swi-onboarding-panel { width: 460px; max-width: calc(100% - 40px); background: white; border: 1px solid var(--border-color); border-radius: 10px; box-shadow: 0 10px 25px rgba(0,0,0,0.1); padding: 24px; display: flex; flex-direction: column; gap: 14px; }
+        .swi-onboarding-panel h3 { margin: 0; font-size: 1.2rem; }
+        .swi-onboarding-panel p { margin: 0; font-size: 0.9rem; color: var(--text-secondary); line-height: 1.5; }
+        .swi-onboarding-steps { font-size: 0.75rem; color: var(--text-secondary); text-transform: uppercase; letter-spacing: 0.5px; }
+        .swi-preset-grid { display: grid; grid-template-columns: 1fr 1fr; gap: 8px; }
+        .swi-preset-btn { text-align: left; }
+        .swi-preset-btn.swi-active { border-color: var(--accent-color); color: var(--accent-color); }
+        .swi-preset-url { display: block; font-size: 0.75rem; color: var(--text-secondary); }
+        .swi-onboarding-footer { display: flex; justify-content: space-between; align-items: center; margin-top: 6px; }
+        .swi-onboarding-nav { display: flex; gap: 8px; }
+        .swi-skip-link { background: none; border: none; color: var(--text-secondary); cursor: pointer; font-size: 0.85rem; text-decoration: underline; padding: 0; }
+        .swi-onboarding-status { font-size: 0.85rem; }
+        .swi-onboarding-status.swi-ok { color: var(--accent-color); }
+        .swi-onboarding-status.swi-error { color: var(--danger-color); }
+    "#;
+    inject_styles("onboarding", css);
+
+    let (step_no, body, nav) = match *step {
+        Step::Connect => (
+            1,
+            html! {
+                <>
+                    <p>{ "Which server are you running? Pick a preset or enter the URL yourself." }</p>
+                    <div class="swi-preset-grid">
+                        { for PROVIDER_PRESETS.iter().map(|preset| {
+                            let on_preset = on_preset.clone();
+                            let url = preset.base_url.to_string();
+                            let cls = if draft.base_url == preset.base_url { "swi-btn swi-preset-btn swi-active" } else { "swi-btn swi-preset-btn" };
+                            html! {
+                                <button class={cls} onclick={Callback::from(move |_| on_preset.emit(url.clone()))}>
+                                    { preset.name }
+                                    <span class="swi-preset-url">{ preset.base_url }</span>
+                                </button>
+                            }
+                        })}
+                    </div>
+                    <div class="swi-fetch-group" style="display: flex; gap: 8px;">
+                        <input class="swi-form-input" type="text" value={draft.base_url.clone()} oninput={on_url_input} style="margin-bottom:0;" />
+                        <button class="swi-btn" onclick={on_test} disabled={*testing}>
+                            { if *testing { "Testing..." } else { "Test" } }
+                        </button>
+                    </div>
+                    if !error_msg.is_empty() {
+                        <div class="swi-onboarding-status swi-error">{ &*error_msg }</div>
+                    } else if !models.is_empty() {
+                        <div class="swi-onboarding-status swi-ok">{ format!("Connected — {} model(s) available", models.len()) }</div>
+                    }
+                </>
+            },
+            html! {
+                <button class="swi-btn swi-btn-primary" onclick={go_to(Step::Model)} disabled={models.is_empty()}>{ "Next" }</button>
+            },
+        ),
+        Step::Model => (
+            2,
+            html! {
+                <>
+                    <p>{ "Choose the model to chat with. You can change it later in the settings." }</p>
+                    <select class="swi-form-select" onchange={on_model_change}>
+                        { for models.iter().map(|m| html! {
+                            <option value={m.clone()} selected={*m == draft.selected_model}>{ m }</option>
+                        }) }
+                    </select>
+                </>
+            },
+            html! {
+                <>
+                    <button class="swi-btn" onclick={go_to(Step::Connect)}>{ "Back" }</button>
+                    <button class="swi-btn swi-btn-primary" onclick={go_to(Step::Prompt)}>{ "Next" }</button>
+                </>
+            },
+        ),
+        Step::Prompt => (
+            3,
+            html! {
+                <>
+                    <p>{ "Optionally tell the assistant how to behave. This becomes the system prompt of new chats." }</p>
+                    <textarea
+                        class="swi-form-textarea"
+                        value={draft.system_prompt.clone()}
+                        oninput={on_prompt_input}
+                        style="height: 100px; resize: none;"
+                    />
+                </>
+            },
+            html! {
+                <>
+                    <button class="swi-btn" onclick={go_to(Step::Model)}>{ "Back" }</button>
+                    <button class="swi-btn swi-btn-primary" onclick={on_finish}>{ "Start chatting" }</button>
+                </>
+            },
+        ),
+    };
+
+    html! {
+        <>
+            <div class="swi-onboarding-backdrop">
+                <div class="swi-onboarding-panel">
+                    <div class="swi-onboarding-steps">{ format!("Step {} of 3", step_no) }</div>
+                    <h3>{ "Welcome! Let's connect to your LLM server" }</h3>
+                    { body }
+                    <div class="swi-onboarding-footer">
+                        <button class="swi-skip-link" onclick={props.on_skip.reform(|_| ())}>{ "Skip and keep defaults" }</button>
+                        <div class="swi-onboarding-nav">{ nav }</div>
+                    </div>
+                </div>
+            </div>
+        </>
+    }
+}
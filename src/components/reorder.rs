@@ -0,0 +1,106 @@
+use yew::prelude::*;
+
+/// Drag-and-drop reordering of a list: the index of the item being dragged and
+/// of the one under the pointer
+#[derive(Clone, PartialEq)]
+pub struct DragReorder {
+    dragging: UseStateHandle<Option<usize>>,
+    over: UseStateHandle<Option<usize>>,
+}
+
+/// Handlers for one item of a reorderable list, to spread onto its element
+pub struct DragHandlers {
+    pub ondragstart: Callback<DragEvent>,
+    pub ondragover: Callback<DragEvent>,
+    pub ondrop: Callback<DragEvent>,
+    pub ondragend: Callback<DragEvent>,
+}
+
+#[hook]
+pub fn use_drag_reorder() -> DragReorder {
+    DragReorder { dragging: use_state(|| None), over: use_state(|| None) }
+}
+
+impl DragReorder {
+    pub fn is_dragging(&self, index: usize) -> bool {
+        *self.dragging == Some(index)
+    }
+
+    /// Class marking where the dragged item would land, on the item under the pointer
+    pub fn drop_class(&self, index: usize) -> Option<&'static str> {
+        drop_class(*self.dragging, *self.over, index)
+    }
+
+    /// Handlers for the item at `index`. `data` is what a drag carries outside
+    /// the list; `on_move` gets the indices to move from and to on a drop.
+    pub fn handlers(&self, index: usize, data: String, on_move: &Callback<(usize, usize)>) -> DragHandlers {
+        let ondragend = {
+            let dragging = self.dragging.clone();
+            let over = self.over.clone();
+            Callback::from(move |_: DragEvent| {
+                dragging.set(None);
+                over.set(None);
+            })
+        };
+        let ondragstart = {
+            let dragging = self.dragging.clone();
+            Callback::from(move |e: DragEvent| {
+                if let Some(dt) = e.data_transfer() {
+                    // Firefox only starts a drag when some data is set
+                    let _ = dt.set_data("text/plain", &data);
+                    dt.set_effect_allowed("move");
+                }
+                dragging.set(Some(index));
+            })
+        };
+        let ondragover = {
+            let active = self.dragging.is_some();
+            let over = self.over.clone();
+            Callback::from(move |e: DragEvent| {
+                if !active {
+                    return;
+                }
+                e.prevent_default();
+                if *over != Some(index) {
+                    over.set(Some(index));
+                }
+            })
+        };
+        let ondrop = {
+            let from = *self.dragging;
+            let on_move = on_move.clone();
+            let end = ondragend.clone();
+            Callback::from(move |e: DragEvent| {
+                e.prevent_default();
+                if let Some(from) = from {
+                    on_move.emit((from, index));
+                }
+                end.emit(e);
+            })
+        };
+        DragHandlers { ondragstart, ondragover, ondrop, ondragend }
+    }
+}
+
+fn drop_class(dragging: Option<usize>, over: Option<usize>, index: usize) -> Option<&'static str> {
+    match (dragging, over) {
+        (Some(from), Some(over)) if over == index && from != index => {
+            Some(if from < index { "swi-drop-after" } else { "swi-drop-before" })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drop_marker_shows_on_the_item_under_the_pointer() {
+        assert_eq!(drop_class(Some(0), Some(2), 2), Some("swi-drop-after"));
+        assert_eq!(drop_class(Some(3), Some(1), 1), Some("swi-drop-before"));
+        assert_eq!(drop_class(Some(0), Some(2), 1), None);
+        assert_eq!(drop_class(Some(2), Some(2), 2), None);
+        assert_eq!(drop_class(None, Some(2), 2), None);
+    }
+}
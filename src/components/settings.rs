@@ -1,9 +1,21 @@
 use yew::prelude::*;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use web_sys::{HtmlInputElement, HtmlTextAreaElement, HtmlSelectElement};
+use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::spawn_local;
 use uuid::Uuid;
 use crate::services::llm::LlmService;
-use crate::models::{AppSettings, SavedPrompt};
+use crate::services::request::RequestConfig;
+use crate::services::export::ExportService;
+use crate::services::changelog::APP_VERSION;
+use crate::services::document_service::DocumentService;
+use crate::services::deployment::DeploymentService;
+use crate::models::{AppSettings, BackendKind, ChatSession, ContextStrategy, CountUnit, RecurrenceInterval, RecurringPrompt, ResponseLength, SavedPrompt, DEFAULT_TEMPERATURE, MAX_REQUEST_RETRIES};
+use crate::components::usage::Usage;
+use crate::components::storage_usage::StorageUsage;
+use crate::components::reorder::{use_drag_reorder, DragHandlers};
+use crate::utils::{format_absolute, format_relative_time, move_item, inject_styles};
 
 #[derive(Properties, PartialEq, Clone)]
 pub struct SettingsProps {
@@ -12,18 +24,81 @@ pub struct SettingsProps {
     pub on_close: Callback<()>,
     pub on_reset: Callback<()>,
     pub on_clear_chats: Callback<()>,
+    pub on_backup_chats: Callback<()>,
+    pub chats: Vec<ChatSession>,
+    /// Settings fields managed by the deployment; their controls are disabled
+    #[prop_or_default]
+    pub locked: Vec<String>,
+    /// Field to scroll to and focus on opening, e.g. "custom_instructions"
+    #[prop_or_default]
+    pub focus_field: Option<String>,
+    /// Shows the changelog again
+    #[prop_or_default]
+    pub on_whats_new: Callback<()>,
+}
+
+/// Whether another saved prompt than `except` already uses `name`, ignoring case
+fn prompt_name_taken(prompts: &[SavedPrompt], name: &str, except: Option<&str>) -> bool {
+    prompts.iter().any(|p| Some(p.id.as_str()) != except && p.name.trim().eq_ignore_ascii_case(name.trim()))
+}
+
+// Shown inside the collapsible "Generation Parameters" group
+const GENERATION_FIELDS: [&str; 6] = ["temperature", "top_p", "frequency_penalty", "presence_penalty", "max_tokens", "stop_sequences"];
+
+// Typed to confirm wiping all chats
+const DELETE_CONFIRM_WORD: &str = "delete";
+
+#[derive(Clone, Copy, PartialEq)]
+enum DangerAction {
+    DeleteChats,
+    ResetSettings,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SettingsTab {
+    General,
+    Data,
 }
 
 #[function_component(SettingsModal)]
 pub fn settings_modal(props: &SettingsProps) -> Html {
-    let available_models = use_state(Vec::new);
+    // Start from the cached list so the dropdown is usable while refreshing
+    let cached = LlmService::cached_models(&props.settings.base_url);
+    let available_models = use_state(|| cached.as_ref().map(|c| c.models.clone()).unwrap_or_default());
+    let fetched_at = use_state(|| cached.as_ref().map(|c| c.fetched_at));
+    let refreshing = use_state(|| false);
+    let refresh_warning = use_state(String::new);
     let error_msg = use_state(String::new);
     let prompt_name_input = use_state(String::new);
+    // Saved prompt being renamed, with the name typed so far
+    let renaming_prompt = use_state(|| None::<(String, String)>);
+    let prompt_name_error = use_state(String::new);
+    let tab = use_state(|| SettingsTab::General);
+    // Validated settings from an imported file and the changes they would make
+    let import_preview = use_state(|| None::<(AppSettings, Vec<String>)>);
+    let import_error = use_state(String::new);
+    // Exported files leave the API key out unless asked
+    let export_api_key = use_state(|| false);
+    let danger_action = use_state(|| None::<DangerAction>);
+    let confirm_text = use_state(String::new);
+    let prompt_drag = use_drag_reorder();
+    // Choices for the next recurring prompt; an empty target means a new chat
+    let recurring_prompt_id = use_state(String::new);
+    let recurring_interval = use_state(|| RecurrenceInterval::Daily);
+    let recurring_target = use_state(String::new);
+
+    let is_locked = |field: &str| props.locked.iter().any(|f| f == field);
+    let managed_note = |field: &str| {
+        if is_locked(field) {
+            html! { <span class="swi-managed-note">{ "Managed by deployment" }</span> }
+        } else {
+            html! {}
+        }
+    };
 
     // Generic helper to emit updates
     let update_settings = {
         let on_save = props.on_save.clone();
-        let current_settings = props.settings.clone();
         move |new_settings: AppSettings| {
             on_save.emit(new_settings);
         }
@@ -42,6 +117,17 @@ pub fn settings_modal(props: &SettingsProps) -> Html {
         })
     };
 
+    let on_custom_instructions_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlTextAreaElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.custom_instructions = input.value();
+            updater(s);
+        })
+    };
+
     let on_url_input = {
         let settings = props.settings.clone();
         let updater = update_settings.clone();
@@ -53,6 +139,28 @@ pub fn settings_modal(props: &SettingsProps) -> Html {
         })
     };
 
+    let on_backend_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.backend_kind = if select.value() == "ollama" { BackendKind::Ollama } else { BackendKind::OpenAI };
+            updater(s);
+        })
+    };
+
+    let on_api_key_input = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.api_key = input.value().trim().to_string();
+            updater(s);
+        })
+    };
+
     let on_model_change = {
         let settings = props.settings.clone();
         let updater = update_settings.clone();
@@ -75,46 +183,378 @@ pub fn settings_modal(props: &SettingsProps) -> Html {
         })
     };
 
+    let on_remove_empty_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.remove_empty_responses = input.checked();
+            updater(s);
+        })
+    };
+
+    let on_keep_alive_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.keep_alive = input.checked();
+            updater(s);
+        })
+    };
+
+    let on_keep_alive_minutes_input = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.keep_alive_minutes = input.value().parse().unwrap_or(0);
+            updater(s);
+        })
+    };
+
+    let on_stall_warning_input = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.stall_warning_seconds = input.value().parse().unwrap_or(0);
+            updater(s);
+        })
+    };
+
+    let on_request_retries_input = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.request_retries = input.value().parse().unwrap_or(0).min(MAX_REQUEST_RETRIES);
+            updater(s);
+        })
+    };
+
+    let on_stop_on_switch_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.stop_on_chat_switch = input.checked();
+            updater(s);
+        })
+    };
+
+    let on_logprobs_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.logprobs_enabled = input.checked();
+            updater(s);
+        })
+    };
+
+    let on_similar_hints_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.similar_question_hints = input.checked();
+            updater(s);
+        })
+    };
+
+    let on_document_suggestions_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.document_suggestions = input.checked();
+            updater(s);
+        })
+    };
+
+    let on_debug_mode_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.debug_mode = input.checked();
+            updater(s);
+        })
+    };
+
+    let on_high_contrast_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.high_contrast = input.checked();
+            updater(s);
+        })
+    };
+
+    let on_reduced_motion_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.reduced_motion = input.checked();
+            updater(s);
+        })
+    };
+
+    let on_prompt_warning_input = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.prompt_warning_tokens = input.value().parse().unwrap_or(0);
+            updater(s);
+        })
+    };
+
+    let on_context_length_input = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.context_length = input.value().parse().unwrap_or(0);
+            updater(s);
+        })
+    };
+
+    // Empty falls back to the context length of every other model
+    let on_model_context_length_input = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            let model = s.selected_model.clone();
+            match input.value().trim().parse::<usize>() {
+                Ok(length) => s.model_context_lengths.insert(model, length),
+                Err(_) => s.model_context_lengths.remove(&model),
+            };
+            updater(s);
+        })
+    };
+
+    let on_context_strategy_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            if let Some(strategy) = ContextStrategy::ALL.iter().copied().find(|s| s.key() == select.value()) {
+                let mut s = settings.clone();
+                s.context_strategy = strategy;
+                updater(s);
+            }
+        })
+    };
+
+    let on_count_unit_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.count_unit = if select.value() == "words" { CountUnit::Words } else { CountUnit::Tokens };
+            updater(s);
+        })
+    };
+
+    let on_response_length_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            // A custom limit, e.g. from a managed configuration, is kept while it stays chosen
+            s.response_length = ResponseLength::PRESETS
+                .iter()
+                .copied()
+                .chain(Some(settings.response_length))
+                .find(|l| l.label() == select.value())
+                .unwrap_or_default();
+            updater(s);
+        })
+    };
+
+    // Out-of-range generation parameters are pointed out instead of saved
+    let param_errors = use_state(HashMap::<&'static str, String>::new);
+    let on_param_input = |field: &'static str, range: RangeInclusive<f32>, apply: fn(&mut AppSettings, Option<f32>)| {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        let errors = param_errors.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let value = match input.value().trim() {
+                "" => Some(None),
+                text => text.parse::<f32>().ok().filter(|v| range.contains(v)).map(Some),
+            };
+            let mut errs = (*errors).clone();
+            match value {
+                Some(value) => {
+                    errs.remove(field);
+                    let mut s = settings.clone();
+                    apply(&mut s, value);
+                    updater(s);
+                }
+                None => {
+                    errs.insert(field, format!("Enter a number from {} to {}, or leave it empty", range.start(), range.end()));
+                }
+            }
+            errors.set(errs);
+        })
+    };
+
+    let on_max_tokens_input = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.max_tokens = input.value().trim().parse().ok().filter(|n| *n > 0);
+            updater(s);
+        })
+    };
+
+    // One sequence per line; `\n` stands for a line break
+    let on_stop_sequences_input = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlTextAreaElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.stop_sequences = input.value().lines().filter(|l| !l.is_empty()).map(|l| l.replace("\\n", "\n")).collect();
+            updater(s);
+        })
+    };
+
+    let on_document_list_change = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.document_list_in_prompt = input.checked();
+            updater(s);
+        })
+    };
+
     let on_doc_context_mode_change = {
         let settings = props.settings.clone();
         let updater = update_settings.clone();
         Callback::from(move |e: Event| {
             let select: HtmlSelectElement = e.target_unchecked_into();
             let mut s = settings.clone();
-            s.document_context_mode = if select.value() == "rag" {
-                crate::models::DocumentContextMode::RAG
-            } else {
-                crate::models::DocumentContextMode::Manual
+            s.document_context_mode = match select.value().as_str() {
+                "rag" => crate::models::DocumentContextMode::RAG,
+                "off" => crate::models::DocumentContextMode::Off,
+                _ => crate::models::DocumentContextMode::Manual,
             };
             updater(s);
         })
     };
 
-    let on_fetch = {
-        let base_url = props.settings.base_url.clone();
+    let on_embedding_model_input = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let mut s = settings.clone();
+            s.embedding_model = input.value().trim().to_string();
+            updater(s);
+        })
+    };
+
+    let refresh_models = {
         let models = available_models.clone();
+        let fetched_at = fetched_at.clone();
+        let refreshing = refreshing.clone();
+        let warning = refresh_warning.clone();
         let err = error_msg.clone();
-        Callback::from(move |_| {
-            let url = base_url.clone();
+        Callback::from(move |request: RequestConfig| {
             let models = models.clone();
+            let fetched_at = fetched_at.clone();
+            let refreshing = refreshing.clone();
+            let warning = warning.clone();
             let err = err.clone();
+            refreshing.set(true);
             spawn_local(async move {
-                match LlmService::fetch_models(&url).await {
-                    Ok(resp) => models.set(resp.data.into_iter().map(|m| m.id).collect()),
-                    Err(e) => err.set(e.to_string()),
+                match LlmService::refresh_models(&request).await {
+                    Ok(entry) => {
+                        err.set(String::new());
+                        warning.set(String::new());
+                        models.set(entry.models);
+                        fetched_at.set(Some(entry.fetched_at));
+                    }
+                    // A failed refresh keeps whatever list we already have
+                    Err(e) if !models.is_empty() => {
+                        let reason = LlmService::diagnose(&request, e).await;
+                        warning.set(format!("Could not refresh the model list. {} Showing the cached list.", reason));
+                    }
+                    Err(e) => err.set(LlmService::diagnose(&request, e).await.to_string()),
                 }
+                refreshing.set(false);
             });
         })
     };
 
+    // Bring the requested field into view once the panel is rendered
+    {
+        let field = props.focus_field.clone();
+        use_effect_with(field, move |field| {
+            let element = field
+                .as_ref()
+                .and_then(|f| web_sys::window()?.document()?.get_element_by_id(&format!("swi-setting-{}", f)));
+            if let Some(element) = element {
+                element.scroll_into_view();
+                if let Ok(element) = element.dyn_into::<web_sys::HtmlElement>() {
+                    let _ = element.focus();
+                }
+            }
+        });
+    }
+
+    // Refresh in the background whenever the modal opens, and when another
+    // server type lists its models elsewhere
+    {
+        let refresh = refresh_models.clone();
+        let request = RequestConfig::from(&props.settings);
+        use_effect_with(props.settings.backend_kind, move |_| refresh.emit(request));
+    }
+
+    let on_fetch = {
+        let refresh = refresh_models.clone();
+        let request = RequestConfig::from(&props.settings);
+        Callback::from(move |_| refresh.emit(request.clone()))
+    };
+
     // -- NEW: Prompt Library Handlers --
 
     // Fix: Explicitly define the input handler here to manage cloning
     let on_name_input = {
         let prompt_name_input = prompt_name_input.clone();
+        let error = prompt_name_error.clone();
         Callback::from(move |e: InputEvent| {
             let i: HtmlInputElement = e.target_unchecked_into();
             prompt_name_input.set(i.value());
+            error.set(String::new());
         })
     };
 
@@ -122,14 +562,17 @@ pub fn settings_modal(props: &SettingsProps) -> Html {
         let settings = props.settings.clone();
         let updater = update_settings.clone();
         let name_state = prompt_name_input.clone();
+        let error = prompt_name_error.clone();
 
         Callback::from(move |_| {
             let name = (*name_state).trim().to_string();
-            if !name.is_empty() {
+            if prompt_name_taken(&settings.saved_prompts, &name, None) {
+                error.set(format!("A saved prompt named \"{}\" already exists.", name));
+            } else if !name.is_empty() {
                 let mut s = settings.clone();
                 s.saved_prompts.push(SavedPrompt {
                     id: Uuid::new_v4().to_string(),
-                    name: name,
+                    name,
                     content: s.system_prompt.clone(),
                 });
                 updater(s);
@@ -138,155 +581,955 @@ pub fn settings_modal(props: &SettingsProps) -> Html {
         })
     };
 
-    let on_load_prompt = {
+    let on_delete_prompt = {
         let settings = props.settings.clone();
         let updater = update_settings.clone();
-        // We technically don't need this wrapper if we handle logic in the loop,
-        // but it's kept here if you want to use a <select> in the future.
-        // For the list UI, we used inline callbacks in the render loop below.
-        Callback::from(move |_: Event| {})
+        Callback::from(move |id: String| {
+            let mut s = settings.clone();
+            s.saved_prompts.retain(|p| p.id != id);
+            s.recurring_prompts.retain(|r| r.prompt_id != id);
+            updater(s);
+        })
     };
 
-    let on_delete_prompt = {
+    let on_rename_input = {
+        let renaming = renaming_prompt.clone();
+        let error = prompt_name_error.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            if let Some((id, _)) = (*renaming).clone() {
+                renaming.set(Some((id, input.value())));
+            }
+            error.set(String::new());
+        })
+    };
+
+    // Keeps the old name when the new one is empty; a taken name keeps the field open
+    let commit_rename = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        let renaming = renaming_prompt.clone();
+        let error = prompt_name_error.clone();
+        Callback::from(move |_: ()| {
+            let Some((id, name)) = (*renaming).clone() else { return };
+            let name = name.trim().to_string();
+            if prompt_name_taken(&settings.saved_prompts, &name, Some(&id)) {
+                error.set(format!("A saved prompt named \"{}\" already exists.", name));
+                return;
+            }
+            let mut s = settings.clone();
+            if let Some(prompt) = s.saved_prompts.iter_mut().find(|p| p.id == id).filter(|p| !name.is_empty() && p.name != name) {
+                prompt.name = name;
+                updater(s);
+            }
+            renaming.set(None);
+        })
+    };
+
+    let on_rename_keydown = {
+        let commit = commit_rename.clone();
+        let renaming = renaming_prompt.clone();
+        let error = prompt_name_error.clone();
+        Callback::from(move |e: KeyboardEvent| match e.key().as_str() {
+            "Enter" => {
+                e.prevent_default();
+                commit.emit(());
+            }
+            "Escape" => {
+                // Only the rename is cancelled, not the modal
+                e.stop_propagation();
+                renaming.set(None);
+                error.set(String::new());
+            }
+            _ => {}
+        })
+    };
+
+    let on_move_prompt = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |(from, to): (usize, usize)| {
+            let mut s = settings.clone();
+            move_item(&mut s.saved_prompts, from, to);
+            updater(s);
+        })
+    };
+
+    // -- Recurring prompts --
+
+    let on_recurring_prompt_select = {
+        let recurring_prompt_id = recurring_prompt_id.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            recurring_prompt_id.set(select.value());
+        })
+    };
+
+    let on_recurring_interval_select = {
+        let recurring_interval = recurring_interval.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            if let Some(interval) = RecurrenceInterval::ALL.iter().copied().find(|i| i.key() == select.value()) {
+                recurring_interval.set(interval);
+            }
+        })
+    };
+
+    let on_recurring_target_select = {
+        let recurring_target = recurring_target.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            recurring_target.set(select.value());
+        })
+    };
+
+    // The prompt picker shows the first saved prompt until another is chosen
+    let chosen_prompt_id = Some((*recurring_prompt_id).clone())
+        .filter(|id| props.settings.saved_prompts.iter().any(|p| &p.id == id))
+        .or_else(|| props.settings.saved_prompts.first().map(|p| p.id.clone()));
+
+    let on_add_recurring = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        let prompt_id = chosen_prompt_id.clone();
+        let interval = *recurring_interval;
+        let target = (*recurring_target).clone();
+        Callback::from(move |_: MouseEvent| {
+            let Some(prompt_id) = prompt_id.clone() else { return };
+            let mut s = settings.clone();
+            let chat_id = Some(target.clone()).filter(|t| !t.is_empty());
+            s.recurring_prompts.push(RecurringPrompt::new(prompt_id, interval, chat_id));
+            updater(s);
+        })
+    };
+
+    let on_toggle_recurring = {
         let settings = props.settings.clone();
         let updater = update_settings.clone();
         Callback::from(move |id: String| {
             let mut s = settings.clone();
-            s.saved_prompts.retain(|p| p.id != id);
+            if let Some(r) = s.recurring_prompts.iter_mut().find(|r| r.id == id) {
+                r.paused = !r.paused;
+            }
             updater(s);
         })
     };
 
+    let on_delete_recurring = {
+        let settings = props.settings.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |id: String| {
+            let mut s = settings.clone();
+            s.recurring_prompts.retain(|r| r.id != id);
+            updater(s);
+        })
+    };
+
+    let select_tab = |target: SettingsTab| {
+        let tab = tab.clone();
+        Callback::from(move |_: MouseEvent| tab.set(target))
+    };
+
+    let on_export_api_key_change = {
+        let export_api_key = export_api_key.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            export_api_key.set(input.checked());
+        })
+    };
+
+    let on_export_settings = {
+        let settings = props.settings.clone();
+        let include_api_key = *export_api_key;
+        Callback::from(move |_: MouseEvent| {
+            let json = ExportService::settings_to_json(&settings, include_api_key);
+            let _ = ExportService::download("settings.json", &json, "application/json");
+        })
+    };
+
+    let on_import_file = {
+        let settings = props.settings.clone();
+        let locked = props.locked.clone();
+        let preview = import_preview.clone();
+        let error = import_error.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let file = input.files().and_then(|f| f.get(0));
+            input.set_value("");
+            let Some(file) = file else { return; };
+            let settings = settings.clone();
+            let locked = locked.clone();
+            let preview = preview.clone();
+            let error = error.clone();
+            spawn_local(async move {
+                let text = wasm_bindgen_futures::JsFuture::from(file.text()).await.ok().and_then(|v| v.as_string());
+                // A bad file only produces a message; current settings stay untouched
+                match text.ok_or_else(|| "Could not read the file.".to_string()).and_then(|t| ExportService::settings_from_json(&t)) {
+                    Ok(imported) => {
+                        let merged = ExportService::merge_settings(&settings, imported);
+                        let merged = DeploymentService::keep_locked(&locked, &settings, merged);
+                        let changes = ExportService::settings_changes(&settings, &merged);
+                        error.set(String::new());
+                        preview.set(Some((merged, changes)));
+                    }
+                    Err(msg) => {
+                        preview.set(None);
+                        error.set(msg);
+                    }
+                }
+            });
+        })
+    };
+
+    let on_apply_import = {
+        let preview = import_preview.clone();
+        let updater = update_settings.clone();
+        Callback::from(move |_: MouseEvent| {
+            if let Some((merged, _)) = (*preview).clone() {
+                updater(merged);
+            }
+            preview.set(None);
+        })
+    };
+
+    let on_cancel_import = {
+        let preview = import_preview.clone();
+        Callback::from(move |_: MouseEvent| preview.set(None))
+    };
+
+    let open_danger = |action: Option<DangerAction>| {
+        let danger_action = danger_action.clone();
+        let confirm_text = confirm_text.clone();
+        Callback::from(move |_: MouseEvent| {
+            confirm_text.set(String::new());
+            danger_action.set(action);
+        })
+    };
+
+    let on_confirm_input = {
+        let confirm_text = confirm_text.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            confirm_text.set(input.value());
+        })
+    };
+
+    let run_danger = |action: DangerAction| {
+        let danger_action = danger_action.clone();
+        let callback = match action {
+            DangerAction::DeleteChats => props.on_clear_chats.clone(),
+            DangerAction::ResetSettings => props.on_reset.clone(),
+        };
+        Callback::from(move |_: MouseEvent| {
+            danger_action.set(None);
+            callback.emit(());
+        })
+    };
+
+    let danger_zone = {
+        let chat_count = props.chats.len();
+        let message_count: usize = props.chats.iter().map(|c| c.messages.iter().filter(|m| m.role != "system").count()).sum();
+        let document_count = DocumentService::get_documents().len();
+        let details = match *danger_action {
+            Some(DangerAction::DeleteChats) => html! {
+                <div class="swi-danger-confirm">
+                    <div>{ format!(
+                        "This permanently removes {} chat{} with {} message{}. Your {} document{} are kept.",
+                        chat_count, if chat_count == 1 { "" } else { "s" },
+                        message_count, if message_count == 1 { "" } else { "s" },
+                        document_count, if document_count == 1 { "" } else { "s" }
+                    ) }</div>
+                    <button class="swi-btn swi-mini-btn" onclick={props.on_backup_chats.reform(|_| ())}>{ "Download backup first" }</button>
+                    <label class="swi-form-label">{ format!("Type \"{}\" to confirm", DELETE_CONFIRM_WORD) }</label>
+                    <input class="swi-form-input" type="text" value={(*confirm_text).clone()} oninput={on_confirm_input} style="margin-bottom:0;" />
+                    <div class="swi-data-row">
+                        <button class="swi-btn swi-btn-danger" disabled={confirm_text.trim() != DELETE_CONFIRM_WORD} onclick={run_danger(DangerAction::DeleteChats)}>{ "Delete all chats" }</button>
+                        <button class="swi-btn" onclick={open_danger(None)}>{ "Cancel" }</button>
+                    </div>
+                </div>
+            },
+            Some(DangerAction::ResetSettings) => {
+                let defaults = AppSettings { onboarding_completed: true, ..AppSettings::default() };
+                let defaults = DeploymentService::keep_locked(&props.locked, &props.settings, defaults);
+                let changes = ExportService::settings_changes(&props.settings, &defaults);
+                html! {
+                    <div class="swi-danger-confirm">
+                        if changes.is_empty() {
+                            <div>{ "All settings already have their default values." }</div>
+                        } else {
+                            <div>{ "These values will change back to their defaults:" }</div>
+                            <ul>
+                                { for changes.iter().map(|c| html! { <li>{ c }</li> }) }
+                            </ul>
+                        }
+                        if !props.locked.is_empty() {
+                            <div>{ "Settings managed by the deployment are kept." }</div>
+                        }
+                        <div class="swi-data-row">
+                            <button class="swi-btn swi-btn-danger" disabled={changes.is_empty()} onclick={run_danger(DangerAction::ResetSettings)}>{ "Reset settings" }</button>
+                            <button class="swi-btn" onclick={open_danger(None)}>{ "Cancel" }</button>
+                        </div>
+                    </div>
+                }
+            }
+            None => html! {},
+        };
+        html! {
+            <div class="swi-danger-zone">
+                <div class="swi-danger-title">{ "Danger zone" }</div>
+                <div class="swi-danger-item">
+                    <span>{ "Delete all chats" }</span>
+                    <button class="swi-btn swi-mini-btn swi-btn-danger" onclick={open_danger(Some(DangerAction::DeleteChats))}>{ "Delete..." }</button>
+                </div>
+                <div class="swi-danger-item">
+                    <span>{ "Reset settings to defaults" }</span>
+                    <button class="swi-btn swi-mini-btn swi-btn-danger" onclick={open_danger(Some(DangerAction::ResetSettings))}>{ "Reset..." }</button>
+                </div>
+                { details }
+            </div>
+        }
+    };
+
+    let model_missing = !available_models.is_empty() && !available_models.contains(&props.settings.selected_model);
+
+    let param_field = |field: &'static str, label: &str, hint: &str, value: Option<f32>, range: RangeInclusive<f32>, apply: fn(&mut AppSettings, Option<f32>)| {
+        html! {
+            <div>
+                <label class="swi-form-label" for={format!("swi-setting-{}", field)}>{ label }</label>
+                <input
+                    id={format!("swi-setting-{}", field)}
+                    class="swi-form-input"
+                    type="number"
+                    min={range.start().to_string()}
+                    max={range.end().to_string()}
+                    step="0.05"
+                    placeholder="Server default"
+                    value={value.map(|v| v.to_string()).unwrap_or_default()}
+                    oninput={on_param_input(field, range, apply)}
+                    disabled={is_locked(field)}
+                    style="margin-bottom:0;"
+                />
+                { managed_note(field) }
+                if let Some(error) = param_errors.get(field) {
+                    <div class="swi-model-warning" role="alert">{ error }</div>
+                }
+                <p style="font-size: 0.8rem; color: var(--text-secondary); margin-top: 5px;">{ hint }</p>
+            </div>
+        }
+    };
+    let generation_focus = props.focus_field.as_deref().is_some_and(|f| GENERATION_FIELDS.contains(&f));
+
     let css = r#"
-        .settings-backdrop { position: absolute; top: 0; left: 0; width: 100%; height: 100%; background: rgba(255,255,255,0.6); backdrop-filter: blur(2px); z-index: 99; cursor: pointer; }
-        .settings-panel { position: absolute; top: 60px; right: 20px; width: 400px; background: white; border: 1px solid var(--border-color); border-radius: 8px; box-shadow: 0 10px 15px -3px rgba(0, 0, 0, 0.1); padding: 20px; z-index: 100; display: flex; flex-direction: column; gap: 15px; max-height: 80vh; overflow-y: auto; }
-        .settings-header { display: flex; justify-content: space-between; align-items: center; border-bottom: 1px solid var(--border-color); padding-bottom: 10px; margin-bottom: 5px; }
-        .settings-header h3 { margin: 0; font-size: 1.1rem; }
-        .close-btn { background: none; border: none; font-size: 1.5rem; line-height: 1; cursor: pointer; color: var(--text-secondary); padding: 0 5px; }
-        .close-btn:hover { color: var(--text-primary); }
-        .form-label { display: block; font-size: 0.85rem; font-weight: 600; margin-bottom: 5px; color: var(--text-secondary); }
-        .fetch-group { display: flex; gap: 8px; }
-        .actions { margin-top: 10px; display: flex; flex-direction: column; gap: 8px; }
+        .swi-settings-backdrop { position: absolute; top: 0; left: 0; width: 100%; height: 100%; background: rgba(255,255,255,0.6); backdrop-filter: blur(2px); z-index: 99; cursor: pointer; }
+        .swi-settings-panel { position: absolute; top: 60px; right: 20px; width: 400px; background: white; border: 1px solid var(--border-color); border-radius: 8px; box-shadow: 0 10px 15px -3px rgba(0, 0, 0, 0.1); padding: 20px; z-index: 100; display: flex; flex-direction: column; gap: 15px; max-height: 80vh; overflow-y: auto; }
+        .swi-settings-header { display: flex; justify-content: space-between; align-items: center; border-bottom: 1px solid var(--border-color); padding-bottom: 10px; margin-bottom: 5px; }
+        .swi-settings-header h3 { margin: 0; font-size: 1.1rem; }
+        .swi-close-btn { background: none; border: none; font-size: 1.5rem; line-height: 1; cursor: pointer; color: var(--text-secondary); padding: 0 5px; }
+        .swi-close-btn:hover { color: var(--text-primary); }
+        .swi-form-label { display: block; font-size: 0.85rem; font-weight: 600; margin-bottom: 5px; color: var(--text-secondary); }
+        .swi-fetch-group { display: flex; gap: 8px; }
+        .swi-actions { margin-top: 10px; display: flex; flex-direction: column; gap: 8px; }
+        .swi-model-status { font-size: 0.75rem; color: var(--text-secondary); margin-top: -5px; }
+        .swi-model-warning { font-size: 0.75rem; color: #b45309; margin-top: 4px; }
+        .swi-settings-group { border: 1px solid var(--border-color); border-radius: 6px; padding: 8px 10px; }
+        .swi-settings-group summary { cursor: pointer; font-size: 0.9rem; font-weight: 600; }
+        .swi-settings-group-body { display: flex; flex-direction: column; gap: 12px; margin-top: 10px; }
+        .swi-form-select.swi-warning { border-color: #f59e0b; background: #fffbeb; }
 
         /* New Styles for Prompt Library */
-        .prompt-tools { display: flex; gap: 5px; margin-bottom: 8px; align-items: center; }
-        .prompt-save-row { display: flex; gap: 5px; margin-top: 5px; }
-        .mini-btn { padding: 4px 8px; font-size: 0.8rem; }
-        .preset-list { display: flex; flex-direction: column; gap: 5px; margin-bottom: 10px; max-height: 100px; overflow-y: auto; border: 1px solid #eee; padding: 5px; border-radius: 4px; }
-        .preset-item { display: flex; justify-content: space-between; align-items: center; font-size: 0.85rem; padding: 4px; background: #f9f9f9; border-radius: 4px; }
-        .preset-item:hover { background: #eee; }
-        .preset-name { cursor: pointer; flex-grow: 1; font-weight: 500; }
-        .del-icon { cursor: pointer; color: #999; padding: 0 5px; }
-        .del-icon:hover { color: red; }
+        .swi-prompt-tools { display: flex; gap: 5px; margin-bottom: 8px; align-items: center; }
+        .swi-prompt-save-row { display: flex; gap: 5px; margin-top: 5px; }
+        .swi-mini-btn { padding: 4px 8px; font-size: 0.8rem; }
+        .swi-preset-list { display: flex; flex-direction: column; gap: 5px; margin-bottom: 10px; max-height: 100px; overflow-y: auto; border: 1px solid #eee; padding: 5px; border-radius: 4px; }
+        .swi-preset-item { display: flex; justify-content: space-between; align-items: center; font-size: 0.85rem; padding: 4px; background: #f9f9f9; border-radius: 4px; }
+        .swi-preset-item:hover { background: #eee; }
+        .swi-preset-name { cursor: pointer; flex-grow: 1; font-weight: 500; }
+        .swi-preset-item.swi-active { background: #eef6ff; }
+        .swi-preset-item.swi-active .swi-preset-name::after { content: " · in use"; font-weight: normal; color: var(--text-secondary); }
+        .swi-preset-rename { flex-grow: 1; margin: 0 4px; padding: 2px 6px; font-size: 0.85rem; }
+        .swi-preset-item.swi-dragging { opacity: 0.4; }
+        .swi-preset-item.swi-drop-before { box-shadow: inset 0 2px 0 var(--accent-color); }
+        .swi-preset-item.swi-drop-after { box-shadow: inset 0 -2px 0 var(--accent-color); }
+        .swi-drag-handle { cursor: grab; color: #bbb; padding: 0 4px 0 0; user-select: none; }
+        .swi-move-btn { border: none; background: none; color: #999; cursor: pointer; padding: 0 3px; font-size: 0.8rem; }
+        .swi-move-btn:hover:not(:disabled) { color: var(--text-primary); }
+        .swi-move-btn:disabled { opacity: 0.3; cursor: default; }
+        .swi-del-icon { cursor: pointer; color: #999; padding: 0 5px; }
+        .swi-preset-item.swi-paused .swi-recurring-info { opacity: 0.5; }
+        .swi-recurring-info { display: flex; flex-direction: column; flex-grow: 1; min-width: 0; }
+        .swi-recurring-meta { font-size: 0.75rem; color: var(--text-secondary); overflow: hidden; text-overflow: ellipsis; white-space: nowrap; }
+        .swi-recurring-form { display: grid; grid-template-columns: 1fr 1fr; gap: 5px; margin: 5px 0; }
+        .swi-recurring-form .swi-form-select { margin-bottom: 0; font-size: 0.85rem; }
+        .swi-del-icon:hover { color: red; }
+
+        .swi-whats-new-btn { margin-left: auto; margin-right: 8px; border: none; background: transparent; color: var(--text-secondary); font-size: 0.8rem; cursor: pointer; }
+        .swi-whats-new-btn:hover { color: var(--accent-color); }
+        /* Tabs & Data */
+        .swi-settings-tabs { display: flex; gap: 4px; border-bottom: 1px solid var(--border-color); margin-top: -10px; }
+        .swi-settings-tab { border: none; background: none; padding: 8px 12px; font-size: 0.85rem; cursor: pointer; color: var(--text-secondary); border-bottom: 2px solid transparent; }
+        .swi-settings-tab.swi-active { color: var(--accent-color); border-bottom-color: var(--accent-color); font-weight: 600; }
+        .swi-data-hint { font-size: 0.8rem; color: var(--text-secondary); margin: 0 0 8px; }
+        .swi-data-row { display: flex; gap: 8px; align-items: center; }
+        .swi-import-preview { margin-top: 10px; padding: 10px; border: 1px solid var(--border-color); border-radius: 6px; background: #f9f9f9; font-size: 0.8rem; }
+        .swi-danger-zone { margin-top: 10px; border: 1px solid #fecaca; border-radius: 6px; padding: 10px; display: flex; flex-direction: column; gap: 8px; }
+        .swi-danger-title { font-size: 0.85rem; font-weight: 600; color: var(--danger-color); }
+        .swi-danger-item { display: flex; justify-content: space-between; align-items: center; font-size: 0.85rem; }
+        .swi-danger-confirm { display: flex; flex-direction: column; gap: 8px; padding: 10px; background: #fef2f2; border-radius: 6px; font-size: 0.8rem; }
+        .swi-danger-confirm ul { margin: 0; padding-left: 18px; word-break: break-word; }
+        .swi-import-preview ul { margin: 6px 0 10px; padding-left: 18px; word-break: break-word; }
+        .swi-managed-note { display: block; font-size: 0.75rem; font-style: italic; color: var(--text-secondary); }
     "#;
+    inject_styles("settings", css);
 
     html! {
         <>
-            <style>{ css }</style>
-            <div class="settings-backdrop" onclick={props.on_close.reform(|_| ())}></div>
+            <div class="swi-settings-backdrop" onclick={props.on_close.reform(|_| ())}></div>
 
-            <div class="settings-panel">
-                <div class="settings-header">
+            <div class="swi-settings-panel">
+                <div class="swi-settings-header">
                     <h3>{ "Configuration" }</h3>
-                    <button class="close-btn" onclick={props.on_close.reform(|_| ())} title="Close">{"×"}</button>
+                    <button class="swi-whats-new-btn" onclick={props.on_whats_new.reform(|_| ())} title="Changes in recent versions">
+                        { format!("v{} · What's new", APP_VERSION) }
+                    </button>
+                    <button class="swi-close-btn" onclick={props.on_close.reform(|_| ())} title="Close">{"×"}</button>
                 </div>
 
-                <div>
-                    <label class="form-label">{ "System Prompt" }</label>
-
-                    // Saved Prompts List
-                    if !props.settings.saved_prompts.is_empty() {
-                        <div class="preset-list">
-                            { for props.settings.saved_prompts.iter().map(|p| {
-                                let id_del = p.id.clone();
-                                let on_click_del = on_delete_prompt.clone();
-                                let content = p.content.clone();
-                                let updater = update_settings.clone();
-                                let settings_c = props.settings.clone();
-
-                                html! {
-                                    <div class="preset-item">
-                                        <span class="preset-name" title={content.clone()}
-                                              onclick={Callback::from(move |_| {
-                                                  let mut s = settings_c.clone();
-                                                  s.system_prompt = content.clone();
-                                                  updater(s);
-                                              })}>
-                                            { &p.name }
-                                        </span>
-                                        <span class="del-icon" onclick={Callback::from(move |_| on_click_del.emit(id_del.clone()))}>{"×"}</span>
-                                    </div>
+                <div class="swi-settings-tabs">
+                    <button class={classes!("swi-settings-tab", (*tab == SettingsTab::General).then_some("swi-active"))} onclick={select_tab(SettingsTab::General)}>{ "General" }</button>
+                    <button class={classes!("swi-settings-tab", (*tab == SettingsTab::Data).then_some("swi-active"))} onclick={select_tab(SettingsTab::Data)}>{ "Data" }</button>
+                </div>
+
+                if *tab == SettingsTab::General {
+                    <>
+                    <div>
+                        <label class="swi-form-label">{ "System Prompt" }</label>
+                        { managed_note("system_prompt") }
+
+                        // Saved Prompts List
+                        if !props.settings.saved_prompts.is_empty() {
+                            <p class="swi-data-hint">{ "Prompt Library: click a prompt to use it as the system prompt." }</p>
+                            <div class="swi-preset-list">
+                                { for props.settings.saved_prompts.iter().enumerate().map(|(i, p)| {
+                                    let last = props.settings.saved_prompts.len() - 1;
+                                    let DragHandlers { ondragstart, ondragover, ondrop, ondragend } = prompt_drag.handlers(i, p.id.clone(), &on_move_prompt);
+                                    let move_up = on_move_prompt.reform(move |_: MouseEvent| (i, i.saturating_sub(1)));
+                                    let move_down = on_move_prompt.reform(move |_: MouseEvent| (i, i + 1));
+                                    let id_del = p.id.clone();
+                                    let on_click_del = on_delete_prompt.clone();
+                                    let content = p.content.clone();
+                                    let updater = update_settings.clone();
+                                    let settings_c = props.settings.clone();
+                                    let prompt_locked = is_locked("system_prompt");
+                                    let active = p.content == props.settings.system_prompt;
+                                    let rename_draft = renaming_prompt.as_ref().filter(|(id, _)| *id == p.id).map(|(_, name)| name.clone());
+                                    let start_rename = {
+                                        let renaming = renaming_prompt.clone();
+                                        let error = prompt_name_error.clone();
+                                        let id = p.id.clone();
+                                        let name = p.name.clone();
+                                        Callback::from(move |_: MouseEvent| {
+                                            error.set(String::new());
+                                            renaming.set(Some((id.clone(), name.clone())));
+                                        })
+                                    };
+
+                                    html! {
+                                        <div
+                                            class={classes!("swi-preset-item", prompt_drag.drop_class(i), prompt_drag.is_dragging(i).then_some("swi-dragging"), active.then_some("swi-active"))}
+                                            draggable="true"
+                                            {ondragstart}
+                                            {ondragover}
+                                            {ondrop}
+                                            {ondragend}
+                                        >
+                                            <span class="swi-drag-handle" title="Drag to reorder">{ "⠿" }</span>
+                                            if let Some(draft) = rename_draft {
+                                                <input
+                                                    class="swi-form-input swi-preset-rename"
+                                                    aria-label={format!("New name for {}", p.name)}
+                                                    value={draft}
+                                                    autofocus=true
+                                                    oninput={on_rename_input.clone()}
+                                                    onkeydown={on_rename_keydown.clone()}
+                                                    onblur={commit_rename.reform(|_| ())}
+                                                />
+                                            } else {
+                                                <span class="swi-preset-name" title={if active { format!("In use\n\n{}", content) } else { format!("Click to use\n\n{}", content) }}
+                                                      onclick={Callback::from(move |_| {
+                                                          if prompt_locked {
+                                                              return;
+                                                          }
+                                                          let mut s = settings_c.clone();
+                                                          s.system_prompt = content.clone();
+                                                          updater(s);
+                                                      })}>
+                                                    { &p.name }
+                                                </span>
+                                                <button class="swi-move-btn" title="Rename" aria-label={format!("Rename {}", p.name)} onclick={start_rename}>{ "✎" }</button>
+                                            }
+                                            <button class="swi-move-btn" title="Move up" aria-label={format!("Move {} up", p.name)} disabled={i == 0} onclick={move_up}>{ "↑" }</button>
+                                            <button class="swi-move-btn" title="Move down" aria-label={format!("Move {} down", p.name)} disabled={i == last} onclick={move_down}>{ "↓" }</button>
+                                            <span class="swi-del-icon" onclick={Callback::from(move |_| on_click_del.emit(id_del.clone()))}>{"×"}</span>
+                                        </div>
+                                    }
+                                })}
+                            </div>
+                        }
+
+                        <textarea
+                            id="swi-setting-system_prompt"
+                            class="swi-form-textarea"
+                            value={props.settings.system_prompt.clone()}
+                            oninput={on_prompt_change}
+                            disabled={is_locked("system_prompt")}
+                            style="height: 100px; resize: none; margin-bottom: 5px;"
+                        />
+
+                        <div class="swi-prompt-save-row">
+                            <input
+                                type="text"
+                                class="swi-form-input"
+                                placeholder="Preset Name (e.g., 'Coder')"
+                                style="margin-bottom:0; font-size: 0.9rem;"
+                                value={(*prompt_name_input).clone()}
+                                oninput={on_name_input} // Uses the pre-defined callback
+                            />
+                            <button class="swi-btn swi-mini-btn" disabled={prompt_name_input.trim().is_empty()} onclick={on_save_prompt}>
+                                { "Save" }
+                            </button>
+                        </div>
+                        if !prompt_name_error.is_empty() {
+                            <div class="swi-model-warning" role="alert">{ &*prompt_name_error }</div>
+                        }
+                    </div>
+
+                    <div>
+                        <label class="swi-form-label">{ "Recurring Prompts" }</label>
+                        if props.settings.saved_prompts.is_empty() {
+                            <p class="swi-data-hint">{ "Save a prompt above to have it sent automatically while the app is open." }</p>
+                        } else {
+                            if !props.settings.recurring_prompts.is_empty() {
+                                <div class="swi-preset-list">
+                                    { for props.settings.recurring_prompts.iter().map(|r| {
+                                        let name = props.settings.saved_prompts.iter().find(|p| p.id == r.prompt_id).map(|p| p.name.clone()).unwrap_or_default();
+                                        let target = r.chat_id.as_ref()
+                                            .and_then(|id| props.chats.iter().find(|c| &c.id == id && c.trashed_at.is_none()))
+                                            .map(|c| c.title.clone())
+                                            .unwrap_or_else(|| "a new chat".to_string());
+                                        let when = match (r.paused, r.interval.period_ms()) {
+                                            (true, _) => "paused".to_string(),
+                                            (false, Some(_)) => format!("next {}", format_absolute(r.next_run_at)),
+                                            (false, None) => "when the app opens".to_string(),
+                                        };
+                                        let id_toggle = r.id.clone();
+                                        let id_del = r.id.clone();
+                                        let on_toggle = on_toggle_recurring.clone();
+                                        let on_del = on_delete_recurring.clone();
+                                        html! {
+                                            <div class={classes!("swi-preset-item", r.paused.then_some("swi-paused"))}>
+                                                <span class="swi-recurring-info">
+                                                    <span class="swi-preset-name">{ name }</span>
+                                                    <span class="swi-recurring-meta">{ format!("{} · {} · into {}", r.interval.label(), when, target) }</span>
+                                                </span>
+                                                <button class="swi-btn swi-mini-btn" onclick={Callback::from(move |_| on_toggle.emit(id_toggle.clone()))}>
+                                                    { if r.paused { "Resume" } else { "Pause" } }
+                                                </button>
+                                                <span class="swi-del-icon" onclick={Callback::from(move |_| on_del.emit(id_del.clone()))}>{"×"}</span>
+                                            </div>
+                                        }
+                                    })}
+                                </div>
+                            }
+                            <div class="swi-recurring-form">
+                                <select class="swi-form-select" onchange={on_recurring_prompt_select} title="Prompt to send">
+                                    { for props.settings.saved_prompts.iter().map(|p| html! {
+                                        <option value={p.id.clone()} selected={chosen_prompt_id.as_ref() == Some(&p.id)}>{ &p.name }</option>
+                                    }) }
+                                </select>
+                                <select class="swi-form-select" onchange={on_recurring_interval_select} title="How often">
+                                    { for RecurrenceInterval::ALL.iter().map(|i| html! {
+                                        <option value={i.key()} selected={*i == *recurring_interval}>{ i.label() }</option>
+                                    }) }
+                                </select>
+                                <select class="swi-form-select" onchange={on_recurring_target_select} title="Chat it is sent into">
+                                    <option value="" selected={recurring_target.is_empty()}>{ "Into a new chat" }</option>
+                                    { for props.chats.iter().filter(|c| c.is_listed()).map(|c| html! {
+                                        <option value={c.id.clone()} selected={*recurring_target == c.id}>{ format!("Into \"{}\"", c.title) }</option>
+                                    }) }
+                                </select>
+                                <button class="swi-btn swi-mini-btn" onclick={on_add_recurring}>{ "Add" }</button>
+                            </div>
+                            <p class="swi-data-hint">{ "Sent automatically while the app is open, never while that chat is still answering." }</p>
+                        }
+                    </div>
+
+                    <div>
+                        <label class="swi-form-label">{ "Custom Instructions" }</label>
+                        <textarea
+                            id="swi-setting-custom_instructions"
+                            class="swi-form-textarea"
+                            placeholder="Applied to every chat, e.g. 'Always answer in metric units.'"
+                            value={props.settings.custom_instructions.clone()}
+                            oninput={on_custom_instructions_change}
+                            disabled={is_locked("custom_instructions")}
+                            style="height: 70px; resize: none; margin-bottom: 0;"
+                        />
+                        { managed_note("custom_instructions") }
+                    </div>
+
+                    <div>
+                        <label class="swi-form-label">{ "Server URL" }</label>
+                        <div class="swi-fetch-group">
+                            <input id="swi-setting-base_url" class="swi-form-input" type="text" value={props.settings.base_url.clone()} oninput={on_url_input} disabled={is_locked("base_url")} style="margin-bottom:0;" />
+                            <button class="swi-btn" onclick={on_fetch} title="Refresh Models">{ "⟳" }</button>
+                        </div>
+                        { managed_note("base_url") }
+                    </div>
+
+                    <div>
+                        <label class="swi-form-label" for="swi-setting-backend_kind">{ "Server Type" }</label>
+                        <select id="swi-setting-backend_kind" class="swi-form-select" onchange={on_backend_change} disabled={is_locked("backend_kind")}>
+                            <option value="openai" selected={props.settings.backend_kind == BackendKind::OpenAI}>{ "OpenAI-compatible (/v1/chat/completions)" }</option>
+                            <option value="ollama" selected={props.settings.backend_kind == BackendKind::Ollama}>{ "Ollama native (/api/chat)" }</option>
+                        </select>
+                        { managed_note("backend_kind") }
+                    </div>
+
+                    <div>
+                        <label class="swi-form-label" for="swi-setting-api_key">{ "API Key" }</label>
+                        <input
+                            id="swi-setting-api_key"
+                            class="swi-form-input"
+                            type="password"
+                            autocomplete="off"
+                            placeholder="Not needed for most local servers"
+                            value={props.settings.api_key.clone()}
+                            oninput={on_api_key_input}
+                            disabled={is_locked("api_key")}
+                            style="margin-bottom:0;"
+                        />
+                        { managed_note("api_key") }
+                        <p style="font-size: 0.8rem; color: var(--text-secondary); margin-top: 5px;">
+                            { "Sent as a bearer token, e.g. for OpenRouter or a server behind authentication. Stored in this browser." }
+                        </p>
+                    </div>
+
+                    <div>
+                        <label class="swi-form-label">{ "Model" }</label>
+                        <select id="swi-setting-selected_model" class={classes!("swi-form-select", model_missing.then_some("swi-warning"))} onchange={on_model_change} disabled={is_locked("selected_model")}>
+                            {
+                                if available_models.is_empty() {
+                                    html! { <option value={props.settings.selected_model.clone()} selected=true>{ &props.settings.selected_model }</option> }
+                                } else {
+                                    html! {
+                                        <>
+                                            if model_missing {
+                                                <option value={props.settings.selected_model.clone()} selected=true>{ format!("{} (not found)", props.settings.selected_model) }</option>
+                                            }
+                                            { for available_models.iter().map(|m| html! { <option value={m.clone()} selected={*m == props.settings.selected_model}>{m}</option> }) }
+                                        </>
+                                    }
                                 }
-                            })}
+                            }
+                        </select>
+                        { managed_note("selected_model") }
+                        if model_missing {
+                            <div class="swi-model-warning">{ "The selected model isn't available on this server. Requests may fail." }</div>
+                        }
+                        <div class="swi-model-status">
+                            if *refreshing {
+                                { "Refreshing model list..." }
+                            } else if let Some(at) = *fetched_at {
+                                { format!("Model list fetched {}", format_relative_time(at, js_sys::Date::now())) }
+                            }
                         </div>
-                    }
+                        if !refresh_warning.is_empty() {
+                            <div class="swi-model-warning">{ &*refresh_warning }</div>
+                        }
+                    </div>
+
+                    <label style="display: flex; gap: 8px; align-items: center; cursor: pointer; font-size: 0.9rem;">
+                        <input type="checkbox" checked={props.settings.stream_enabled} onchange={on_stream_change} disabled={is_locked("stream_enabled")}/>
+                        { "Stream Responses" }
+                        { managed_note("stream_enabled") }
+                    </label>
+
+                    <label style="display: flex; gap: 8px; align-items: center; cursor: pointer; font-size: 0.9rem;" title="An empty answer is replaced by a notice with a Retry button either way">
+                        <input type="checkbox" checked={props.settings.remove_empty_responses} onchange={on_remove_empty_change} disabled={is_locked("remove_empty_responses")}/>
+                        { "Remove empty responses from the chat" }
+                        { managed_note("remove_empty_responses") }
+                    </label>
+
+                    <div>
+                        <label style="display: flex; gap: 8px; align-items: center; cursor: pointer; font-size: 0.9rem;" title="For servers that unload the model when idle. Pings pause while the tab is hidden or a response is generating.">
+                            <input type="checkbox" checked={props.settings.keep_alive} onchange={on_keep_alive_change} disabled={is_locked("keep_alive")}/>
+                            { "Keep the model loaded while idle" }
+                            { managed_note("keep_alive") }
+                        </label>
+                        if props.settings.keep_alive {
+                            <label style="display: flex; gap: 8px; align-items: center; font-size: 0.85rem; margin: 6px 0 0 24px;">
+                                { "Ping every" }
+                                <input id="swi-setting-keep_alive_minutes" class="swi-form-input" type="number" min="1" step="1" value={props.settings.keep_alive_minutes.to_string()} oninput={on_keep_alive_minutes_input} disabled={is_locked("keep_alive_minutes")} style="width: 70px; margin-bottom: 0;" />
+                                { "minutes" }
+                                { managed_note("keep_alive_minutes") }
+                            </label>
+                        }
+                    </div>
+
+                    <label style="display: flex; gap: 8px; align-items: center; font-size: 0.9rem;" title="Shows a warning, without stopping the response, when the server sends nothing for this long. 0 turns the warning off.">
+                        { "Warn after" }
+                        <input id="swi-setting-stall_warning_seconds" class="swi-form-input" type="number" min="0" step="5" value={props.settings.stall_warning_seconds.to_string()} oninput={on_stall_warning_input} disabled={is_locked("stall_warning_seconds")} style="width: 70px; margin-bottom: 0;" />
+                        { "seconds without a response" }
+                        { managed_note("stall_warning_seconds") }
+                    </label>
+
+                    <label style="display: flex; gap: 8px; align-items: center; font-size: 0.9rem;" title="Only after connection errors and a busy server (HTTP 429 or 503), waiting longer each time. 0 turns retrying off.">
+                        { "Retry failed requests" }
+                        <input id="swi-setting-request_retries" class="swi-form-input" type="number" min="0" max={MAX_REQUEST_RETRIES.to_string()} value={props.settings.request_retries.to_string()} oninput={on_request_retries_input} disabled={is_locked("request_retries")} style="width: 70px; margin-bottom: 0;" />
+                        { "times" }
+                        { managed_note("request_retries") }
+                    </label>
+
+                    <label style="display: flex; gap: 8px; align-items: center; cursor: pointer; font-size: 0.9rem;">
+                        <input type="checkbox" checked={props.settings.stop_on_chat_switch} onchange={on_stop_on_switch_change} disabled={is_locked("stop_on_chat_switch")}/>
+                        { "Stop generation when leaving a chat" }
+                        { managed_note("stop_on_chat_switch") }
+                    </label>
+
+                    <label style="display: flex; gap: 8px; align-items: center; cursor: pointer; font-size: 0.9rem;" title="Compares each question with your earlier ones, without delaying the send">
+                        <input type="checkbox" checked={props.settings.similar_question_hints} onchange={on_similar_hints_change} disabled={is_locked("similar_question_hints")}/>
+                        { "Point out questions I asked before" }
+                        { managed_note("similar_question_hints") }
+                    </label>
+
+                    <label style="display: flex; gap: 8px; align-items: center; cursor: pointer; font-size: 0.9rem;" title="Matches what you type against your documents' words while you type">
+                        <input type="checkbox" checked={props.settings.document_suggestions} onchange={on_document_suggestions_change} disabled={is_locked("document_suggestions")}/>
+                        { "Suggest documents related to my message" }
+                        { managed_note("document_suggestions") }
+                    </label>
+
+                    <label style="display: flex; gap: 8px; align-items: center; cursor: pointer; font-size: 0.9rem;" title="Not all servers support this; the data is not saved">
+                        <input type="checkbox" checked={props.settings.logprobs_enabled} onchange={on_logprobs_change} disabled={is_locked("logprobs_enabled")}/>
+                        { "Request token probabilities (logprobs)" }
+                        { managed_note("logprobs_enabled") }
+                    </label>
+
+                    <label style="display: flex; gap: 8px; align-items: center; cursor: pointer; font-size: 0.9rem;" title="Stores the exact request with each message; uses more storage">
+                        <input type="checkbox" checked={props.settings.debug_mode} onchange={on_debug_mode_change} disabled={is_locked("debug_mode")}/>
+                        { "Debug mode (keep full request payloads)" }
+                        { managed_note("debug_mode") }
+                    </label>
 
-                    <textarea
-                        class="form-textarea"
-                        value={props.settings.system_prompt.clone()}
-                        oninput={on_prompt_change}
-                        style="height: 100px; resize: none; margin-bottom: 5px;"
-                    />
+                    <label style="display: flex; gap: 8px; align-items: center; cursor: pointer; font-size: 0.9rem;" title="Darker text and borders, and a visible outline on whatever has focus">
+                        <input type="checkbox" checked={props.settings.high_contrast} onchange={on_high_contrast_change} disabled={is_locked("high_contrast")}/>
+                        { "High contrast" }
+                        { managed_note("high_contrast") }
+                    </label>
 
-                    <div class="prompt-save-row">
+                    <label style="display: flex; gap: 8px; align-items: center; cursor: pointer; font-size: 0.9rem;" title="Always on when your system is set to reduce motion">
+                        <input type="checkbox" checked={props.settings.reduced_motion} onchange={on_reduced_motion_change} disabled={is_locked("reduced_motion")}/>
+                        { "Reduce motion (no sliding or animated transitions)" }
+                        { managed_note("reduced_motion") }
+                    </label>
+
+                    <div>
+                        <label class="swi-form-label">{ "Document Context Mode" }</label>
+                        <select id="swi-setting-document_context_mode" class="swi-form-select" onchange={on_doc_context_mode_change} disabled={is_locked("document_context_mode")}>
+                            <option value="rag" selected={props.settings.document_context_mode == crate::models::DocumentContextMode::RAG}>{ "RAG (Automatic Context)" }</option>
+                            <option value="manual" selected={props.settings.document_context_mode == crate::models::DocumentContextMode::Manual}>{ "Manual (Use @doc-id in prompts)" }</option>
+                            <option value="off" selected={props.settings.document_context_mode == crate::models::DocumentContextMode::Off}>{ "Off (Never send documents)" }</option>
+                        </select>
+                        { managed_note("document_context_mode") }
+                        <p style="font-size: 0.8rem; color: var(--text-secondary); margin-top: 5px;">
+                            { "Choose how documents are used in conversations." }
+                        </p>
+                        if props.settings.document_context_mode == crate::models::DocumentContextMode::Manual {
+                            <label style="display: flex; gap: 8px; align-items: center; cursor: pointer; font-size: 0.9rem;" title="The model can then ask for a document, and its @ mention becomes a chip you can click to attach it">
+                                <input type="checkbox" checked={props.settings.document_list_in_prompt} onchange={on_document_list_change} disabled={is_locked("document_list_in_prompt")}/>
+                                { "Tell the model which documents can be attached" }
+                                { managed_note("document_list_in_prompt") }
+                            </label>
+                        }
+                    </div>
+
+                    <div>
+                        <label class="swi-form-label">{ "Embedding Model" }</label>
+                        <input id="swi-setting-embedding_model" class="swi-form-input" type="text" placeholder="e.g. nomic-embed-text" value={props.settings.embedding_model.clone()} oninput={on_embedding_model_input} disabled={is_locked("embedding_model")} style="margin-bottom:0;" />
+                        { managed_note("embedding_model") }
+                        <p style="font-size: 0.8rem; color: var(--text-secondary); margin-top: 5px;">
+                            { "With a model set, documents are indexed through /v1/embeddings and RAG sends only the most relevant chunks. Leave empty to send whole documents." }
+                        </p>
+                    </div>
+
+                    <div>
+                        <label class="swi-form-label">{ "Confirm Large Requests" }</label>
+                        <input class="swi-form-input" type="number" min="0" step="500" value={props.settings.prompt_warning_tokens.to_string()} oninput={on_prompt_warning_input} disabled={is_locked("prompt_warning_tokens")} style="margin-bottom:0;" />
+                        { managed_note("prompt_warning_tokens") }
+                        <p style="font-size: 0.8rem; color: var(--text-secondary); margin-top: 5px;">
+                            { "Ask before sending a message whose estimated size exceeds this many tokens (0 = never ask)." }
+                        </p>
+                    </div>
+
+                    <div>
+                        <label class="swi-form-label">{ "Context Length" }</label>
+                        <input id="swi-setting-context_length" class="swi-form-input" type="number" min="0" step="1024" value={props.settings.context_length.to_string()} oninput={on_context_length_input} disabled={is_locked("context_length")} style="margin-bottom:0;" />
+                        { managed_note("context_length") }
+                        <p style="font-size: 0.8rem; color: var(--text-secondary); margin-top: 5px;">
+                            { "Tokens the model accepts per request; used for the usage bar under the header and the count next to the input." }
+                        </p>
+                        <label class="swi-form-label" for="swi-setting-model_context_lengths" style="margin-top: 8px;">{ format!("Context Length of {}", props.settings.selected_model) }</label>
                         <input
-                            type="text"
-                            class="form-input"
-                            placeholder="Preset Name (e.g., 'Coder')"
-                            style="margin-bottom:0; font-size: 0.9rem;"
-                            value={(*prompt_name_input).clone()}
-                            oninput={on_name_input} // Uses the pre-defined callback
+                            id="swi-setting-model_context_lengths"
+                            class="swi-form-input"
+                            type="number"
+                            min="0"
+                            step="1024"
+                            placeholder="Same as above"
+                            value={props.settings.model_context_lengths.get(&props.settings.selected_model).map(|n| n.to_string()).unwrap_or_default()}
+                            oninput={on_model_context_length_input}
+                            disabled={is_locked("model_context_lengths")}
+                            style="margin-bottom:0;"
                         />
-                        <button class="btn mini-btn" disabled={prompt_name_input.is_empty()} onclick={on_save_prompt}>
-                            { "Save" }
-                        </button>
+                        { managed_note("model_context_lengths") }
                     </div>
-                </div>
 
-                <div>
-                    <label class="form-label">{ "Server URL" }</label>
-                    <div class="fetch-group">
-                        <input class="form-input" type="text" value={props.settings.base_url.clone()} oninput={on_url_input} style="margin-bottom:0;" />
-                        <button class="btn" onclick={on_fetch} title="Refresh Models">{ "⟳" }</button>
+                    <div>
+                        <label class="swi-form-label" for="swi-setting-context_strategy">{ "When the Context Is Full" }</label>
+                        <select id="swi-setting-context_strategy" class="swi-form-select" onchange={on_context_strategy_change} disabled={is_locked("context_strategy")}>
+                            { for ContextStrategy::ALL.iter().map(|s| html! {
+                                <option value={s.key()} selected={*s == props.settings.context_strategy}>{ s.label() }</option>
+                            }) }
+                        </select>
+                        { managed_note("context_strategy") }
+                        <p style="font-size: 0.8rem; color: var(--text-secondary); margin-top: 5px;">
+                            { "The system prompt and your new message are always sent; room is kept for the answer. Messages that were left out stay in the chat." }
+                        </p>
                     </div>
-                </div>
 
-                <div>
-                    <label class="form-label">{ "Model" }</label>
-                    <select class="form-select" onchange={on_model_change}>
-                        {
-                            if available_models.is_empty() {
-                                html! { <option value={props.settings.selected_model.clone()} selected=true>{ &props.settings.selected_model }</option> }
-                            } else {
-                                html! { for available_models.iter().map(|m| html! { <option value={m.clone()}>{m}</option> }) }
+                    <div>
+                        <label class="swi-form-label" for="swi-setting-response_length">{ "Response Length" }</label>
+                        <select id="swi-setting-response_length" class="swi-form-select" onchange={on_response_length_change} disabled={is_locked("response_length")} style="margin-bottom:0;">
+                            { for ResponseLength::PRESETS.iter().map(|l| html! {
+                                <option value={l.label()} selected={props.settings.response_length == *l}>{ l.describe() }</option>
+                            }) }
+                            if let custom @ ResponseLength::Custom(_) = props.settings.response_length {
+                                <option value={custom.label()} selected=true>{ custom.describe() }</option>
                             }
+                        </select>
+                        { managed_note("response_length") }
+                        <p style="font-size: 0.8rem; color: var(--text-secondary); margin-top: 5px;">
+                            { "Default for chats that don't pick their own from the length button above the input." }
+                        </p>
+                    </div>
+
+                    <details class="swi-settings-group" open={generation_focus}>
+                        <summary>{ "Generation Parameters" }</summary>
+                        <div class="swi-settings-group-body">
+                            <div>
+                                <label class="swi-form-label" for="swi-setting-temperature">{ format!("Temperature: {:.2}", props.settings.temperature) }</label>
+                                <input
+                                    id="swi-setting-temperature"
+                                    type="range"
+                                    min="0"
+                                    max="2"
+                                    step="0.05"
+                                    value={props.settings.temperature.to_string()}
+                                    oninput={on_param_input("temperature", 0.0..=2.0, |s, v| s.temperature = v.unwrap_or(DEFAULT_TEMPERATURE))}
+                                    disabled={is_locked("temperature")}
+                                    style="width: 100%;"
+                                />
+                                { managed_note("temperature") }
+                                <p style="font-size: 0.8rem; color: var(--text-secondary); margin-top: 5px;">
+                                    { "Lower is more focused, higher more varied. A chat can use its own from the model button above the input." }
+                                </p>
+                            </div>
+                            { param_field("top_p", "Top P", "Samples only from the most likely tokens that together reach this probability (0–1).", props.settings.top_p, 0.0..=1.0, |s, v| s.top_p = v) }
+                            { param_field("frequency_penalty", "Frequency Penalty", "Positive values make the model repeat words less (-2 to 2).", props.settings.frequency_penalty, -2.0..=2.0, |s, v| s.frequency_penalty = v) }
+                            { param_field("presence_penalty", "Presence Penalty", "Positive values push the model towards new topics (-2 to 2).", props.settings.presence_penalty, -2.0..=2.0, |s, v| s.presence_penalty = v) }
+                            <div>
+                                <label class="swi-form-label" for="swi-setting-max_tokens">{ "Max Tokens" }</label>
+                                <input id="swi-setting-max_tokens" class="swi-form-input" type="number" min="1" step="256" placeholder="No limit" value={props.settings.max_tokens.map(|n| n.to_string()).unwrap_or_default()} oninput={on_max_tokens_input} disabled={is_locked("max_tokens")} style="margin-bottom:0;" />
+                                { managed_note("max_tokens") }
+                                <p style="font-size: 0.8rem; color: var(--text-secondary); margin-top: 5px;">
+                                    { "Longest answer, for response lengths that don't set their own." }
+                                </p>
+                            </div>
+                            <div>
+                                <label class="swi-form-label" for="swi-setting-stop_sequences">{ "Stop Sequences" }</label>
+                                <textarea
+                                    id="swi-setting-stop_sequences"
+                                    class="swi-form-input"
+                                    rows="2"
+                                    value={props.settings.stop_sequences.iter().map(|s| s.replace('\n', "\\n")).collect::<Vec<_>>().join("\n")}
+                                    oninput={on_stop_sequences_input}
+                                    disabled={is_locked("stop_sequences")}
+                                    style="margin-bottom:0; resize: vertical;"
+                                />
+                                { managed_note("stop_sequences") }
+                                <p style="font-size: 0.8rem; color: var(--text-secondary); margin-top: 5px;">
+                                    { "One per line; the answer ends before any of them. Write \\n for a line break." }
+                                </p>
+                            </div>
+                        </div>
+                    </details>
+
+                    <div>
+                        <label class="swi-form-label">{ "Show Sizes In" }</label>
+                        <select class="swi-form-select" onchange={on_count_unit_change} disabled={is_locked("count_unit")}>
+                            <option value="tokens" selected={props.settings.count_unit == CountUnit::Tokens}>{ "Tokens" }</option>
+                            <option value="words" selected={props.settings.count_unit == CountUnit::Words}>{ "Words and characters" }</option>
+                        </select>
+                        { managed_note("count_unit") }
+                    </div>
+                    </>
+                }
+
+                if *tab == SettingsTab::Data {
+                    <div>
+                        <label class="swi-form-label">{ "Settings" }</label>
+                        <p class="swi-data-hint">{ "Copy your configuration to another browser. Chats are not included." }</p>
+                        <div class="swi-data-row">
+                            <button class="swi-btn" onclick={on_export_settings}>{ "Export settings" }</button>
+                            <label class="swi-btn" for="settings-import-input">{ "Import settings" }</label>
+                            <input id="settings-import-input" type="file" accept=".json,application/json" style="display: none;" onchange={on_import_file} />
+                        </div>
+                        if !props.settings.api_key.is_empty() {
+                            <label style="display: flex; gap: 8px; align-items: center; cursor: pointer; font-size: 0.85rem; margin-top: 8px;">
+                                <input type="checkbox" checked={*export_api_key} onchange={on_export_api_key_change}/>
+                                { "Include the API key in the exported file" }
+                            </label>
                         }
-                    </select>
-                </div>
+                        if !import_error.is_empty() {
+                            <div class="swi-model-warning">{ &*import_error }</div>
+                        }
+                        if let Some((_, changes)) = &*import_preview {
+                            <div class="swi-import-preview">
+                                if changes.is_empty() {
+                                    <div>{ "The file matches your current settings." }</div>
+                                } else {
+                                    <div>{ "Importing will change:" }</div>
+                                    <ul>
+                                        { for changes.iter().map(|c| html! { <li>{ c }</li> }) }
+                                    </ul>
+                                }
+                                <div class="swi-data-row">
+                                    <button class="swi-btn swi-btn-primary" disabled={changes.is_empty()} onclick={on_apply_import}>{ "Apply" }</button>
+                                    <button class="swi-btn" onclick={on_cancel_import}>{ "Cancel" }</button>
+                                </div>
+                            </div>
+                        }
+                    </div>
 
-                <label style="display: flex; gap: 8px; align-items: center; cursor: pointer; font-size: 0.9rem;">
-                    <input type="checkbox" checked={props.settings.stream_enabled} onchange={on_stream_change}/>
-                    { "Stream Responses" }
-                </label>
-
-                <div>
-                    <label class="form-label">{ "Document Context Mode" }</label>
-                    <select class="form-select" onchange={on_doc_context_mode_change}>
-                        <option value="rag" selected={props.settings.document_context_mode == crate::models::DocumentContextMode::RAG}>{ "RAG (Automatic Context)" }</option>
-                        <option value="manual" selected={props.settings.document_context_mode == crate::models::DocumentContextMode::Manual}>{ "Manual (Use @doc-id in prompts)" }</option>
-                    </select>
-                    <p style="font-size: 0.8rem; color: var(--text-secondary); margin-top: 5px;">
-                        { "Choose how documents are used in conversations." }
-                    </p>
-                </div>
+                    <Usage chats={props.chats.clone()} count_unit={props.settings.count_unit} />
 
-                <div class="actions">
-                    <hr style="width: 100%; border: 0; border-top: 1px solid var(--border-color);" />
-                    <button class="btn btn-danger" onclick={props.on_clear_chats.reform(|_| ())}>{ "Delete All Chats" }</button>
-                    <button class="btn" onclick={props.on_reset.reform(|_| ())}>{ "Reset Settings" }</button>
-                </div>
+                    <div id="swi-setting-storage">
+                        <StorageUsage />
+                    </div>
+
+                    { danger_zone }
+                }
                 if !error_msg.is_empty() { <div style="color: red; font-size: 0.8rem;">{ &*error_msg }</div> }
             </div>
         </>
     }
-}
\ No newline at end of file
+}
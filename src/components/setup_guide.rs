@@ -0,0 +1,114 @@
+use yew::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+use crate::services::error::LlmError;
+use crate::services::llm::LlmService;
+use crate::services::request::RequestConfig;
+use crate::models::PROVIDER_PRESETS;
+use crate::utils::inject_styles;
+
+#[derive(Properties, PartialEq)]
+pub struct SetupGuideProps {
+    /// Server the test is sent to
+    pub request: RequestConfig,
+    /// Why the last message in this chat got no answer
+    pub last_error: Option<LlmError>,
+    /// Whether the server address is set by the deployment
+    #[prop_or_default]
+    pub url_locked: bool,
+    pub on_base_url_change: Callback<String>,
+    /// A test reached the server
+    pub on_connected: Callback<()>,
+    pub on_open_settings: Callback<()>,
+}
+
+/// Shown in an empty chat until the server has answered once, so a wrong
+/// address is noticed before the first message rather than after it
+#[function_component(SetupGuide)]
+pub fn setup_guide(props: &SetupGuideProps) -> Html {
+    let testing = use_state(|| false);
+    // Outcome of the last test: the number of models, or the diagnosis
+    let test_result = use_state(|| None::<Result<usize, LlmError>>);
+
+    // A result is about the address it was tested with
+    {
+        let test_result = test_result.clone();
+        use_effect_with(props.request.base_url.clone(), move |_| test_result.set(None));
+    }
+
+    let on_test = {
+        let testing = testing.clone();
+        let test_result = test_result.clone();
+        let request = props.request.clone();
+        let on_connected = props.on_connected.clone();
+        Callback::from(move |_| {
+            let testing = testing.clone();
+            let test_result = test_result.clone();
+            let request = request.clone();
+            let on_connected = on_connected.clone();
+            testing.set(true);
+            spawn_local(async move {
+                match LlmService::test_connection(&request).await {
+                    Ok(models) => {
+                        test_result.set(Some(Ok(models.len())));
+                        on_connected.emit(());
+                    }
+                    Err(e) => test_result.set(Some(Err(LlmService::diagnose(&request, e).await))),
+                }
+                testing.set(false);
+            });
+        })
+    };
+
+    let css = r#"
+        .swi-setup-guide { max-width: 520px; margin: 40px auto; padding: 20px; border: 1px solid var(--border-color); border-radius: 10px; display: flex; flex-direction: column; gap: 12px; font-size: 0.9rem; }
+        .swi-setup-guide h3 { margin: 0; font-size: 1.05rem; }
+        .swi-setup-guide p { margin: 0; color: var(--text-secondary); line-height: 1.5; }
+        .swi-setup-url { display: flex; align-items: center; gap: 8px; }
+        .swi-setup-url code { flex-grow: 1; background: #f5f5f5; border-radius: 4px; padding: 4px 8px; overflow-wrap: anywhere; }
+        .swi-setup-presets { display: flex; flex-wrap: wrap; gap: 6px; align-items: center; }
+        .swi-setup-presets span { color: var(--text-secondary); font-size: 0.85rem; }
+        .swi-setup-error { color: var(--danger-color); }
+        .swi-setup-ok { color: var(--accent-color); }
+    "#;
+    inject_styles("setup_guide", css);
+
+    // The test is more recent than the chat's failed message
+    let error = match &*test_result {
+        Some(Err(e)) => Some(e.clone()),
+        Some(Ok(_)) => None,
+        None => props.last_error.clone(),
+    };
+
+    html! {
+        <div class="swi-setup-guide">
+            <h3>{ "Not connected to a server yet" }</h3>
+            <p>{ "Messages are sent to the server below. Check that it is running and reachable before you start." }</p>
+            <div class="swi-setup-url">
+                <code>{ &props.request.base_url }</code>
+                <button class="swi-btn swi-mini-btn" onclick={on_test} disabled={*testing}>
+                    { if *testing { "Testing..." } else { "Test connection" } }
+                </button>
+            </div>
+            if !props.url_locked {
+                <div class="swi-setup-presets">
+                    <span>{ "Or use:" }</span>
+                    { for PROVIDER_PRESETS.iter().filter(|p| p.base_url != props.request.base_url).map(|preset| {
+                        let url = preset.base_url.to_string();
+                        html! {
+                            <button class="swi-btn swi-mini-btn" title={preset.base_url} onclick={props.on_base_url_change.reform(move |_| url.clone())}>
+                                { preset.name }
+                            </button>
+                        }
+                    }) }
+                    <button class="swi-btn swi-mini-btn" onclick={props.on_open_settings.reform(|_| ())}>{ "Other address…" }</button>
+                </div>
+            }
+            if let Some(Ok(count)) = &*test_result {
+                <div class="swi-setup-ok" role="status">{ format!("Connected — {} model(s) available", count) }</div>
+            }
+            if let Some(error) = error {
+                <div class="swi-setup-error" role="alert">{ error.to_string() }</div>
+            }
+        </div>
+    }
+}
@@ -1,6 +1,60 @@
 use yew::prelude::*;
-use crate::models::ChatSession;
+use std::collections::{BTreeSet, HashMap};
+use web_sys::{window, HtmlInputElement, HtmlSelectElement};
+use crate::models::{ChatSession, CountUnit, Document, Message};
 use crate::components::documents::Documents;
+use crate::components::reorder::{use_drag_reorder, DragHandlers};
+use crate::services::export::{ExportService, FineTuneOptions};
+use crate::services::request::RequestConfig;
+use crate::utils::{format_absolute, format_date_group, format_relative_time, markdown_to_plain, move_item, inject_styles};
+
+/// Mutations applied to several chats at once
+#[derive(Clone, PartialEq, Debug)]
+pub enum BulkAction {
+    Trash,
+    Archive,
+    Restore,
+    DeleteForever,
+    MoveToFolder(Option<String>),
+    SetLabel(Option<String>),
+}
+
+/// Color labels a chat can carry: (key stored on the chat, name, color)
+pub const CHAT_LABELS: &[(&str, &str, &str)] = &[
+    ("red", "Red", "#ef4444"),
+    ("orange", "Orange", "#f97316"),
+    ("yellow", "Yellow", "#eab308"),
+    ("green", "Green", "#22c55e"),
+    ("blue", "Blue", "#3b82f6"),
+    ("purple", "Purple", "#a855f7"),
+];
+
+/// Name and color of a label key, if it's a known one
+fn chat_label(key: &str) -> Option<(&'static str, &'static str)> {
+    CHAT_LABELS.iter().find(|(k, _, _)| *k == key).map(|(_, name, color)| (*name, *color))
+}
+
+// Characters of the last message shown under a chat's title
+const PREVIEW_CHARS: usize = 80;
+
+/// One line of a message as plain text, prefixed with who wrote it
+fn message_preview(message: &Message) -> String {
+    let plain = markdown_to_plain(&message.content);
+    let line = plain.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut preview: String = line.chars().take(PREVIEW_CHARS).collect();
+    if line.chars().count() > PREVIEW_CHARS {
+        preview.push('…');
+    }
+    let author = if message.role == "user" { "You" } else { "AI" };
+    format!("{}: {}", author, preview)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ChatView {
+    Chats,
+    Archived,
+    Trash,
+}
 
 #[derive(Properties, PartialEq)]
 pub struct SidebarProps {
@@ -10,94 +64,655 @@ pub struct SidebarProps {
     pub on_select: Callback<String>,
     pub on_delete: Callback<(MouseEvent, String)>,
     pub on_new: Callback<()>,
+    pub on_bulk_action: Callback<(BulkAction, Vec<String>)>,
+    pub selected_document_id: Option<String>,
+    pub on_document_selected: Callback<Option<String>>,
+    #[prop_or_default]
+    pub on_document_uploaded: Callback<Document>,
+    /// Server and model documents are indexed with; no model means no indexing
+    #[prop_or_default]
+    pub request: RequestConfig,
+    #[prop_or_default]
+    pub embedding_model: String,
+    /// Drops deleted documents from the chats' document scopes
+    #[prop_or_default]
+    pub on_documents_deleted: Callback<Vec<String>>,
+    /// Opens the settings at a field
+    #[prop_or_default]
+    pub on_open_setting: Callback<String>,
+    /// Ids of the documents added from the chat, in order
+    #[prop_or_default]
+    pub documents_added: Vec<String>,
+    #[prop_or_default]
+    pub count_unit: CountUnit,
+    pub folder_order: Vec<String>,
+    pub on_reorder_folders: Callback<Vec<String>>,
+}
+
+/// Folders in use, in the saved order with new folders appended as first seen
+fn ordered_folders(chats: &[ChatSession], order: &[String]) -> Vec<(String, usize)> {
+    let mut folders: Vec<(String, usize)> = order.iter().map(|f| (f.clone(), 0)).collect();
+    for folder in chats.iter().filter(|c| c.is_listed()).filter_map(|c| c.folder.as_ref()) {
+        match folders.iter_mut().find(|(f, _)| f == folder) {
+            Some(entry) => entry.1 += 1,
+            None => folders.push((folder.clone(), 1)),
+        }
+    }
+    folders.retain(|(_, n)| *n > 0);
+    folders
 }
 
 #[function_component(Sidebar)]
 pub fn sidebar(props: &SidebarProps) -> Html {
     let width = if props.open { "260px" } else { "0px" };
+    let view = use_state(|| ChatView::Chats);
+    let search = use_state(String::new);
+    let select_mode = use_state(|| false);
+    let selected = use_state(BTreeSet::<String>::new);
+    // Options of the fine-tuning export while its panel is open
+    let fine_tune = use_state(|| None::<FineTuneOptions>);
+    let folder_filter = use_state(|| None::<String>);
+    let label_filter = use_state(|| None::<String>);
+    // Chat whose label picker is open
+    let label_picker = use_state(|| None::<String>);
+    let folder_drag = use_drag_reorder();
+    // Previews by chat id, with the id and length of the message each was made from
+    let previews = use_mut_ref(HashMap::<String, (String, usize, String)>::new);
+    let folders = ordered_folders(&props.chats, &props.folder_order);
+    // A filter on a folder that no longer has chats shows everything again
+    let active_folder = (*folder_filter).clone().filter(|f| folders.iter().any(|(name, _)| name == f));
+
+    // Chats matching the current view and search, in list order
+    let query = search.to_lowercase();
+    let mut visible: Vec<&ChatSession> = props.chats.iter()
+        .filter(|c| match *view {
+            ChatView::Chats => c.is_listed(),
+            ChatView::Archived => c.archived && c.trashed_at.is_none(),
+            ChatView::Trash => c.trashed_at.is_some(),
+        })
+        .filter(|c| *view != ChatView::Chats || active_folder.is_none() || c.folder == active_folder)
+        .filter(|c| label_filter.is_none() || c.label == *label_filter)
+        .filter(|c| {
+            query.is_empty()
+                || c.title.to_lowercase().contains(&query)
+                || c.folder.as_ref().is_some_and(|f| f.to_lowercase().contains(&query))
+                || c.label.as_deref().and_then(chat_label).is_some_and(|(name, _)| name.to_lowercase().contains(&query))
+        })
+        .collect();
+    if *view == ChatView::Trash {
+        // Most recently deleted first
+        visible.sort_by(|a, b| b.trashed_at.partial_cmp(&a.trashed_at).unwrap_or(std::cmp::Ordering::Equal));
+    }
+    // Trashed chats are grouped by when they were deleted, the rest by creation
+    let group_time = |c: &ChatSession| match *view {
+        ChatView::Trash => c.trashed_at.unwrap_or(c.created_at),
+        _ => c.created_at,
+    };
+    let now = js_sys::Date::now();
+    let visible_ids: BTreeSet<String> = visible.iter().map(|c| c.id.clone()).collect();
+    // Selections hidden by the filter are not acted on
+    let targets: Vec<String> = selected.intersection(&visible_ids).cloned().collect();
+
+    let on_search = {
+        let search = search.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            search.set(input.value());
+        })
+    };
+
+    let on_view_change = {
+        let view = view.clone();
+        let selected = selected.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            view.set(match select.value().as_str() {
+                "archived" => ChatView::Archived,
+                "trash" => ChatView::Trash,
+                _ => ChatView::Chats,
+            });
+            selected.set(BTreeSet::new());
+        })
+    };
+
+    let toggle_select_mode = {
+        let select_mode = select_mode.clone();
+        let selected = selected.clone();
+        Callback::from(move |_: MouseEvent| {
+            select_mode.set(!*select_mode);
+            selected.set(BTreeSet::new());
+        })
+    };
+
+    let all_selected = !visible_ids.is_empty() && visible_ids.is_subset(&selected);
+    let on_select_all = {
+        let selected = selected.clone();
+        let visible_ids = visible_ids.clone();
+        Callback::from(move |_: Event| {
+            let mut next = (*selected).clone();
+            if visible_ids.is_subset(&next) {
+                next.retain(|id| !visible_ids.contains(id));
+            } else {
+                next.extend(visible_ids.iter().cloned());
+            }
+            selected.set(next);
+        })
+    };
+
+    let bulk = |action: BulkAction| {
+        let on_bulk = props.on_bulk_action.clone();
+        let targets = targets.clone();
+        let selected = selected.clone();
+        Callback::from(move |_: MouseEvent| {
+            if targets.is_empty() { return; }
+            if action == BulkAction::DeleteForever {
+                let confirmed = window()
+                    .and_then(|w| w.confirm_with_message(&format!("Permanently delete {} chat(s)?", targets.len())).ok())
+                    .unwrap_or(false);
+                if !confirmed { return; }
+            }
+            on_bulk.emit((action.clone(), targets.clone()));
+            selected.set(BTreeSet::new());
+        })
+    };
+
+    let on_move_to_folder = {
+        let on_bulk = props.on_bulk_action.clone();
+        let targets = targets.clone();
+        let selected = selected.clone();
+        Callback::from(move |_: MouseEvent| {
+            if targets.is_empty() { return; }
+            let name = window().and_then(|w| w.prompt_with_message("Folder name (leave empty to remove from folder)").ok().flatten());
+            // Cancelling the prompt does nothing
+            let Some(name) = name else { return; };
+            let folder = Some(name.trim().to_string()).filter(|n| !n.is_empty());
+            on_bulk.emit((BulkAction::MoveToFolder(folder), targets.clone()));
+            selected.set(BTreeSet::new());
+        })
+    };
+
+    let on_export = {
+        let chats = props.chats.clone();
+        let targets = targets.clone();
+        Callback::from(move |_: MouseEvent| {
+            let export: Vec<&ChatSession> = chats.iter().filter(|c| targets.contains(&c.id)).collect();
+            if export.is_empty() { return; }
+            if let Ok(json) = serde_json::to_string_pretty(&export) {
+                let _ = ExportService::download("chats-export.json", &json, "application/json");
+            }
+        })
+    };
+
+    let toggle_fine_tune = {
+        let fine_tune = fine_tune.clone();
+        Callback::from(move |_: MouseEvent| {
+            fine_tune.set(if fine_tune.is_some() { None } else { Some(FineTuneOptions::default()) });
+        })
+    };
+
+    // Counting the tokens of every selected chat is slow, so the export is only
+    // redone when the options or the selected chats change
+    let fine_tune_export = {
+        let options = (*fine_tune).clone().filter(|_| *select_mode);
+        let export_chats: Vec<ChatSession> = match options {
+            Some(_) => props.chats.iter().filter(|c| targets.contains(&c.id)).cloned().collect(),
+            None => Vec::new(),
+        };
+        use_memo((options, export_chats), |(options, chats)| {
+            let chats: Vec<&ChatSession> = chats.iter().collect();
+            options.as_ref().map(|options| ExportService::to_fine_tune_jsonl(&chats, options))
+        })
+    };
+
+    let fine_tune_panel = match (&*fine_tune, &*fine_tune_export) {
+        (Some(options), Some(export)) => {
+            let set_option = |apply: fn(&mut FineTuneOptions, &HtmlInputElement)| {
+                let fine_tune = fine_tune.clone();
+                let options = options.clone();
+                Callback::from(move |e: Event| {
+                    let input: HtmlInputElement = e.target_unchecked_into();
+                    let mut next = options.clone();
+                    apply(&mut next, &input);
+                    fine_tune.set(Some(next));
+                })
+            };
+            let on_download = {
+                let jsonl = export.jsonl.clone();
+                Callback::from(move |_: MouseEvent| {
+                    let _ = ExportService::download("fine-tune.jsonl", &jsonl, "application/jsonl");
+                })
+            };
+            html! {
+                <div class="swi-fine-tune-panel">
+                    <label><input type="checkbox" checked={options.strip_system} onchange={set_option(|o, i| o.strip_system = i.checked())} />{ "Leave out system messages" }</label>
+                    <label><input type="checkbox" checked={options.skip_excluded} onchange={set_option(|o, i| o.skip_excluded = i.checked())} />{ "Leave out messages marked \"Not sent to model\"" }</label>
+                    <label title="Longer chats are split into several examples between turns">
+                        { "Max tokens per example" }
+                        <input class="swi-fine-tune-limit" type="number" min="0" step="512" value={options.max_tokens.to_string()} onchange={set_option(|o, i| o.max_tokens = i.value().parse().unwrap_or(0))} />
+                    </label>
+                    if !export.warnings.is_empty() {
+                        <ul class="swi-fine-tune-warnings">
+                            { for export.warnings.iter().map(|w| html! { <li>{ w }</li> }) }
+                        </ul>
+                    }
+                    <button class="swi-bulk-btn" disabled={export.examples == 0} onclick={on_download}>
+                        { format!("Download {} example{}", export.examples, if export.examples == 1 { "" } else { "s" }) }
+                    </button>
+                </div>
+            }
+        }
+        _ => html! {},
+    };
+
+    let bulk_bar = if *select_mode {
+        let none = targets.is_empty();
+        html! {
+            <div class="swi-bulk-bar">
+                <label class="swi-bulk-select-all">
+                    <input type="checkbox" checked={all_selected} onchange={on_select_all} />
+                    { format!("{} selected", targets.len()) }
+                </label>
+                <div class="swi-bulk-actions">
+                    if *view == ChatView::Chats {
+                        <button class="swi-bulk-btn" disabled={none} onclick={bulk(BulkAction::Archive)}>{ "Archive" }</button>
+                        <button class="swi-bulk-btn" disabled={none} onclick={on_move_to_folder}>{ "Move to folder" }</button>
+                    } else {
+                        <button class="swi-bulk-btn" disabled={none} onclick={bulk(BulkAction::Restore)}>{ "Restore" }</button>
+                    }
+                    <button class="swi-bulk-btn" disabled={none} onclick={on_export}>{ "Export JSON" }</button>
+                    <button class={classes!("swi-bulk-btn", fine_tune.is_some().then_some("swi-active"))} disabled={none} onclick={toggle_fine_tune} title="Export as chat fine-tuning examples">{ "Fine-tune JSONL" }</button>
+                    if *view == ChatView::Trash {
+                        <button class="swi-bulk-btn swi-danger" disabled={none} onclick={bulk(BulkAction::DeleteForever)}>{ "Delete forever" }</button>
+                    } else {
+                        <button class="swi-bulk-btn swi-danger" disabled={none} onclick={bulk(BulkAction::Trash)}>{ "Delete" }</button>
+                    }
+                </div>
+                { fine_tune_panel }
+            </div>
+        }
+    } else {
+        html! {}
+    };
+
+    let on_move_folder = {
+        let on_reorder = props.on_reorder_folders.clone();
+        let names: Vec<String> = folders.iter().map(|(f, _)| f.clone()).collect();
+        Callback::from(move |(from, to): (usize, usize)| {
+            let mut names = names.clone();
+            move_item(&mut names, from, to);
+            on_reorder.emit(names);
+        })
+    };
+
+    let folder_list = if *view == ChatView::Chats && !folders.is_empty() {
+        let last = folders.len() - 1;
+        html! {
+            <div class="swi-folder-list">
+                { for folders.iter().enumerate().map(|(i, (name, count))| {
+                    let is_active = active_folder.as_deref() == Some(name.as_str());
+                    let on_click = {
+                        let folder_filter = folder_filter.clone();
+                        let name = name.clone();
+                        Callback::from(move |_: MouseEvent| {
+                            folder_filter.set(if is_active { None } else { Some(name.clone()) });
+                        })
+                    };
+                    let DragHandlers { ondragstart, ondragover, ondrop, ondragend } = folder_drag.handlers(i, name.clone(), &on_move_folder);
+                    // Buttons must not also toggle the folder filter
+                    let move_by = |to: usize| {
+                        let on_move = on_move_folder.clone();
+                        Callback::from(move |e: MouseEvent| {
+                            e.stop_propagation();
+                            on_move.emit((i, to));
+                        })
+                    };
+                    html! {
+                        <div
+                            key={name.clone()}
+                            class={classes!("swi-folder-item", is_active.then_some("swi-active"), folder_drag.drop_class(i), folder_drag.is_dragging(i).then_some("swi-dragging"))}
+                            draggable="true"
+                            onclick={on_click}
+                            {ondragstart}
+                            {ondragover}
+                            {ondrop}
+                            {ondragend}
+                            title={if is_active { "Show all chats" } else { "Show only chats in this folder" }}
+                        >
+                            <span class="swi-folder-handle">{ "⠿" }</span>
+                            <span class="swi-folder-name">{ name }</span>
+                            <span class="swi-folder-count">{ count }</span>
+                            <button class="swi-folder-move" title="Move up" aria-label={format!("Move folder {} up", name)} disabled={i == 0} onclick={move_by(i.saturating_sub(1))}>{ "↑" }</button>
+                            <button class="swi-folder-move" title="Move down" aria-label={format!("Move folder {} down", name)} disabled={i == last} onclick={move_by(i + 1)}>{ "↓" }</button>
+                        </div>
+                    }
+                }) }
+            </div>
+        }
+    } else {
+        html! {}
+    };
+
+    // Shown once any chat carries a label, or while filtering by one
+    let labels_in_use = props.chats.iter().any(|c| c.label.as_deref().and_then(chat_label).is_some());
+    let label_filter_row = if labels_in_use || label_filter.is_some() {
+        html! {
+            <div class="swi-label-filter" role="group" aria-label="Filter by label">
+                { for CHAT_LABELS.iter().map(|(key, name, color)| {
+                    let is_active = label_filter.as_deref() == Some(*key);
+                    let label_filter = label_filter.clone();
+                    let key = key.to_string();
+                    html! {
+                        <button
+                            key={key.clone()}
+                            class={classes!("swi-label-dot", is_active.then_some("swi-active"))}
+                            style={format!("background: {};", color)}
+                            title={if is_active { "Show all chats".to_string() } else { format!("Show only chats labeled {}", name) }}
+                            aria-pressed={is_active.to_string()}
+                            onclick={Callback::from(move |_| label_filter.set(if is_active { None } else { Some(key.clone()) }))}
+                        />
+                    }
+                }) }
+            </div>
+        }
+    } else {
+        html! {}
+    };
 
     // CSS for this specific component
     let css = r#"
-        .sidebar { background: var(--bg-sidebar); border-right: 1px solid var(--border-color); display: flex; flex-direction: column; transition: width 0.3s cubic-bezier(0.25, 0.8, 0.25, 1); overflow: hidden; flex-shrink: 0; }
-        .sidebar-content { width: 260px; height: 100%; display: flex; flex-direction: column; padding: 10px; }
-        .chat-list { flex-grow: 1; overflow-y: auto; margin-top: 10px; }
-        .chat-item { padding: 10px; border-radius: 6px; cursor: pointer; display: flex; justify-content: space-between; align-items: center; margin-bottom: 2px; font-size: 0.9rem; color: var(--text-primary); }
-        .chat-item:hover { background: #eaeaeb; }
-        .chat-item.active { background: #e0e0e0; font-weight: 500; }
-        .chat-item .del-btn { opacity: 0; border: none; background: none; color: #999; cursor: pointer; padding: 2px 6px; border-radius: 4px; }
-        .chat-item:hover .del-btn { opacity: 1; }
-        .chat-item .del-btn:hover { background: #dcdcdc; color: #d32f2f; }
-        .new-chat-btn { width: 100%; padding: 10px; border: 1px solid var(--border-color); background: white; border-radius: 6px; cursor: pointer; text-align: left; display: flex; gap: 10px; transition: background 0.2s; }
-        .new-chat-btn:hover { background: #f0f0f0; }
+        .swi-sidebar { background: var(--bg-sidebar); border-right: 1px solid var(--border-color); display: flex; flex-direction: column; transition: width var(--motion-slow) cubic-bezier(0.25, 0.8, 0.25, 1); overflow: hidden; flex-shrink: 0; }
+        .swi-sidebar-content { width: 260px; height: 100%; display: flex; flex-direction: column; padding: 10px; }
+        .swi-chat-list { flex-grow: 1; overflow-y: auto; margin-top: 10px; }
+        .swi-chat-item { padding: 10px; border-radius: 6px; cursor: pointer; display: flex; justify-content: space-between; align-items: center; margin-bottom: 2px; font-size: 0.9rem; color: var(--text-primary); }
+        .swi-chat-item:hover { background: #eaeaeb; }
+        .swi-chat-item.swi-active { background: #e0e0e0; font-weight: 500; }
+        .swi-chat-item .swi-del-btn { opacity: 0; border: none; background: none; color: #999; cursor: pointer; padding: 2px 6px; border-radius: 4px; }
+        .swi-chat-item:hover .swi-del-btn { opacity: 1; }
+        .swi-chat-item .swi-del-btn:hover { background: #dcdcdc; color: #d32f2f; }
+        .swi-new-chat-btn { width: 100%; padding: 10px; border: 1px solid var(--border-color); background: white; border-radius: 6px; cursor: pointer; text-align: left; display: flex; gap: 10px; transition: background var(--motion-fast); }
+        .swi-new-chat-btn:hover { background: #f0f0f0; }
+
+        /* Chat list tools & bulk actions */
+        .swi-chat-tools { display: flex; gap: 6px; margin-top: 10px; }
+        .swi-chat-search { flex: 1; min-width: 0; padding: 6px 8px; border: 1px solid var(--border-color); border-radius: 6px; font-size: 0.85rem; font-family: inherit; }
+        .swi-chat-view-select { padding: 6px 4px; border: 1px solid var(--border-color); border-radius: 6px; font-size: 0.8rem; font-family: inherit; background: white; }
+        .swi-select-toggle { border: 1px solid var(--border-color); background: white; border-radius: 6px; padding: 6px 8px; font-size: 0.8rem; cursor: pointer; }
+        .swi-select-toggle.swi-active { border-color: var(--accent-color); color: var(--accent-color); }
+        .swi-bulk-bar { margin-top: 8px; padding: 8px; border: 1px solid var(--border-color); border-radius: 6px; background: white; display: flex; flex-direction: column; gap: 6px; }
+        .swi-bulk-select-all { display: flex; align-items: center; gap: 6px; font-size: 0.8rem; font-weight: 600; }
+        .swi-bulk-actions { display: flex; flex-wrap: wrap; gap: 4px; }
+        .swi-bulk-btn { border: 1px solid var(--border-color); background: white; border-radius: 4px; padding: 3px 8px; font-size: 0.75rem; cursor: pointer; }
+        .swi-bulk-btn:hover:not(:disabled) { background: #f0f0f0; }
+        .swi-bulk-btn:disabled { opacity: 0.5; cursor: default; }
+        .swi-bulk-btn.swi-danger { color: var(--danger-color); }
+        .swi-bulk-btn.swi-active { border-color: var(--accent-color); color: var(--accent-color); }
+        .swi-fine-tune-panel { display: flex; flex-direction: column; gap: 4px; padding-top: 6px; border-top: 1px solid var(--border-color); font-size: 0.75rem; }
+        .swi-fine-tune-panel label { display: flex; align-items: center; gap: 6px; }
+        .swi-fine-tune-limit { width: 70px; padding: 2px 4px; border: 1px solid var(--border-color); border-radius: 4px; font-size: 0.75rem; }
+        .swi-fine-tune-warnings { margin: 0; padding-left: 16px; color: #b45309; word-break: break-word; }
+        .swi-chat-item .swi-chat-select { margin: 0 8px 0 0; flex-shrink: 0; }
+        .swi-chat-item .swi-chat-text { flex: 1; min-width: 0; display: flex; flex-direction: column; }
+        .swi-chat-item .swi-chat-title { overflow: hidden; text-overflow: ellipsis; white-space: nowrap; }
+        .swi-chat-item .swi-chat-preview { overflow: hidden; text-overflow: ellipsis; white-space: nowrap; font-size: 0.75rem; font-weight: normal; color: var(--text-secondary); }
+        .swi-chat-unread { width: 8px; height: 8px; border-radius: 50%; background: var(--accent-color); margin-right: 6px; flex-shrink: 0; }
+        .swi-chat-folder { font-size: 0.7rem; color: var(--text-secondary); background: #eaeaeb; border-radius: 8px; padding: 1px 6px; margin-right: 6px; flex-shrink: 0; }
+        .swi-chat-group { font-size: 0.7rem; font-weight: 600; color: var(--text-secondary); text-transform: uppercase; letter-spacing: 0.03em; padding: 10px 10px 4px; }
+        .swi-chat-model { font-size: 0.65rem; color: var(--text-secondary); border: 1px solid var(--border-color); border-radius: 8px; padding: 0 5px; margin-left: 6px; max-width: 80px; overflow: hidden; text-overflow: ellipsis; white-space: nowrap; flex-shrink: 0; }
+        .swi-chat-date { font-size: 0.7rem; color: var(--text-secondary); margin-left: 6px; flex-shrink: 0; }
+        .swi-folder-list { margin-top: 8px; display: flex; flex-direction: column; gap: 1px; }
+        .swi-folder-item { display: flex; align-items: center; gap: 4px; padding: 4px 8px; border-radius: 6px; font-size: 0.8rem; cursor: pointer; color: var(--text-secondary); }
+        .swi-folder-item:hover { background: #eaeaeb; }
+        .swi-folder-item.swi-active { background: #e0e0e0; color: var(--text-primary); font-weight: 500; }
+        .swi-folder-item.swi-dragging { opacity: 0.4; }
+        .swi-folder-item.swi-drop-before { box-shadow: inset 0 2px 0 var(--accent-color); }
+        .swi-folder-item.swi-drop-after { box-shadow: inset 0 -2px 0 var(--accent-color); }
+        .swi-folder-name { flex: 1; overflow: hidden; text-overflow: ellipsis; white-space: nowrap; }
+        .swi-folder-count { font-size: 0.7rem; }
+        .swi-folder-handle { cursor: grab; color: #bbb; user-select: none; }
+        .swi-folder-move { border: none; background: none; color: #999; cursor: pointer; padding: 0 2px; font-size: 0.75rem; opacity: 0; }
+        .swi-folder-item:hover .swi-folder-move, .swi-folder-move:focus { opacity: 1; }
+        .swi-folder-move:disabled { opacity: 0 !important; cursor: default; }
+        .swi-label-filter { display: flex; gap: 6px; padding: 8px 10px 0; }
+        .swi-label-dot { width: 14px; height: 14px; border-radius: 50%; border: 2px solid transparent; padding: 0; cursor: pointer; flex-shrink: 0; }
+        .swi-label-filter .swi-label-dot { opacity: 0.45; }
+        .swi-label-filter .swi-label-dot:hover, .swi-label-filter .swi-label-dot.swi-active { opacity: 1; }
+        .swi-label-dot.swi-active { border-color: var(--text-primary); }
+        .swi-chat-label { width: 8px; height: 8px; border-radius: 50%; margin-right: 6px; flex-shrink: 0; }
+        .swi-chat-item .swi-label-btn { opacity: 0; border: none; background: none; color: #999; cursor: pointer; padding: 2px 4px; border-radius: 4px; font-size: 0.7rem; }
+        .swi-chat-item:hover .swi-label-btn, .swi-label-btn:focus { opacity: 1; }
+        .swi-label-picker { display: flex; align-items: center; gap: 6px; padding: 4px 10px 8px 28px; }
+        .swi-label-none { border: none; background: none; color: var(--text-secondary); cursor: pointer; font-size: 0.75rem; padding: 0 4px; }
+        .swi-chat-list-empty { padding: 10px; font-size: 0.8rem; color: var(--text-secondary); text-align: center; }
 
         /* Documents Section */
-        .documents-section { margin-top: 15px; }
-        .documents-header { display: flex; justify-content: space-between; align-items: center; padding: 8px 12px; cursor: pointer; border-radius: 6px; transition: background 0.2s; }
-        .documents-header:hover { background: #eaeaeb; }
-        .documents-header h3 { font-size: 0.85rem; font-weight: 600; color: var(--text-secondary); margin: 0; text-transform: uppercase; letter-spacing: 0.5px; }
-        .expand-icon-wrapper { display: flex; align-items: center; }
-        .expand-icon { transition: transform 0.3s ease; width: 16px; height: 16px; color: var(--text-secondary); }
-        .expand-icon.rotated { transform: rotate(180deg); }
+        .swi-documents-section { margin-top: 15px; }
+        .swi-documents-header { display: flex; justify-content: space-between; align-items: center; padding: 8px 12px; cursor: pointer; border-radius: 6px; transition: background var(--motion-fast); }
+        .swi-documents-header:hover { background: #eaeaeb; }
+        .swi-documents-header h3 { font-size: 0.85rem; font-weight: 600; color: var(--text-secondary); margin: 0; text-transform: uppercase; letter-spacing: 0.5px; }
+        .swi-expand-icon-wrapper { display: flex; align-items: center; }
+        .swi-expand-icon { transition: transform var(--motion-slow) ease; width: 16px; height: 16px; color: var(--text-secondary); }
+        .swi-expand-icon.swi-rotated { transform: rotate(180deg); }
 
         /* Document List */
-        .documents-list { display: flex; flex-direction: column; gap: 6px; margin-top: 12px; }
-        .document-item { padding: 10px; border-radius: 8px; cursor: pointer; display: flex; align-items: center; gap: 10px; transition: all 0.2s; background: white; border: 1px solid var(--border-color); }
-        .document-item:hover { border-color: var(--accent-color); box-shadow: 0 2px 6px rgba(0,0,0,0.05); }
-        .document-item.selected { background: #f0f8f5; border-color: var(--accent-color); box-shadow: 0 2px 6px rgba(16,163,127,0.15); }
-        .document-content { display: flex; align-items: center; gap: 10px; flex: 1; min-width: 0; }
-        .document-info { display: flex; flex-direction: column; min-width: 0; }
-        .document-name { font-size: 0.9rem; font-weight: 500; color: var(--text-primary); white-space: nowrap; overflow: hidden; text-overflow: ellipsis; }
-        .document-meta { display: flex; align-items: center; gap: 6px; margin-top: 2px; font-size: 0.75rem; color: var(--text-secondary); }
-        .document-separator { color: #d0d0d0; }
-        .document-chunks, .document-tokens { color: var(--text-secondary); }
-        .document-delete-btn { border: 1px solid var(--border-color); background: transparent; padding: 6px; border-radius: 4px; cursor: pointer; opacity: 0; transition: all 0.2s; color: var(--text-secondary); }
-        .document-delete-btn:hover { background: #fee2e2; border-color: var(--danger-color); color: var(--danger-color); }
-        .document-item:hover .document-delete-btn { opacity: 1; }
+        .swi-documents-list { display: flex; flex-direction: column; gap: 6px; margin-top: 12px; }
+        .swi-document-item { padding: 10px; border-radius: 8px; cursor: pointer; display: flex; align-items: center; gap: 10px; transition: all var(--motion-fast); background: white; border: 1px solid var(--border-color); }
+        .swi-document-item:hover { border-color: var(--accent-color); box-shadow: 0 2px 6px rgba(0,0,0,0.05); }
+        .swi-document-item.swi-selected { background: #f0f8f5; border-color: var(--accent-color); box-shadow: 0 2px 6px rgba(16,163,127,0.15); }
+        .swi-document-content { display: flex; align-items: center; gap: 10px; flex: 1; min-width: 0; }
+        .swi-document-info { display: flex; flex-direction: column; min-width: 0; }
+        .swi-document-name { font-size: 0.9rem; font-weight: 500; color: var(--text-primary); white-space: nowrap; overflow: hidden; text-overflow: ellipsis; }
+        .swi-document-meta { display: flex; align-items: center; gap: 6px; margin-top: 2px; font-size: 0.75rem; color: var(--text-secondary); }
+        .swi-document-separator { color: #d0d0d0; }
+        .swi-document-chunks, .swi-document-tokens, .swi-document-size { color: var(--text-secondary); }
+        .swi-document-delete-btn { border: 1px solid var(--border-color); background: transparent; padding: 6px; border-radius: 4px; cursor: pointer; opacity: 0; transition: all var(--motion-fast); color: var(--text-secondary); }
+        .swi-document-delete-btn:hover { background: #fee2e2; border-color: var(--danger-color); color: var(--danger-color); }
+        .swi-document-item:hover .swi-document-delete-btn { opacity: 1; }
+        .swi-document-item.swi-disabled { opacity: 0.5; }
+        .swi-document-toggle-btn { border: 1px solid var(--border-color); background: transparent; padding: 6px; border-radius: 4px; cursor: pointer; opacity: 0; transition: all var(--motion-fast); color: var(--text-secondary); }
+        .swi-document-toggle-btn.swi-off { opacity: 1; }
+        .swi-document-toggle-btn:hover { border-color: var(--accent-color); color: var(--accent-color); }
+        .swi-document-item:hover .swi-document-toggle-btn { opacity: 1; }
+        .swi-documents-sort { margin-left: auto; border: 1px solid var(--border-color); border-radius: 4px; background: white; font-size: 0.7rem; font-family: inherit; padding: 1px 2px; color: var(--text-secondary); }
+        .swi-documents-sort + .swi-documents-bulk-btn { margin-left: 8px; }
+        .swi-document-date-suffix { font-weight: 400; color: var(--text-secondary); font-size: 0.75rem; }
+        .swi-document-index { display: flex; align-items: center; gap: 6px; margin-top: 4px; }
+        .swi-index-badge { font-size: 0.7rem; padding: 1px 6px; border-radius: 8px; border: 1px solid var(--border-color); color: var(--text-secondary); white-space: nowrap; }
+        .swi-index-badge.swi-index-ok { border-color: #27ae60; color: #27ae60; }
+        .swi-index-badge.swi-index-busy { border-color: var(--accent-color); color: var(--accent-color); }
+        .swi-index-badge.swi-index-failed { border-color: #e74c3c; color: #e74c3c; }
+        .swi-index-btn { border: none; background: transparent; padding: 0; font-size: 0.7rem; color: var(--text-secondary); cursor: pointer; }
+        .swi-index-btn:hover { color: var(--accent-color); }
+        .swi-documents-bulk-btn { margin-left: auto; margin-right: 8px; border: none; background: none; font-size: 0.75rem; color: var(--text-secondary); cursor: pointer; text-decoration: underline; }
+        .swi-documents-filter { display: flex; align-items: center; gap: 6px; margin-bottom: 8px; }
+        .swi-documents-search { flex: 1; min-width: 0; padding: 5px 8px; border: 1px solid var(--border-color); border-radius: 6px; font-size: 0.8rem; font-family: inherit; }
+        .swi-documents-filter .swi-documents-bulk-btn { margin: 0; white-space: nowrap; }
+        .swi-documents-confirm { border: 1px solid var(--danger-color); border-radius: 6px; padding: 8px 10px; margin-bottom: 8px; font-size: 0.8rem; background: #fef2f2; }
+        .swi-documents-confirm ul { margin: 6px 0; padding-left: 18px; max-height: 120px; overflow-y: auto; }
+        .swi-documents-confirm-actions { display: flex; justify-content: flex-end; gap: 6px; }
+        .swi-documents-undo { display: flex; align-items: center; justify-content: space-between; margin-bottom: 8px; padding: 6px 10px; border-radius: 6px; background: #333; color: white; font-size: 0.8rem; }
+        .swi-documents-undo .swi-documents-bulk-btn { margin: 0; color: white; }
 
         /* Upload Button */
-        .document-upload { padding: 8px 0; }
-        .upload-btn { display: flex; align-items: center; justify-content: center; gap: 8px; width: 100%; padding: 10px; border: 2px dashed var(--border-color); background: white; border-radius: 8px; cursor: pointer; transition: all 0.2s; font-size: 0.9rem; color: var(--text-primary); }
-        .upload-btn:hover { border-color: var(--accent-color); background: #f9fffc; }
-        .upload-btn svg { color: var(--accent-color); }
+        .swi-document-upload { padding: 8px 0; }
+        .swi-upload-btn { display: flex; align-items: center; justify-content: center; gap: 8px; width: 100%; padding: 10px; border: 2px dashed var(--border-color); background: white; border-radius: 8px; cursor: pointer; transition: all var(--motion-fast); font-size: 0.9rem; color: var(--text-primary); }
+        .swi-upload-btn:hover { border-color: var(--accent-color); background: #f9fffc; }
+        .swi-upload-btn svg { color: var(--accent-color); }
+        .swi-upload-progress { margin-top: 6px; font-size: 0.8rem; color: var(--text-secondary); text-align: center; }
+        .swi-upload-error { margin-top: 6px; font-size: 0.8rem; color: var(--danger-color); text-align: center; }
+        .swi-upload-error .swi-documents-bulk-btn { margin: 0 0 0 4px; color: var(--danger-color); }
 
         /* No Documents State */
-        .no-documents { display: flex; flex-direction: column; align-items: center; justify-content: center; padding: 30px 20px; text-align: center; border-radius: 8px; border: 2px dashed var(--border-color); background: #fafafa; }
-        .no-documents-icon { margin-bottom: 12px; color: var(--text-secondary); opacity: 0.6; }
-        .no-documents p { margin: 8px 0 0 0; font-size: 0.85rem; color: var(--text-secondary); line-height: 1.4; }
-        .no-documents .hint { font-size: 0.75rem; color: var(--text-secondary); opacity: 0.7; }
+        .swi-no-documents { display: flex; flex-direction: column; align-items: center; justify-content: center; padding: 30px 20px; text-align: center; border-radius: 8px; border: 2px dashed var(--border-color); background: #fafafa; }
+        .swi-no-documents-icon { margin-bottom: 12px; color: var(--text-secondary); opacity: 0.6; }
+        .swi-no-documents p { margin: 8px 0 0 0; font-size: 0.85rem; color: var(--text-secondary); line-height: 1.4; }
+        .swi-no-documents .swi-hint { font-size: 0.75rem; color: var(--text-secondary); opacity: 0.7; }
 
         /* Sidebar separator */
-        .documents-section::before { content: ""; display: block; height: 1px; background: var(--border-color); margin-bottom: 15px; }
+        .swi-documents-section::before { content: ""; display: block; height: 1px; background: var(--border-color); margin-bottom: 15px; }
     "#;
+    inject_styles("sidebar", css);
 
     html! {
         <>
-            <style>{ css }</style>
-            <div class="sidebar" style={format!("width: {};", width)}>
-                <div class="sidebar-content">
-                    <button class="new-chat-btn" onclick={props.on_new.reform(|_| ())}>
+            <div class="swi-sidebar" style={format!("width: {};", width)}>
+                <div class="swi-sidebar-content">
+                    <button class="swi-new-chat-btn" onclick={props.on_new.reform(|_| ())}>
                         <span>{ "+" }</span>
                         <span>{ "New Chat" }</span>
                     </button>
-                    <div class="chat-list">
-                        { for props.chats.iter().map(|chat| {
+                    <div class="swi-chat-tools">
+                        <input class="swi-chat-search" type="text" placeholder="Search chats..." value={(*search).clone()} oninput={on_search} />
+                        <select class="swi-chat-view-select" onchange={on_view_change}>
+                            <option value="chats" selected={*view == ChatView::Chats}>{ "Chats" }</option>
+                            <option value="archived" selected={*view == ChatView::Archived}>{ "Archived" }</option>
+                            <option value="trash" selected={*view == ChatView::Trash}>{ "Trash" }</option>
+                        </select>
+                        <button class={if *select_mode { "swi-select-toggle swi-active" } else { "swi-select-toggle" }} onclick={toggle_select_mode} title="Select several chats">
+                            { if *select_mode { "Done" } else { "Select" } }
+                        </button>
+                    </div>
+                    { bulk_bar }
+                    { folder_list }
+                    { label_filter_row }
+                    <div class="swi-chat-list">
+                        { for visible.iter().enumerate().map(|(i, chat)| {
+                            let group = format_date_group(group_time(chat), now);
+                            let group_header = if i == 0 || format_date_group(group_time(visible[i - 1]), now) != group {
+                                html! { <div class="swi-chat-group">{ group }</div> }
+                            } else {
+                                html! {}
+                            };
                             let id = chat.id.clone();
                             let is_active = id == props.active_chat_id;
-                            let active_class = if is_active { "active" } else { "" };
-                            let on_sel = props.on_select.clone();
+                            let active_class = if is_active { "swi-active" } else { "" };
+                            let is_checked = selected.contains(&id);
+                            let on_click = if *select_mode {
+                                // In select mode a click toggles the chat instead of opening it
+                                let selected = selected.clone();
+                                let id = id.clone();
+                                Callback::from(move |_: MouseEvent| {
+                                    let mut next = (*selected).clone();
+                                    if !next.remove(&id) {
+                                        next.insert(id.clone());
+                                    }
+                                    selected.set(next);
+                                })
+                            } else {
+                                let on_sel = props.on_select.clone();
+                                let id = id.clone();
+                                Callback::from(move |_: MouseEvent| on_sel.emit(id.clone()))
+                            };
+                            // Recomputed only when the last message changes
+                            let preview = match chat.messages.iter().rev().find(|m| !m.queued && m.role != "system" && !m.content.trim().is_empty()) {
+                                Some(last) => {
+                                    let mut cache = previews.borrow_mut();
+                                    match cache.get(&chat.id) {
+                                        Some((id, len, text)) if *id == last.id && *len == last.content.len() => text.clone(),
+                                        _ => {
+                                            let text = message_preview(last);
+                                            cache.insert(chat.id.clone(), (last.id.clone(), last.content.len(), text.clone()));
+                                            text
+                                        }
+                                    }
+                                }
+                                None => String::new(),
+                            };
                             let on_del = props.on_delete.clone();
                             let id_c = id.clone();
+                            let picker_open = label_picker.as_deref() == Some(id.as_str());
+                            let open_picker = {
+                                let label_picker = label_picker.clone();
+                                let id = id.clone();
+                                Callback::from(move |e: MouseEvent| {
+                                    e.prevent_default();
+                                    e.stop_propagation();
+                                    label_picker.set(if picker_open { None } else { Some(id.clone()) });
+                                })
+                            };
+                            let set_label = |label: Option<&str>| {
+                                let on_bulk = props.on_bulk_action.clone();
+                                let label_picker = label_picker.clone();
+                                let label = label.map(str::to_string);
+                                let id = id.clone();
+                                Callback::from(move |_: MouseEvent| {
+                                    on_bulk.emit((BulkAction::SetLabel(label.clone()), vec![id.clone()]));
+                                    label_picker.set(None);
+                                })
+                            };
 
                             html! {
-                                <div class={format!("chat-item {}", active_class)} onclick={Callback::from(move |_| on_sel.emit(id.clone()))}>
-                                    <span style="overflow: hidden; text-overflow: ellipsis; white-space: nowrap;">{ &chat.title }</span>
-                                    <button class="del-btn" onclick={Callback::from(move |e| on_del.emit((e, id_c.clone())))}>{ "×" }</button>
+                                <key={chat.id.clone()}>
+                                { group_header }
+                                <div
+                                    class={format!("swi-chat-item {}", active_class)}
+                                    onclick={on_click}
+                                    oncontextmenu={open_picker.clone()}
+                                    title={format!("Created {}", format_absolute(chat.created_at))}
+                                >
+                                    if *select_mode {
+                                        <input class="swi-chat-select" type="checkbox" checked={is_checked} />
+                                    }
+                                    if let Some(folder) = &chat.folder {
+                                        <span class="swi-chat-folder">{ folder }</span>
+                                    }
+                                    if let Some((name, color)) = chat.label.as_deref().and_then(chat_label) {
+                                        <span class="swi-chat-label" style={format!("background: {};", color)} title={format!("Label: {}", name)}></span>
+                                    }
+                                    if chat.unread {
+                                        <span class="swi-chat-unread" title="New response" aria-label="New response"></span>
+                                    }
+                                    <div class="swi-chat-text">
+                                        <span class="swi-chat-title">{ &chat.title }</span>
+                                        if !preview.is_empty() {
+                                            <span class="swi-chat-preview">{ preview }</span>
+                                        }
+                                    </div>
+                                    if let Some(model) = &chat.model_override {
+                                        <span class="swi-chat-model" title={format!("Uses {}", model)}>{ model }</span>
+                                    }
+                                    if let Some(at) = chat.trashed_at {
+                                        <span class="swi-chat-date" title={format_absolute(at)}>{ format!("deleted {}", format_relative_time(at, now)) }</span>
+                                    }
+                                    if !*select_mode {
+                                        <button class="swi-label-btn" title="Label" aria-label={format!("Label {}", chat.title)} onclick={open_picker}>{ "●" }</button>
+                                    }
+                                    if !*select_mode && *view == ChatView::Chats {
+                                        <button class="swi-del-btn" onclick={Callback::from(move |e| on_del.emit((e, id_c.clone())))}>{ "×" }</button>
+                                    }
                                 </div>
+                                if picker_open {
+                                    <div class="swi-label-picker">
+                                        { for CHAT_LABELS.iter().map(|(key, name, color)| html! {
+                                            <button
+                                                key={*key}
+                                                class={classes!("swi-label-dot", (chat.label.as_deref() == Some(*key)).then_some("swi-active"))}
+                                                style={format!("background: {};", color)}
+                                                title={*name}
+                                                onclick={set_label(Some(key))}
+                                            />
+                                        }) }
+                                        <button class="swi-label-none" title="Remove the label" onclick={set_label(None)}>{ "None" }</button>
+                                    </div>
+                                }
+                                </>
                             }
                         })}
+                        if visible.is_empty() {
+                            <div class="swi-chat-list-empty">{ "No chats here" }</div>
+                        }
                     </div>
 
-                    <Documents on_document_selected={Callback::from(|id: String| { let _ = id; })} />
+                    <Documents
+                        selected_document_id={props.selected_document_id.clone()}
+                        on_document_selected={props.on_document_selected.clone()}
+                        on_uploaded={props.on_document_uploaded.clone()}
+                        request={props.request.clone()}
+                        embedding_model={props.embedding_model.clone()}
+                        on_deleted={props.on_documents_deleted.clone()}
+                        on_open_setting={props.on_open_setting.clone()}
+                        added_elsewhere={props.documents_added.clone()}
+                        count_unit={props.count_unit}
+                    />
                 </div>
             </div>
         </>
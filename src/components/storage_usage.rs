@@ -0,0 +1,102 @@
+use yew::prelude::*;
+use crate::services::document_service::DocumentService;
+use crate::services::storage::LocalStorage;
+use crate::utils::{format_bytes, inject_styles};
+
+// Browsers typically allow about this much local storage per site
+const QUOTA_BYTES: usize = 5 * 1024 * 1024;
+// Documents listed individually in the breakdown
+const LISTED_DOCUMENTS: usize = 8;
+
+/// Measured storage use, split into documents and everything else
+#[derive(Clone, PartialEq)]
+struct StorageBreakdown {
+    total: usize,
+    documents: Vec<(String, usize)>,
+    documents_total: usize,
+}
+
+/// How much of the browser's storage this app uses, measured on request
+#[function_component(StorageUsage)]
+pub fn storage_usage() -> Html {
+    let breakdown = use_state(|| None::<StorageBreakdown>);
+
+    let on_measure = {
+        let breakdown = breakdown.clone();
+        Callback::from(move |_: MouseEvent| {
+            let entries = LocalStorage::entry_sizes();
+            let document_keys = DocumentService::storage_keys();
+            let documents_total = entries.iter().filter(|(k, _)| document_keys.contains(&k.as_str())).map(|(_, s)| *s).sum();
+            let documents = DocumentService::get_documents();
+            let sizes = DocumentService::storage_sizes(&documents);
+            let mut documents: Vec<(String, usize)> = documents
+                .into_iter()
+                .map(|d| {
+                    let size = sizes.get(&d.id).copied().unwrap_or(0);
+                    (d.filename, size)
+                })
+                .collect();
+            documents.sort_by_key(|d| std::cmp::Reverse(d.1));
+            breakdown.set(Some(StorageBreakdown {
+                total: entries.iter().map(|(_, s)| *s).sum(),
+                documents,
+                documents_total,
+            }));
+        })
+    };
+
+    let css = r#"
+        .swi-storage-meter { height: 8px; background: #f0f0f0; border-radius: 4px; overflow: hidden; display: flex; margin: 8px 0 4px; }
+        .swi-storage-meter .swi-docs { background: var(--accent-color); }
+        .swi-storage-meter .swi-other { background: #9ca3af; }
+        .swi-storage-rows { font-size: 0.8rem; margin: 6px 0 8px; padding-left: 18px; }
+        .swi-storage-rows li span { color: var(--text-secondary); }
+    "#;
+    inject_styles("storage_usage", css);
+
+    let body = match &*breakdown {
+        None => html! {
+            <div class="swi-data-row">
+                <button class="swi-btn" onclick={on_measure}>{ "Show storage use" }</button>
+            </div>
+        },
+        Some(b) => {
+            let percent = |bytes: usize| (bytes as f64 * 100.0 / QUOTA_BYTES as f64).min(100.0);
+            let other = b.total.saturating_sub(b.documents_total);
+            let unlisted: usize = b.documents.iter().skip(LISTED_DOCUMENTS).map(|(_, s)| *s).sum();
+            html! {
+                <>
+                    <div class="swi-storage-meter" title={format!("{} of about {}", format_bytes(b.total), format_bytes(QUOTA_BYTES))}>
+                        <div class="swi-docs" style={format!("width: {:.1}%;", percent(b.documents_total))}></div>
+                        <div class="swi-other" style={format!("width: {:.1}%;", percent(other))}></div>
+                    </div>
+                    <p class="swi-data-hint">{ format!("{} used of about {}", format_bytes(b.total), format_bytes(QUOTA_BYTES)) }</p>
+                    <ul class="swi-storage-rows">
+                        <li>{ format!("Documents: {}", format_bytes(b.documents_total)) }
+                            <ul>
+                                { for b.documents.iter().take(LISTED_DOCUMENTS).map(|(name, size)| html! {
+                                    <li>{ name }<span>{ format!(" — {}", format_bytes(*size)) }</span></li>
+                                }) }
+                                if unlisted > 0 {
+                                    <li>{ format!("{} more", b.documents.len() - LISTED_DOCUMENTS) }<span>{ format!(" — {}", format_bytes(unlisted)) }</span></li>
+                                }
+                            </ul>
+                        </li>
+                        <li>{ format!("Chats, settings and other data: {}", format_bytes(other)) }</li>
+                    </ul>
+                    <div class="swi-data-row">
+                        <button class="swi-btn" onclick={on_measure}>{ "Refresh" }</button>
+                    </div>
+                </>
+            }
+        }
+    };
+
+    html! {
+        <div style="margin-top: 12px;">
+            <label class="swi-form-label">{ "Storage" }</label>
+            <p class="swi-data-hint">{ "Space used in this browser. Deleting large documents frees the most." }</p>
+            { body }
+        </div>
+    }
+}
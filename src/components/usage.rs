@@ -0,0 +1,126 @@
+use yew::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+use crate::models::{ChatSession, CountUnit};
+use crate::services::export::ExportService;
+use crate::services::usage::{UsageService, UsageSummary};
+use crate::utils::{format_count, format_date, inject_styles};
+
+#[derive(Properties, PartialEq)]
+pub struct UsageProps {
+    pub chats: Vec<ChatSession>,
+    #[prop_or_default]
+    pub count_unit: CountUnit,
+}
+
+/// Usage overview, computed only once the user asks for it
+#[function_component(Usage)]
+pub fn usage(props: &UsageProps) -> Html {
+    let summary = use_state(|| None::<UsageSummary>);
+    let computing = use_state(|| false);
+    let copied = use_state(|| false);
+
+    let on_show = {
+        let summary = summary.clone();
+        let computing = computing.clone();
+        let chats = props.chats.clone();
+        Callback::from(move |_: MouseEvent| {
+            computing.set(true);
+            let summary = summary.clone();
+            let computing = computing.clone();
+            let chats = chats.clone();
+            spawn_local(async move {
+                summary.set(Some(UsageService::summarize(chats).await));
+                computing.set(false);
+            });
+        })
+    };
+
+    let on_copy = {
+        let summary = summary.clone();
+        let copied = copied.clone();
+        Callback::from(move |_: MouseEvent| {
+            if let Some(s) = &*summary {
+                let markdown = UsageService::to_markdown(s);
+                let copied = copied.clone();
+                spawn_local(async move {
+                    copied.set(ExportService::copy_to_clipboard(&markdown).await.is_ok());
+                });
+            }
+        })
+    };
+
+    let css = r#"
+        .swi-usage-stats { display: grid; grid-template-columns: repeat(2, 1fr); gap: 6px 12px; font-size: 0.8rem; margin: 8px 0; }
+        .swi-usage-stats b { display: block; font-size: 1rem; }
+        .swi-heatmap { display: grid; grid-template-rows: repeat(7, 10px); grid-auto-flow: column; grid-auto-columns: 10px; gap: 2px; margin: 8px 0; }
+        .swi-heatmap-cell { border-radius: 2px; background: #ebedf0; }
+        .swi-heatmap-cell.swi-l1 { background: #9be9a8; }
+        .swi-heatmap-cell.swi-l2 { background: #40c463; }
+        .swi-heatmap-cell.swi-l3 { background: #30a14e; }
+        .swi-heatmap-cell.swi-l4 { background: #216e39; }
+        .swi-usage-models { font-size: 0.8rem; margin: 0 0 8px; padding-left: 18px; }
+    "#;
+    inject_styles("usage", css);
+
+    let body = match (&*summary, *computing) {
+        (_, true) => html! { <p class="swi-data-hint">{ "Counting messages..." }</p> },
+        (None, false) => html! {
+            <div class="swi-data-row">
+                <button class="swi-btn" onclick={on_show}>{ "Show usage" }</button>
+            </div>
+        },
+        (Some(s), false) => {
+            let busiest = s.messages_per_day.values().copied().max().unwrap_or(0).max(1);
+            let cells = UsageService::heatmap_days().into_iter().map(|day| {
+                let count = s.messages_per_day.get(&UsageService::day_key(day)).copied().unwrap_or(0);
+                // Four intensity levels relative to the busiest day
+                let level = if count == 0 { 0 } else { 1 + (count * 3) / busiest };
+                html! {
+                    <div
+                        class={classes!("swi-heatmap-cell", (level > 0).then(|| format!("swi-l{}", level)))}
+                        title={format!("{}: {} message{}", format_date(day), count, if count == 1 { "" } else { "s" })}
+                    ></div>
+                }
+            });
+            html! {
+                <>
+                    <div class="swi-heatmap">{ for cells }</div>
+                    <div class="swi-usage-stats">
+                        <div><b>{ format_count(s.chats) }</b>{ "chats" }</div>
+                        <div><b>{ format_count(s.messages) }</b>{ "messages" }</div>
+                        { match props.count_unit {
+                            CountUnit::Tokens => html! { <div><b>{ format_count(s.tokens_generated) }</b>{ "tokens generated" }</div> },
+                            CountUnit::Words => html! { <div><b>{ format_count(s.words_generated) }</b>{ "words generated" }</div> },
+                        } }
+                        <div><b>{ format!("{:.1}", s.average_chat_length) }</b>{ "messages per chat" }</div>
+                        if s.empty_responses > 0 {
+                            <div><b>{ format_count(s.empty_responses) }</b>{ "empty responses" }</div>
+                        }
+                    </div>
+                    if !s.models.is_empty() {
+                        <ul class="swi-usage-models">
+                            { for s.models.iter().take(5).map(|(model, n)| html! {
+                                <li>{ format!("{} — {} repl{}", model, n, if *n == 1 { "y" } else { "ies" }) }</li>
+                            }) }
+                        </ul>
+                    }
+                    if s.replies_without_model > 0 {
+                        <p class="swi-data-hint">{ format!("{} older replies have no recorded model.", format_count(s.replies_without_model)) }</p>
+                    }
+                    <div class="swi-data-row">
+                        <button class="swi-btn" onclick={on_copy}>{ if *copied { "Copied" } else { "Copy summary as Markdown" } }</button>
+                        <button class="swi-btn" onclick={on_show}>{ "Refresh" }</button>
+                    </div>
+                </>
+            }
+        }
+    };
+
+    html! {
+        <div style="margin-top: 12px;">
+            <label class="swi-form-label">{ "Usage" }</label>
+            <p class="swi-data-hint">{ "Messages per day over the last 16 weeks, computed from the chats stored in this browser." }</p>
+            { body }
+        </div>
+    }
+}
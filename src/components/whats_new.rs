@@ -0,0 +1,59 @@
+use yew::prelude::*;
+use crate::services::changelog::{Release, APP_VERSION};
+use crate::utils::inject_styles;
+
+#[derive(Properties, PartialEq)]
+pub struct WhatsNewProps {
+    /// Newest first
+    pub releases: Vec<&'static Release>,
+    pub on_close: Callback<()>,
+    /// Opens the settings at the field an entry links to
+    pub on_open_setting: Callback<String>,
+}
+
+/// Changes since the version the user last saw
+#[function_component(WhatsNew)]
+pub fn whats_new(props: &WhatsNewProps) -> Html {
+    let css = r#"
+        .swi-whats-new-backdrop { position: absolute; top: 0; left: 0; width: 100%; height: 100%; background: rgba(255,255,255,0.6); backdrop-filter: blur(2px); z-index: 99; cursor: pointer; }
+        .swi-whats-new-panel { position: absolute; top: 60px; left: 50%; transform: translateX(-50%); width: min(520px, 90%); max-height: 80vh; overflow-y: auto; background: white; border: 1px solid var(--border-color); border-radius: 8px; box-shadow: 0 10px 15px -3px rgba(0, 0, 0, 0.1); padding: 20px; z-index: 100; display: flex; flex-direction: column; gap: 12px; font-size: 0.9rem; }
+        .swi-whats-new-header { display: flex; justify-content: space-between; align-items: center; }
+        .swi-whats-new-header h3 { margin: 0; font-size: 1.05rem; }
+        .swi-whats-new-version { font-weight: 600; color: var(--text-secondary); font-size: 0.8rem; margin-bottom: 4px; }
+        .swi-whats-new-panel ul { margin: 0; padding-left: 18px; display: flex; flex-direction: column; gap: 6px; }
+        .swi-whats-new-link { border: none; background: transparent; padding: 0; margin-left: 4px; color: var(--accent-color); cursor: pointer; font-size: 0.85rem; }
+    "#;
+    inject_styles("whats_new", css);
+
+    html! {
+        <>
+            <div class="swi-whats-new-backdrop" onclick={props.on_close.reform(|_| ())}></div>
+            <div class="swi-whats-new-panel" role="dialog">
+                <div class="swi-whats-new-header">
+                    <h3>{ format!("What's new in {}", APP_VERSION) }</h3>
+                    <button class="swi-btn-icon" title="Close" onclick={props.on_close.reform(|_| ())}>{ "×" }</button>
+                </div>
+                { for props.releases.iter().map(|(version, entries)| html! {
+                    <div>
+                        <div class="swi-whats-new-version">{ format!("Version {}", version) }</div>
+                        <ul>
+                            { for entries.iter().map(|entry| html! {
+                                <li>
+                                    { entry.text }
+                                    if let Some(field) = entry.setting {
+                                        <button class="swi-whats-new-link" onclick={props.on_open_setting.reform(move |_| field.to_string())}>
+                                            { "Open settings →" }
+                                        </button>
+                                    }
+                                </li>
+                            })}
+                        </ul>
+                    </div>
+                })}
+                <div>
+                    <button class="swi-btn" onclick={props.on_close.reform(|_| ())}>{ "Got it" }</button>
+                </div>
+            </div>
+        </>
+    }
+}
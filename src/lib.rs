@@ -1,3 +1,6 @@
+// The crate keeps the project's CamelCase name, which the lint would flag
+#![allow(non_snake_case)]
+
 // cargo: dep = "yew"
 // cargo: dep = "serde"
 // cargo: dep = "serde_json"
@@ -9,8 +12,8 @@
 // cargo: dep = "web-sys"
 // cargo: dep = "uuid"
 // cargo: dep = "js-sys"
-// cargo: dep = "anyhow"
 // cargo: dep = "console_error_panic_hook"
+// cargo: dep = "gloo-timers"
 
 mod components;
 mod services;
@@ -19,10 +22,95 @@ mod utils;
 mod app;
 
 use wasm_bindgen::prelude::*;
-use app::App;
+use yew::AppHandle;
+use app::{App, AppProps};
+use models::MountConfig;
+use services::host_events::{HostCommand, HostCommands};
 
 #[wasm_bindgen(start)]
 pub fn run_app() {
     utils::set_panic_hook();
+    // Pages embedding the app set this and call `mount` themselves
+    let manual = web_sys::window()
+        .and_then(|w| js_sys::Reflect::get(&w, &"SWI_MANUAL_MOUNT".into()).ok())
+        .is_some_and(|v| v.is_truthy());
+    if manual {
+        return;
+    }
+    // The app fills the whole page when it owns it
+    if let Some(document) = web_sys::window().and_then(|w| w.document()) {
+        if let Some(html) = document.document_element().and_then(|e| e.dyn_into::<web_sys::HtmlElement>().ok()) {
+            let _ = html.style().set_property("height", "100%");
+        }
+        if let Some(body) = document.body() {
+            let _ = body.style().set_property("margin", "0");
+            let _ = body.style().set_property("height", "100%");
+        }
+    }
     yew::Renderer::<App>::new().render();
-}
\ No newline at end of file
+}
+
+/// A mounted instance of the app, returned to JavaScript by `mount`.
+/// The app reports what happens as `swi:*` CustomEvents on the mount element
+/// (`message_sent`, `response_completed`, `chat_created`, `document_uploaded`
+/// and `error`), with a plain object in `detail`.
+#[wasm_bindgen]
+pub struct MountHandle {
+    app: Option<AppHandle<App>>,
+    commands: HostCommands,
+}
+
+#[wasm_bindgen]
+impl MountHandle {
+    /// Removes the app from its element; calling it again does nothing
+    pub fn unmount(&mut self) {
+        if let Some(app) = self.app.take() {
+            app.destroy();
+        }
+    }
+
+    /// Sends `text` in the open chat, as if the user had typed it
+    pub fn send_message(&self, text: String) -> Result<(), JsValue> {
+        self.send(HostCommand::SendMessage(text))
+    }
+
+    /// Replaces the system prompt for new chats, like saving it in the settings
+    pub fn set_system_prompt(&self, text: String) -> Result<(), JsValue> {
+        self.send(HostCommand::SetSystemPrompt(text))
+    }
+
+    /// Switches to the chat with `id`
+    pub fn open_chat(&self, id: String) -> Result<(), JsValue> {
+        self.send(HostCommand::OpenChat(id))
+    }
+}
+
+impl MountHandle {
+    fn send(&self, command: HostCommand) -> Result<(), JsValue> {
+        if self.app.is_none() {
+            return Err("The app has been unmounted".into());
+        }
+        self.commands.send(command).map_err(|e| JsValue::from_str(&e))
+    }
+}
+
+/// Renders the app into the element matching `selector`, which should have a
+/// height. `config` may set `base_url`, `model`, `theme` (CSS variables) and
+/// `storage_key_prefix`; leave it out to use the stored settings.
+#[wasm_bindgen]
+pub fn mount(selector: &str, config: JsValue) -> Result<MountHandle, JsValue> {
+    let document = web_sys::window().and_then(|w| w.document()).ok_or("No document available")?;
+    let element = document
+        .query_selector(selector)?
+        .ok_or_else(|| JsValue::from_str(&format!("No element matches '{}'", selector)))?;
+    let config: MountConfig = if config.is_undefined() || config.is_null() {
+        MountConfig::default()
+    } else {
+        let json = js_sys::JSON::stringify(&config)?.as_string().unwrap_or_default();
+        serde_json::from_str(&json).map_err(|e| JsValue::from_str(&format!("Invalid mount config: {}", e)))?
+    };
+    let commands = HostCommands::default();
+    let props = AppProps { config, host: Some(element.clone()), commands: commands.clone() };
+    let app = yew::Renderer::<App>::with_root_and_props(element, props).render();
+    Ok(MountHandle { app: Some(app), commands })
+}
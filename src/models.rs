@@ -1,32 +1,393 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
 pub struct Message {
+    #[serde(default = "new_message_id")] // Assigned once to older messages, then saved
+    pub id: String,
     pub role: String,
     pub content: String,
+    #[serde(default)] // Set when the generation was stopped before it finished
+    pub interrupted: bool,
+    #[serde(default)] // Set with `interrupted` when an error, not the user, stopped it
+    pub errored: bool,
+    #[serde(default)] // Raw stream payloads that could not be decoded
+    pub undecoded_chunks: Vec<String>,
+    #[serde(default)] // Ids of the documents sent as context with this message
+    pub context_documents: Vec<String>,
+    #[serde(skip)] // Too large to keep in localStorage
+    pub logprobs: Option<Vec<TokenLogprob>>,
+    #[serde(default)] // Missing for messages saved before timestamps were recorded
+    pub created_at: Option<f64>,
+    #[serde(default)] // Model that generated an assistant message
+    pub model: Option<String>,
+    #[serde(default)] // Documents referenced with @ in a user message
+    pub document_refs: Vec<DocumentRef>,
+    #[serde(default, skip_serializing_if = "Option::is_none")] // What the user typed, when the stored content adds context or placeholders
+    pub typed: Option<String>,
+    #[serde(default)] // What was sent to the model for this user message
+    pub composition: Option<RequestComposition>,
+    #[serde(default)] // Counted once when the message is complete
+    pub token_count: Option<usize>,
+    #[serde(default)] // Kept in the chat but never sent to the model
+    pub excluded: bool,
+    #[serde(default)] // Sent by a recurring prompt rather than typed
+    pub automated: bool,
+    #[serde(default)] // Waiting for the chat's current response before it is sent
+    pub queued: bool,
+    #[serde(default)] // Queued because the server was unreachable; sent once it answers again
+    pub awaiting_server: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")] // Response this one replaced when it was regenerated
+    pub previous: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")] // Broken partial answers this one was retried after
+    pub failed_attempts: Vec<FailedAttempt>,
+    #[serde(default, skip_serializing_if = "Option::is_none")] // How a completed assistant message was generated
+    pub metrics: Option<MessageMetrics>,
+    #[serde(default, skip_serializing_if = "Option::is_none")] // Shown with the answer, never sent back to the model
+    pub reasoning: Option<Reasoning>,
+}
+
+/// What a reasoning model thought before it answered
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Reasoning {
+    pub text: String,
+    /// The answer started after it; false while thinking or when stopped mid-thought
+    pub finished: bool,
+}
+
+/// Summary of how a request was put together, kept small enough to store with
+/// every user message
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct RequestComposition {
+    pub model: String,
+    pub system_prompt: String,
+    pub history_messages: usize,
+    #[serde(default)] // History messages left out because they were excluded
+    pub excluded_messages: usize,
+    #[serde(default)] // Oldest history messages left out to fit the context
+    pub omitted_messages: usize,
+    /// Documents sent as context, e.g. "report.pdf (12 chunks)"
+    pub documents: Vec<String>,
+    pub sections: Vec<CompositionSection>,
+    pub total_tokens: usize,
+    #[serde(default)] // Repeated chunk overlap left out of the document context
+    pub deduplicated_tokens: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")] // Only kept in debug mode
+    pub payload: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")] // Start of the answer sent as an assistant message
+    pub prefill: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")] // Response length and its token cap, unless Normal
+    pub response_length: Option<String>,
+}
+
+/// The part of every request that doesn't depend on the conversation
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct ContextOverhead {
+    pub system_tokens: usize,
+    pub instruction_tokens: usize,
+    pub document_tokens: usize,
+    pub context_length: usize,
+}
+
+impl ContextOverhead {
+    pub fn total(&self) -> usize {
+        self.system_tokens + self.instruction_tokens + self.document_tokens
+    }
+
+    /// Tokens left for history and the next message
+    pub fn remaining(&self) -> usize {
+        self.context_length.saturating_sub(self.total())
+    }
+
+    /// Less than a quarter of the context, or 1024 tokens, is left for the conversation
+    pub fn is_tight(&self) -> bool {
+        self.context_length > 0 && self.remaining() < (self.context_length / 4).min(1024)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct CompositionSection {
+    pub label: String,
+    pub tokens: usize,
+}
+
+/// A document mentioned in a message. The filename is kept so the reference can
+/// still be shown after the document is deleted.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct DocumentRef {
+    pub id: String,
+    pub filename: String,
+}
+
+impl DocumentRef {
+    /// Text that stands in for the `@id` mention in the stored and sent message.
+    /// Which document it is goes by the message's `document_refs`, in order.
+    pub fn placeholder(&self) -> String {
+        format!("[Document: {}]", self.filename)
+    }
+
+    /// Placeholder of messages stored while it carried the id
+    fn id_placeholder(&self) -> String {
+        format!("[Document: {}, id {}]", self.filename, self.id)
+    }
+
+    /// How the mention appears in the message input, before it is sent as `@id`
+    pub fn mention(&self) -> String {
+        format!("@{}", self.filename)
+    }
+}
+
+/// Probability information for one generated token
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f64,
+}
+
+impl Message {
+    pub fn new(role: &str, content: impl Into<String>) -> Self {
+        Self {
+            id: new_message_id(),
+            role: role.to_string(),
+            content: content.into(),
+            created_at: Some(js_sys::Date::now()),
+            ..Default::default()
+        }
+    }
+
+    /// What the user typed for a sent message, with `@id` mentions
+    pub fn typed_text(&self) -> String {
+        self.typed.clone().unwrap_or_else(|| self.content.clone())
+    }
+
+    /// Recovers the typed text of a user message stored before it was kept
+    /// apart, by stripping RAG context and turning document placeholders back
+    /// into `@id` mentions. Placeholders with ids lose them.
+    pub fn separate_typed_text(&mut self) {
+        if self.role != "user" || self.typed.is_some() || (self.context_documents.is_empty() && self.document_refs.is_empty()) {
+            return;
+        }
+        let mut text = self.content.clone();
+        if !self.context_documents.is_empty() {
+            if let Some(pos) = text.rfind("User message:\n") {
+                text = text[pos + "User message:\n".len()..].to_string();
+            }
+        }
+        for doc in &self.document_refs {
+            text = text.replace(&doc.id_placeholder(), &format!("@{}", doc.id));
+            self.content = self.content.replace(&doc.id_placeholder(), &doc.placeholder());
+        }
+        for doc in &self.document_refs {
+            text = text.replace(&doc.placeholder(), &format!("@{}", doc.id));
+        }
+        if text != self.content {
+            self.typed = Some(text);
+        }
+    }
+
+    /// Sets an assistant message from the model's `output`, which may start with
+    /// a think block, continuing the `prefill` it was sent. The reasoning is kept
+    /// apart from the answer. Servers that ignore a trailing assistant message
+    /// answer from scratch, usually repeating the prefill, which then isn't
+    /// added a second time.
+    pub fn set_output(&mut self, output: &str, prefill: &str) {
+        let (reasoning, answer) = crate::utils::split_reasoning(output);
+        self.reasoning = reasoning.map(|text| Reasoning { text: text.to_string(), finished: answer.is_some() });
+        let answer = answer.unwrap_or_default();
+        self.content = if answer.trim_start().starts_with(prefill.trim()) {
+            answer.to_string()
+        } else {
+            format!("{}{}", prefill, answer)
+        };
+        self.token_count = None;
+    }
+
+    /// Moves the reasoning of an answer stored before it was kept apart out of its content
+    pub fn separate_inline_reasoning(&mut self) {
+        if self.role == "assistant" && self.reasoning.is_none() && self.content.trim_start().starts_with(THINK_OPEN) {
+            let output = std::mem::take(&mut self.content);
+            self.set_output(&output, "");
+        }
+    }
+
+    /// Token count of the content, preferring the stored one
+    pub fn tokens(&self) -> usize {
+        self.token_count.unwrap_or_else(|| crate::utils::count_tokens(&self.content))
+    }
+}
+
+/// Size and speed of a generated answer
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct MessageMetrics {
+    /// Tokens of the answer, as reported by the server or counted here
+    pub completion_tokens: usize,
+    /// Tokens of the request; only known when the server reports usage
+    pub prompt_tokens: Option<usize>,
+    /// Milliseconds from sending the request to the first streamed text
+    pub time_to_first_token_ms: Option<f64>,
+    /// Milliseconds from sending the request to the end of the answer
+    pub total_duration_ms: f64,
+    /// Generation speed after the first token, or over the whole request without streaming
+    pub tokens_per_second: Option<f64>,
+}
+
+impl MessageMetrics {
+    /// Metrics of `content` for a request sent at `started_at` and finished now.
+    /// Token counts reported by the server win over counting them here.
+    pub fn measure(content: &str, started_at: f64, first_token_at: Option<f64>, usage: Option<&Usage>) -> Self {
+        let now = js_sys::Date::now();
+        let completion_tokens = usage.map_or_else(|| crate::utils::count_tokens(content), |u| u.completion_tokens);
+        let generating_ms = now - first_token_at.unwrap_or(started_at);
+        Self {
+            completion_tokens,
+            prompt_tokens: usage.map(|u| u.prompt_tokens),
+            time_to_first_token_ms: first_token_at.map(|t| t - started_at),
+            total_duration_ms: now - started_at,
+            tokens_per_second: (generating_ms > 0.0 && completion_tokens > 0).then(|| completion_tokens as f64 * 1000.0 / generating_ms),
+        }
+    }
+
+    /// One line for under the answer, e.g. "412 tok · 38.2 tok/s · 1.2s TTFT"
+    pub fn summary(&self) -> String {
+        let mut parts = vec![format!("{} tok", self.completion_tokens)];
+        if let Some(speed) = self.tokens_per_second {
+            parts.push(format!("{:.1} tok/s", speed));
+        }
+        match self.time_to_first_token_ms {
+            Some(ms) => parts.push(format!("{:.1}s TTFT", ms / 1000.0)),
+            None => parts.push(format!("{:.1}s", self.total_duration_ms / 1000.0)),
+        }
+        parts.join(" · ")
+    }
+
+    /// The details the summary leaves out
+    pub fn details(&self) -> String {
+        let prompt = match self.prompt_tokens {
+            Some(tokens) => format!("{} prompt tokens", tokens),
+            None => "Prompt tokens not reported by the server".to_string(),
+        };
+        format!("{}, {} completion tokens, {:.1}s in total", prompt, self.completion_tokens, self.total_duration_ms / 1000.0)
+    }
+}
+
+/// Partial answer kept when a response failed and was retried; never sent to the model
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct FailedAttempt {
+    pub content: String,
+    /// When the attempt was started
+    pub at: f64,
+}
+
+/// Text the assistant's answer is made to start with
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Prefill {
+    pub text: String,
+    /// Kept for every message instead of the next one only
+    pub pinned: bool,
 }
 
+/// Languages a chat can ask its answers to be written in
+pub const RESPONSE_LANGUAGES: [&str; 10] = ["English", "German", "French", "Spanish", "Italian", "Portuguese", "Dutch", "Polish", "Japanese", "Chinese"];
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct ChatSession {
     pub id: String,
     pub title: String,
     pub messages: Vec<Message>,
     pub created_at: f64,
+    #[serde(default)] // None = send the full history, Some(n) = only the last n exchanges
+    pub history_window: Option<usize>,
+    #[serde(default)] // None = every document is in scope, Some(ids) = only these
+    pub document_scope: Option<Vec<String>>,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)] // Set when the chat was moved to the trash
+    pub trashed_at: Option<f64>,
+    #[serde(default)] // Color key, see `CHAT_LABELS` in the sidebar
+    pub label: Option<String>,
+    #[serde(default)] // Show the retrieved chunks for approval before each RAG request
+    pub review_context: bool,
+    #[serde(default)] // Times the model answered with nothing, for the usage overview
+    pub empty_responses: usize,
+    #[serde(default)] // None = auto, the model answers in whatever language fits
+    pub response_language: Option<String>,
+    #[serde(default)] // A response completed while another chat was open
+    pub unread: bool,
+    #[serde(default)] // Start of the next answer, or of every answer when pinned
+    pub prefill: Option<Prefill>,
+    #[serde(default)] // None = the default from the settings
+    pub response_length: Option<ResponseLength>,
+    #[serde(default)] // None = the model selected in the settings
+    pub model_override: Option<String>,
+    #[serde(default)] // None = the temperature in the settings
+    pub temperature_override: Option<f32>,
+    #[serde(default)] // Sent instead of the messages it covers; they stay in the chat
+    pub summary: Option<String>,
+    #[serde(default)] // Id of the last message the summary covers
+    pub summary_until: Option<String>,
 }
 
 impl ChatSession {
+    /// Drops the summary when it covers any of the messages `ids`, which are
+    /// about to change, so the next request summarizes them as they are then
+    pub fn invalidate_summary(&mut self, ids: &[String]) {
+        let covered = self.summary_until.as_deref().and_then(|until| crate::utils::summary_end(&self.messages, until)).unwrap_or(0);
+        if self.messages[..covered].iter().any(|m| ids.contains(&m.id)) {
+            self.summary = None;
+            self.summary_until = None;
+        }
+    }
+
+    /// Drops the documents `keep` rejects from the chat's document scope;
+    /// true if any were in it
+    pub fn retain_scoped_documents(&mut self, keep: impl Fn(&str) -> bool) -> bool {
+        let Some(scope) = self.document_scope.as_mut() else { return false };
+        let before = scope.len();
+        scope.retain(|id| keep(id));
+        scope.len() != before
+    }
+
     pub fn new(system_prompt: String) -> Self {
         Self {
             id: Uuid::new_v4().to_string(),
             title: "New Chat".to_string(),
-            messages: vec![Message {
-                role: "system".to_string(),
-                content: system_prompt,
-            }],
+            messages: vec![Message::new("system", system_prompt)],
             created_at: js_sys::Date::now(),
+            history_window: None,
+            document_scope: None,
+            archived: false,
+            folder: None,
+            trashed_at: None,
+            label: None,
+            review_context: false,
+            empty_responses: 0,
+            response_language: None,
+            unread: false,
+            prefill: None,
+            response_length: None,
+            model_override: None,
+            temperature_override: None,
+            summary: None,
+            summary_until: None,
         }
     }
+
+    /// Whether the chat shows up in the regular chat list
+    pub fn is_listed(&self) -> bool {
+        !self.archived && self.trashed_at.is_none()
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
@@ -36,6 +397,111 @@ pub struct SavedPrompt {
     pub content: String,
 }
 
+/// How often a recurring prompt is sent
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RecurrenceInterval {
+    Hourly,
+    Daily,
+    /// Once each time the app is opened
+    OnAppOpen,
+}
+
+impl RecurrenceInterval {
+    pub const ALL: [RecurrenceInterval; 3] = [Self::Hourly, Self::Daily, Self::OnAppOpen];
+
+    /// Time between runs; `None` when runs follow app starts instead
+    pub fn period_ms(self) -> Option<f64> {
+        match self {
+            Self::Hourly => Some(60.0 * 60.0 * 1000.0),
+            Self::Daily => Some(24.0 * 60.0 * 60.0 * 1000.0),
+            Self::OnAppOpen => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Hourly => "Hourly",
+            Self::Daily => "Daily",
+            Self::OnAppOpen => "On app open",
+        }
+    }
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Hourly => "hourly",
+            Self::Daily => "daily",
+            Self::OnAppOpen => "on_app_open",
+        }
+    }
+}
+
+/// What happens to the history when a request would not fit the context
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextStrategy {
+    /// Send everything and let the server decide
+    SendAll,
+    /// Leave out the oldest exchanges, a question and its answers at a time
+    #[default]
+    SlidingWindow,
+    /// Leave out the oldest messages one by one
+    TruncateOldest,
+    /// Fold the oldest exchanges into a summary of the chat
+    Summarize,
+}
+
+impl ContextStrategy {
+    pub const ALL: [ContextStrategy; 4] = [Self::SendAll, Self::SlidingWindow, Self::TruncateOldest, Self::Summarize];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::SendAll => "Send everything",
+            Self::SlidingWindow => "Drop the oldest exchanges",
+            Self::TruncateOldest => "Drop the oldest messages",
+            Self::Summarize => "Summarize the oldest exchanges",
+        }
+    }
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::SendAll => "send_all",
+            Self::SlidingWindow => "sliding_window",
+            Self::TruncateOldest => "truncate_oldest",
+            Self::Summarize => "summarize",
+        }
+    }
+}
+
+/// A saved prompt sent automatically into a chat of its own
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct RecurringPrompt {
+    pub id: String,
+    /// The saved prompt whose text is sent
+    pub prompt_id: String,
+    pub interval: RecurrenceInterval,
+    #[serde(default)] // Created on the first run when missing or deleted
+    pub chat_id: Option<String>,
+    /// Stored so a reload doesn't send it again early
+    pub next_run_at: f64,
+    #[serde(default)]
+    pub paused: bool,
+}
+
+impl RecurringPrompt {
+    /// First timed run is one interval from now
+    pub fn new(prompt_id: String, interval: RecurrenceInterval, chat_id: Option<String>) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            prompt_id,
+            interval,
+            chat_id,
+            next_run_at: js_sys::Date::now() + interval.period_ms().unwrap_or(0.0),
+            paused: false,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
 pub struct DocumentChunk {
     pub id: String,
@@ -43,6 +509,8 @@ pub struct DocumentChunk {
     pub chunk_index: usize,
     pub content: String,
     pub created_at: f64,
+    #[serde(default)] // Missing for chunks stored before per-chunk counting
+    pub token_count: usize,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
@@ -55,6 +523,132 @@ pub struct Document {
     pub total_tokens: usize,
     pub content_preview: String,
     pub full_content: String,
+    #[serde(default = "default_true")] // Disabled documents are kept but never used as context
+    pub enabled: bool,
+    #[serde(default)] // Counted on upload; older documents are counted when shown
+    pub word_count: Option<usize>,
+    #[serde(default)] // Embeddings of the chunks, used by RAG retrieval
+    pub index: IndexStatus,
+}
+
+/// How far a document's chunks have been embedded
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum IndexStatus {
+    #[default]
+    NotIndexed,
+    Indexing { done: usize, total: usize },
+    Indexed { model: String },
+    Failed { error: String },
+}
+
+impl IndexStatus {
+    /// Indexed with `model`; an index built with another model can't be searched
+    pub fn is_current(&self, model: &str) -> bool {
+        matches!(self, IndexStatus::Indexed { model: m } if m == model)
+    }
+
+    /// Short label for badges, judged against the configured embedding model
+    pub fn label(&self, model: &str) -> String {
+        match self {
+            IndexStatus::NotIndexed => "Not indexed".to_string(),
+            IndexStatus::Indexing { done, total } => format!("Indexing {}%", done * 100 / (*total).max(1)),
+            IndexStatus::Indexed { model: m } if m == model => "Indexed".to_string(),
+            IndexStatus::Indexed { .. } => "Stale index".to_string(),
+            IndexStatus::Failed { .. } => "Index failed".to_string(),
+        }
+    }
+
+    /// Longer explanation for tooltips
+    pub fn detail(&self) -> String {
+        match self {
+            IndexStatus::NotIndexed => "Not indexed; RAG matches this document by keywords".to_string(),
+            IndexStatus::Indexing { done, total } => format!("Embedding chunk {} of {}", done, total),
+            IndexStatus::Indexed { model } => format!("Indexed with {}", model),
+            IndexStatus::Failed { error } => format!("Indexing failed: {}", error),
+        }
+    }
+}
+
+impl Document {
+    /// Word count of the content, preferring the stored one
+    pub fn words(&self) -> usize {
+        self.word_count.unwrap_or_else(|| crate::utils::count_words(&self.full_content))
+    }
+}
+
+/// Protocol the model server speaks
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// `/v1/chat/completions`, streaming server-sent events
+    #[default]
+    OpenAI,
+    /// Ollama's own `/api/chat`, streaming newline-delimited JSON
+    Ollama,
+}
+
+/// Unit for the sizes shown next to the input, in the document list and in
+/// the statistics
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CountUnit {
+    #[default]
+    Tokens,
+    Words,
+}
+
+/// How long answers should be. Each preset caps the answer's tokens and may
+/// ask the model for a matching style; Custom only sets the cap.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseLength {
+    Short,
+    /// No cap, the server's own default
+    #[default]
+    Normal,
+    Detailed,
+    Custom(u32),
+}
+
+impl ResponseLength {
+    pub const PRESETS: [ResponseLength; 3] = [ResponseLength::Short, ResponseLength::Normal, ResponseLength::Detailed];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ResponseLength::Short => "Short",
+            ResponseLength::Normal => "Normal",
+            ResponseLength::Detailed => "Detailed",
+            ResponseLength::Custom(_) => "Custom",
+        }
+    }
+
+    /// `max_tokens` sent with the request
+    pub fn max_tokens(&self) -> Option<u32> {
+        match self {
+            ResponseLength::Short => Some(300),
+            ResponseLength::Normal => None,
+            ResponseLength::Detailed => Some(4096),
+            ResponseLength::Custom(tokens) => Some(*tokens),
+        }
+    }
+
+    /// Added to the system message of the request
+    pub fn style_hint(&self) -> Option<&'static str> {
+        match self {
+            ResponseLength::Short => Some("Keep answers concise, under 150 words."),
+            ResponseLength::Detailed => Some("Give thorough, detailed answers."),
+            _ => None,
+        }
+    }
+
+    /// E.g. "Short (max 300 tokens)"
+    pub fn describe(&self) -> String {
+        match self.max_tokens() {
+            Some(tokens) => format!("{} (max {} tokens)", self.label(), tokens),
+            None => self.label().to_string(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
@@ -62,7 +656,59 @@ pub enum DocumentContextMode {
     #[serde(rename = "manual")]
     Manual,  // User manually references documents
     #[default]
+    #[allow(clippy::upper_case_acronyms)]
     RAG,     // Automatic retrieval of relevant chunks (default)
+    #[serde(rename = "off")]
+    Off,     // Documents are never added to requests
+}
+
+/// Known local servers offered during onboarding
+#[derive(Clone, PartialEq, Debug)]
+pub struct ProviderPreset {
+    pub name: &'static str,
+    pub base_url: &'static str,
+}
+
+pub const PROVIDER_PRESETS: &[ProviderPreset] = &[
+    ProviderPreset { name: "llama.cpp", base_url: "http://localhost:8080" },
+    ProviderPreset { name: "LM Studio", base_url: "http://localhost:1234" },
+    ProviderPreset { name: "Ollama", base_url: "http://localhost:11434" },
+    ProviderPreset { name: "vLLM", base_url: "http://localhost:8000" },
+];
+
+pub fn new_message_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_prompt_warning_tokens() -> usize {
+    4000
+}
+
+fn default_context_length() -> usize {
+    8192
+}
+
+fn default_keep_alive_minutes() -> u32 {
+    4
+}
+
+fn default_request_retries() -> u32 {
+    2
+}
+
+/// Most retries a request gets; more would keep the user waiting for minutes
+pub const MAX_REQUEST_RETRIES: u32 = 5;
+
+fn default_stall_warning_seconds() -> u32 {
+    30
+}
+
+fn default_temperature() -> f32 {
+    DEFAULT_TEMPERATURE
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
@@ -75,6 +721,77 @@ pub struct AppSettings {
     pub saved_prompts: Vec<SavedPrompt>,
     #[serde(default)] // Ensures backward compatibility with existing localStorage data
     pub document_context_mode: DocumentContextMode,
+    #[serde(default = "default_true")] // Existing users have already set things up
+    pub onboarding_completed: bool,
+    #[serde(default)]
+    pub stop_on_chat_switch: bool,
+    #[serde(default = "default_prompt_warning_tokens")] // 0 disables the confirmation
+    pub prompt_warning_tokens: usize,
+    #[serde(default)]
+    pub logprobs_enabled: bool,
+    #[serde(default)] // Keeps full request payloads with each message
+    pub debug_mode: bool,
+    #[serde(default)] // Appended to the system prompt of every request
+    pub custom_instructions: String,
+    #[serde(default = "default_context_length")] // Tokens the model can take in one request
+    pub context_length: usize,
+    #[serde(default)] // Folders in sidebar order; folders not listed come after
+    pub folder_order: Vec<String>,
+    #[serde(default = "default_true")] // Point out questions that were asked before
+    pub similar_question_hints: bool,
+    #[serde(default)] // Saved prompts sent automatically on a schedule
+    pub recurring_prompts: Vec<RecurringPrompt>,
+    #[serde(default)] // Tokens, or words and characters for writing
+    pub count_unit: CountUnit,
+    #[serde(default)]
+    pub high_contrast: bool,
+    #[serde(default)] // Also on whenever the system asks for reduced motion
+    pub reduced_motion: bool,
+    #[serde(default)] // Empty = RAG sends whole documents instead of retrieving chunks
+    pub embedding_model: String,
+    #[serde(default)] // Manual mode tells the model which documents it can ask for
+    pub document_list_in_prompt: bool,
+    #[serde(default = "default_true")] // Empty answers are dropped instead of kept in the chat
+    pub remove_empty_responses: bool,
+    #[serde(default)] // Pings the server while idle so it keeps the model loaded
+    pub keep_alive: bool,
+    #[serde(default = "default_keep_alive_minutes")]
+    pub keep_alive_minutes: u32,
+    #[serde(default = "default_stall_warning_seconds")] // Silence before a generation is flagged; 0 disables
+    pub stall_warning_seconds: u32,
+    #[serde(default)] // For chats that don't choose their own
+    pub response_length: ResponseLength,
+    #[serde(default = "default_true")] // Offer documents that match the typed message as context
+    pub document_suggestions: bool,
+    #[serde(default = "default_temperature")] // For chats that don't choose their own
+    pub temperature: f32,
+    #[serde(default)] // Cap for answers whose response length sets none
+    pub max_tokens: Option<u32>,
+    #[serde(default)] // None = the server's default, as for the penalties
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default)]
+    pub presence_penalty: Option<f32>,
+    #[serde(default)] // The model stops before writing any of these
+    pub stop_sequences: Vec<String>,
+    #[serde(default)] // Sent as a bearer token; empty sends no Authorization header
+    pub api_key: String,
+    #[serde(default)]
+    pub backend_kind: BackendKind,
+    #[serde(default = "default_request_retries")] // After connection errors and HTTP 429/503
+    pub request_retries: u32,
+    #[serde(default)] // Models whose context differs from `context_length`
+    pub model_context_lengths: BTreeMap<String, usize>,
+    #[serde(default)] // For requests that would not fit the context
+    pub context_strategy: ContextStrategy,
+}
+
+impl AppSettings {
+    /// Tokens `model` accepts per request
+    pub fn context_length_for(&self, model: &str) -> usize {
+        self.model_context_lengths.get(model).copied().unwrap_or(self.context_length)
+    }
 }
 
 impl Default for AppSettings {
@@ -86,42 +803,260 @@ impl Default for AppSettings {
             stream_enabled: true,
             saved_prompts: Vec::new(),
             document_context_mode: DocumentContextMode::RAG,
+            onboarding_completed: false,
+            stop_on_chat_switch: false,
+            prompt_warning_tokens: default_prompt_warning_tokens(),
+            logprobs_enabled: false,
+            debug_mode: false,
+            custom_instructions: String::new(),
+            context_length: default_context_length(),
+            folder_order: Vec::new(),
+            similar_question_hints: true,
+            recurring_prompts: Vec::new(),
+            count_unit: CountUnit::Tokens,
+            high_contrast: false,
+            reduced_motion: false,
+            document_list_in_prompt: false,
+            remove_empty_responses: true,
+            keep_alive: false,
+            keep_alive_minutes: default_keep_alive_minutes(),
+            stall_warning_seconds: default_stall_warning_seconds(),
+            response_length: ResponseLength::Normal,
+            document_suggestions: true,
+            temperature: DEFAULT_TEMPERATURE,
+            max_tokens: None,
+            api_key: String::new(),
+            backend_kind: BackendKind::OpenAI,
+            request_retries: default_request_retries(),
+            model_context_lengths: BTreeMap::new(),
+            context_strategy: ContextStrategy::SlidingWindow,
+            top_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop_sequences: Vec::new(),
+            embedding_model: String::new(),
         }
     }
 }
 
-// API DTOs (Unchanged)
-#[derive(Serialize, Debug)]
+/// Settings preset by whoever deployed the app, from `window.SWI_CONFIG` or the URL
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct DeploymentConfig {
+    /// Validated values by settings field name
+    pub values: serde_json::Map<String, serde_json::Value>,
+    /// Fields users can't change in the settings
+    pub locked: Vec<String>,
+}
+
+/// Options passed by a host page when mounting the app into one of its elements
+#[derive(Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(default)]
+pub struct MountConfig {
+    /// Replaces the stored server URL
+    pub base_url: Option<String>,
+    /// Replaces the stored model
+    pub model: Option<String>,
+    /// CSS variables to override, e.g. {"accent-color": "#2563eb"}
+    pub theme: BTreeMap<String, String>,
+    /// Prepended to the storage keys of chats and settings, so several mounts on
+    /// one page keep their own. Documents are shared between mounts.
+    pub storage_key_prefix: String,
+}
+
+impl MountConfig {
+    /// Inline style declaring the theme's CSS variables
+    pub fn theme_style(&self) -> String {
+        self.theme
+            .iter()
+            .map(|(name, value)| {
+                // Keep each value to a single declaration
+                let value: String = value.chars().filter(|c| !matches!(c, ';' | '{' | '}')).collect();
+                format!("--{}: {};", name.trim_start_matches("--"), value)
+            })
+            .collect()
+    }
+}
+
+/// Last successful model list fetched from a server
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct ModelCacheEntry {
+    pub models: Vec<String>,
+    pub fetched_at: f64,
+}
+
+// API DTOs
+/// A message as sent to the server; local-only fields of `Message` are left out
+#[derive(Serialize, Debug, Clone)]
+pub struct ApiMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl From<&Message> for ApiMessage {
+    fn from(msg: &Message) -> Self {
+        Self {
+            role: msg.role.clone(),
+            content: msg.content.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
 pub struct ChatRequest {
-    pub messages: Vec<Message>,
+    pub messages: Vec<ApiMessage>,
     pub model: String,
     pub temperature: f32,
     pub stream: bool,
+    /// Asks for `usage` with the last streamed chunk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_logprobs: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct StreamOptions {
+    pub include_usage: bool,
 }
 
+/// Sampling temperature until the settings choose another
+pub const DEFAULT_TEMPERATURE: f32 = 0.7;
+
 #[derive(Deserialize, Debug)]
 pub struct ChatResponse {
     pub choices: Vec<ChatChoice>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+/// Token counts the server reports for a request
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens: usize,
+    #[serde(default)]
+    pub completion_tokens: usize,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct ChatChoice {
-    pub message: Message,
+    pub message: ResponseMessage,
+    // Kept raw so an unexpected shape never fails the whole response
+    #[serde(default)]
+    pub logprobs: Option<serde_json::Value>,
+}
+
+/// Opens the reasoning of a model that thinks before answering
+pub const THINK_OPEN: &str = "<think>";
+pub const THINK_CLOSE: &str = "</think>";
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ResponseMessage {
+    pub content: String,
+    #[serde(default)] // Reasoning, from servers that send it apart from the answer
+    pub reasoning_content: Option<String>,
+}
+
+impl ResponseMessage {
+    /// The answer, after its reasoning in a think block
+    pub fn text(&self) -> String {
+        match self.reasoning_content.as_deref().filter(|r| !r.trim().is_empty()) {
+            Some(reasoning) => format!("{}{}{}\n{}", THINK_OPEN, reasoning, THINK_CLOSE, self.content),
+            None => self.content.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ChoiceLogprobs {
+    #[serde(default)]
+    content: Option<Vec<TokenLogprob>>,
+}
+
+/// Token logprobs from a choice's raw `logprobs` field, if the server sent usable ones
+pub fn parse_logprobs(raw: &Option<serde_json::Value>) -> Option<Vec<TokenLogprob>> {
+    let value = raw.as_ref()?.clone();
+    serde_json::from_value::<ChoiceLogprobs>(value).ok()?.content
 }
 
 #[derive(Deserialize, Debug)]
 pub struct StreamResponse {
     pub choices: Vec<StreamChoice>,
+    #[serde(default)] // Sent with the last chunk by servers that report usage while streaming
+    pub usage: Option<Usage>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct StreamChoice {
+    #[serde(default)] // Some proxies leave it out for single-choice streams
+    pub index: u32,
     pub delta: StreamDelta,
+    #[serde(default)]
+    pub logprobs: Option<serde_json::Value>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct StreamDelta {
     pub content: Option<String>,
+    #[serde(default)] // Reasoning, from servers that send it apart from the answer
+    pub reasoning_content: Option<String>,
+}
+
+impl StreamDelta {
+    /// Appends the delta's text to `content`; false if it had none. Reasoning
+    /// goes in a think block, the way models that inline their reasoning
+    /// write it, and `in_reasoning` tracks whether that block is still open.
+    pub fn append_to(&self, content: &mut String, in_reasoning: &mut bool) -> bool {
+        let mut appended = false;
+        if let Some(reasoning) = self.reasoning_content.as_deref().filter(|r| !r.is_empty()) {
+            if !*in_reasoning {
+                content.push_str(THINK_OPEN);
+                *in_reasoning = true;
+            }
+            content.push_str(reasoning);
+            appended = true;
+        }
+        if let Some(text) = self.content.as_deref().filter(|t| !t.is_empty()) {
+            if *in_reasoning {
+                content.push_str(THINK_CLOSE);
+                content.push('\n');
+                *in_reasoning = false;
+            }
+            content.push_str(text);
+            appended = true;
+        }
+        appended
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct EmbeddingRequest<'a> {
+    pub model: &'a str,
+    pub input: &'a [String],
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EmbeddingResponse {
+    pub data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EmbeddingData {
+    pub embedding: Vec<f32>,
+    #[serde(default)]
+    pub index: usize,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
@@ -132,4 +1067,306 @@ pub struct ModelListResponse {
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct ModelInfo {
     pub id: String,
-}
\ No newline at end of file
+}
+
+/// Body of Ollama's `/api/chat`, which takes the sampling parameters as `options`
+#[derive(Serialize, Debug)]
+pub struct OllamaChatRequest<'a> {
+    pub model: &'a str,
+    pub messages: &'a [ApiMessage],
+    pub stream: bool,
+    pub options: OllamaOptions<'a>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OllamaOptions<'a> {
+    pub temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    pub stop: &'a [String],
+}
+
+impl<'a> From<&'a ChatRequest> for OllamaChatRequest<'a> {
+    fn from(req: &'a ChatRequest) -> Self {
+        Self {
+            model: &req.model,
+            messages: &req.messages,
+            stream: req.stream,
+            options: OllamaOptions {
+                temperature: req.temperature,
+                num_predict: req.max_tokens,
+                top_p: req.top_p,
+                frequency_penalty: req.frequency_penalty,
+                presence_penalty: req.presence_penalty,
+                stop: &req.stop,
+            },
+        }
+    }
+}
+
+/// A complete answer of Ollama's `/api/chat`, or one line of its stream
+#[derive(Deserialize, Debug)]
+pub struct OllamaChatResponse {
+    pub message: OllamaMessage,
+    #[serde(default)] // Set on the last line, which carries the token counts
+    pub done: bool,
+    #[serde(default)]
+    pub prompt_eval_count: Option<usize>,
+    #[serde(default)]
+    pub eval_count: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OllamaMessage {
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)] // Reasoning of thinking models
+    pub thinking: Option<String>,
+}
+
+impl OllamaChatResponse {
+    fn usage(&self) -> Option<Usage> {
+        self.done.then(|| Usage {
+            prompt_tokens: self.prompt_eval_count.unwrap_or_default(),
+            completion_tokens: self.eval_count.unwrap_or_default(),
+        })
+    }
+}
+
+impl From<OllamaChatResponse> for ChatResponse {
+    fn from(resp: OllamaChatResponse) -> Self {
+        let usage = resp.usage();
+        let message = ResponseMessage { content: resp.message.content, reasoning_content: resp.message.thinking };
+        Self { choices: vec![ChatChoice { message, logprobs: None }], usage }
+    }
+}
+
+impl From<OllamaChatResponse> for StreamResponse {
+    fn from(resp: OllamaChatResponse) -> Self {
+        let usage = resp.usage();
+        let delta = StreamDelta { content: Some(resp.message.content), reasoning_content: resp.message.thinking };
+        Self { choices: vec![StreamChoice { index: 0, delta, logprobs: None }], usage }
+    }
+}
+
+/// Ollama's model list, from `/api/tags`
+#[derive(Deserialize, Debug)]
+pub struct OllamaTagsResponse {
+    pub models: Vec<OllamaModel>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OllamaModel {
+    pub name: String,
+}
+
+impl From<OllamaTagsResponse> for ModelListResponse {
+    fn from(tags: OllamaTagsResponse) -> Self {
+        Self { data: tags.models.into_iter().map(|m| ModelInfo { id: m.name }).collect() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ollama_requests_carry_sampling_options() {
+        let request = ChatRequest {
+            messages: vec![ApiMessage { role: "user".into(), content: "hi".into() }],
+            model: "llama3".into(),
+            temperature: 0.5,
+            stream: true,
+            stream_options: Some(StreamOptions { include_usage: true }),
+            logprobs: None,
+            top_logprobs: None,
+            max_tokens: Some(300),
+            top_p: Some(0.9),
+            frequency_penalty: None,
+            presence_penalty: Some(0.25),
+            stop: vec!["END".into()],
+        };
+        let body = serde_json::to_value(OllamaChatRequest::from(&request)).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "model": "llama3",
+                "messages": [{ "role": "user", "content": "hi" }],
+                "stream": true,
+                "options": { "temperature": 0.5, "num_predict": 300, "top_p": 0.9f32, "presence_penalty": 0.25, "stop": ["END"] },
+            })
+        );
+
+        let bare = ChatRequest { max_tokens: None, top_p: None, presence_penalty: None, stop: Vec::new(), ..request };
+        let body = serde_json::to_value(OllamaChatRequest::from(&bare)).unwrap();
+        assert_eq!(body["options"], serde_json::json!({ "temperature": 0.5 }));
+    }
+
+    #[test]
+    fn ollama_answers_become_chat_responses() {
+        let json = r#"{"message":{"role":"assistant","content":"Hi!","thinking":"A greeting."},"done":true,"prompt_eval_count":12,"eval_count":3}"#;
+        let response = ChatResponse::from(serde_json::from_str::<OllamaChatResponse>(json).unwrap());
+        assert_eq!(response.choices.len(), 1);
+        assert_eq!(response.choices[0].message.content, "Hi!");
+        assert_eq!(response.choices[0].message.reasoning_content.as_deref(), Some("A greeting."));
+        assert_eq!(response.usage, Some(Usage { prompt_tokens: 12, completion_tokens: 3 }));
+    }
+
+    #[test]
+    fn ollama_stream_lines_become_chunks() {
+        let line = r#"{"message":{"role":"assistant","content":"Hel"},"done":false}"#;
+        let chunk = StreamResponse::from(serde_json::from_str::<OllamaChatResponse>(line).unwrap());
+        assert_eq!(chunk.choices[0].index, 0);
+        assert_eq!(chunk.choices[0].delta.content.as_deref(), Some("Hel"));
+        assert_eq!(chunk.usage, None);
+
+        // The last line carries the counts and may leave out the message text
+        let last = r#"{"message":{"role":"assistant"},"done":true,"eval_count":7}"#;
+        let chunk = StreamResponse::from(serde_json::from_str::<OllamaChatResponse>(last).unwrap());
+        assert_eq!(chunk.choices[0].delta.content.as_deref(), Some(""));
+        assert_eq!(chunk.usage, Some(Usage { prompt_tokens: 0, completion_tokens: 7 }));
+    }
+
+    #[test]
+    fn ollama_tags_become_the_model_list() {
+        let tags: OllamaTagsResponse = serde_json::from_str(r#"{"models":[{"name":"llama3:8b","size":1},{"name":"qwen3"}]}"#).unwrap();
+        let list = ModelListResponse::from(tags);
+        assert_eq!(list.data, [ModelInfo { id: "llama3:8b".into() }, ModelInfo { id: "qwen3".into() }]);
+    }
+
+    fn answer(output: &str, prefill: &str) -> Message {
+        let mut message = Message { role: "assistant".into(), ..Default::default() };
+        message.set_output(output, prefill);
+        message
+    }
+
+    #[test]
+    fn reasoning_is_kept_apart_from_the_answer() {
+        let message = answer("<think>The user greets me.</think>\nHello!", "");
+        assert_eq!(message.content, "Hello!");
+        assert_eq!(message.reasoning, Some(Reasoning { text: "The user greets me.".into(), finished: true }));
+    }
+
+    #[test]
+    fn unfinished_reasoning_has_no_answer_yet() {
+        let message = answer("<think>Let me see", "");
+        assert_eq!(message.content, "");
+        assert_eq!(message.reasoning, Some(Reasoning { text: "Let me see".into(), finished: false }));
+    }
+
+    #[test]
+    fn prefill_goes_before_the_answer_not_the_reasoning() {
+        let message = answer("<think>Keep it short.</think>\nthree reasons.", "Here are ");
+        assert_eq!(message.content, "Here are three reasons.");
+        assert!(message.reasoning.is_some_and(|r| r.finished));
+
+        let message = answer("<think>Still thinking", "Here are ");
+        assert_eq!(message.content, "Here are ");
+        assert!(message.reasoning.is_some_and(|r| !r.finished));
+    }
+
+    #[test]
+    fn repeated_prefill_is_not_doubled() {
+        assert_eq!(answer("Here are three reasons.", "Here are ").content, "Here are three reasons.");
+        assert_eq!(answer("three reasons.", "Here are ").content, "Here are three reasons.");
+    }
+
+    #[test]
+    fn reasoning_is_never_sent_back() {
+        let message = answer("<think>Private chain of thought</think>\nThe answer.", "");
+        assert_eq!(ApiMessage::from(&message).content, "The answer.");
+    }
+
+    #[test]
+    fn stored_inline_reasoning_is_separated() {
+        let mut message = Message { role: "assistant".into(), content: "<think>Old</think>\nAnswer".into(), ..Default::default() };
+        message.separate_inline_reasoning();
+        assert_eq!(message.content, "Answer");
+        assert_eq!(message.reasoning.map(|r| r.text), Some("Old".to_string()));
+
+        let mut message = Message { role: "user".into(), content: "<think>typed</think>".into(), ..Default::default() };
+        message.separate_inline_reasoning();
+        assert_eq!(message.content, "<think>typed</think>");
+    }
+
+    fn summarized_chat() -> ChatSession {
+        let mut chat: ChatSession = serde_json::from_str(r#"{"id":"c","title":"t","created_at":0,"messages":[]}"#).unwrap();
+        chat.messages = ["sys", "q1", "a1", "q2"]
+            .iter()
+            .map(|id| Message { id: id.to_string(), role: "user".into(), ..Default::default() })
+            .collect();
+        chat.summary = Some("They said hello.".into());
+        chat.summary_until = Some("a1".into());
+        chat
+    }
+
+    #[test]
+    fn changing_a_covered_message_drops_the_summary() {
+        let mut chat = summarized_chat();
+        chat.invalidate_summary(&["q1".to_string()]);
+        assert_eq!((chat.summary, chat.summary_until), (None, None));
+    }
+
+    #[test]
+    fn changing_a_later_message_keeps_the_summary() {
+        let mut chat = summarized_chat();
+        chat.invalidate_summary(&["q2".to_string()]);
+        assert!(chat.summary.is_some());
+        assert_eq!(chat.summary_until.as_deref(), Some("a1"));
+    }
+
+    #[test]
+    fn scopes_keep_only_the_documents_asked_for() {
+        let mut chat = summarized_chat();
+        assert!(!chat.retain_scoped_documents(|_| false));
+        assert_eq!(chat.document_scope, None);
+
+        chat.document_scope = Some(vec!["a".into(), "b".into(), "c".into()]);
+        assert!(chat.retain_scoped_documents(|id| id != "b"));
+        assert_eq!(chat.document_scope, Some(vec!["a".to_string(), "c".to_string()]));
+        assert!(!chat.retain_scoped_documents(|_| true));
+        assert!(chat.retain_scoped_documents(|_| false));
+        assert_eq!(chat.document_scope, Some(Vec::new()));
+    }
+
+    #[test]
+    fn typed_text_is_the_message_as_typed() {
+        let plain = Message { role: "user".into(), content: "Hi".into(), ..Default::default() };
+        assert_eq!(plain.typed_text(), "Hi");
+
+        let with_context = Message {
+            role: "user".into(),
+            content: "Relevant documents:\n\nUser message:\nnot this\n\nUser message:\nWhat is it?".into(),
+            typed: Some("What is it?".into()),
+            context_documents: vec!["d".into()],
+            ..Default::default()
+        };
+        assert_eq!(with_context.typed_text(), "What is it?");
+    }
+
+    #[test]
+    fn typed_text_of_older_messages_is_recovered() {
+        let mut message = Message {
+            role: "user".into(),
+            content: "Relevant documents:\n\nUser message:\nSee [Document: a.md, id d1]".into(),
+            context_documents: vec!["d1".into()],
+            document_refs: vec![DocumentRef { id: "d1".into(), filename: "a.md".into() }],
+            ..Default::default()
+        };
+        message.separate_typed_text();
+        assert_eq!(message.typed.as_deref(), Some("See @d1"));
+        assert!(message.content.ends_with("See [Document: a.md]"));
+
+        // Nothing to recover from a message stored as typed
+        let mut message = Message { role: "user".into(), content: "User message:\nquoted".into(), ..Default::default() };
+        message.separate_typed_text();
+        assert_eq!(message.typed, None);
+    }
+}
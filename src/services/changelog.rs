@@ -0,0 +1,178 @@
+use crate::services::storage::{LocalStorage, RawStore};
+
+/// Version of this build, from Cargo.toml
+pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Version whose news the user last saw
+const LAST_SEEN_KEY: &str = "last_seen_version";
+
+/// One change worth telling users about
+#[derive(PartialEq)]
+pub struct ChangelogEntry {
+    pub text: &'static str,
+    /// Settings field the change is configured with, linked from the entry
+    pub setting: Option<&'static str>,
+}
+
+/// A version and what changed in it
+pub type Release = (&'static str, &'static [ChangelogEntry]);
+
+/// Newest first. Add a release here whenever the version in Cargo.toml changes.
+pub const CHANGELOG: &[Release] = &[(
+    "0.2.0",
+    &[
+        ChangelogEntry {
+            text: "Ollama's own API can be chosen as the server type, and an API key can be sent to OpenAI-compatible servers.",
+            setting: Some("backend_kind"),
+        },
+        ChangelogEntry {
+            text: "Requests that fail for a passing reason are retried, waiting a little longer each time.",
+            setting: Some("request_retries"),
+        },
+        ChangelogEntry {
+            text: "Old messages that no longer fit the context can be summarized instead of left out.",
+            setting: Some("context_strategy"),
+        },
+        ChangelogEntry {
+            text: "Response length presets set how long answers are, from a short reply to a custom token limit.",
+            setting: Some("response_length"),
+        },
+        ChangelogEntry {
+            text: "Type / to insert a saved prompt and @ to attach a document to your message.",
+            setting: None,
+        },
+        ChangelogEntry {
+            text: "Messages written while the server is unreachable wait and go out once it answers again.",
+            setting: None,
+        },
+        ChangelogEntry {
+            text: "A regenerated answer can be compared with the one it replaced, and a failed answer is kept when you retry it.",
+            setting: None,
+        },
+        ChangelogEntry {
+            text: "The reasoning of thinking models is shown apart from the answer, collapsed once the answer starts.",
+            setting: None,
+        },
+        ChangelogEntry {
+            text: "The model can be kept loaded while you are away from the chat.",
+            setting: Some("keep_alive_minutes"),
+        },
+    ],
+), (
+    "0.1.0",
+    &[
+        ChangelogEntry {
+            text: "Documents can be indexed with an embedding model, so RAG sends only the most relevant chunks.",
+            setting: Some("embedding_model"),
+        },
+        ChangelogEntry {
+            text: "Chats can be exported as ChatML or as a messages JSON array from the header's Export button.",
+            setting: None,
+        },
+        ChangelogEntry {
+            text: "Messages sent while a chat is answering are queued, and each response can be stopped on its own.",
+            setting: None,
+        },
+        ChangelogEntry {
+            text: "A warning appears when the system prompt, instructions and documents leave little room in the context.",
+            setting: Some("context_length"),
+        },
+        ChangelogEntry {
+            text: "Chats are stored in a new format; the old data is kept until you confirm the migration.",
+            setting: None,
+        },
+    ],
+)];
+
+pub struct ChangelogService;
+
+impl ChangelogService {
+    /// Releases the user hasn't seen the news of, newest first. A fresh install
+    /// has nothing to catch up on and is marked as up to date right away; an
+    /// install from before versions were recorded sees the current release only.
+    pub fn unseen(prefix: &str, fresh_install: bool) -> Vec<&'static Release> {
+        Self::unseen_in(&LocalStorage, prefix, fresh_install)
+    }
+
+    fn unseen_in(store: &impl RawStore, prefix: &str, fresh_install: bool) -> Vec<&'static Release> {
+        let last_seen = store.get_raw(&Self::key(prefix)).and_then(|raw| serde_json::from_str::<String>(&raw).ok());
+        match last_seen {
+            None if fresh_install => {
+                Self::mark_seen_in(store, prefix);
+                Vec::new()
+            }
+            None => CHANGELOG.iter().filter(|(version, _)| *version == APP_VERSION).collect(),
+            Some(seen) => CHANGELOG
+                .iter()
+                .filter(|(version, _)| parse_version(version) > parse_version(&seen))
+                .collect(),
+        }
+    }
+
+    pub fn mark_seen(prefix: &str) {
+        Self::mark_seen_in(&LocalStorage, prefix);
+    }
+
+    fn mark_seen_in(store: &impl RawStore, prefix: &str) {
+        if let Ok(json) = serde_json::to_string(APP_VERSION) {
+            let _ = store.try_set_raw(&Self::key(prefix), &json);
+        }
+    }
+
+    fn key(prefix: &str) -> String {
+        format!("{}{}", prefix, LAST_SEEN_KEY)
+    }
+}
+
+/// "0.10.2" as [0, 10, 2], so versions compare by number rather than as text
+fn parse_version(version: &str) -> Vec<u32> {
+    version.split('.').map(|part| part.trim().parse().unwrap_or(0)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::storage::MemoryStore;
+
+    fn versions(releases: Vec<&Release>) -> Vec<&str> {
+        releases.into_iter().map(|(version, _)| *version).collect()
+    }
+
+    #[test]
+    fn versions_compare_by_number() {
+        assert_eq!(parse_version("0.10.2"), [0, 10, 2]);
+        assert!(parse_version("0.10.0") > parse_version("0.9.9"));
+        assert!(parse_version("1.0") < parse_version("1.0.1"));
+        assert_eq!(parse_version("x.1"), [0, 1]);
+    }
+
+    #[test]
+    fn the_current_version_has_a_release() {
+        assert_eq!(CHANGELOG[0].0, APP_VERSION);
+        assert!(CHANGELOG.windows(2).all(|w| parse_version(w[0].0) > parse_version(w[1].0)));
+    }
+
+    #[test]
+    fn releases_after_the_last_seen_are_unseen() {
+        let store = MemoryStore::with(&[("p_last_seen_version", "\"0.1.0\"")]);
+        let unseen = versions(ChangelogService::unseen_in(&store, "p_", false));
+        assert_eq!(unseen, CHANGELOG.iter().map(|(v, _)| *v).filter(|v| parse_version(v) > vec![0, 1, 0]).collect::<Vec<_>>());
+        assert!(unseen.contains(&APP_VERSION));
+
+        let current = format!("{:?}", APP_VERSION);
+        let store = MemoryStore::with(&[("p_last_seen_version", current.as_str())]);
+        assert!(ChangelogService::unseen_in(&store, "p_", false).is_empty());
+    }
+
+    #[test]
+    fn a_fresh_install_is_up_to_date() {
+        let store = MemoryStore::default();
+        assert!(ChangelogService::unseen_in(&store, "p_", true).is_empty());
+        assert_eq!(store.get_raw("p_last_seen_version"), Some(format!("{:?}", APP_VERSION)));
+    }
+
+    #[test]
+    fn an_install_from_before_versions_sees_the_current_release() {
+        let store = MemoryStore::default();
+        assert_eq!(versions(ChangelogService::unseen_in(&store, "p_", false)), [APP_VERSION]);
+    }
+}
@@ -0,0 +1,286 @@
+use serde_json::{Map, Value};
+use reqwest::Url;
+use web_sys::{console, window};
+
+use crate::models::{AppSettings, DeploymentConfig};
+
+/// Global object a deployment can define before the app starts
+const GLOBAL_CONFIG: &str = "SWI_CONFIG";
+/// Key listing the fields users may not change
+const LOCKED_KEY: &str = "locked";
+/// Shorter names accepted for settings fields
+const ALIASES: &[(&str, &str)] = &[("model", "selected_model")];
+/// The user's own data and progress, which a deployment doesn't preset, and
+/// the API key, which would be readable by everyone visiting the page
+const NOT_CONFIGURABLE: &[&str] = &["saved_prompts", "folder_order", "onboarding_completed", "recurring_prompts", "api_key"];
+/// Fields a link could use to send the user's chats to another server
+const NOT_FROM_URL: &[&str] = &["base_url"];
+
+/// Where a deployment setting came from. Only the page itself may lock fields;
+/// anyone can hand out a link.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ConfigSource {
+    Global,
+    Url,
+}
+
+impl ConfigSource {
+    fn name(self) -> &'static str {
+        match self {
+            ConfigSource::Global => GLOBAL_CONFIG,
+            ConfigSource::Url => "URL",
+        }
+    }
+}
+
+pub struct DeploymentService;
+
+impl DeploymentService {
+    /// Reads `window.SWI_CONFIG`, then the page's query parameters. Query parameters
+    /// win, except for fields the global config already locks; they can't lock
+    /// fields or set the server URL. Invalid values are logged and skipped.
+    pub fn load() -> DeploymentConfig {
+        let mut config = DeploymentConfig::default();
+        let mut warnings = Vec::new();
+        if let Some(global) = Self::global_config() {
+            warnings.extend(Self::merge(&mut config, global, ConfigSource::Global));
+        }
+        warnings.extend(Self::merge(&mut config, Self::query_config(), ConfigSource::Url));
+        for warning in warnings {
+            console::warn_1(&warning.into());
+        }
+        config
+    }
+
+    /// Seeds settings with the configured values on first run; locked values are
+    /// applied on every start so a stored change can't outlive the lock.
+    pub fn apply(config: &DeploymentConfig, settings: AppSettings, first_run: bool) -> AppSettings {
+        let values: Map<String, Value> = config
+            .values
+            .iter()
+            .filter(|(field, _)| first_run || config.locked.contains(field))
+            .map(|(field, value)| (field.clone(), value.clone()))
+            .collect();
+        Self::with_values(settings, values)
+    }
+
+    /// `settings` with the locked fields taken from `pinned`
+    pub fn keep_locked(locked: &[String], pinned: &AppSettings, settings: AppSettings) -> AppSettings {
+        if locked.is_empty() {
+            return settings;
+        }
+        let pinned = serde_json::to_value(pinned).unwrap_or_default();
+        let values: Map<String, Value> = locked
+            .iter()
+            .filter_map(|field| Some((field.clone(), pinned.get(field)?.clone())))
+            .collect();
+        Self::with_values(settings, values)
+    }
+
+    fn with_values(settings: AppSettings, values: Map<String, Value>) -> AppSettings {
+        if values.is_empty() {
+            return settings;
+        }
+        let Ok(Value::Object(mut fields)) = serde_json::to_value(&settings) else { return settings };
+        fields.extend(values);
+        serde_json::from_value(Value::Object(fields)).unwrap_or(settings)
+    }
+
+    /// Adds the values of `source` to `config`, returning why any were skipped
+    fn merge(config: &mut DeploymentConfig, source: Map<String, Value>, from: ConfigSource) -> Vec<String> {
+        let origin = from.name();
+        let mut warnings = Vec::new();
+        let already_locked = config.locked.clone();
+        for (key, value) in source {
+            if key == LOCKED_KEY && from == ConfigSource::Url {
+                warnings.push(format!("{}: ignoring '{}', fields can only be locked by {}", origin, key, GLOBAL_CONFIG));
+                continue;
+            }
+            if key == LOCKED_KEY {
+                let names: Vec<String> = match value {
+                    Value::String(list) => list.split(',').map(|s| s.trim().to_string()).collect(),
+                    Value::Array(list) => list.iter().filter_map(|v| v.as_str().map(str::to_string)).collect(),
+                    _ => Vec::new(),
+                };
+                for name in names.iter().filter(|n| !n.is_empty()) {
+                    match Self::field_name(name) {
+                        Some(field) if !config.locked.contains(&field) => config.locked.push(field),
+                        Some(_) => {}
+                        None => warnings.push(format!("{}: '{}' is not a setting that can be locked", origin, name)),
+                    }
+                }
+                continue;
+            }
+            let Some(field) = Self::field_name(&key) else {
+                warnings.push(format!("{}: ignoring '{}', which is not a setting that can be preset", origin, key));
+                continue;
+            };
+            if from == ConfigSource::Url && NOT_FROM_URL.contains(&field.as_str()) {
+                warnings.push(format!("{}: ignoring '{}', which can't be set from a link", origin, key));
+                continue;
+            }
+            if already_locked.contains(&field) {
+                continue;
+            }
+            match Self::validate(&field, value) {
+                Ok(value) => {
+                    config.values.insert(field, value);
+                }
+                Err(err) => warnings.push(format!("{}: ignoring '{}': {}", origin, key, err)),
+            }
+        }
+        warnings
+    }
+
+    /// Settings field a config key refers to, if it's one a deployment may set
+    fn field_name(key: &str) -> Option<String> {
+        let field = ALIASES.iter().find(|(alias, _)| *alias == key).map_or(key, |(_, field)| field);
+        let defaults = serde_json::to_value(AppSettings::default()).unwrap_or_default();
+        (defaults.get(field).is_some() && !NOT_CONFIGURABLE.contains(&field)).then(|| field.to_string())
+    }
+
+    /// Converts text from the URL to the field's type and checks it the way the
+    /// settings form does: the value must fit the field, numbers can't be negative.
+    fn validate(field: &str, value: Value) -> Result<Value, String> {
+        let Ok(Value::Object(mut defaults)) = serde_json::to_value(AppSettings::default()) else {
+            return Err("settings unavailable".to_string());
+        };
+        let value = match (defaults.get(field), value) {
+            (Some(Value::Bool(_)), Value::String(text)) => match text.trim() {
+                "true" | "1" | "yes" | "on" => Value::Bool(true),
+                "false" | "0" | "no" | "off" => Value::Bool(false),
+                other => return Err(format!("'{}' is not true or false", other)),
+            },
+            (Some(Value::Number(_)), Value::String(text)) => text
+                .trim()
+                .parse::<u64>()
+                .map(Value::from)
+                .map_err(|_| format!("'{}' is not a whole number of 0 or more", text))?,
+            (_, value) => value,
+        };
+        if field == "base_url" {
+            let url = value.as_str().and_then(|u| Url::parse(u).ok()).ok_or("not a valid URL")?;
+            if url.scheme() != "http" && url.scheme() != "https" {
+                return Err("the server URL must start with http:// or https://".to_string());
+            }
+        }
+        defaults.insert(field.to_string(), value.clone());
+        serde_json::from_value::<AppSettings>(Value::Object(defaults)).map_err(|e| e.to_string())?;
+        Ok(value)
+    }
+
+    fn global_config() -> Option<Map<String, Value>> {
+        let window = window()?;
+        let global = js_sys::Reflect::get(&window, &GLOBAL_CONFIG.into()).ok()?;
+        if global.is_undefined() || global.is_null() {
+            return None;
+        }
+        let json = js_sys::JSON::stringify(&global).ok()?.as_string()?;
+        match serde_json::from_str::<Value>(&json) {
+            Ok(Value::Object(map)) => Some(map),
+            _ => {
+                console::warn_1(&format!("{} must be an object", GLOBAL_CONFIG).into());
+                None
+            }
+        }
+    }
+
+    /// Query parameters naming a settings field (or `locked`), as strings
+    fn query_config() -> Map<String, Value> {
+        let Some(href) = window().and_then(|w| w.location().href().ok()) else { return Map::new() };
+        let Ok(url) = web_sys::Url::new(&href) else { return Map::new() };
+        let params = url.search_params();
+        let defaults = serde_json::to_value(AppSettings::default()).unwrap_or_default();
+        let keys = defaults
+            .as_object()
+            .map(|fields| fields.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .chain(ALIASES.iter().map(|(alias, _)| alias.to_string()))
+            .chain(std::iter::once(LOCKED_KEY.to_string()));
+        keys.filter_map(|key| params.get(&key).map(|value| (key, Value::String(value))))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn source(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => unreachable!(),
+        }
+    }
+
+    fn load(global: Value, url: Value) -> DeploymentConfig {
+        let mut config = DeploymentConfig::default();
+        DeploymentService::merge(&mut config, source(global), ConfigSource::Global);
+        DeploymentService::merge(&mut config, source(url), ConfigSource::Url);
+        config
+    }
+
+    #[test]
+    fn url_values_win_over_unlocked_global_values() {
+        let config = load(json!({"model": "small", "temperature": 0.2}), json!({"model": "large"}));
+        assert_eq!(config.values["selected_model"], "large");
+        assert_eq!(config.values["temperature"], 0.2);
+        assert!(config.locked.is_empty());
+    }
+
+    #[test]
+    fn global_locks_win_over_url_values() {
+        let config = load(json!({"model": "small", "locked": "model"}), json!({"model": "large"}));
+        assert_eq!(config.values["selected_model"], "small");
+        assert_eq!(config.locked, ["selected_model"]);
+    }
+
+    #[test]
+    fn url_cannot_lock_fields() {
+        let config = load(json!({}), json!({"model": "large", "locked": "model,temperature"}));
+        assert!(config.locked.is_empty());
+        assert_eq!(config.values["selected_model"], "large");
+    }
+
+    #[test]
+    fn url_cannot_set_the_server_or_key() {
+        let config = load(json!({}), json!({"base_url": "https://evil.example", "api_key": "x", "locked": "base_url"}));
+        assert!(config.values.is_empty());
+        assert!(config.locked.is_empty());
+    }
+
+    #[test]
+    fn global_config_sets_and_locks_the_server() {
+        let config = load(json!({"base_url": "https://llm.example", "locked": ["base_url"]}), json!({}));
+        assert_eq!(config.values["base_url"], "https://llm.example");
+        assert_eq!(config.locked, ["base_url"]);
+        let config = load(json!({"base_url": "ftp://llm.example"}), json!({}));
+        assert!(config.values.is_empty());
+    }
+
+    #[test]
+    fn nobody_presets_the_api_key() {
+        let config = load(json!({"api_key": "secret", "locked": "api_key"}), json!({}));
+        assert!(config.values.is_empty());
+        assert!(config.locked.is_empty());
+    }
+
+    #[test]
+    fn url_text_is_converted_to_the_field_type() {
+        let config = load(json!({}), json!({"stream_enabled": "off", "context_length": "4096", "keep_alive": "maybe"}));
+        assert_eq!(config.values["stream_enabled"], false);
+        assert_eq!(config.values["context_length"], 4096);
+        assert!(!config.values.contains_key("keep_alive"));
+    }
+
+    #[test]
+    fn url_values_only_seed_a_first_run() {
+        let config = load(json!({"model": "small", "locked": "model"}), json!({"temperature": "1"}));
+        let stored = AppSettings { selected_model: "mine".into(), temperature: 0.5, ..AppSettings::default() };
+        let settings = DeploymentService::apply(&config, stored, false);
+        assert_eq!(settings.selected_model, "small");
+        assert_eq!(settings.temperature, 0.5);
+    }
+}
@@ -1,11 +1,55 @@
-use crate::models::{Document, DocumentChunk, DocumentContextMode};
-use anyhow::Result;
-use tiktoken_rs::cl100k_base;
-use std::collections::HashSet;
-use crate::services::storage::LocalStorage;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use crate::models::{AppSettings, Document, DocumentChunk, DocumentContextMode, DocumentRef, IndexStatus};
+use crate::services::error::DocumentError;
+use crate::services::storage::{LocalStorage, StorageRecovery};
+use crate::services::llm::LlmService;
+use crate::services::request::RequestConfig;
+use crate::services::similarity::STOP_WORDS;
 
 const CHUNK_SIZE: usize = 1000;
 const CHUNK_OVERLAP: usize = 200;
+// Chunks embedded per request while indexing
+const EMBED_BATCH: usize = 16;
+// Chunks retrieval adds to a request, from the index and from keyword matches each
+const RAG_CHUNKS: usize = 6;
+// Shortest repeated text (in bytes) taken as chunk overlap when merging neighbours
+const MIN_MERGE_OVERLAP: usize = 20;
+// Documents named in the reference list; larger libraries are summarized
+const REFERENCE_LIST_MAX: usize = 50;
+// A document is suggested as context when it contains this many of the message's
+// keywords, and at least this share of them
+const SUGGESTION_MIN_TERMS: usize = 3;
+const SUGGESTION_MIN_SHARE: f64 = 0.6;
+
+// (chunk count, content length) of a document when its size was measured
+type SizeFingerprint = (usize, usize);
+
+thread_local! {
+    // Storage size per document id, with the fingerprint it was measured at
+    static STORAGE_SIZES: RefCell<HashMap<String, (SizeFingerprint, usize)>> = RefCell::new(HashMap::new());
+    // Keywords of each document's content, by document id; content never changes
+    // after upload, and deleted documents are dropped
+    static DOCUMENT_KEYWORDS: RefCell<HashMap<String, HashSet<String>>> = RefCell::new(HashMap::new());
+}
+
+/// Document context for a request
+#[derive(Default)]
+pub struct ChunkContext {
+    pub text: String,
+    /// Ids of the documents the context drew on
+    pub documents: Vec<String>,
+    /// Tokens of repeated overlap left out where neighbouring chunks were merged
+    pub deduplicated_tokens: usize,
+}
+
+/// Documents deleted together, with their stored data so the deletion can be undone
+#[derive(Clone, PartialEq, Default)]
+pub struct RemovedDocuments {
+    pub documents: Vec<Document>,
+    chunks: Vec<DocumentChunk>,
+    embeddings: HashMap<String, Vec<f32>>,
+}
 
 #[derive(Clone, Default)]
 pub struct DocumentService;
@@ -13,26 +57,43 @@ pub struct DocumentService;
 impl DocumentService {
     const KEY_DOCUMENTS: &'static str = "documents_v1";
     const KEY_CHUNKS: &'static str = "document_chunks_v1";
+    // Embedding of each indexed chunk, by chunk id
+    const KEY_EMBEDDINGS: &'static str = "document_embeddings_v1";
 
     /// Get file type from filename
     fn get_file_type(filename: &str) -> String {
         filename
             .split('.')
-            .last()
+            .next_back()
             .unwrap_or("txt")
             .to_lowercase()
     }
 
-    /// Parse a document file (PDF or text) and convert it to markdown chunks
-    pub async fn process_document(filename: &str, content: &[u8]) -> Result<Document> {
+    /// Parse a document file (PDF or text) and convert it to markdown chunks.
+    /// `on_progress` receives (chunks done, total chunks) while counting tokens.
+    pub async fn process_document(
+        filename: &str,
+        content: &[u8],
+        on_progress: impl Fn(usize, usize) + 'static,
+    ) -> Result<Document, DocumentError> {
         let file_type = Self::get_file_type(filename);
         let markdown_content = match file_type.as_str() {
-            "pdf" => Self::pdf_to_markdown(content).await?,
+            "pdf" => Self::pdf_to_markdown(content).await,
             "txt" | "md" => String::from_utf8_lossy(content).to_string(),
-            _ => return Err(anyhow::anyhow!("Unsupported file type: {}", file_type)),
+            _ => return Err(DocumentError::Unsupported(file_type)),
         };
 
         let chunks = Self::chunk_text(&markdown_content);
+        // Counted with the real tokenizer, pausing now and then so the page stays
+        // responsive and progress is shown
+        let mut chunk_tokens = Vec::with_capacity(chunks.len());
+        for (i, chunk) in chunks.iter().enumerate() {
+            chunk_tokens.push(Self::count_tokens(chunk));
+            if i % 25 == 0 || i + 1 == chunks.len() {
+                on_progress(i + 1, chunks.len());
+                gloo_timers::future::TimeoutFuture::new(0).await;
+            }
+        }
         let total_tokens = Self::count_tokens(&markdown_content);
 
         let document = Document {
@@ -43,31 +104,33 @@ impl DocumentService {
             chunk_count: chunks.len(),
             total_tokens,
             content_preview: markdown_content.chars().take(200).collect(),
+            word_count: Some(crate::utils::count_words(&markdown_content)),
             full_content: markdown_content,
+            enabled: true,
+            index: IndexStatus::NotIndexed,
         };
 
-        // Store document metadata
-        let mut documents: Vec<Document> = LocalStorage::get_vec(Self::KEY_DOCUMENTS);
-        documents.push(document.clone());
-        LocalStorage::set(Self::KEY_DOCUMENTS, &documents);
-
-        // Store chunks
-        Self::store_chunks(&document.id, &chunks).await;
+        // Chunks first, so a full storage never leaves a document without them
+        Self::store_chunks(&document.id, &chunks, &chunk_tokens)?;
+        if let Err(reason) = LocalStorage::append(Self::KEY_DOCUMENTS, document.clone()) {
+            Self::delete_documents(&[document.id]);
+            return Err(DocumentError::from_storage(reason));
+        }
 
         Ok(document)
     }
 
     /// Convert PDF to markdown
     /// Since pdf2md requires file paths, we'll extract text from PDF bytes
-    async fn pdf_to_markdown(content: &[u8]) -> Result<String> {
+    async fn pdf_to_markdown(content: &[u8]) -> String {
         // For WASM environment without pdf2md support, extract plain text
         // In a real implementation, you would use a PDF parsing library
         // For now, return a simplified representation
-        Ok(format!(
+        format!(
             "[PDF Document - Text extraction from PDF bytes]\n\nFile size: {} bytes\nNote: Full PDF parsing requires backend processing.\n\nRaw content preview:\n{}",
             content.len(),
             String::from_utf8_lossy(&content[..std::cmp::min(content.len(), 500)])
-        ))
+        )
     }
 
     /// Chunk text into manageable pieces with overlap
@@ -100,19 +163,13 @@ impl DocumentService {
         chunks
     }
 
-    /// Count tokens in text using cl100k_base tokenizer
+    /// Count tokens in text using the shared cl100k_base tokenizer
     fn count_tokens(text: &str) -> usize {
-        match cl100k_base() {
-            Ok(tokenizer) => {
-                let tokens = tokenizer.encode(text, HashSet::new());
-                tokens.len()
-            }
-            Err(_) => text.split_whitespace().count(),
-        }
+        crate::utils::count_tokens(text)
     }
 
     /// Store document chunks in local storage
-    async fn store_chunks(document_id: &str, chunks: &[String]) {
+    fn store_chunks(document_id: &str, chunks: &[String], chunk_tokens: &[usize]) -> Result<(), DocumentError> {
         let chunk_list: Vec<DocumentChunk> = chunks
             .iter()
             .enumerate()
@@ -122,14 +179,18 @@ impl DocumentService {
                 chunk_index: idx,
                 content: content.clone(),
                 created_at: js_sys::Date::now(),
+                token_count: chunk_tokens.get(idx).copied().unwrap_or(0),
             })
             .collect();
 
-        // For the first document, set chunks directly; for others, get and extend
-        let existing_chunks: Vec<DocumentChunk> = LocalStorage::get_vec(Self::KEY_CHUNKS);
-        let mut all_chunks: Vec<DocumentChunk> = existing_chunks;
-        all_chunks.extend(chunk_list);
-        LocalStorage::set(Self::KEY_CHUNKS, &all_chunks);
+        LocalStorage::append_all(Self::KEY_CHUNKS, chunk_list).map_err(DocumentError::from_storage)
+    }
+
+    /// Repair unreadable document data on startup, reporting what was recovered
+    pub fn recover_storage() -> Vec<StorageRecovery> {
+        let (_, documents) = LocalStorage::get_vec_recovering::<Document>(Self::KEY_DOCUMENTS, "documents");
+        let (_, chunks) = LocalStorage::get_vec_recovering::<DocumentChunk>(Self::KEY_CHUNKS, "document chunks");
+        documents.into_iter().chain(chunks).collect()
     }
 
     /// Get all documents
@@ -137,8 +198,112 @@ impl DocumentService {
         LocalStorage::get_vec(Self::KEY_DOCUMENTS)
     }
 
+    /// Documents that may be used as context
+    fn enabled_documents() -> Vec<Document> {
+        let mut documents = Self::get_documents();
+        documents.retain(|d| d.enabled);
+        documents
+    }
+
+    /// Enable or disable a document without deleting it
+    pub fn set_document_enabled(document_id: &str, enabled: bool) {
+        let mut documents: Vec<Document> = LocalStorage::get_vec(Self::KEY_DOCUMENTS);
+        if let Some(doc) = documents.iter_mut().find(|d| d.id == document_id) {
+            doc.enabled = enabled;
+        }
+        LocalStorage::set(Self::KEY_DOCUMENTS, &documents);
+    }
+
+    /// Enable or disable every document at once
+    pub fn set_all_enabled(enabled: bool) {
+        let mut documents: Vec<Document> = LocalStorage::get_vec(Self::KEY_DOCUMENTS);
+        for doc in documents.iter_mut() {
+            doc.enabled = enabled;
+        }
+        LocalStorage::set(Self::KEY_DOCUMENTS, &documents);
+    }
+
+    /// Documents the chat can draw context from (`None` scope = all documents)
+    pub fn documents_in_scope(scope: Option<&[String]>) -> Vec<Document> {
+        let mut documents = Self::enabled_documents();
+        if let Some(ids) = scope {
+            documents.retain(|d| ids.contains(&d.id));
+        }
+        documents
+    }
+
+    /// Tokens of document context a request will carry before any @ mentions,
+    /// from the stored counts: for RAG, the most the retrieved chunks can take,
+    /// or everything in scope without an embedding model; the scoped and
+    /// selected documents for Manual
+    pub fn projected_context_tokens(mode: &DocumentContextMode, scope: Option<&[String]>, selected: Option<&str>, embedding_model: &str) -> usize {
+        match mode {
+            DocumentContextMode::Off => 0,
+            DocumentContextMode::RAG if embedding_model.is_empty() => Self::documents_in_scope(scope).iter().map(|d| d.total_tokens).sum(),
+            DocumentContextMode::RAG => {
+                let documents = Self::documents_in_scope(scope);
+                let indexed = |c: &DocumentChunk| documents.iter().any(|d| d.id == c.document_id && d.index.is_current(embedding_model));
+                projected_rag_tokens(&Self::context_chunks(scope), indexed)
+            }
+            DocumentContextMode::Manual => Self::enabled_documents()
+                .iter()
+                .filter(|d| scope.is_some_and(|ids| ids.contains(&d.id)) || selected == Some(d.id.as_str()))
+                .map(|d| d.total_tokens)
+                .sum(),
+        }
+    }
+
+    /// Chunks RAG draws on for a chat, by document and then in document order
+    pub fn context_chunks(scope: Option<&[String]>) -> Vec<DocumentChunk> {
+        let documents = Self::documents_in_scope(scope);
+        let mut chunks: Vec<DocumentChunk> = LocalStorage::get_vec::<DocumentChunk>(Self::KEY_CHUNKS)
+            .into_iter()
+            .filter(|c| documents.iter().any(|d| d.id == c.document_id))
+            .collect();
+        let position = |id: &str| documents.iter().position(|d| d.id == id);
+        chunks.sort_by_key(|c| (position(&c.document_id), c.chunk_index));
+        chunks
+    }
+
+    /// Context built from chunks in the given order. Neighbouring chunks of a
+    /// document are merged into one span, without the text they overlap by.
+    pub fn context_from_chunks(chunks: &[DocumentChunk]) -> ChunkContext {
+        if chunks.is_empty() {
+            return ChunkContext::default();
+        }
+        Self::merge_chunks(chunks, &Self::get_documents())
+    }
+
+    /// `context_from_chunks` over the given documents
+    fn merge_chunks(chunks: &[DocumentChunk], documents: &[Document]) -> ChunkContext {
+        let mut result = ChunkContext { text: String::from("Relevant documents:\n\n"), ..Default::default() };
+        let mut previous: Option<&DocumentChunk> = None;
+        for chunk in chunks {
+            if !result.documents.contains(&chunk.document_id) {
+                let Some(doc) = documents.iter().find(|d| d.id == chunk.document_id) else { continue };
+                result.text.push_str(&format!("=== Document: {} (Type: {}) ===\n", doc.filename, doc.file_type));
+                result.documents.push(chunk.document_id.clone());
+            }
+            let overlap = previous
+                .filter(|p| p.document_id == chunk.document_id && p.chunk_index + 1 == chunk.chunk_index)
+                .map_or(0, |p| overlap_len(&p.content, &chunk.content));
+            if overlap > 0 {
+                // Continue the previous span instead of starting a new one
+                result.text.truncate(result.text.len() - 2);
+                result.deduplicated_tokens += Self::count_tokens(&chunk.content[..overlap]);
+            }
+            result.text.push_str(&chunk.content[overlap..]);
+            result.text.push_str("\n\n");
+            previous = Some(chunk);
+        }
+        result
+    }
+
     /// Get chunks for a specific document
     pub fn get_document_chunks(document_id: &str) -> Vec<DocumentChunk> {
+        if !Self::enabled_documents().iter().any(|d| d.id == document_id) {
+            return Vec::new();
+        }
         let all_chunks: Vec<DocumentChunk> = LocalStorage::get_vec(Self::KEY_CHUNKS);
         all_chunks
             .into_iter()
@@ -146,30 +311,265 @@ impl DocumentService {
             .collect()
     }
 
+    /// Serialized size in bytes of each document with its chunks, by document id.
+    /// Sizes are cached, so chunks are only read when a document is new or changed.
+    pub fn storage_sizes(documents: &[Document]) -> HashMap<String, usize> {
+        let fingerprint = |d: &Document| (d.chunk_count, d.full_content.len());
+        let stale: Vec<&Document> = STORAGE_SIZES.with(|cache| {
+            let cache = cache.borrow();
+            documents
+                .iter()
+                .filter(|d| cache.get(&d.id).is_none_or(|(f, _)| *f != fingerprint(d)))
+                .collect()
+        });
+
+        if !stale.is_empty() {
+            let chunks: Vec<DocumentChunk> = LocalStorage::get_vec(Self::KEY_CHUNKS);
+            let mut sizes: HashMap<&str, usize> = stale
+                .iter()
+                .map(|d| (d.id.as_str(), serde_json::to_string(d).map(|s| s.len()).unwrap_or(0)))
+                .collect();
+            for chunk in &chunks {
+                if let Some(size) = sizes.get_mut(chunk.document_id.as_str()) {
+                    // Plus one for the separating comma
+                    *size += serde_json::to_string(chunk).map(|s| s.len() + 1).unwrap_or(0);
+                }
+            }
+            STORAGE_SIZES.with(|cache| {
+                let mut cache = cache.borrow_mut();
+                for d in &stale {
+                    cache.insert(d.id.clone(), (fingerprint(d), sizes[d.id.as_str()]));
+                }
+            });
+        }
+
+        STORAGE_SIZES.with(|cache| {
+            let cache = cache.borrow();
+            documents
+                .iter()
+                .filter_map(|d| cache.get(&d.id).map(|(_, size)| (d.id.clone(), *size)))
+                .collect()
+        })
+    }
+
+    /// Storage keys holding document data
+    pub fn storage_keys() -> [&'static str; 3] {
+        [Self::KEY_DOCUMENTS, Self::KEY_CHUNKS, Self::KEY_EMBEDDINGS]
+    }
+
     /// Delete a document and its chunks
     pub fn delete_document(document_id: &str) {
-        // Remove document
+        Self::delete_documents(&[document_id.to_string()]);
+    }
+
+    /// Delete several documents with one write per storage key, returning what
+    /// was removed so it can be put back
+    pub fn delete_documents(ids: &[String]) -> RemovedDocuments {
+        let (documents, kept): (Vec<Document>, Vec<Document>) =
+            LocalStorage::get_vec(Self::KEY_DOCUMENTS).into_iter().partition(|d: &Document| ids.contains(&d.id));
+        LocalStorage::set(Self::KEY_DOCUMENTS, &kept);
+
+        let (chunks, kept): (Vec<DocumentChunk>, Vec<DocumentChunk>) =
+            LocalStorage::get_vec(Self::KEY_CHUNKS).into_iter().partition(|c: &DocumentChunk| ids.contains(&c.document_id));
+        LocalStorage::set(Self::KEY_CHUNKS, &kept);
+
+        let embeddings: HashMap<String, Vec<f32>> = LocalStorage::get(Self::KEY_EMBEDDINGS).unwrap_or_default();
+        let (removed, kept): (HashMap<_, _>, HashMap<_, _>) =
+            embeddings.into_iter().partition(|(id, _)| chunks.iter().any(|c| c.id == *id));
+        if !removed.is_empty() {
+            LocalStorage::set(Self::KEY_EMBEDDINGS, &kept);
+        }
+
+        STORAGE_SIZES.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            for id in ids {
+                cache.remove(id);
+            }
+        });
+        forget_keywords(ids);
+        RemovedDocuments { documents, chunks, embeddings: removed }
+    }
+
+    /// Put deleted documents back, in upload order among the others. Fails,
+    /// restoring nothing, when storage has no room for them.
+    pub fn restore_documents(removed: RemovedDocuments) -> Result<(), DocumentError> {
+        let previous: Vec<Document> = LocalStorage::try_get_vec(Self::KEY_DOCUMENTS).map_err(DocumentError::Parse)?;
+        let mut documents = previous.clone();
+        documents.extend(removed.documents);
+        documents.sort_by(|a, b| a.upload_date.total_cmp(&b.upload_date));
+        LocalStorage::try_set(Self::KEY_DOCUMENTS, &documents).map_err(DocumentError::from_storage)?;
+
+        let previous_chunks: Vec<DocumentChunk> = LocalStorage::get_vec(Self::KEY_CHUNKS);
+        if let Err(reason) = LocalStorage::append_all(Self::KEY_CHUNKS, removed.chunks) {
+            // A document without its chunks would look restored but never be found
+            LocalStorage::set(Self::KEY_DOCUMENTS, &previous);
+            return Err(DocumentError::from_storage(reason));
+        }
+
+        if !removed.embeddings.is_empty() {
+            let stored = LocalStorage::try_get::<HashMap<String, Vec<f32>>>(Self::KEY_EMBEDDINGS);
+            let removed_embeddings = removed.embeddings;
+            let saved = stored.and_then(|embeddings| {
+                let mut embeddings = embeddings.unwrap_or_default();
+                embeddings.extend(removed_embeddings);
+                LocalStorage::try_set(Self::KEY_EMBEDDINGS, &embeddings)
+            });
+            if let Err(reason) = saved {
+                // Without their vectors the chunks would never be retrieved
+                LocalStorage::set(Self::KEY_CHUNKS, &previous_chunks);
+                LocalStorage::set(Self::KEY_DOCUMENTS, &previous);
+                return Err(DocumentError::from_storage(reason));
+            }
+        }
+        Ok(())
+    }
+
+    fn set_index_status(document_id: &str, status: &IndexStatus) {
         let mut documents: Vec<Document> = LocalStorage::get_vec(Self::KEY_DOCUMENTS);
-        documents.retain(|d| d.id != document_id);
-        LocalStorage::set(Self::KEY_DOCUMENTS, &documents);
+        if let Some(doc) = documents.iter_mut().find(|d| d.id == document_id) {
+            doc.index = status.clone();
+            LocalStorage::set(Self::KEY_DOCUMENTS, &documents);
+        }
+    }
 
-        // Remove chunks
-        let mut chunks: Vec<DocumentChunk> = LocalStorage::get_vec(Self::KEY_CHUNKS);
-        chunks.retain(|c| c.document_id != document_id);
-        LocalStorage::set(Self::KEY_CHUNKS, &chunks);
+    fn remove_embeddings(document_id: &str) {
+        let chunk_ids: Vec<String> = LocalStorage::get_vec::<DocumentChunk>(Self::KEY_CHUNKS)
+            .into_iter()
+            .filter(|c| c.document_id == document_id)
+            .map(|c| c.id)
+            .collect();
+        let mut embeddings: HashMap<String, Vec<f32>> = LocalStorage::get(Self::KEY_EMBEDDINGS).unwrap_or_default();
+        let before = embeddings.len();
+        embeddings.retain(|id, _| !chunk_ids.contains(id));
+        if embeddings.len() != before {
+            LocalStorage::set(Self::KEY_EMBEDDINGS, &embeddings);
+        }
     }
 
-    /// Get the context mode from settings
-    pub fn get_context_mode() -> DocumentContextMode {
-        let settings: Option<crate::models::AppSettings> = LocalStorage::get("chat_settings_v1");
-        settings
-            .map(|s| s.document_context_mode)
-            .unwrap_or(DocumentContextMode::RAG)
+    /// Embeds every chunk of a document with `model`, starting over so a failed
+    /// or stale index is rebuilt. The status is stored after each batch;
+    /// `on_progress` is called after each store so lists can refresh.
+    pub async fn index_document(document_id: &str, request: &RequestConfig, model: &str, on_progress: impl Fn()) -> IndexStatus {
+        let mut chunks: Vec<DocumentChunk> = LocalStorage::get_vec::<DocumentChunk>(Self::KEY_CHUNKS)
+            .into_iter()
+            .filter(|c| c.document_id == document_id)
+            .collect();
+        chunks.sort_by_key(|c| c.chunk_index);
+        Self::remove_embeddings(document_id);
+
+        let total = chunks.len();
+        for (batch_index, batch) in chunks.chunks(EMBED_BATCH).enumerate() {
+            Self::set_index_status(document_id, &IndexStatus::Indexing { done: batch_index * EMBED_BATCH, total });
+            on_progress();
+            let input: Vec<String> = batch.iter().map(|c| c.content.clone()).collect();
+            match LlmService::embed(request, model, &input).await {
+                Ok(vectors) => {
+                    let mut embeddings: HashMap<String, Vec<f32>> = LocalStorage::get(Self::KEY_EMBEDDINGS).unwrap_or_default();
+                    embeddings.extend(batch.iter().map(|c| c.id.clone()).zip(vectors));
+                    if let Err(err) = LocalStorage::try_set(Self::KEY_EMBEDDINGS, &embeddings) {
+                        let status = IndexStatus::Failed { error: format!("The embeddings could not be stored: {}", err) };
+                        Self::set_index_status(document_id, &status);
+                        on_progress();
+                        return status;
+                    }
+                }
+                Err(err) => {
+                    let status = IndexStatus::Failed { error: err.to_string() };
+                    Self::set_index_status(document_id, &status);
+                    on_progress();
+                    return status;
+                }
+            }
+        }
+        let status = IndexStatus::Indexed { model: model.to_string() };
+        Self::set_index_status(document_id, &status);
+        on_progress();
+        status
+    }
+
+    /// RAG context for a message, with the ids of the documents it drew on.
+    /// Without an embedding model every document in scope is sent whole. With one,
+    /// the chunks closest to the message are taken from documents indexed with that
+    /// model, and documents that aren't contribute their best keyword matches.
+    pub async fn rag_context(query: &str, scope: Option<&[String]>, request: &RequestConfig, model: &str) -> ChunkContext {
+        if model.is_empty() {
+            let text = Self::get_all_documents_text(scope);
+            let documents = if text.is_empty() { Vec::new() } else { Self::documents_in_scope(scope).into_iter().map(|d| d.id).collect() };
+            return ChunkContext { text, documents, deduplicated_tokens: 0 };
+        }
+        Self::context_from_chunks(&Self::retrieve_chunks(query, scope, request, model).await)
+    }
+
+    /// The chunks `rag_context` sends for a message, in document order; every
+    /// chunk in scope without an embedding model
+    pub async fn retrieve_chunks(query: &str, scope: Option<&[String]>, request: &RequestConfig, model: &str) -> Vec<DocumentChunk> {
+        let chunks = Self::context_chunks(scope);
+        if model.is_empty() {
+            return chunks;
+        }
+        let documents = Self::documents_in_scope(scope);
+        let indexed = |c: &DocumentChunk| documents.iter().any(|d| d.id == c.document_id && d.index.is_current(model));
+        let (mut semantic, mut keyword): (Vec<DocumentChunk>, Vec<DocumentChunk>) = chunks.into_iter().partition(|c| indexed(c));
+
+        // The index can't be searched without the message's embedding either
+        let query_embedding = if semantic.is_empty() {
+            None
+        } else {
+            LlmService::embed(request, model, &[query.to_string()]).await.ok().and_then(|mut v| v.pop())
+        };
+        let mut selected = match query_embedding {
+            Some(query_embedding) => {
+                let embeddings: HashMap<String, Vec<f32>> = LocalStorage::get(Self::KEY_EMBEDDINGS).unwrap_or_default();
+                let (closest, mut unscored) = closest_chunks(semantic, &query_embedding, &embeddings);
+                keyword.append(&mut unscored);
+                closest
+            }
+            None => {
+                keyword.append(&mut semantic);
+                Vec::new()
+            }
+        };
+
+        let terms = keyword_terms(query);
+        let matches = |c: &DocumentChunk| {
+            let content = c.content.to_lowercase();
+            terms.iter().filter(|t| content.contains(t.as_str())).count()
+        };
+        keyword.retain(|c| matches(c) > 0);
+        keyword.sort_by_key(|c| std::cmp::Reverse(matches(c)));
+        keyword.truncate(RAG_CHUNKS);
+        selected.append(&mut keyword);
+
+        // Back in document order, so neighbouring chunks read naturally
+        let position = |id: &str| documents.iter().position(|d| d.id == id);
+        selected.sort_by_key(|c| (position(&c.document_id), c.chunk_index));
+        selected
+    }
+
+    /// The candidate sharing the most keywords with a message, if it shares enough
+    /// of them to look related. Each document's keywords are collected once.
+    pub fn related_document<'a>(query: &str, candidates: &'a [Document]) -> Option<&'a Document> {
+        let terms: Vec<String> = keyword_terms(query).into_iter().filter(|t| !STOP_WORDS.contains(&t.as_str())).collect();
+        if terms.len() < SUGGESTION_MIN_TERMS {
+            return None;
+        }
+        DOCUMENT_KEYWORDS.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            candidates
+                .iter()
+                .map(|doc| {
+                    let keywords = cache.entry(doc.id.clone()).or_insert_with(|| keyword_terms(&doc.full_content).into_iter().collect());
+                    (terms.iter().filter(|t| keywords.contains(*t)).count(), doc)
+                })
+                .filter(|(hits, _)| *hits >= SUGGESTION_MIN_TERMS && *hits as f64 >= terms.len() as f64 * SUGGESTION_MIN_SHARE)
+                .max_by_key(|(hits, _)| *hits)
+                .map(|(_, doc)| doc)
+        })
     }
 
     /// Get document content by document ID
     pub fn get_document_content_by_id(document_id: &str) -> Option<String> {
-        let documents = Self::get_documents();
+        let documents = Self::enabled_documents();
         for doc in documents {
             if doc.id == document_id {
                 return Some(doc.full_content);
@@ -178,43 +578,37 @@ impl DocumentService {
         None
     }
 
-    /// Build context from documents for the chat
-    pub async fn build_context(&self, query: &str, _limit: usize) -> String {
-        let mode = Self::get_context_mode();
-        
-        match mode {
-            DocumentContextMode::RAG => {
-                // For RAG mode, return all documents as a simple implementation
-                Self::get_all_documents_text()
-            }
-            DocumentContextMode::Manual => {
-                // In manual mode, documents are referenced via @doc-id in prompts
-                // We need to extract those references and build context from them
-                Self::build_manual_context(query)
-            }
-        }
-    }
-
     /// Build context for manual mode by extracting @doc-id references from the query
-    /// Returns both the context (for LLM) and the cleaned message (for display)
-    pub async fn build_manual_context_with_display(&self, query: &str) -> (String, String) {
-        let documents = Self::get_documents();
+    /// Returns the context (for LLM), the cleaned message (for display) and the ids of
+    /// the documents used. Documents scoped to the chat and the document selected in
+    /// the sidebar are included without an @ reference.
+    pub async fn build_manual_context_with_display(&self, query: &str, scope: Option<&[String]>, selected: Option<&str>) -> (String, String, Vec<String>) {
+        let documents = Self::enabled_documents();
         
         if documents.is_empty() {
-            return (String::new(), query.to_string());
+            return (String::new(), query.to_string(), Vec::new());
         }
 
         // Find all @doc-id patterns in the query
-        let mut referenced_docs: Vec<String> = Vec::new();
+        let mut referenced_docs: Vec<String> = scope.map(|ids| ids.to_vec()).unwrap_or_default();
+        if let Some(id) = selected {
+            if !referenced_docs.iter().any(|d| d == id) {
+                referenced_docs.push(id.to_string());
+            }
+        }
+        referenced_docs.retain(|id| documents.iter().any(|d| d.id == *id));
         let mut current_query = query.to_string();
         
         for doc in &documents {
             let doc_ref = format!("@{}", doc.id);
-            if query.contains(&doc_ref) && !referenced_docs.contains(&doc.id) {
-                referenced_docs.push(doc.id.clone());
+            if query.contains(&doc_ref) {
+                if !referenced_docs.contains(&doc.id) {
+                    referenced_docs.push(doc.id.clone());
+                }
                 
                 // Replace @doc-id with a cleaner placeholder for display
-                current_query = current_query.replace(&doc_ref, &format!("[Document: {}]", doc.filename));
+                let placeholder = DocumentRef { id: doc.id.clone(), filename: doc.filename.clone() }.placeholder();
+                current_query = current_query.replace(&doc_ref, &placeholder);
             }
         }
 
@@ -233,74 +627,58 @@ impl DocumentService {
 
         // If no documents were referenced, return empty context and original query
         if referenced_docs.is_empty() {
-            return (String::new(), query.to_string());
+            return (String::new(), query.to_string(), Vec::new());
         }
 
-        (context, current_query)
+        (context, current_query, referenced_docs)
     }
 
-    /// Build context for manual mode by extracting @doc-id references from the query
-    fn build_manual_context(query: &str) -> String {
-        let documents = Self::get_documents();
-        
-        if documents.is_empty() {
-            return String::new();
-        }
-
-        // Find all @doc-id patterns in the query
-        let mut referenced_docs: Vec<String> = Vec::new();
-        let mut current_query = query.to_string();
-        
-        for doc in &documents {
-            let doc_ref = format!("@{}", doc.id);
-            if query.contains(&doc_ref) && !referenced_docs.contains(&doc.id) {
-                referenced_docs.push(doc.id.clone());
-                
-                // Replace @doc-id with a placeholder that we can replace later
-                current_query = current_query.replace(&doc_ref, &format!("[Document: {}]", doc.filename));
-            }
-        }
-
-        // Build the context with referenced document content
-        let mut context = String::from("Document context:\n\n");
-        for doc_id in &referenced_docs {
-            if let Some(doc_content) = Self::get_document_content_by_id(doc_id) {
-                if let Some(doc) = documents.iter().find(|d| d.id == *doc_id) {
-                    context.push_str(&format!(
-                        "=== Document: {} (Type: {}, Chunks: {}) ===\n{}\n\n",
-                        doc.filename, doc.file_type, doc.chunk_count, doc_content
-                    ));
-                }
-            }
-        }
+    /// Documents mentioned as `@id` in a message, in order of first mention
+    pub fn document_refs_in(query: &str) -> Vec<DocumentRef> {
+        let mut mentions: Vec<(usize, DocumentRef)> = Self::get_documents()
+            .into_iter()
+            .filter_map(|d| {
+                let pos = query.find(&format!("@{}", d.id))?;
+                Some((pos, DocumentRef { id: d.id, filename: d.filename }))
+            })
+            .collect();
+        mentions.sort_by_key(|(pos, _)| *pos);
+        mentions.into_iter().map(|(_, r)| r).collect()
+    }
 
-        // If no documents were referenced, return empty context
-        if referenced_docs.is_empty() {
-            return String::new();
+    /// The document list for a request's system message, when Manual mode is
+    /// set to include it
+    pub fn request_document_list(settings: &AppSettings, scope: Option<&[String]>) -> String {
+        if settings.document_context_mode == DocumentContextMode::Manual && settings.document_list_in_prompt {
+            Self::get_document_list_for_reference(scope)
+        } else {
+            String::new()
         }
-
-        context
     }
 
-    /// Get a list of documents for manual reference (e.g., @doc-id format)
-    fn get_document_list_for_reference() -> String {
-        let documents = Self::get_documents();
+    /// Documents the user can attach in Manual mode, so the model can ask for one
+    /// by name. Limited to the chat's scope and to `REFERENCE_LIST_MAX` entries.
+    fn get_document_list_for_reference(scope: Option<&[String]>) -> String {
+        let documents = Self::documents_in_scope(scope);
         
         if documents.is_empty() {
             return String::new();
         }
 
-        let mut list = String::from("Available documents for reference:\n\n");
-        for doc in documents {
-            list.push_str(&format!("- @{}: {} (Type: {}, {} chunks)\n", doc.id, doc.filename, doc.file_type, doc.chunk_count));
+        let mut list = String::from("Documents the user can attach. To ask for one, write @ followed by its file name, e.g. @report.pdf:\n");
+        for doc in documents.iter().take(REFERENCE_LIST_MAX) {
+            list.push_str(&format!("- @{} ({}, {} chunks)\n", doc.filename, doc.file_type, doc.chunk_count));
+        }
+        if documents.len() > REFERENCE_LIST_MAX {
+            list.push_str(&format!("- … and {} more\n", documents.len() - REFERENCE_LIST_MAX));
         }
         
         list
     }
 
     /// Get all document text for RAG context
-    fn get_all_documents_text() -> String {
-        let documents = Self::get_documents();
+    fn get_all_documents_text(scope: Option<&[String]>) -> String {
+        let documents = Self::documents_in_scope(scope);
         
         if documents.is_empty() {
             return String::new();
@@ -319,3 +697,204 @@ impl DocumentService {
         context
     }
 }
+
+/// Length in bytes of the longest start of `next` that `previous` ends with,
+/// or 0 when they share too little to be the overlap between chunks
+fn overlap_len(previous: &str, next: &str) -> usize {
+    next.char_indices()
+        .map(|(i, c)| i + c.len_utf8())
+        .take_while(|&end| end <= previous.len())
+        .filter(|&end| end >= MIN_MERGE_OVERLAP && previous.ends_with(&next[..end]))
+        .last()
+        .unwrap_or(0)
+}
+
+/// The `RAG_CHUNKS` chunks closest to `query`, best first, and the chunks
+/// that have no embedding to compare, which only a keyword search can find
+fn closest_chunks(chunks: Vec<DocumentChunk>, query: &[f32], embeddings: &HashMap<String, Vec<f32>>) -> (Vec<DocumentChunk>, Vec<DocumentChunk>) {
+    let (mut scored, unscored): (Vec<DocumentChunk>, Vec<DocumentChunk>) = chunks.into_iter().partition(|c| embeddings.contains_key(&c.id));
+    let score = |c: &DocumentChunk| cosine_similarity(query, &embeddings[&c.id]);
+    scored.sort_by(|a, b| score(b).total_cmp(&score(a)));
+    scored.truncate(RAG_CHUNKS);
+    (scored, unscored)
+}
+
+/// The most tokens RAG can retrieve from `chunks`: its largest `RAG_CHUNKS`
+/// of the indexed chunks and as many of the others, found by keyword
+fn projected_rag_tokens(chunks: &[DocumentChunk], indexed: impl Fn(&DocumentChunk) -> bool) -> usize {
+    let largest = |indexed_side: bool| {
+        let mut tokens: Vec<usize> = chunks
+            .iter()
+            .filter(|c| indexed(c) == indexed_side)
+            .map(|c| if c.token_count > 0 { c.token_count } else { DocumentService::count_tokens(&c.content) })
+            .collect();
+        tokens.sort_unstable_by(|a, b| b.cmp(a));
+        tokens.into_iter().take(RAG_CHUNKS).sum::<usize>()
+    };
+    largest(true) + largest(false)
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denominator = norm(a) * norm(b);
+    if denominator == 0.0 { 0.0 } else { dot / denominator }
+}
+
+/// Lowercased words of a message worth matching on
+/// Drops the cached keywords of deleted documents
+fn forget_keywords(ids: &[String]) {
+    DOCUMENT_KEYWORDS.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        for id in ids {
+            cache.remove(id);
+        }
+    });
+}
+
+fn keyword_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 2)
+        .map(str::to_lowercase)
+        .collect();
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks_of(document_id: &str, text: &str) -> Vec<DocumentChunk> {
+        DocumentService::chunk_text(text)
+            .into_iter()
+            .enumerate()
+            .map(|(i, content)| DocumentChunk { id: format!("{}-{}", document_id, i), document_id: document_id.to_string(), chunk_index: i, content, ..Default::default() })
+            .collect()
+    }
+
+    fn document(id: &str) -> Document {
+        Document { id: id.to_string(), filename: format!("{}.md", id), file_type: "md".to_string(), ..Default::default() }
+    }
+
+    fn sample_text() -> String {
+        (0..60).map(|i| format!("Sentence number {} of the sample document. ", i)).collect()
+    }
+
+    #[test]
+    fn adjacent_chunks_merge_into_one_span() {
+        let text = sample_text();
+        let chunks = chunks_of("a", &text);
+        assert_eq!(chunks.len(), 3);
+        let context = DocumentService::merge_chunks(&chunks[..2], &[document("a")]);
+        let span = context.text.split("===\n").nth(1).unwrap().trim_end();
+        // The overlap appears once, so the span is the start of the document itself
+        assert!(text.starts_with(span));
+        assert_eq!(span.len(), chunks[1].content.len() + CHUNK_SIZE - CHUNK_OVERLAP);
+        assert_eq!(context.text.matches(&chunks[1].content[..CHUNK_OVERLAP]).count(), 1);
+        assert!(context.deduplicated_tokens > 0);
+    }
+
+    #[test]
+    fn non_adjacent_chunks_stay_apart() {
+        let text = sample_text();
+        let chunks = chunks_of("a", &text);
+        // Chunks 0 and 2 share no neighbour, even when their text happens to overlap
+        let mut far = chunks[2].clone();
+        far.content = format!("{}{}", &chunks[0].content[chunks[0].content.len() - 50..], far.content);
+        let context = DocumentService::merge_chunks(&[chunks[0].clone(), far.clone()], &[document("a")]);
+        assert!(context.text.contains(&format!("{}\n\n{}", chunks[0].content, far.content)));
+        assert_eq!(context.deduplicated_tokens, 0);
+
+        // Consecutive indexes of different documents are not neighbours either
+        let mut other = chunks[1].clone();
+        other.document_id = "b".to_string();
+        let context = DocumentService::merge_chunks(&[chunks[0].clone(), other.clone()], &[document("a"), document("b")]);
+        assert!(context.text.contains(&other.content));
+        assert_eq!(context.deduplicated_tokens, 0);
+    }
+
+    fn chunk(id: &str, tokens: usize) -> DocumentChunk {
+        DocumentChunk { id: id.to_string(), document_id: "a".to_string(), token_count: tokens, ..Default::default() }
+    }
+
+    #[test]
+    fn cosine_similarity_compares_directions() {
+        assert!((cosine_similarity(&[1.0, 2.0], &[2.0, 4.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 3.0]).abs() < 1e-6);
+        assert!((cosine_similarity(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-6);
+        // A zero vector is similar to nothing rather than NaN
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+    }
+
+    fn document_with(id: &str, content: &str) -> Document {
+        Document { full_content: content.to_string(), ..document(id) }
+    }
+
+    #[test]
+    fn related_document_needs_enough_shared_keywords() {
+        let docs = [
+            document_with("invoice", "Invoices list the customer, billing address, due date and payment terms."),
+            document_with("garden", "Tomatoes need sunlight, regular watering and support stakes."),
+        ];
+        let found = DocumentService::related_document("When is the payment due for this customer invoice?", &docs);
+        assert_eq!(found.map(|d| d.id.as_str()), Some("invoice"));
+        // Too few keywords to go on, and keywords no document shares
+        assert!(DocumentService::related_document("payment terms", &docs).is_none());
+        assert!(DocumentService::related_document("Which train leaves for the coast tomorrow morning?", &docs).is_none());
+        assert!(DocumentService::related_document("When is the payment due for this customer invoice?", &[]).is_none());
+    }
+
+    #[test]
+    fn deleted_documents_leave_the_keyword_cache() {
+        let query = "Compare quarterly revenue growth across regions";
+        let before = [document_with("report", "Quarterly revenue growth was strong in all regions.")];
+        assert!(DocumentService::related_document(query, &before).is_some());
+        assert!(DOCUMENT_KEYWORDS.with(|c| c.borrow().contains_key("report")));
+
+        forget_keywords(&["report".to_string()]);
+        assert!(!DOCUMENT_KEYWORDS.with(|c| c.borrow().contains_key("report")));
+        // A new document reusing the id is read afresh
+        let after = [document_with("report", "Notes from the team offsite.")];
+        assert!(DocumentService::related_document(query, &after).is_none());
+    }
+
+    #[test]
+    fn keyword_terms_are_distinct_lowercase_words() {
+        assert_eq!(keyword_terms("The API, the api and THE Api-key: 42 ok"), ["and", "api", "key", "the"]);
+        assert!(keyword_terms("a b c! ok").is_empty());
+    }
+
+    #[test]
+    fn chunks_without_an_embedding_are_not_ranked() {
+        let embeddings: HashMap<String, Vec<f32>> = [("near", vec![1.0, 0.0]), ("far", vec![0.0, 1.0])]
+            .iter()
+            .map(|(id, v)| (id.to_string(), v.clone()))
+            .collect();
+        let chunks = vec![chunk("far", 0), chunk("missing", 0), chunk("near", 0)];
+        let (closest, unscored) = closest_chunks(chunks, &[1.0, 0.1], &embeddings);
+        assert_eq!(closest.iter().map(|c| c.id.as_str()).collect::<Vec<_>>(), ["near", "far"]);
+        assert_eq!(unscored.iter().map(|c| c.id.as_str()).collect::<Vec<_>>(), ["missing"]);
+    }
+
+    #[test]
+    fn closest_chunks_keeps_only_the_best() {
+        let embeddings: HashMap<String, Vec<f32>> = (0..RAG_CHUNKS + 2).map(|i| (i.to_string(), vec![1.0, i as f32])).collect();
+        let chunks = (0..RAG_CHUNKS + 2).map(|i| chunk(&i.to_string(), 0)).collect();
+        let (closest, _) = closest_chunks(chunks, &[1.0, 0.0], &embeddings);
+        assert_eq!(closest.len(), RAG_CHUNKS);
+        assert_eq!(closest[0].id, "0");
+    }
+
+    #[test]
+    fn rag_projection_counts_only_what_can_be_retrieved() {
+        let chunks: Vec<DocumentChunk> = (1..=RAG_CHUNKS + 2).map(|i| chunk(&i.to_string(), i * 10)).collect();
+        let largest: usize = (3..=RAG_CHUNKS + 2).map(|i| i * 10).sum();
+        assert_eq!(projected_rag_tokens(&chunks, |_| true), largest);
+        assert_eq!(projected_rag_tokens(&chunks, |_| false), largest);
+        // Indexed chunks and keyword matches are retrieved separately
+        assert_eq!(projected_rag_tokens(&chunks[..2], |c| c.id == "1"), 30);
+    }
+}
@@ -0,0 +1,265 @@
+use std::fmt;
+
+/// Why a request to the model server failed
+#[derive(Clone, PartialEq, Debug)]
+pub enum LlmError {
+    /// Nothing answered at the server's address
+    Network { url: String },
+    /// The server answered, but the browser withheld the response
+    Cors { url: String, hint: String },
+    /// An HTTPS page may not call a plain HTTP server
+    MixedContent { url: String },
+    Timeout,
+    /// HTTP 401 or 403
+    Auth { code: u16 },
+    /// HTTP 429: rate limited or out of credits
+    Quota,
+    BadStatus { code: u16, body: String },
+    /// The response didn't have the shape the API describes
+    Parse(String),
+    /// The server reported an error partway through a streamed answer
+    Stream(String),
+    /// The request could not be put together, e.g. from a malformed server address
+    Config(String),
+    /// The server doesn't offer what the feature needs
+    Unsupported(String),
+    /// The user stopped the request
+    Aborted,
+}
+
+impl LlmError {
+    /// Error for a response with an unsuccessful status
+    pub fn from_status(code: u16, body: String) -> Self {
+        match code {
+            401 | 403 => LlmError::Auth { code },
+            429 => LlmError::Quota,
+            _ => LlmError::BadStatus { code, body },
+        }
+    }
+
+    /// Whether sending the same request again may work
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            LlmError::Network { .. } | LlmError::Timeout | LlmError::Quota => true,
+            LlmError::BadStatus { code, .. } => *code >= 500,
+            _ => false,
+        }
+    }
+
+    /// Settings field the user most likely has to fix
+    pub fn settings_field(&self) -> Option<&'static str> {
+        match self {
+            LlmError::Network { .. } | LlmError::Cors { .. } | LlmError::MixedContent { .. } | LlmError::Config(_) => Some("base_url"),
+            LlmError::Auth { .. } => Some("api_key"),
+            LlmError::BadStatus { code: 400 | 404, body } if blames_model(body) => Some("selected_model"),
+            LlmError::Stream(message) if blames_model(message) => Some("selected_model"),
+            LlmError::Unsupported(_) => Some("embedding_model"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LlmError::Network { url } => write!(f, "Could not reach the server at {}. Check that it is running and that the address and port are right.", url),
+            LlmError::Cors { url, hint } => write!(
+                f,
+                "The server at {} is running, but the browser blocked its response because the server doesn't allow requests from this page (CORS). {}",
+                url, hint
+            ),
+            LlmError::MixedContent { url } => write!(
+                f,
+                "The browser blocked the request to {} because this page is served over HTTPS and the server isn't. Serve the server over HTTPS or open this page over HTTP.",
+                url
+            ),
+            LlmError::Timeout => write!(f, "The server took too long to answer."),
+            LlmError::Auth { code } => write!(f, "The server refused access (HTTP {}). Check the API key and the server address in the settings.", code),
+            LlmError::Quota => write!(f, "The server is rate limiting requests or the account is out of credits (HTTP 429). Try again in a moment."),
+            LlmError::BadStatus { code: code @ (400 | 404), body } if blames_model(body) => write!(
+                f,
+                "The server rejected the request (HTTP {}): {} Check that the model name is correct in the settings.",
+                code,
+                snippet(body)
+            ),
+            LlmError::BadStatus { code, body } if body.trim().is_empty() => write!(f, "The server answered with HTTP {}.", code),
+            LlmError::BadStatus { code, body } => write!(f, "The server answered with HTTP {}: {}", code, snippet(body)),
+            LlmError::Parse(detail) => write!(f, "The server's response could not be read: {}", detail),
+            LlmError::Stream(message) => write!(f, "The server stopped the answer with an error: {}", snippet(message)),
+            LlmError::Config(detail) => write!(f, "The request could not be made: {}. Check the server address in the settings.", detail),
+            LlmError::Unsupported(detail) => write!(f, "{}", detail),
+            LlmError::Aborted => write!(f, "The request was stopped."),
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+/// Longest part of a response body shown in an error
+const BODY_SNIPPET_CHARS: usize = 300;
+
+/// Whether a rejection is about the model, as when it isn't served
+fn blames_model(body: &str) -> bool {
+    body.to_lowercase().contains("model")
+}
+
+fn snippet(body: &str) -> String {
+    let body = body.trim();
+    match body.char_indices().nth(BODY_SNIPPET_CHARS) {
+        Some((end, _)) => format!("{}…", &body[..end]),
+        None => body.to_string(),
+    }
+}
+
+impl From<reqwest::Error> for LlmError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_builder() {
+            LlmError::Config(err.to_string())
+        } else if err.is_timeout() {
+            LlmError::Timeout
+        } else if err.is_decode() {
+            LlmError::Parse(err.to_string())
+        } else if let Some(status) = err.status() {
+            LlmError::from_status(status.as_u16(), String::new())
+        } else {
+            // Fetch reports refused connections and blocked responses alike;
+            // `LlmService::diagnose` tells them apart
+            LlmError::Network { url: err.url().map(|u| u.origin().ascii_serialization()).unwrap_or_default() }
+        }
+    }
+}
+
+impl From<serde_json::Error> for LlmError {
+    fn from(err: serde_json::Error) -> Self {
+        LlmError::Parse(err.to_string())
+    }
+}
+
+/// Why a document could not be added
+#[derive(Clone, PartialEq, Debug)]
+pub enum DocumentError {
+    /// File type, e.g. "docx"
+    Unsupported(String),
+    Parse(String),
+    /// Browser storage is full
+    Quota,
+    /// Storing failed for another reason, e.g. unreadable data in the way
+    Storage(String),
+}
+
+impl DocumentError {
+    /// Error for data the browser or the stored data refused, with the reason given
+    pub fn from_storage(reason: String) -> Self {
+        if reason.contains("QuotaExceeded") || reason.contains("QUOTA") {
+            DocumentError::Quota
+        } else {
+            DocumentError::Storage(reason)
+        }
+    }
+
+    /// Whether the user can make room and try the same file again
+    pub fn needs_storage(&self) -> bool {
+        matches!(self, DocumentError::Quota)
+    }
+}
+
+impl fmt::Display for DocumentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DocumentError::Unsupported(file_type) => write!(f, "Unsupported file type: {}. Upload PDF, TXT or MD files.", file_type),
+            DocumentError::Parse(detail) => write!(f, "The document could not be read: {}", detail),
+            DocumentError::Quota => write!(f, "The browser's storage is full. Delete documents or chats to make room."),
+            DocumentError::Storage(detail) => write!(f, "The document could not be stored: {}", detail),
+        }
+    }
+}
+
+impl std::error::Error for DocumentError {}
+
+impl From<serde_json::Error> for DocumentError {
+    fn from(err: serde_json::Error) -> Self {
+        DocumentError::Parse(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serde_error(json: &str) -> serde_json::Error {
+        serde_json::from_str::<Vec<String>>(json).unwrap_err()
+    }
+
+    // Responses can only be built by hand outside the browser
+    #[cfg(not(target_arch = "wasm32"))]
+    fn status_error(code: u16) -> reqwest::Error {
+        let response = http::Response::builder().status(code).body("").unwrap();
+        reqwest::Response::from(response).error_for_status().unwrap_err()
+    }
+
+    #[test]
+    fn serde_failures_are_parse_errors() {
+        for json in ["[1, 2]", "[\"a\"", "{}"] {
+            let err = serde_error(json);
+            let detail = err.to_string();
+            assert_eq!(LlmError::from(err), LlmError::Parse(detail.clone()));
+            assert_eq!(DocumentError::from(serde_error(json)), DocumentError::Parse(detail));
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn reqwest_status_errors_map_by_code() {
+        assert_eq!(LlmError::from(status_error(401)), LlmError::Auth { code: 401 });
+        assert_eq!(LlmError::from(status_error(403)), LlmError::Auth { code: 403 });
+        assert_eq!(LlmError::from(status_error(429)), LlmError::Quota);
+        assert_eq!(LlmError::from(status_error(500)), LlmError::BadStatus { code: 500, body: String::new() });
+    }
+
+    #[test]
+    fn requests_that_cannot_be_built_are_configuration_errors() {
+        let err = reqwest::Client::new().get("not a url").build().unwrap_err();
+        let error = LlmError::from(err);
+        assert!(matches!(error, LlmError::Config(_)), "{:?}", error);
+        assert_eq!(error.settings_field(), Some("base_url"));
+        assert!(!error.is_retryable());
+    }
+
+    #[test]
+    fn unreadable_responses_are_not_retried() {
+        assert!(!LlmError::Parse("expected value".into()).is_retryable());
+        assert!(LlmError::Timeout.is_retryable());
+    }
+
+    #[test]
+    fn only_a_full_storage_is_a_quota_error() {
+        assert_eq!(DocumentError::from_storage("JsValue(QuotaExceededError: The quota has been exceeded.)".into()), DocumentError::Quota);
+        assert_eq!(DocumentError::from_storage("NS_ERROR_DOM_QUOTA_REACHED".into()), DocumentError::Quota);
+        let unreadable = "The data stored under 'documents' is unreadable: EOF".to_string();
+        assert_eq!(DocumentError::from_storage(unreadable.clone()), DocumentError::Storage(unreadable));
+    }
+
+    #[test]
+    fn stream_errors_show_the_server_message() {
+        let error = LlmError::Stream("model requires more system memory".into());
+        assert_eq!(error.to_string(), "The server stopped the answer with an error: model requires more system memory");
+        assert_eq!(error.settings_field(), Some("selected_model"));
+        assert!(!error.is_retryable());
+    }
+
+    #[test]
+    fn rejections_show_what_the_server_said() {
+        let unknown_model = LlmError::from_status(404, r#"{"error":"model 'llama9' not found"}"#.to_string());
+        assert!(unknown_model.to_string().contains("llama9"));
+        assert!(unknown_model.to_string().contains("model name"));
+        assert_eq!(unknown_model.settings_field(), Some("selected_model"));
+
+        let bad_request = LlmError::from_status(400, r#"{"error":"max_tokens must be positive"}"#.to_string());
+        assert_eq!(bad_request.to_string(), r#"The server answered with HTTP 400: {"error":"max_tokens must be positive"}"#);
+        assert_eq!(bad_request.settings_field(), None);
+
+        let empty = LlmError::from_status(404, String::new());
+        assert_eq!(empty.to_string(), "The server answered with HTTP 404.");
+        assert_eq!(empty.settings_field(), None);
+    }
+}
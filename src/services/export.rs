@@ -0,0 +1,449 @@
+use crate::models::{ApiMessage, AppSettings, ChatSession, Message};
+use crate::services::document_service::DocumentService;
+use crate::services::llm::{LlmService, RequestInputs};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{window, Blob, BlobPropertyBag, HtmlAnchorElement, HtmlDocument, HtmlTextAreaElement, Url};
+
+pub struct ExportService;
+
+// Marks settings files so unrelated JSON is rejected on import
+const SETTINGS_FILE_KIND: &str = "simple-web-interface-settings";
+const SETTINGS_FILE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct SettingsFile {
+    kind: String,
+    version: u32,
+    settings: AppSettings,
+}
+
+/// Choices for the fine-tuning export
+#[derive(Clone, PartialEq)]
+pub struct FineTuneOptions {
+    pub strip_system: bool,
+    /// Leave out messages marked "Not sent to model"
+    pub skip_excluded: bool,
+    /// Longest example in tokens; longer chats are split between turns. 0 = no limit
+    pub max_tokens: usize,
+}
+
+impl Default for FineTuneOptions {
+    fn default() -> Self {
+        Self { strip_system: false, skip_excluded: true, max_tokens: 0 }
+    }
+}
+
+/// JSONL ready to download, plus what the user should know about it
+pub struct FineTuneExport {
+    pub jsonl: String,
+    pub examples: usize,
+    pub warnings: Vec<String>,
+}
+
+/// Formats a single chat can be exported in
+#[derive(Clone, Copy, PartialEq)]
+pub enum ChatFormat {
+    /// `<|im_start|>role ... <|im_end|>` turns, ending on an open assistant turn
+    ChatMl,
+    /// The `messages` array that chat templates are applied to
+    MessagesJson,
+    Markdown,
+}
+
+impl ChatFormat {
+    pub const ALL: [ChatFormat; 3] = [ChatFormat::ChatMl, ChatFormat::MessagesJson, ChatFormat::Markdown];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ChatFormat::ChatMl => "ChatML",
+            ChatFormat::MessagesJson => "Messages JSON",
+            ChatFormat::Markdown => "Markdown",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ChatFormat::ChatMl => "txt",
+            ChatFormat::MessagesJson => "json",
+            ChatFormat::Markdown => "md",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            ChatFormat::ChatMl => "text/plain",
+            ChatFormat::MessagesJson => "application/json",
+            ChatFormat::Markdown => "text/markdown",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct FineTuneExample<'a> {
+    messages: Vec<FineTuneMessage<'a>>,
+}
+
+#[derive(Serialize)]
+struct FineTuneMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+impl ExportService {
+    /// Serialize messages to Markdown, keeping their order and roles
+    pub fn to_markdown(title: &str, messages: &[Message]) -> String {
+        let mut out = format!("# {}\n\n", title);
+        let blocks: Vec<String> = messages
+            .iter()
+            .map(|m| format!("**{}:**\n\n{}\n", Self::role_label(&m.role), m.content.trim_end()))
+            .collect();
+        out.push_str(&blocks.join("\n---\n\n"));
+        out
+    }
+
+    /// The chat as the model would receive it with the next request: assembled
+    /// by the same function as a real request, so custom instructions, the
+    /// response language, the history window and excluded messages apply. Document context is only ever
+    /// attached to the message being sent, so none appears here. Queued messages
+    /// have not been sent yet and are left out.
+    pub fn chat_to_format(chat: &ChatSession, settings: &AppSettings, format: ChatFormat) -> String {
+        let sent: Vec<Message> = chat.messages.iter().filter(|m| !m.queued).cloned().collect();
+        let last = sent.last().map(|m| m.content.as_str()).unwrap_or_default();
+        let document_list = DocumentService::request_document_list(settings, chat.document_scope.as_deref());
+        let (req, _) = LlmService::compose_request(
+            settings,
+            &RequestInputs {
+                history: &sent,
+                window: chat.history_window,
+                language: chat.response_language.as_deref(),
+                length: chat.response_length.unwrap_or(settings.response_length),
+                document_list: &document_list,
+                message: last,
+                ..Default::default()
+            },
+        );
+        match format {
+            ChatFormat::ChatMl => Self::to_chatml(&req.messages),
+            ChatFormat::MessagesJson => serde_json::to_string_pretty(&req.messages).unwrap_or_default(),
+            ChatFormat::Markdown => {
+                let messages: Vec<Message> = req.messages.iter().map(|m| Message::new(&m.role, m.content.as_str())).collect();
+                Self::to_markdown(&chat.title, &messages)
+            }
+        }
+    }
+
+    fn to_chatml(messages: &[ApiMessage]) -> String {
+        let mut out: String = messages
+            .iter()
+            .map(|m| format!("<|im_start|>{}\n{}<|im_end|>\n", m.role, m.content))
+            .collect();
+        // Leaves the prompt ready for the model to answer
+        if messages.last().is_none_or(|m| m.role != "assistant") {
+            out.push_str("<|im_start|>assistant\n");
+        }
+        out
+    }
+
+    fn role_label(role: &str) -> String {
+        let mut chars = role.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+            None => String::new(),
+        }
+    }
+
+    /// Chats as chat fine-tuning examples, one `{"messages": [...]}` per line. Every
+    /// example ends on an answer; a trailing question without one is left out.
+    pub fn to_fine_tune_jsonl(chats: &[&ChatSession], options: &FineTuneOptions) -> FineTuneExport {
+        let mut lines = Vec::new();
+        let mut warnings = Vec::new();
+        for chat in chats {
+            let messages: Vec<&Message> = chat
+                .messages
+                .iter()
+                .filter(|m| !m.queued)
+                .filter(|m| !(options.skip_excluded && m.excluded))
+                .filter(|m| !m.content.trim().is_empty())
+                .collect();
+            let system: Vec<&Message> = if options.strip_system {
+                Vec::new()
+            } else {
+                messages.iter().copied().filter(|m| m.role == "system").collect()
+            };
+
+            // A turn is a user message and everything up to the next one that
+            // follows an answer, so examples split between turns end on an answer
+            let mut turns: Vec<Vec<&Message>> = Vec::new();
+            for msg in messages.iter().copied().filter(|m| m.role != "system") {
+                match turns.last_mut() {
+                    Some(turn) if msg.role != "user" || !turn.iter().any(|m| m.role == "assistant") => turn.push(msg),
+                    _ => turns.push(vec![msg]),
+                }
+            }
+            let unanswered = turns.last().is_some_and(|t| !t.iter().any(|m| m.role == "assistant"));
+            if unanswered {
+                turns.pop();
+            }
+            if turns.is_empty() {
+                warnings.push(format!("\"{}\" has no answered messages and was skipped.", chat.title));
+                continue;
+            }
+            if unanswered {
+                warnings.push(format!("\"{}\" ends with a user message that has no answer; it was left out.", chat.title));
+            }
+
+            let system_tokens: usize = system.iter().map(|m| m.tokens()).sum();
+            let mut example: Vec<&Message> = Vec::new();
+            let mut example_tokens = system_tokens;
+            for turn in turns {
+                let turn_tokens: usize = turn.iter().map(|m| m.tokens()).sum();
+                if options.max_tokens > 0 {
+                    if !example.is_empty() && example_tokens + turn_tokens > options.max_tokens {
+                        lines.push(Self::fine_tune_line(&system, &example));
+                        example.clear();
+                        example_tokens = system_tokens;
+                    }
+                    if system_tokens + turn_tokens > options.max_tokens {
+                        warnings.push(format!(
+                            "\"{}\" has a turn of {} tokens, over the limit; it was exported on its own.",
+                            chat.title, system_tokens + turn_tokens
+                        ));
+                    }
+                }
+                example.extend(turn);
+                example_tokens += turn_tokens;
+            }
+            lines.push(Self::fine_tune_line(&system, &example));
+        }
+        FineTuneExport { examples: lines.len(), jsonl: lines.join("\n"), warnings }
+    }
+
+    fn fine_tune_line(system: &[&Message], messages: &[&Message]) -> String {
+        let example = FineTuneExample {
+            messages: system
+                .iter()
+                .chain(messages)
+                .map(|m| FineTuneMessage { role: &m.role, content: &m.content })
+                .collect(),
+        };
+        serde_json::to_string(&example).unwrap_or_default()
+    }
+
+    /// Turn a chat title into something safe to use as a file name
+    pub fn file_name(title: &str, extension: &str) -> String {
+        let cleaned: String = title
+            .chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        let cleaned = cleaned.trim_matches('_');
+        let base = if cleaned.is_empty() { "chat" } else { cleaned };
+        format!("{}.{}", base, extension)
+    }
+
+    /// Offer `content` as a file download through a temporary Blob URL
+    pub fn download(filename: &str, content: &str, mime: &str) -> Result<(), JsValue> {
+        let document = window().and_then(|w| w.document()).ok_or("No document available")?;
+
+        let parts = js_sys::Array::new();
+        parts.push(&JsValue::from_str(content));
+        let options = BlobPropertyBag::new();
+        options.set_type(mime);
+        let blob = Blob::new_with_str_sequence_and_options(&parts, &options)?;
+        let url = Url::create_object_url_with_blob(&blob)?;
+
+        let anchor: HtmlAnchorElement = document.create_element("a")?.dyn_into()?;
+        anchor.set_href(&url);
+        anchor.set_download(filename);
+        anchor.click();
+
+        Url::revoke_object_url(&url)
+    }
+
+    /// Serialize settings (without chats) for a settings file
+    pub fn settings_to_json(settings: &AppSettings, include_api_key: bool) -> String {
+        let mut settings = settings.clone();
+        if !include_api_key {
+            settings.api_key.clear();
+        }
+        let file = SettingsFile {
+            kind: SETTINGS_FILE_KIND.to_string(),
+            version: SETTINGS_FILE_VERSION,
+            settings,
+        };
+        serde_json::to_string_pretty(&file).unwrap_or_default()
+    }
+
+    /// Validate a settings file and return the settings it contains
+    pub fn settings_from_json(json: &str) -> Result<AppSettings, String> {
+        let file: SettingsFile = serde_json::from_str(json).map_err(|e| format!("Not a valid settings file: {}", e))?;
+        if file.kind != SETTINGS_FILE_KIND {
+            return Err("This file does not contain settings for this app.".to_string());
+        }
+        if file.version > SETTINGS_FILE_VERSION {
+            return Err(format!("Settings file version {} is newer than this app supports.", file.version));
+        }
+        Ok(file.settings)
+    }
+
+    /// Apply imported settings on top of the current ones. Saved prompts are merged
+    /// by id; local-only state like onboarding progress and recurring prompts is kept,
+    /// and so is the API key when the file was exported without one.
+    pub fn merge_settings(current: &AppSettings, imported: AppSettings) -> AppSettings {
+        let mut merged = imported;
+        merged.onboarding_completed = current.onboarding_completed;
+        if merged.api_key.is_empty() {
+            merged.api_key = current.api_key.clone();
+        }
+        merged.recurring_prompts = current.recurring_prompts.clone();
+        let mut prompts = current.saved_prompts.clone();
+        for prompt in merged.saved_prompts.drain(..) {
+            match prompts.iter_mut().find(|p| p.id == prompt.id) {
+                Some(existing) => *existing = prompt,
+                None => prompts.push(prompt),
+            }
+        }
+        merged.saved_prompts = prompts;
+        merged
+    }
+
+    /// Human-readable list of the fields that differ between two settings
+    pub fn settings_changes(current: &AppSettings, next: &AppSettings) -> Vec<String> {
+        let before = serde_json::to_value(current).unwrap_or_default();
+        let after = serde_json::to_value(next).unwrap_or_default();
+        let mut changes = Vec::new();
+        if let (Some(before), Some(after)) = (before.as_object(), after.as_object()) {
+            for (key, new_value) in after {
+                let old_value = before.get(key).cloned().unwrap_or_default();
+                if old_value == *new_value {
+                    continue;
+                }
+                if key == "api_key" {
+                    changes.push("API key".to_string());
+                } else if key == "saved_prompts" {
+                    let added = next.saved_prompts.iter().filter(|p| !current.saved_prompts.iter().any(|c| c.id == p.id)).count();
+                    let updated = next.saved_prompts.iter().filter(|p| current.saved_prompts.iter().any(|c| c.id == p.id && c != *p)).count();
+                    changes.push(format!("saved prompts: {} added, {} updated", added, updated));
+                } else {
+                    changes.push(format!("{}: {} → {}", key, Self::short_value(&old_value), Self::short_value(new_value)));
+                }
+            }
+        }
+        changes
+    }
+
+    fn short_value(value: &serde_json::Value) -> String {
+        let text = match value {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        if text.chars().count() > 40 {
+            format!("{}…", text.chars().take(40).collect::<String>())
+        } else {
+            text
+        }
+    }
+
+    /// Write text to the system clipboard. Pages not served over HTTPS have
+    /// no clipboard API, so there the text is selected and copied instead.
+    pub async fn copy_to_clipboard(text: &str) -> Result<(), JsValue> {
+        let navigator = window().ok_or("No window available")?.navigator();
+        let has_clipboard = js_sys::Reflect::get(&navigator, &"clipboard".into()).map(|c| !c.is_undefined()).unwrap_or(false);
+        if has_clipboard && JsFuture::from(navigator.clipboard().write_text(text)).await.is_ok() {
+            return Ok(());
+        }
+        Self::copy_selection(text)
+    }
+
+    /// Copies through a hidden text field, for browsers without the clipboard API
+    fn copy_selection(text: &str) -> Result<(), JsValue> {
+        let document = window().and_then(|w| w.document()).ok_or("No document available")?;
+        let body = document.body().ok_or("No document body")?;
+        let field: HtmlTextAreaElement = document.create_element("textarea")?.dyn_into()?;
+        field.set_value(text);
+        field.set_attribute("readonly", "")?;
+        field.style().set_property("position", "fixed")?;
+        field.style().set_property("opacity", "0")?;
+        body.append_child(&field)?;
+        field.select();
+        let copied = document.dyn_into::<HtmlDocument>()?.exec_command("copy");
+        field.remove();
+        if copied? {
+            Ok(())
+        } else {
+            Err("The browser refused to copy".into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> Message {
+        Message { role: role.to_string(), content: content.to_string(), ..Default::default() }
+    }
+
+    fn chat(messages: Vec<Message>) -> ChatSession {
+        let mut chat: ChatSession = serde_json::from_str(r#"{"id":"c","title":"t","created_at":0,"messages":[]}"#).unwrap();
+        chat.messages = messages;
+        chat
+    }
+
+    #[test]
+    fn fine_tune_leaves_out_queued_messages() {
+        let mut waiting = message("user", "still queued");
+        waiting.queued = true;
+        let chat = chat(vec![message("user", "hi"), message("assistant", "hello"), waiting]);
+        let export = ExportService::to_fine_tune_jsonl(&[&chat], &FineTuneOptions::default());
+        assert_eq!(export.examples, 1);
+        assert!(!export.jsonl.contains("still queued"));
+        assert!(export.warnings.is_empty());
+    }
+
+    fn roles(line: &str) -> Vec<String> {
+        let example: serde_json::Value = serde_json::from_str(line).unwrap();
+        example["messages"].as_array().unwrap().iter().map(|m| m["role"].as_str().unwrap().to_string()).collect()
+    }
+
+    #[test]
+    fn fine_tune_examples_keep_system_messages_unless_stripped() {
+        let chat = chat(vec![message("system", "Be brief."), message("user", "hi"), message("assistant", "hello")]);
+        let export = ExportService::to_fine_tune_jsonl(&[&chat], &FineTuneOptions::default());
+        assert_eq!(roles(&export.jsonl), ["system", "user", "assistant"]);
+        let stripped = ExportService::to_fine_tune_jsonl(&[&chat], &FineTuneOptions { strip_system: true, ..Default::default() });
+        assert_eq!(roles(&stripped.jsonl), ["user", "assistant"]);
+    }
+
+    #[test]
+    fn fine_tune_drops_an_unanswered_last_question_with_a_warning() {
+        let answered = chat(vec![message("user", "one"), message("assistant", "1"), message("user", "two")]);
+        let unanswered = chat(vec![message("user", "hello?")]);
+        let export = ExportService::to_fine_tune_jsonl(&[&answered, &unanswered], &FineTuneOptions::default());
+        assert_eq!(export.examples, 1);
+        assert_eq!(roles(&export.jsonl), ["user", "assistant"]);
+        assert_eq!(export.warnings.len(), 2);
+    }
+
+    #[test]
+    fn fine_tune_splits_long_chats_between_answered_turns() {
+        let long = "word ".repeat(40);
+        let chat = chat(vec![
+            message("user", &long),
+            message("assistant", &long),
+            // Never answered, so it must not end an example on its own
+            message("user", &long),
+            message("user", &long),
+            message("assistant", &long),
+            message("user", &long),
+            message("assistant", &long),
+        ]);
+        let export = ExportService::to_fine_tune_jsonl(&[&chat], &FineTuneOptions { max_tokens: 100, ..Default::default() });
+        let examples: Vec<Vec<String>> = export.jsonl.lines().map(roles).collect();
+        assert_eq!(examples, [vec!["user", "assistant"], vec!["user", "user", "assistant"], vec!["user", "assistant"]]);
+        assert_eq!(export.examples, 3);
+        // The second turn is over the limit on its own
+        assert_eq!(export.warnings.len(), 1);
+    }
+}
@@ -0,0 +1,117 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use serde::Serialize;
+use web_sys::{CustomEvent, CustomEventInit, Element};
+use yew::Callback;
+
+/// Names are prefixed so they can't collide with the host page's own events
+const EVENT_PREFIX: &str = "swi:";
+
+/// Dispatches CustomEvents on the element the app was mounted into, so the
+/// host page can follow along. Does nothing when the app owns the page.
+#[derive(Clone, PartialEq, Default)]
+pub struct HostEvents {
+    target: Option<Element>,
+}
+
+impl HostEvents {
+    pub fn new(target: Option<Element>) -> Self {
+        Self { target }
+    }
+
+    /// Fires `swi:<name>` with `payload` as a plain JS object in `detail`
+    pub fn emit<T: Serialize>(&self, name: &str, payload: &T) {
+        let Some(target) = &self.target else { return };
+        let Ok(json) = serde_json::to_string(payload) else { return };
+        let Ok(detail) = js_sys::JSON::parse(&json) else { return };
+
+        let init = CustomEventInit::new();
+        init.set_detail(&detail);
+        init.set_bubbles(true);
+        if let Ok(event) = CustomEvent::new_with_event_init_dict(&format!("{}{}", EVENT_PREFIX, name), &init) {
+            let _ = target.dispatch_event(&event);
+        }
+    }
+}
+
+/// `swi:message_sent`, once the user's message is in the chat
+#[derive(Serialize)]
+pub struct MessageSent {
+    pub chat_id: String,
+    pub message_id: String,
+    pub content: String,
+}
+
+/// `swi:response_completed`, when an answer finished without being stopped
+#[derive(Serialize)]
+pub struct ResponseCompleted {
+    pub chat_id: String,
+    pub message_id: String,
+    pub content: String,
+    pub metrics: ResponseMetrics,
+}
+
+#[derive(Serialize)]
+pub struct ResponseMetrics {
+    pub model: Option<String>,
+    pub tokens: Option<usize>,
+    /// From sending the request to the last token
+    pub duration_ms: f64,
+}
+
+/// `swi:chat_created`
+#[derive(Serialize)]
+pub struct ChatCreated {
+    pub chat_id: String,
+}
+
+/// `swi:document_uploaded`, after the document was chunked and stored
+#[derive(Serialize)]
+pub struct DocumentUploaded {
+    pub document_id: String,
+    pub filename: String,
+    pub chunk_count: usize,
+}
+
+/// `swi:error`, for failures the user is shown
+#[derive(Serialize)]
+pub struct HostError {
+    pub chat_id: Option<String>,
+    pub message: String,
+}
+
+/// Something the host page asks the app to do
+pub enum HostCommand {
+    SendMessage(String),
+    SetSystemPrompt(String),
+    OpenChat(String),
+}
+
+type CommandHandler = Callback<HostCommand, Result<(), String>>;
+
+/// Lets the mount handle call into the running app. The app registers its
+/// handler on every render so commands always see the current state.
+#[derive(Clone, Default)]
+pub struct HostCommands(Rc<RefCell<Option<CommandHandler>>>);
+
+impl PartialEq for HostCommands {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl HostCommands {
+    pub fn set_handler(&self, handler: Option<CommandHandler>) {
+        *self.0.borrow_mut() = handler;
+    }
+
+    pub fn send(&self, command: HostCommand) -> Result<(), String> {
+        // Released before emitting, since the handler may re-render and re-register
+        let handler = self.0.borrow().clone();
+        match handler {
+            Some(handler) => handler.emit(command),
+            None => Err("The app is not mounted".to_string()),
+        }
+    }
+}
@@ -1,61 +1,619 @@
-use crate::models::{ChatRequest, ChatResponse, Message, ModelListResponse};
-use anyhow::Result;
+use crate::models::{parse_logprobs, ApiMessage, AppSettings, BackendKind, ChatRequest, ChatResponse, CompositionSection, Document, DEFAULT_TEMPERATURE, EmbeddingRequest, EmbeddingResponse, Message, ModelCacheEntry, ModelListResponse, OllamaChatRequest, OllamaChatResponse, OllamaTagsResponse, RequestComposition, ResponseLength, StreamOptions, StreamResponse, TokenLogprob};
+use crate::utils::{build_request_messages, count_tokens, history_window_start, split_reasoning};
+use crate::services::error::LlmError;
+use crate::services::request::{AbortHandle, RequestConfig, RequestPipeline, RetryLayer};
+use crate::services::sse::{SseEvent, SseParser};
+use crate::services::storage::LocalStorage;
 use reqwest::{Client, Response};
+use std::collections::HashMap;
+use web_sys::{RequestInit, RequestMode};
+
+const KEY_MODEL_CACHE: &str = "model_cache_v1";
+/// Alternatives requested per token when logprobs are enabled
+const TOP_LOGPROBS: u8 = 5;
+/// Separates the chat's system prompt from the global custom instructions
+const CUSTOM_INSTRUCTIONS_HEADER: &str = "\n\n--- Custom instructions ---\n";
+/// How to allow cross-origin requests, by the default port of each server
+const CORS_HINTS: &[(&str, &str)] = &[
+    ("8080", "llama.cpp: start llama-server with --host 0.0.0.0 and CORS enabled."),
+    ("1234", "LM Studio: turn on \"Enable CORS\" in the server settings."),
+    ("11434", "Ollama: set OLLAMA_ORIGINS to this page's origin (or *) before starting it."),
+];
+
+type Result<T> = std::result::Result<T, LlmError>;
 
 pub struct LlmService;
 
+/// Where a stream chunk's choice goes
+#[derive(Debug, PartialEq)]
+pub enum ChoiceRoute {
+    /// Part of the answer being shown
+    Primary,
+    /// Another completion; `first` is set for its first chunk only
+    Ignored { first: bool, primary: u32 },
+}
+
+/// Routes stream chunks by choice index. Only one completion is requested, so
+/// the first index seen owns the answer and chunks for any other are dropped,
+/// even when servers interleave them.
+#[derive(Default)]
+pub struct ChoiceRouter {
+    primary: Option<u32>,
+    ignored: Vec<u32>,
+}
+
+impl ChoiceRouter {
+    pub fn route(&mut self, index: u32) -> ChoiceRoute {
+        let primary = *self.primary.get_or_insert(index);
+        if index == primary {
+            return ChoiceRoute::Primary;
+        }
+        let first = !self.ignored.contains(&index);
+        if first {
+            self.ignored.push(index);
+        }
+        ChoiceRoute::Ignored { first, primary }
+    }
+}
+
+/// What a request is composed from besides the settings
+#[derive(Default)]
+pub struct RequestInputs<'a> {
+    /// The chat so far, ending on the message being sent
+    pub history: &'a [Message],
+    /// The chat's history window, if it has one
+    pub window: Option<usize>,
+    /// The chat's response language, if it has one
+    pub language: Option<&'a str>,
+    /// Sent as a trailing assistant message for the model to continue
+    pub prefill: Option<&'a str>,
+    pub length: ResponseLength,
+    /// Prepended to the outgoing copy of the last message only
+    pub doc_context: &'a str,
+    /// Added to the system message after custom instructions
+    pub document_list: &'a str,
+    /// The text of the message being sent, as typed
+    pub message: &'a str,
+    /// The documents `doc_context` was built from
+    pub documents: &'a [Document],
+}
+
 impl LlmService {
     fn get_clean_url(base: &str) -> String {
         base.trim_end_matches('/').to_string()
     }
 
-    pub async fn fetch_models(base_url: &str) -> Result<ModelListResponse> {
-        let client = Client::new();
-        let url = format!("{}/v1/models", Self::get_clean_url(base_url));
-        let resp = client.get(url).send().await?;
-        let data = resp.json::<ModelListResponse>().await?;
-        Ok(data)
+    fn models_url(config: &RequestConfig) -> String {
+        match config.backend {
+            BackendKind::OpenAI => format!("{}/v1/models", Self::get_clean_url(&config.base_url)),
+            BackendKind::Ollama => format!("{}/api/tags", Self::get_clean_url(&config.base_url)),
+        }
+    }
+
+    pub async fn fetch_models(config: &RequestConfig) -> Result<ModelListResponse> {
+        let resp = RequestPipeline::new(config, RetryLayer::configured(config)).send(Client::new().get(Self::models_url(config))).await?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(LlmError::from_status(status.as_u16(), resp.text().await.unwrap_or_default()));
+        }
+        match config.backend {
+            BackendKind::OpenAI => Ok(resp.json::<ModelListResponse>().await?),
+            BackendKind::Ollama => Ok(resp.json::<OllamaTagsResponse>().await?.into()),
+        }
+    }
+
+    /// Checks that the server is reachable and returns the ids of its models
+    pub async fn test_connection(config: &RequestConfig) -> Result<Vec<String>> {
+        let resp = Self::fetch_models(config).await?;
+        Ok(resp.data.into_iter().map(|m| m.id).collect())
     }
 
+    /// Model list cached from the last successful fetch against this server
+    pub fn cached_models(base_url: &str) -> Option<ModelCacheEntry> {
+        let cache: HashMap<String, ModelCacheEntry> = LocalStorage::get(KEY_MODEL_CACHE).unwrap_or_default();
+        cache.get(&Self::get_clean_url(base_url)).cloned()
+    }
+
+    /// Fetch the model list and update the cache. On failure the cache is left
+    /// untouched.
+    pub async fn refresh_models(config: &RequestConfig) -> Result<ModelCacheEntry> {
+        let models = Self::fetch_models(config).await?.data.into_iter().map(|m| m.id).collect();
+
+        let entry = ModelCacheEntry { models, fetched_at: js_sys::Date::now() };
+        let mut cache: HashMap<String, ModelCacheEntry> = LocalStorage::get(KEY_MODEL_CACHE).unwrap_or_default();
+        cache.insert(Self::get_clean_url(&config.base_url), entry.clone());
+        LocalStorage::set(KEY_MODEL_CACHE, &cache);
+        Ok(entry)
+    }
+
+    /// Aborting `abort` cancels the fetch and any retry still waiting; reading
+    /// the body is up to the caller, with `parse_response` or `stream_parser`
+    /// and `parse_stream_chunk`. `on_retry` hears of each retry as it starts.
     pub async fn chat_completion_request(
-        base_url: &str,
+        config: &RequestConfig,
         request: &ChatRequest,
+        abort: &AbortHandle,
+        on_retry: impl Fn(u32, u32) + 'static,
     ) -> Result<Response> {
-        let client = Client::new();
-        let url = format!("{}/v1/chat/completions", Self::get_clean_url(base_url));
-
-        let resp = client
-            .post(url)
-            .json(request)
-            .send()
-            .await?;
+        let base_url = Self::get_clean_url(&config.base_url);
+        let builder = match config.backend {
+            BackendKind::OpenAI => Client::new().post(format!("{}/v1/chat/completions", base_url)).json(request),
+            BackendKind::Ollama => Client::new().post(format!("{}/api/chat", base_url)).json(&OllamaChatRequest::from(request)),
+        };
+        let resp = abort.run(RequestPipeline::new(config, RetryLayer::configured(config).on_retry(on_retry)).send(builder)).await??;
 
         // We return the raw reqwest::Response here to allow
         // the caller to decide between .bytes_stream() or .json()
         Ok(resp)
     }
 
-    /// Helper to generate a title summary
-    pub async fn generate_title(base_url: &str, model: &str, messages: &[Message]) -> Result<String> {
-        let mut summary_messages = messages.to_vec();
-        summary_messages.push(Message {
-            role: "user".into(),
-            content: "Generate a short title (4-6 words) for this chat. No quotes.".into()
-        });
+    /// A complete (non-streaming) answer of the chat endpoint
+    pub fn parse_response(backend: BackendKind, body: &str) -> serde_json::Result<ChatResponse> {
+        match backend {
+            BackendKind::OpenAI => serde_json::from_str(body),
+            BackendKind::Ollama => serde_json::from_str::<OllamaChatResponse>(body).map(ChatResponse::from),
+        }
+    }
 
+    /// Splits a streamed answer into chunks: SSE events, or Ollama's lines
+    pub fn stream_parser(backend: BackendKind) -> SseParser {
+        match backend {
+            BackendKind::OpenAI => SseParser::new(),
+            BackendKind::Ollama => SseParser::ndjson(),
+        }
+    }
+
+    /// One chunk of a streamed answer
+    pub fn parse_stream_chunk(backend: BackendKind, data: &str) -> serde_json::Result<StreamResponse> {
+        match backend {
+            BackendKind::OpenAI => serde_json::from_str(data),
+            BackendKind::Ollama => serde_json::from_str::<OllamaChatResponse>(data).map(StreamResponse::from),
+        }
+    }
+
+    /// The message of an error the server sent in place of a stream chunk:
+    /// `{"error": "..."}` from Ollama, `{"error": {"message": "..."}}` from
+    /// OpenAI-compatible servers
+    pub fn stream_error(data: &str) -> Option<String> {
+        if !data.contains("\"error\"") {
+            return None;
+        }
+        let value: serde_json::Value = serde_json::from_str(data).ok()?;
+        match value.get("error")? {
+            serde_json::Value::String(message) => Some(message.clone()),
+            error => Some(error.get("message").and_then(|m| m.as_str()).map_or_else(|| error.to_string(), str::to_string)),
+        }
+    }
+
+    /// Embeds each input with `model`, in input order
+    pub async fn embed(config: &RequestConfig, model: &str, input: &[String]) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/v1/embeddings", Self::get_clean_url(&config.base_url));
+        let request = Client::new().post(url).json(&EmbeddingRequest { model, input });
+        let resp = RequestPipeline::new(config, RetryLayer::none()).send(request).await?;
+        let status = resp.status();
+        if status.as_u16() == 404 {
+            return Err(LlmError::Unsupported(format!("The server has no embeddings endpoint, so '{}' can't be used to index documents.", model)));
+        }
+        if !status.is_success() {
+            return Err(LlmError::from_status(status.as_u16(), resp.text().await.unwrap_or_default()));
+        }
+        let mut data = resp.json::<EmbeddingResponse>().await?.data;
+        if data.len() != input.len() {
+            return Err(LlmError::Parse(format!("expected {} embeddings, got {}", input.len(), data.len())));
+        }
+        data.sort_by_key(|d| d.index);
+        Ok(data.into_iter().map(|d| d.embedding).collect())
+    }
+
+    /// Smallest possible completion, sent so an idle server keeps the model
+    /// loaded. Ollama also gets its own `keep_alive` hint; its native API
+    /// loads the model for a chat without messages.
+    pub async fn keep_alive(config: &RequestConfig, model: &str, minutes: u32) -> Result<()> {
+        let keep_alive = format!("{}m", minutes.saturating_mul(2));
+        let (url, body) = match config.backend {
+            BackendKind::OpenAI => (
+                format!("{}/v1/chat/completions", Self::get_clean_url(&config.base_url)),
+                serde_json::json!({
+                    "model": model,
+                    "messages": [{ "role": "user", "content": "." }],
+                    "max_tokens": 1,
+                    "stream": false,
+                    "keep_alive": keep_alive,
+                }),
+            ),
+            BackendKind::Ollama => (
+                format!("{}/api/chat", Self::get_clean_url(&config.base_url)),
+                serde_json::json!({ "model": model, "messages": [], "keep_alive": keep_alive }),
+            ),
+        };
+        let resp = RequestPipeline::new(config, RetryLayer::none()).send(Client::new().post(url).json(&body)).await?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(LlmError::from_status(status.as_u16(), resp.text().await.unwrap_or_default()));
+        }
+        Ok(())
+    }
+
+    /// Narrows down a failed request. Fetch reports a blocked CORS response and
+    /// a server that isn't running the same way, so a `no-cors` request tells
+    /// them apart: it succeeds whenever the server answers at all.
+    pub async fn diagnose(config: &RequestConfig, error: LlmError) -> LlmError {
+        if !matches!(error, LlmError::Network { .. }) {
+            return error;
+        }
+        let url = Self::get_clean_url(&config.base_url);
+        let page_protocol = web_sys::window().and_then(|w| w.location().protocol().ok()).unwrap_or_default();
+        if page_protocol == "https:" && url.starts_with("http://") && !Self::is_loopback(&url) {
+            return LlmError::MixedContent { url };
+        }
+        if !Self::answers_opaquely(&Self::models_url(config)).await {
+            return LlmError::Network { url };
+        }
+        let port = web_sys::Url::new(&url).map(|u| u.port()).unwrap_or_default();
+        let hints: Vec<&str> = match CORS_HINTS.iter().find(|(p, _)| *p == port) {
+            Some((_, hint)) => vec![hint],
+            None => CORS_HINTS.iter().map(|(_, hint)| *hint).collect(),
+        };
+        LlmError::Cors { url, hint: hints.join(" ") }
+    }
+
+    fn is_loopback(url: &str) -> bool {
+        web_sys::Url::new(url).is_ok_and(|u| matches!(u.hostname().as_str(), "localhost" | "127.0.0.1" | "[::1]"))
+    }
+
+    /// Whether the server responds to a request the browser won't let us read
+    async fn answers_opaquely(url: &str) -> bool {
+        let Some(window) = web_sys::window() else { return false };
+        let init = RequestInit::new();
+        init.set_mode(RequestMode::NoCors);
+        wasm_bindgen_futures::JsFuture::from(window.fetch_with_str_and_init(url, &init)).await.is_ok()
+    }
+
+    /// A short title for the conversation, without surrounding quotes; empty
+    /// when the model didn't come up with one
+    pub async fn generate_title(config: &RequestConfig, model: &str, messages: &[Message]) -> Result<String> {
+        let mut summary_messages: Vec<ApiMessage> = messages.iter().map(ApiMessage::from).collect();
+        summary_messages.push(ApiMessage::from(&Message::new(
+            "user",
+            "Generate a short title (4-6 words) for this chat. No quotes.",
+        )));
+        let title = Self::complete(config, model, summary_messages, &AbortHandle::new()).await?;
+        Ok(title.trim_matches(|c| c == '"' || c == '\'').trim().to_string())
+    }
+
+    /// `previous`, the summary of the conversation before `messages`, brought
+    /// up to date with them
+    pub async fn summarize(config: &RequestConfig, model: &str, previous: Option<&str>, messages: &[Message], abort: &AbortHandle) -> Result<String> {
+        let mut request_messages = Vec::new();
+        if let Some(previous) = previous {
+            request_messages.push(ApiMessage::from(&Message::new("system", format!("Summary of earlier conversation: {}", previous))));
+        }
+        request_messages.extend(messages.iter().map(ApiMessage::from));
+        request_messages.push(ApiMessage::from(&Message::new(
+            "user",
+            "Summarize this conversation so far, including the earlier summary if there is one. Keep names, facts, decisions and open questions; leave out pleasantries. Answer with the summary only.",
+        )));
+        Self::complete(config, model, request_messages, abort).await
+    }
+
+    /// The trimmed answer to a request made by the app itself, without its reasoning
+    async fn complete(config: &RequestConfig, model: &str, messages: Vec<ApiMessage>, abort: &AbortHandle) -> Result<String> {
         let req = ChatRequest {
-            messages: summary_messages,
+            messages,
             model: model.to_string(),
-            temperature: 0.7,
+            temperature: DEFAULT_TEMPERATURE,
             stream: false,
+            stream_options: None,
+            logprobs: None,
+            top_logprobs: None,
+            max_tokens: None,
+            top_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            stop: Vec::new(),
         };
 
-        let resp = Self::chat_completion_request(base_url, &req).await?;
-        let json: ChatResponse = resp.json().await?;
+        let resp = Self::chat_completion_request(config, &req, abort, |_, _| {}).await?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(LlmError::from_status(status.as_u16(), resp.text().await.unwrap_or_default()));
+        }
+        let json = Self::parse_response(config.backend, &resp.text().await?)?;
 
         Ok(json.choices
             .first()
-            .map(|c| c.message.content.trim().to_string())
-            .unwrap_or_else(|| "New Chat".to_string()))
+            .map(|c| split_reasoning(&c.message.content).1.unwrap_or_default().trim().to_string())
+            .unwrap_or_default())
+    }
+
+    /// Assemble the request for the last message of the history and describe
+    /// what went into it. The system message gets custom instructions, the
+    /// document list, the response language and the style hint of the response
+    /// length, in that order; the length also sets `max_tokens` unless it leaves
+    /// that to the settings. Sampling parameters come from the settings, and so
+    /// does the strategy that leaves out old history the context can't take.
+    pub fn compose_request(settings: &AppSettings, inputs: &RequestInputs) -> (ChatRequest, RequestComposition) {
+        let RequestInputs { history, window, language, prefill, length, doc_context, document_list, message, documents } = *inputs;
+        let mut llm_messages = history.to_vec();
+        if !doc_context.is_empty() {
+            // For LLM, prepend document context and keep original message
+            llm_messages.pop();
+            llm_messages.push(Message::new("user", format!("{}User message:\n{}", doc_context, message)));
+        }
+        // Only the outgoing copy changes; the chat's stored system message stays as it is
+        let language = language.map(|l| format!("Respond in {} unless explicitly asked otherwise.", l)).unwrap_or_default();
+        let additions = [
+            (CUSTOM_INSTRUCTIONS_HEADER, settings.custom_instructions.trim()),
+            ("\n\n", document_list.trim()),
+            ("\n\n", language.as_str()),
+            ("\n\n", length.style_hint().unwrap_or_default()),
+        ];
+        for (header, text) in additions.iter().filter(|(_, text)| !text.is_empty()) {
+            match llm_messages.iter_mut().find(|m| m.role == "system") {
+                Some(system) => {
+                    system.content.push_str(header);
+                    system.content.push_str(text);
+                    system.token_count = None;
+                }
+                None => llm_messages.insert(0, Message::new("system", text.to_string())),
+            }
+        }
+
+        // Apply the chat's history window (system messages are always kept)
+        let last = llm_messages.len().saturating_sub(1);
+        let window_start = history_window_start(&llm_messages[..last], window);
+        let in_window: Vec<&Message> = llm_messages
+            .iter()
+            .enumerate()
+            .filter(|(i, m)| *i >= window_start || m.role == "system")
+            .map(|(_, m)| m)
+            .collect();
+        let excluded_messages = in_window.iter().filter(|m| m.excluded).count();
+        let included: Vec<Message> = in_window.into_iter().filter(|m| !m.excluded).cloned().collect();
+
+        // Room is kept for the answer and the prefill
+        let prefill = prefill.filter(|p| !p.is_empty());
+        let max_tokens = length.max_tokens().or(settings.max_tokens);
+        let context_length = settings.context_length_for(&settings.selected_model);
+        let answer_room = max_tokens.map_or((context_length / 4).min(1024), |n| n as usize) + prefill.map_or(0, count_tokens);
+        let kept = if context_length > 0 {
+            build_request_messages(&included, settings.context_strategy, context_length.saturating_sub(answer_room))
+        } else {
+            included.clone()
+        };
+        let omitted_messages = included.len() - kept.len();
+        let sent: Vec<&Message> = kept.iter().collect();
+
+        let mut messages: Vec<ApiMessage> = sent.iter().map(|m| ApiMessage::from(*m)).collect();
+        if let Some(prefill) = prefill {
+            messages.push(ApiMessage { role: "assistant".to_string(), content: prefill.to_string() });
+        }
+        let req = ChatRequest {
+            messages,
+            model: settings.selected_model.clone(),
+            temperature: settings.temperature,
+            stream: settings.stream_enabled,
+            stream_options: settings.stream_enabled.then_some(StreamOptions { include_usage: true }),
+            logprobs: settings.logprobs_enabled.then_some(true),
+            top_logprobs: settings.logprobs_enabled.then_some(TOP_LOGPROBS),
+            max_tokens,
+            top_p: settings.top_p,
+            frequency_penalty: settings.frequency_penalty,
+            presence_penalty: settings.presence_penalty,
+            stop: settings.stop_sequences.clone(),
+        };
+
+        let (system, rest): (Vec<&Message>, Vec<&Message>) = sent.iter().partition(|m| m.role == "system");
+        let history_sent = &rest[..rest.len().saturating_sub(1)];
+        let tokens_of = |msgs: &[&Message]| msgs.iter().map(|m| m.tokens()).sum::<usize>();
+
+        let mut history_label = format!("History ({} messages", history_sent.len());
+        if excluded_messages > 0 {
+            history_label.push_str(&format!(", {} excluded", excluded_messages));
+        }
+        if omitted_messages > 0 {
+            history_label.push_str(&format!(", {} left out to fit the context", omitted_messages));
+        }
+        history_label.push(')');
+        let mut sections = vec![
+            CompositionSection { label: "System prompt".to_string(), tokens: tokens_of(&system) },
+            CompositionSection { label: history_label, tokens: tokens_of(history_sent) },
+        ];
+        if !doc_context.is_empty() {
+            sections.push(CompositionSection { label: "Documents".to_string(), tokens: count_tokens(doc_context) });
+        }
+        sections.push(CompositionSection { label: "Your message".to_string(), tokens: count_tokens(message) });
+        if let Some(prefill) = prefill {
+            sections.push(CompositionSection { label: "Response prefill".to_string(), tokens: count_tokens(prefill) });
+        }
+
+        let composition = RequestComposition {
+            model: req.model.clone(),
+            system_prompt: system.iter().map(|m| m.content.as_str()).collect::<Vec<_>>().join("\n\n"),
+            history_messages: history_sent.len(),
+            excluded_messages,
+            omitted_messages,
+            documents: documents
+                .iter()
+                .map(|d| format!("{} ({} chunk{})", d.filename, d.chunk_count, if d.chunk_count == 1 { "" } else { "s" }))
+                .collect(),
+            total_tokens: sections.iter().map(|s| s.tokens).sum(),
+            deduplicated_tokens: 0,
+            sections,
+            payload: if settings.debug_mode { serde_json::to_string_pretty(&req).ok() } else { None },
+            prefill: prefill.map(str::to_string),
+            response_length: (length != ResponseLength::Normal).then(|| length.describe()),
+        };
+        (req, composition)
+    }
+
+    /// Whether a response should be read as SSE. The content type wins over what
+    /// was requested, since some servers ignore the `stream` flag.
+    pub fn is_event_stream(resp: &Response, stream_requested: bool) -> bool {
+        let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("");
+        Self::is_event_stream_type(content_type, stream_requested)
+    }
+
+    fn is_event_stream_type(content_type: &str, stream_requested: bool) -> bool {
+        let content_type = content_type.to_lowercase();
+        if content_type.contains("text/event-stream") {
+            true
+        } else if content_type.contains("application/json") {
+            false
+        } else {
+            stream_requested
+        }
+    }
+
+    /// A streamed body that turned out to be one plain JSON response
+    pub fn single_json_body(backend: BackendKind, raw: &str) -> Option<ChatResponse> {
+        if !raw.trim_start().starts_with('{') {
+            return None;
+        }
+        Self::parse_response(backend, raw).ok()
+    }
+
+    /// The assistant message of a complete (non-streaming) response, continuing `prefill`
+    pub fn message_from_response(json: &ChatResponse, model: &str, prefill: &str) -> Option<Message> {
+        let choice = json.choices.first()?;
+        let mut message = Message::new("assistant", "");
+        message.set_output(&choice.message.text(), prefill);
+        message.logprobs = parse_logprobs(&choice.logprobs);
+        message.created_at = Some(js_sys::Date::now());
+        message.model = Some(model.to_string());
+        message.token_count = Some(count_tokens(&message.content));
+        Some(message)
+    }
+
+    /// Joins the deltas of a streamed body that arrived all at once, continuing
+    /// `prefill`. Only the first choice index is kept, as in the streaming path.
+    pub fn message_from_sse(backend: BackendKind, body: &str, model: &str, prefill: &str) -> Option<Message> {
+        let (output, logprobs) = Self::join_sse(backend, body)?;
+        let mut message = Message::new("assistant", "");
+        message.set_output(&output, prefill);
+        message.model = Some(model.to_string());
+        message.logprobs = logprobs;
+        message.token_count = Some(count_tokens(&message.content));
+        Some(message)
+    }
+
+    /// Text and token probabilities of the first choice in a streamed body;
+    /// `None` when no chunk of it could be decoded
+    fn join_sse(backend: BackendKind, body: &str) -> Option<(String, Option<Vec<TokenLogprob>>)> {
+        let mut content = String::new();
+        let mut logprobs: Option<Vec<TokenLogprob>> = None;
+        let mut router = ChoiceRouter::default();
+        let mut parsed_any = false;
+        let mut in_reasoning = false;
+        let mut parser = Self::stream_parser(backend);
+        let mut events = parser.push(body.as_bytes());
+        events.extend(parser.finish());
+        for event in events {
+            let SseEvent::Data { data, .. } = event else { break };
+            let Ok(json) = Self::parse_stream_chunk(backend, &data) else { continue };
+            parsed_any = true;
+            for choice in json.choices.iter().filter(|c| router.route(c.index) == ChoiceRoute::Primary) {
+                if let Some(tokens) = parse_logprobs(&choice.logprobs) {
+                    logprobs.get_or_insert_with(Vec::new).extend(tokens);
+                }
+                choice.delta.append_to(&mut content, &mut in_reasoning);
+            }
+        }
+        parsed_any.then_some((content, logprobs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(index: u32, text: &str) -> String {
+        format!("data: {{\"choices\":[{{\"index\":{},\"delta\":{{\"content\":{:?}}}}}]}}\n\n", index, text)
+    }
+
+    #[test]
+    fn stream_errors_are_told_from_chunks() {
+        assert_eq!(LlmService::stream_error(r#"{"error":"model 'x' not found"}"#), Some("model 'x' not found".to_string()));
+        assert_eq!(LlmService::stream_error(r#"{"error":{"message":"overloaded","type":"server_error"}}"#), Some("overloaded".to_string()));
+        assert_eq!(LlmService::stream_error(r#"{"error":{"code":500}}"#), Some(r#"{"code":500}"#.to_string()));
+        assert_eq!(LlmService::stream_error(r#"{"message":{"content":"the \"error\" word"},"done":false}"#), None);
+        assert_eq!(LlmService::stream_error("not json with \"error\""), None);
+    }
+
+    #[test]
+    fn composed_requests_carry_the_chat_settings() {
+        let message = |role: &str, content: &str| Message { role: role.to_string(), content: content.to_string(), ..Default::default() };
+        let history = [message("system", "Be brief."), message("user", "old"), message("assistant", "answer"), message("user", "new")];
+        let settings = AppSettings { custom_instructions: String::new(), ..Default::default() };
+        let (req, composition) = LlmService::compose_request(
+            &settings,
+            &RequestInputs { history: &history, language: Some("French"), prefill: Some("Sure"), message: "new", ..Default::default() },
+        );
+        let sent: Vec<(&str, &str)> = req.messages.iter().map(|m| (m.role.as_str(), m.content.as_str())).collect();
+        assert_eq!(
+            sent,
+            [("system", "Be brief.\n\nRespond in French unless explicitly asked otherwise."), ("user", "old"), ("assistant", "answer"), ("user", "new"), ("assistant", "Sure")]
+        );
+        assert_eq!(composition.history_messages, 2);
+    }
+
+    #[test]
+    fn first_index_seen_owns_the_answer() {
+        let mut router = ChoiceRouter::default();
+        assert_eq!(router.route(2), ChoiceRoute::Primary);
+        assert_eq!(router.route(0), ChoiceRoute::Ignored { first: true, primary: 2 });
+        assert_eq!(router.route(2), ChoiceRoute::Primary);
+        assert_eq!(router.route(0), ChoiceRoute::Ignored { first: false, primary: 2 });
+        assert_eq!(router.route(1), ChoiceRoute::Ignored { first: true, primary: 2 });
+    }
+
+    #[test]
+    fn interleaved_choices_do_not_mix() {
+        let body = [chunk(0, "Hello"), chunk(1, "Bonjour"), chunk(0, ", world"), chunk(1, " le monde"), "data: [DONE]\n\n".to_string()].concat();
+        let (content, _) = LlmService::join_sse(BackendKind::OpenAI, &body).unwrap();
+        assert_eq!(content, "Hello, world");
+    }
+
+    #[test]
+    fn choices_sharing_one_chunk_are_split() {
+        let body = concat!(
+            "data: {\"choices\":[{\"index\":1,\"delta\":{\"content\":\"B\"}},{\"index\":0,\"delta\":{\"content\":\"A\"}}]}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"a\"}},{\"index\":1,\"delta\":{\"content\":\"b\"}}]}\n\n",
+        );
+        let (content, _) = LlmService::join_sse(BackendKind::OpenAI, body).unwrap();
+        assert_eq!(content, "Bb");
+    }
+
+    #[test]
+    fn content_type_wins_over_the_stream_flag() {
+        assert!(!LlmService::is_event_stream_type("application/json; charset=utf-8", true));
+        assert!(LlmService::is_event_stream_type("text/event-stream", false));
+        assert!(LlmService::is_event_stream_type("", true));
+        assert!(!LlmService::is_event_stream_type("", false));
+    }
+
+    #[test]
+    fn json_body_while_streaming() {
+        let body = "\n{\"choices\":[{\"message\":{\"role\":\"assistant\",\"content\":\"Hi there\"}}]}";
+        let json = LlmService::single_json_body(BackendKind::OpenAI, body).unwrap();
+        assert_eq!(json.choices[0].message.text(), "Hi there");
+        // A real stream is not mistaken for one
+        assert!(LlmService::single_json_body(BackendKind::OpenAI, &chunk(0, "Hi")).is_none());
+    }
+
+    #[test]
+    fn sse_body_while_not_streaming() {
+        let body = [chunk(0, "Hel"), chunk(0, "lo"), "data: [DONE]\n\n".to_string()].concat();
+        assert!(LlmService::parse_response(BackendKind::OpenAI, &body).is_err());
+        let (content, _) = LlmService::join_sse(BackendKind::OpenAI, &body).unwrap();
+        assert_eq!(content, "Hello");
+        assert!(LlmService::join_sse(BackendKind::OpenAI, "not a stream").is_none());
+    }
+
+    #[test]
+    fn missing_index_counts_as_the_first_choice() {
+        let body = "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\ndata: {\"choices\":[{\"delta\":{\"content\":\" there\"}}]}\n\n";
+        let (content, _) = LlmService::join_sse(BackendKind::OpenAI, body).unwrap();
+        assert_eq!(content, "Hi there");
     }
-}
\ No newline at end of file
+}
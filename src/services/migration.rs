@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::models::ChatSession;
+use crate::services::storage::{LocalStorage, RawStore};
+
+/// Key the current version of the app stores chats under
+const CHATS_KEY: &str = "llm_chats_v3";
+/// Remembers a migration until the user has confirmed or reverted it
+const STATE_KEY: &str = "chat_migration_v1";
+/// Appended to a migrated key to name the copy of its original data
+const BACKUP_SUFFIX: &str = ".bak";
+
+/// A change of the stored chat format
+struct Migration {
+    /// Key chats in the old format were stored under
+    from: &'static str,
+    /// Converts one stored chat to the next format
+    upgrade: fn(Value) -> Value,
+}
+
+/// Oldest first; the last one produces the format stored under `CHATS_KEY`
+const MIGRATIONS: &[Migration] = &[Migration { from: "llm_chats_v2", upgrade: v2_to_v3 }];
+
+/// A migration whose original data is kept until the user decides
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ChatMigration {
+    pub from_key: String,
+    pub to_key: String,
+    pub backup_key: String,
+    pub chats: usize,
+    pub migrated_at: f64,
+    /// The user went back to the original data, which the app now uses as is
+    #[serde(default)]
+    pub restored: bool,
+}
+
+pub struct MigrationService;
+
+impl MigrationService {
+    /// Runs on startup, before chats are read. Moves chats stored under an older
+    /// key to the current format and keeps the original under a `.bak` key.
+    /// Returns the key to read and write chats under, and the migration to ask
+    /// the user about, if one is waiting.
+    pub fn prepare_chats(prefix: &str) -> (String, Option<ChatMigration>) {
+        Self::prepare_chats_in(&LocalStorage, prefix, js_sys::Date::now())
+    }
+
+    /// `prepare_chats` on `store`. The old key is only removed once the backup,
+    /// the migrated chats and the migration state are all written; if any of
+    /// them fails the old data stays in use as it is.
+    fn prepare_chats_in(store: &impl RawStore, prefix: &str, now: f64) -> (String, Option<ChatMigration>) {
+        let state_key = format!("{}{}", prefix, STATE_KEY);
+        let current = format!("{}{}", prefix, CHATS_KEY);
+        if let Some(state) = store.get_raw(&state_key).and_then(|raw| serde_json::from_str::<ChatMigration>(&raw).ok()) {
+            return if state.restored {
+                (state.from_key, None)
+            } else {
+                (state.to_key.clone(), Some(state))
+            };
+        }
+        if store.get_raw(&current).is_some() {
+            return (current, None);
+        }
+
+        // The newest older key that holds chats, and the migrations from there on
+        let Some(start) = MIGRATIONS.iter().rposition(|m| store.get_raw(&format!("{}{}", prefix, m.from)).is_some()) else {
+            return (current, None);
+        };
+        let from_key = format!("{}{}", prefix, MIGRATIONS[start].from);
+        let Some(raw) = store.get_raw(&from_key) else { return (current, None) };
+        // Data that isn't a list is left alone, for the recovery on load to deal with
+        let Ok(mut chats) = serde_json::from_str::<Vec<Value>>(&raw) else { return (from_key, None) };
+        for migration in &MIGRATIONS[start..] {
+            chats = chats.into_iter().map(migration.upgrade).collect();
+        }
+
+        let backup_key = format!("{}{}", from_key, BACKUP_SUFFIX);
+        let state = ChatMigration {
+            from_key: from_key.clone(),
+            to_key: current.clone(),
+            backup_key: backup_key.clone(),
+            chats: chats.len(),
+            migrated_at: now,
+            restored: false,
+        };
+        let written = store
+            .try_set_raw(&backup_key, &raw)
+            .and_then(|_| store.try_set_raw(&current, &serde_json::to_string(&chats).map_err(|e| e.to_string())?))
+            .and_then(|_| store.try_set_raw(&state_key, &serde_json::to_string(&state).map_err(|e| e.to_string())?));
+        if written.is_err() {
+            // Storage is full; try again on the next start
+            store.remove(&state_key);
+            store.remove(&current);
+            store.remove(&backup_key);
+            return (from_key, None);
+        }
+        store.remove(&from_key);
+        (current, Some(state))
+    }
+
+    /// The user is happy with the migrated chats: the original data is deleted
+    pub fn confirm(prefix: &str, migration: &ChatMigration) {
+        LocalStorage::remove(&migration.backup_key);
+        LocalStorage::remove(&format!("{}{}", prefix, STATE_KEY));
+    }
+
+    /// Puts the original data back under its old key and drops the migrated
+    /// copy. The app keeps using the old key from then on, so the data stays
+    /// exactly as it was before the migration.
+    pub fn restore(prefix: &str, migration: &ChatMigration) -> Result<(), String> {
+        Self::restore_in(&LocalStorage, prefix, migration)
+    }
+
+    /// `restore` on `store`. Nothing is removed unless the original data and the
+    /// new state were both written.
+    fn restore_in(store: &impl RawStore, prefix: &str, migration: &ChatMigration) -> Result<(), String> {
+        let raw = store.get_raw(&migration.backup_key).ok_or("The original data is no longer stored")?;
+        let state_key = format!("{}{}", prefix, STATE_KEY);
+        let state = serde_json::to_string(&ChatMigration { restored: true, ..migration.clone() }).map_err(|e| e.to_string())?;
+        let not_restored = |e: String| format!("The original chats could not be put back, nothing was changed: {}", e);
+        store.try_set_raw(&migration.from_key, &raw).map_err(not_restored)?;
+        if let Err(e) = store.try_set_raw(&state_key, &state) {
+            store.remove(&migration.from_key);
+            return Err(not_restored(e));
+        }
+        store.remove(&migration.to_key);
+        store.remove(&migration.backup_key);
+        Ok(())
+    }
+}
+
+/// v3 stores every chat field explicitly, so fields added since v2 no longer
+/// depend on defaults when read. Chats that don't parse are kept as they are.
+fn v2_to_v3(chat: Value) -> Value {
+    serde_json::from_value::<ChatSession>(chat.clone())
+        .ok()
+        .and_then(|c| serde_json::to_value(c).ok())
+        .unwrap_or(chat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::storage::MemoryStore;
+
+    const OLD: &str = "p_llm_chats_v2";
+    const NEW: &str = "p_llm_chats_v3";
+    const BACKUP: &str = "p_llm_chats_v2.bak";
+    const STATE: &str = "p_chat_migration_v1";
+    const CHATS: &str = r#"[{"id":"c1","title":"Hello","messages":[],"created_at":1.0}]"#;
+
+    fn keys(store: &MemoryStore) -> Vec<String> {
+        store.items.borrow().keys().cloned().collect()
+    }
+
+    #[test]
+    fn migration_keeps_a_backup_and_moves_the_chats() {
+        let store = MemoryStore::with(&[(OLD, CHATS)]);
+        let (key, migration) = MigrationService::prepare_chats_in(&store, "p_", 5.0);
+        assert_eq!(key, NEW);
+        assert_eq!(migration.unwrap().chats, 1);
+        assert_eq!(keys(&store), [STATE, BACKUP, NEW]);
+        assert_eq!(store.get_raw(BACKUP).unwrap(), CHATS);
+    }
+
+    #[test]
+    fn failed_writes_leave_the_old_chats_in_place() {
+        for refused in [BACKUP, NEW, STATE] {
+            let store = MemoryStore::with(&[(OLD, CHATS)]).refusing(refused);
+            let (key, migration) = MigrationService::prepare_chats_in(&store, "p_", 5.0);
+            assert_eq!(key, OLD, "{} refused", refused);
+            assert!(migration.is_none());
+            assert_eq!(keys(&store), [OLD], "{} refused", refused);
+            assert_eq!(store.get_raw(OLD).unwrap(), CHATS);
+        }
+    }
+
+    #[test]
+    fn restore_puts_the_original_back() {
+        let store = MemoryStore::with(&[(OLD, CHATS)]);
+        let (_, migration) = MigrationService::prepare_chats_in(&store, "p_", 5.0);
+        MigrationService::restore_in(&store, "p_", &migration.unwrap()).unwrap();
+        assert_eq!(keys(&store), [STATE, OLD]);
+        assert_eq!(MigrationService::prepare_chats_in(&store, "p_", 6.0), (OLD.to_string(), None));
+    }
+
+    #[test]
+    fn failed_restore_changes_nothing() {
+        for refused in [OLD, STATE] {
+            let migrated = MemoryStore::with(&[(OLD, CHATS)]);
+            let (_, migration) = MigrationService::prepare_chats_in(&migrated, "p_", 5.0);
+            let before = migrated.items.borrow().clone();
+            let store = MemoryStore { items: std::cell::RefCell::new(before.clone()), refused: vec![refused.to_string()] };
+            assert!(MigrationService::restore_in(&store, "p_", migration.as_ref().unwrap()).is_err());
+            assert_eq!(*store.items.borrow(), before, "{} refused", refused);
+        }
+    }
+}
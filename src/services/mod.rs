@@ -1,3 +1,13 @@
 pub mod llm;
+pub mod error;
+pub mod request;
+pub mod sse;
 pub mod storage;
-pub mod document_service;
\ No newline at end of file
+pub mod document_service;
+pub mod export;
+pub mod usage;
+pub mod similarity;
+pub mod host_events;
+pub mod deployment;
+pub mod migration;
+pub mod changelog;
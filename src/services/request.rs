@@ -0,0 +1,421 @@
+use crate::models::{AppSettings, BackendKind, MAX_REQUEST_RETRIES};
+use crate::services::error::LlmError;
+use futures_util::future::{select, Either, LocalBoxFuture, Shared};
+use futures_util::FutureExt;
+use gloo_timers::future::TimeoutFuture;
+use reqwest::{Client, Request, RequestBuilder, Response};
+use std::future::Future;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{console, AbortController};
+
+type Result<T> = std::result::Result<T, LlmError>;
+
+/// Wait before the first retry is twice this
+const RETRY_BASE_DELAY_MS: u32 = 500;
+
+/// Longest `Retry-After` waited for; a server asking for more gets its 429 back
+const MAX_RETRY_AFTER_MS: u32 = 60_000;
+
+/// Status of an attempt that got an answer, or why it got none
+pub type Outcome = Result<u16>;
+
+/// The model server and how requests to it are sent, taken from the settings
+/// of the app making them
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct RequestConfig {
+    pub base_url: String,
+    /// Sent as a bearer token; empty sends no Authorization header
+    pub api_key: String,
+    /// Logs every attempt to the console
+    pub debug: bool,
+    /// Decides the endpoints and body shapes of the model server
+    pub backend: BackendKind,
+    /// Retries of a request that failed for a passing reason
+    pub retries: u32,
+}
+
+impl From<&AppSettings> for RequestConfig {
+    fn from(settings: &AppSettings) -> Self {
+        Self {
+            base_url: settings.base_url.clone(),
+            api_key: settings.api_key.clone(),
+            debug: settings.debug_mode,
+            backend: settings.backend_kind,
+            retries: settings.request_retries.min(MAX_REQUEST_RETRIES),
+        }
+    }
+}
+
+/// One concern applied to every request to the model server. Each hook has a
+/// neutral default, so a layer only implements the part it is about.
+pub trait RequestLayer {
+    /// Changes the request before it is sent
+    fn prepare(&self, request: RequestBuilder) -> RequestBuilder {
+        request
+    }
+
+    /// Milliseconds to wait before sending attempt `attempt + 1`, or None to
+    /// keep the outcome of attempt `attempt` (counted from 0)
+    fn retry_delay(&self, _attempt: u32, _outcome: &Outcome) -> Option<u32> {
+        None
+    }
+
+    /// Sees the outcome of each attempt
+    fn observe(&self, _request: &str, _attempt: u32, _outcome: &Outcome) {}
+
+    /// Told when the wait for retry `retry` (counted from 1) starts
+    fn retrying(&self, _retry: u32) {}
+}
+
+pub struct AuthLayer {
+    pub api_key: String,
+}
+
+impl RequestLayer for AuthLayer {
+    fn prepare(&self, request: RequestBuilder) -> RequestBuilder {
+        if self.api_key.is_empty() {
+            request
+        } else {
+            request.bearer_auth(&self.api_key)
+        }
+    }
+}
+
+/// Sends a request again after a failure that may pass — no connection, a
+/// timeout, HTTP 429 or 503 — waiting twice as long each time. Other errors
+/// would only repeat themselves.
+pub struct RetryLayer {
+    pub retries: u32,
+    /// Wait before the first retry is twice this
+    pub base_delay_ms: u32,
+    /// Told the number of each retry before waiting for it, and how many there can be
+    pub on_retry: Option<Box<dyn Fn(u32, u32)>>,
+}
+
+impl RetryLayer {
+    /// One attempt only
+    pub fn none() -> Self {
+        Self { retries: 0, base_delay_ms: 0, on_retry: None }
+    }
+
+    /// As many retries as `config` allows
+    pub fn configured(config: &RequestConfig) -> Self {
+        Self { retries: config.retries, base_delay_ms: RETRY_BASE_DELAY_MS, on_retry: None }
+    }
+
+    pub fn on_retry(self, on_retry: impl Fn(u32, u32) + 'static) -> Self {
+        Self { on_retry: Some(Box::new(on_retry)), ..self }
+    }
+}
+
+impl RequestLayer for RetryLayer {
+    fn retry_delay(&self, attempt: u32, outcome: &Outcome) -> Option<u32> {
+        let transient = match outcome {
+            Ok(status) => matches!(status, 429 | 503),
+            Err(e) => matches!(e, LlmError::Network { .. } | LlmError::Timeout),
+        };
+        if !transient || attempt >= self.retries {
+            return None;
+        }
+        Some(self.base_delay_ms.saturating_mul(2u32.saturating_pow(attempt + 1)))
+    }
+
+    fn retrying(&self, retry: u32) {
+        if let Some(on_retry) = &self.on_retry {
+            on_retry(retry, self.retries);
+        }
+    }
+}
+
+/// Milliseconds a rate-limited answer asks to wait in its `Retry-After`
+/// header, when given in seconds
+fn retry_after_ms(resp: &Response) -> Option<u32> {
+    if resp.status().as_u16() != 429 {
+        return None;
+    }
+    let seconds: u32 = resp.headers().get("retry-after")?.to_str().ok()?.trim().parse().ok()?;
+    Some(seconds.saturating_mul(1000))
+}
+
+/// The wait before a retry: the server's `Retry-After` when it asks for longer
+/// than the back-off, None when it asks for longer than is worth waiting
+fn retry_wait(delay: u32, retry_after: Option<u32>) -> Option<u32> {
+    match retry_after {
+        Some(ms) if ms > MAX_RETRY_AFTER_MS => None,
+        Some(ms) => Some(delay.max(ms)),
+        None => Some(delay),
+    }
+}
+
+pub struct LoggingLayer {
+    pub enabled: bool,
+}
+
+impl RequestLayer for LoggingLayer {
+    fn observe(&self, request: &str, attempt: u32, outcome: &Outcome) {
+        if !self.enabled {
+            return;
+        }
+        let result = match outcome {
+            Ok(status) => format!("HTTP {}", status),
+            Err(e) => e.to_string(),
+        };
+        let retry = if attempt > 0 { format!(" (retry {})", attempt) } else { String::new() };
+        console::debug_1(&format!("{}{}: {}", request, retry, result).into());
+    }
+}
+
+/// Stops a request from outside, e.g. when the user presses Stop. Futures run
+/// through `run` are dropped on abort, which makes reqwest abort their fetch.
+#[derive(Clone)]
+pub struct AbortHandle {
+    controller: AbortController,
+    /// Resolves once the handle is aborted. Made once, so every `run` shares
+    /// one abort listener.
+    aborted: Shared<LocalBoxFuture<'static, ()>>,
+}
+
+impl AbortHandle {
+    pub fn new() -> Self {
+        let controller = AbortController::new().expect("AbortController is available in every supported browser");
+        let signal = controller.signal();
+        let promise = js_sys::Promise::new(&mut |resolve, _| {
+            let _ = signal.add_event_listener_with_callback("abort", &resolve);
+        });
+        let aborted = async move {
+            let _ = JsFuture::from(promise).await;
+        };
+        Self { controller, aborted: aborted.boxed_local().shared() }
+    }
+
+    pub fn abort(&self) {
+        self.controller.abort();
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.controller.signal().aborted()
+    }
+
+    /// The output of `future`, or `LlmError::Aborted` if the handle is aborted first
+    pub async fn run<T>(&self, future: impl Future<Output = T>) -> Result<T> {
+        if self.is_aborted() {
+            return Err(LlmError::Aborted);
+        }
+        match select(Box::pin(future), self.aborted.clone()).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right(_) => Err(LlmError::Aborted),
+        }
+    }
+}
+
+impl PartialEq for AbortHandle {
+    fn eq(&self, other: &Self) -> bool {
+        self.controller == other.controller
+    }
+}
+
+impl std::fmt::Debug for AbortHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AbortHandle").field("aborted", &self.is_aborted()).finish()
+    }
+}
+
+impl Default for AbortHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The layers a request passes through, in order
+pub struct RequestPipeline {
+    layers: Vec<Box<dyn RequestLayer>>,
+}
+
+impl RequestPipeline {
+    /// The layers for `config`, with `retry` for this kind of request
+    pub fn new(config: &RequestConfig, retry: RetryLayer) -> Self {
+        Self::with_layers(vec![
+            Box::new(AuthLayer { api_key: config.api_key.clone() }),
+            Box::new(retry),
+            Box::new(LoggingLayer { enabled: config.debug }),
+        ])
+    }
+
+    pub fn with_layers(layers: Vec<Box<dyn RequestLayer>>) -> Self {
+        Self { layers }
+    }
+
+    /// Sends the request through every layer. An answer with an unsuccessful
+    /// status is returned as it is, for the caller to read the body of.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        self.send_with(request, |request| async move { Ok(Client::new().execute(request).await?) }).await
+    }
+
+    /// `send`, with every attempt made by `execute`
+    async fn send_with<F, Fut>(&self, request: RequestBuilder, mut execute: F) -> Result<Response>
+    where
+        F: FnMut(Request) -> Fut,
+        Fut: Future<Output = Result<Response>>,
+    {
+        let mut request = self.layers.iter().fold(request, |request, layer| layer.prepare(request)).build()?;
+        let label = format!("{} {}", request.method(), request.url());
+        let mut attempt = 0;
+        loop {
+            // Bodies that can't be copied (streams) are sent once
+            let retry = request.try_clone();
+            let result = execute(request).await;
+            let outcome: Outcome = match &result {
+                Ok(resp) => Ok(resp.status().as_u16()),
+                Err(e) => Err(e.clone()),
+            };
+            for layer in &self.layers {
+                layer.observe(&label, attempt, &outcome);
+            }
+            let retry_after = result.as_ref().ok().and_then(retry_after_ms);
+            let delay = self.layers.iter().find_map(|layer| layer.retry_delay(attempt, &outcome)).and_then(|ms| retry_wait(ms, retry_after));
+            match (retry, delay) {
+                (Some(retry), Some(ms)) => {
+                    for layer in &self.layers {
+                        layer.retrying(attempt + 1);
+                    }
+                    if ms > 0 {
+                        TimeoutFuture::new(ms).await;
+                    }
+                    request = retry;
+                    attempt += 1;
+                }
+                _ => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    // Only the native tests send requests
+    #[cfg(not(target_arch = "wasm32"))]
+    use std::{cell::RefCell, rc::Rc};
+
+    fn prepared(layer: &dyn RequestLayer) -> Request {
+        layer.prepare(Client::new().post("http://localhost:8080/v1/chat/completions")).build().unwrap()
+    }
+
+    fn retry(retries: u32) -> RetryLayer {
+        RetryLayer { retries, base_delay_ms: 100, on_retry: None }
+    }
+
+    #[test]
+    fn auth_layer_sends_the_key_as_a_bearer_token() {
+        let request = prepared(&AuthLayer { api_key: "secret".into() });
+        assert_eq!(request.headers()["authorization"], "Bearer secret");
+    }
+
+    #[test]
+    fn auth_layer_without_a_key_sends_no_header() {
+        let request = prepared(&AuthLayer { api_key: String::new() });
+        assert!(request.headers().get("authorization").is_none());
+    }
+
+    #[test]
+    fn retry_layer_backs_off_on_transient_failures() {
+        let layer = retry(3);
+        assert_eq!(layer.retry_delay(0, &Err(LlmError::Timeout)), Some(200));
+        assert_eq!(layer.retry_delay(1, &Err(LlmError::Network { url: String::new() })), Some(400));
+        assert_eq!(layer.retry_delay(2, &Ok(429)), Some(800));
+        assert_eq!(layer.retry_delay(0, &Ok(503)), Some(200));
+    }
+
+    #[test]
+    fn retry_layer_keeps_lasting_failures() {
+        let layer = retry(3);
+        assert_eq!(layer.retry_delay(0, &Ok(200)), None);
+        assert_eq!(layer.retry_delay(0, &Ok(400)), None);
+        assert_eq!(layer.retry_delay(0, &Ok(500)), None);
+        assert_eq!(layer.retry_delay(0, &Err(LlmError::Auth { code: 401 })), None);
+        assert_eq!(layer.retry_delay(0, &Err(LlmError::Aborted)), None);
+    }
+
+    #[test]
+    fn retry_layer_stops_after_its_retries() {
+        assert_eq!(retry(2).retry_delay(2, &Err(LlmError::Timeout)), None);
+        assert_eq!(RetryLayer::none().retry_delay(0, &Err(LlmError::Timeout)), None);
+    }
+
+    #[test]
+    fn retries_are_capped() {
+        let settings = AppSettings { request_retries: 50, ..Default::default() };
+        assert_eq!(RequestConfig::from(&settings).retries, MAX_REQUEST_RETRIES);
+    }
+
+    #[test]
+    fn retry_after_lengthens_the_wait() {
+        assert_eq!(retry_wait(200, None), Some(200));
+        assert_eq!(retry_wait(200, Some(5_000)), Some(5_000));
+        assert_eq!(retry_wait(800, Some(0)), Some(800));
+        assert_eq!(retry_wait(200, Some(MAX_RETRY_AFTER_MS + 1)), None);
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn retry_after_is_read_from_rate_limited_answers() {
+        let answer = |status: u16, retry_after: &str| Response::from(http::Response::builder().status(status).header("retry-after", retry_after).body("").unwrap());
+        assert_eq!(retry_after_ms(&answer(429, "3")), Some(3_000));
+        assert_eq!(retry_after_ms(&answer(503, "3")), None);
+        assert_eq!(retry_after_ms(&answer(429, "Wed, 21 Oct 2026 07:28:00 GMT")), None);
+    }
+
+    /// Sends through `retry` to a server answering each attempt with the next
+    /// of `answers`; returns the final status and the number of attempts
+    #[cfg(not(target_arch = "wasm32"))]
+    fn send_to(retry: RetryLayer, answers: Vec<Outcome>) -> (Outcome, usize) {
+        let answers = RefCell::new(answers.into_iter());
+        let attempts = RefCell::new(0);
+        let pipeline = RequestPipeline::with_layers(vec![Box::new(retry)]);
+        let request = Client::new().post("http://localhost:8080/v1/chat/completions").body("{}");
+        let result = pipeline
+            .send_with(request, |_| {
+                *attempts.borrow_mut() += 1;
+                let answer = answers.borrow_mut().next().expect("no more attempts than answers");
+                async move { answer.map(|status| Response::from(http::Response::builder().status(status).body("").unwrap())) }
+            })
+            .now_or_never()
+            .expect("retries without a delay finish at once");
+        let attempts = *attempts.borrow();
+        (result.map(|resp| resp.status().as_u16()), attempts)
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn send_retries_until_an_attempt_succeeds() {
+        let answers = vec![Ok(503), Err(LlmError::Network { url: String::new() }), Ok(429), Ok(200)];
+        assert_eq!(send_to(RetryLayer { retries: 3, base_delay_ms: 0, on_retry: None }, answers), (Ok(200), 4));
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn send_reports_each_retry_it_waits_for() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let layer = {
+            let seen = seen.clone();
+            RetryLayer { retries: 2, base_delay_ms: 0, on_retry: None }.on_retry(move |n, of| seen.borrow_mut().push((n, of)))
+        };
+        assert_eq!(send_to(layer, vec![Ok(429), Ok(429), Ok(429)]), (Ok(429), 3));
+        assert_eq!(*seen.borrow(), vec![(1, 2), (2, 2)]);
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn send_returns_the_last_answer_once_retries_run_out() {
+        let answers = vec![Ok(503), Ok(503), Ok(503)];
+        assert_eq!(send_to(RetryLayer { retries: 1, base_delay_ms: 0, on_retry: None }, answers), (Ok(503), 2));
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn send_does_not_repeat_a_lasting_failure() {
+        let retry = RetryLayer { retries: 3, base_delay_ms: 0, on_retry: None };
+        assert_eq!(send_to(retry, vec![Err(LlmError::Auth { code: 401 }), Ok(200)]), (Err(LlmError::Auth { code: 401 }), 1));
+        assert_eq!(send_to(RetryLayer::none(), vec![Ok(503), Ok(200)]), (Ok(503), 1));
+    }
+}
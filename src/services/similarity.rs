@@ -0,0 +1,84 @@
+use std::collections::HashSet;
+use gloo_timers::future::TimeoutFuture;
+
+use crate::models::ChatSession;
+use crate::utils::markdown_to_plain;
+
+/// Messages compared between yields to the browser
+const MESSAGES_PER_SLICE: usize = 200;
+/// Overlap needed before two questions count as the same
+const MATCH_THRESHOLD: f64 = 0.75;
+/// Questions with fewer distinct words are too short to compare meaningfully
+const MIN_WORDS: usize = 4;
+
+/// Words that say nothing about what a question is about
+pub const STOP_WORDS: &[&str] = &[
+    "the", "and", "for", "are", "but", "not", "you", "your", "with", "this", "that",
+    "what", "how", "why", "can", "could", "would", "should", "does", "did", "from",
+    "about", "into", "there", "their", "have", "has", "was", "were", "will", "its",
+    "please", "tell", "explain", "some", "any",
+];
+
+/// An earlier user message that closely matches a new one
+#[derive(Clone, PartialEq, Debug)]
+pub struct SimilarQuestion {
+    pub chat_id: String,
+    pub chat_title: String,
+    pub message_id: String,
+    pub asked_at: f64,
+}
+
+pub struct SimilarityService;
+
+impl SimilarityService {
+    /// Best earlier user message matching `text`, if the overlap is strong enough.
+    /// Yields regularly so long histories don't block the page.
+    pub async fn find_similar(chats: Vec<ChatSession>, text: String) -> Option<SimilarQuestion> {
+        let words = Self::normalized_words(&text);
+        if words.len() < MIN_WORDS {
+            return None;
+        }
+
+        let mut best: Option<(f64, SimilarQuestion)> = None;
+        let mut processed = 0;
+        for chat in chats.iter().filter(|c| c.trashed_at.is_none()) {
+            for msg in chat.messages.iter().filter(|m| m.role == "user") {
+                let score = Self::overlap(&words, &Self::normalized_words(&msg.content));
+                if score >= MATCH_THRESHOLD && best.as_ref().is_none_or(|(s, _)| score > *s) {
+                    best = Some((score, SimilarQuestion {
+                        chat_id: chat.id.clone(),
+                        chat_title: chat.title.clone(),
+                        message_id: msg.id.clone(),
+                        asked_at: msg.created_at.unwrap_or(chat.created_at),
+                    }));
+                }
+
+                processed += 1;
+                if processed % MESSAGES_PER_SLICE == 0 {
+                    TimeoutFuture::new(0).await;
+                }
+            }
+        }
+        best.map(|(_, m)| m)
+    }
+
+    /// Distinct lowercase words of three or more letters, without stop words
+    /// and without markup such as link targets
+    fn normalized_words(text: &str) -> HashSet<String> {
+        markdown_to_plain(text)
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| w.chars().count() >= 3)
+            .map(|w| w.to_lowercase())
+            .filter(|w| !STOP_WORDS.contains(&w.as_str()))
+            .collect()
+    }
+
+    /// Shared words relative to the larger set, so a short question doesn't match
+    /// every long message that happens to contain its words
+    fn overlap(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+        if b.len() < MIN_WORDS {
+            return 0.0;
+        }
+        a.intersection(b).count() as f64 / a.len().max(b.len()) as f64
+    }
+}
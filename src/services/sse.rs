@@ -0,0 +1,187 @@
+/// One event of a server-sent event stream
+#[derive(Clone, PartialEq, Debug)]
+pub enum SseEvent {
+    /// The event's data lines, joined; `event` is its `event:` field if it had one
+    Data { event: Option<String>, data: String },
+    /// `data: [DONE]`, sent by OpenAI-style servers after the last chunk
+    Done,
+}
+
+/// Splits a streamed response body into events. Chunks may end anywhere, even
+/// inside a line or a UTF-8 character; lines may end in `\n`, `\r\n` or `\r`.
+/// Also reads newline-delimited JSON, where every line is the data of one event.
+#[derive(Default)]
+pub struct SseParser {
+    /// Lines are events by themselves instead of `data:` fields
+    ndjson: bool,
+    /// Bytes of the line not finished yet
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Option<String>,
+    /// The last chunk ended in `\r`, so a `\n` starting the next one belongs to it
+    after_cr: bool,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ndjson() -> Self {
+        Self { ndjson: true, ..Self::default() }
+    }
+
+    /// The events `chunk` completes
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        let mut chunk = chunk;
+        if std::mem::take(&mut self.after_cr) {
+            chunk = chunk.strip_prefix(b"\n").unwrap_or(chunk);
+        }
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        let mut start = 0;
+        let mut i = 0;
+        while i < self.buffer.len() {
+            let next = match self.buffer[i] {
+                b'\n' => i + 1,
+                b'\r' if self.buffer.get(i + 1) == Some(&b'\n') => i + 2,
+                b'\r' => {
+                    self.after_cr = i + 1 == self.buffer.len();
+                    i + 1
+                }
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            };
+            let line = String::from_utf8_lossy(&self.buffer[start..i]).into_owned();
+            self.read_line(&line, &mut events);
+            start = next;
+            i = next;
+        }
+        self.buffer.drain(..start);
+        events
+    }
+
+    /// The event the stream ended in the middle of, if any
+    pub fn finish(&mut self) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        if !self.buffer.is_empty() {
+            let line = String::from_utf8_lossy(&std::mem::take(&mut self.buffer)).into_owned();
+            self.read_line(&line, &mut events);
+        }
+        events.extend(self.dispatch());
+        events
+    }
+
+    fn read_line(&mut self, line: &str, events: &mut Vec<SseEvent>) {
+        if self.ndjson {
+            if !line.trim().is_empty() {
+                events.push(SseEvent::Data { event: None, data: line.to_string() });
+            }
+            return;
+        }
+        if line.is_empty() {
+            events.extend(self.dispatch());
+            return;
+        }
+        // Comments, e.g. `: ping` keep-alives
+        if line.starts_with(':') {
+            return;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => match &mut self.data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => self.data = Some(value.to_string()),
+            },
+            "event" => self.event = Some(value.to_string()),
+            // `id` and `retry` only matter for reconnecting
+            _ => {}
+        }
+    }
+
+    /// The event collected since the last blank line
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        let data = self.data.take()?;
+        if data.trim() == "[DONE]" {
+            return Some(SseEvent::Done);
+        }
+        Some(SseEvent::Data { event, data })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(mut parser: SseParser, chunks: &[&[u8]]) -> Vec<SseEvent> {
+        let mut events: Vec<SseEvent> = chunks.iter().flat_map(|chunk| parser.push(chunk)).collect();
+        events.extend(parser.finish());
+        events
+    }
+
+    fn data(data: &str) -> SseEvent {
+        SseEvent::Data { event: None, data: data.to_string() }
+    }
+
+    #[test]
+    fn split_mid_token() {
+        let events = parse(SseParser::new(), &[b"data: {\"content\":\"Hel", b"lo\"}\n\ndata: [DO", b"NE]\n\n"]);
+        assert_eq!(events, vec![data("{\"content\":\"Hello\"}"), SseEvent::Done]);
+    }
+
+    #[test]
+    fn split_inside_a_character() {
+        let text = "data: grüß\n\n".as_bytes();
+        let at = text.iter().position(|b| *b >= 0x80).unwrap() + 1;
+        assert_eq!(parse(SseParser::new(), &[&text[..at], &text[at..]]), vec![data("grüß")]);
+    }
+
+    #[test]
+    fn split_mid_line() {
+        let events = parse(SseParser::new(), &[b"da", b"ta: one\nda", b"ta: two\n", b"\nevent: end\ndata:", b" x\n\n"]);
+        assert_eq!(events, vec![data("one\ntwo"), SseEvent::Data { event: Some("end".into()), data: "x".into() }]);
+    }
+
+    #[test]
+    fn crlf_lines() {
+        let events = parse(SseParser::new(), &[b"data: a\r\n\r\n: ping\r\ndata: b\r\n\r\n"]);
+        assert_eq!(events, vec![data("a"), data("b")]);
+    }
+
+    #[test]
+    fn crlf_split_between_cr_and_lf() {
+        let events = parse(SseParser::new(), &[b"data: a\r", b"\n\r", b"\ndata: b\r\r"]);
+        assert_eq!(events, vec![data("a"), data("b")]);
+    }
+
+    #[test]
+    fn every_split_gives_the_same_events() {
+        let stream = "event: delta\r\ndata: {\"t\":\"é\"}\r\n\r\n: keep-alive\ndata: 1\ndata: 2\n\rdata: [DONE]\n\n".as_bytes();
+        let whole = parse(SseParser::new(), &[stream]);
+        assert_eq!(whole.len(), 3);
+        for at in 0..=stream.len() {
+            assert_eq!(parse(SseParser::new(), &[&stream[..at], &stream[at..]]), whole, "split at {}", at);
+        }
+    }
+
+    #[test]
+    fn unterminated_event_is_read_on_finish() {
+        assert_eq!(parse(SseParser::new(), &[b"data: last"]), vec![data("last")]);
+    }
+
+    #[test]
+    fn ndjson_lines_split_across_chunks() {
+        let events = parse(SseParser::ndjson(), &[b"{\"a\":", b"1}\r\n\n{\"a\"", b":2}"]);
+        assert_eq!(events, vec![data("{\"a\":1}"), data("{\"a\":2}")]);
+    }
+}
@@ -3,7 +3,171 @@ use web_sys::window;
 
 pub struct LocalStorage;
 
+/// A stored value that no longer parsed and was repaired on load.
+/// The original raw value is kept under `backup_key`; when that copy could not
+/// be written the value is left in place and `raw` is the only other copy.
+#[derive(Clone, PartialEq, Debug)]
+pub struct StorageRecovery {
+    pub key: String,
+    pub label: String,
+    pub backup_key: Option<String>,
+    pub raw: String,
+    pub recovered: usize,
+    pub total: usize,
+    pub backed_up_at: f64,
+}
+
+/// Raw string storage, so code that must not lose data on a failed write can
+/// be tested against storage that fails
+pub trait RawStore {
+    fn get_raw(&self, key: &str) -> Option<String>;
+    fn try_set_raw(&self, key: &str, value: &str) -> Result<(), String>;
+    fn remove(&self, key: &str);
+}
+
+impl RawStore for LocalStorage {
+    fn get_raw(&self, key: &str) -> Option<String> {
+        LocalStorage::get_raw(key)
+    }
+
+    fn try_set_raw(&self, key: &str, value: &str) -> Result<(), String> {
+        LocalStorage::try_set_raw(key, value)
+    }
+
+    fn remove(&self, key: &str) {
+        LocalStorage::remove(key)
+    }
+}
+
+/// In-memory storage for tests; writes to the `refused` keys fail as if storage were full
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStore {
+    pub items: std::cell::RefCell<std::collections::BTreeMap<String, String>>,
+    pub refused: Vec<String>,
+}
+
+#[cfg(test)]
+impl MemoryStore {
+    pub fn with(items: &[(&str, &str)]) -> Self {
+        let items = items.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Self { items: std::cell::RefCell::new(items), refused: Vec::new() }
+    }
+
+    pub fn refusing(self, key: &str) -> Self {
+        let mut refused = self.refused;
+        refused.push(key.to_string());
+        Self { refused, ..self }
+    }
+}
+
+#[cfg(test)]
+impl RawStore for MemoryStore {
+    fn get_raw(&self, key: &str) -> Option<String> {
+        self.items.borrow().get(key).cloned()
+    }
+
+    fn try_set_raw(&self, key: &str, value: &str) -> Result<(), String> {
+        if self.refused.iter().any(|k| k == key) {
+            return Err("QuotaExceededError".to_string());
+        }
+        self.items.borrow_mut().insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) {
+        self.items.borrow_mut().remove(key);
+    }
+}
+
 impl LocalStorage {
+    pub fn get_raw(key: &str) -> Option<String> {
+        let window = window()?;
+        let storage = window.local_storage().ok()??;
+        storage.get_item(key).ok()?
+    }
+
+    /// Stores `value` as it is, reporting a value the browser refused to store
+    pub fn try_set_raw(key: &str, value: &str) -> Result<(), String> {
+        let storage = window()
+            .and_then(|w| w.local_storage().ok().flatten())
+            .ok_or("Local storage is not available")?;
+        storage.set_item(key, value).map_err(|e| e.as_string().unwrap_or_else(|| format!("{:?}", e)))
+    }
+
+    /// Copies an unreadable value aside before anything can overwrite it
+    fn backup_corrupt(key: &str, raw: &str, at: f64) -> Result<String, String> {
+        let backup_key = format!("{}_corrupt_{}", key, at as u64);
+        Self::try_set_raw(&backup_key, raw)?;
+        Ok(backup_key)
+    }
+
+    /// Like `get_vec`, but when the list fails to parse the raw value is backed up,
+    /// every element that still parses is kept and the repaired list is written back.
+    /// Without a backup the stored value is left as it was.
+    pub fn get_vec_recovering<T: for<'de> Deserialize<'de> + Serialize>(key: &str, label: &str) -> (Vec<T>, Option<StorageRecovery>) {
+        let raw = match Self::get_raw(key) {
+            Some(raw) => raw,
+            None => return (Vec::new(), None),
+        };
+        if let Ok(list) = serde_json::from_str::<Vec<T>>(&raw) {
+            return (list, None);
+        }
+
+        let backed_up_at = js_sys::Date::now();
+        let backup_key = Self::backup_corrupt(key, &raw, backed_up_at).ok();
+        let elements: Vec<serde_json::Value> = serde_json::from_str(&raw).unwrap_or_default();
+        let total = elements.len();
+        let list: Vec<T> = elements
+            .into_iter()
+            .filter_map(|e| serde_json::from_value(e).ok())
+            .collect();
+        if backup_key.is_some() {
+            Self::set(key, &list);
+        }
+        let report = StorageRecovery { key: key.to_string(), label: label.to_string(), backup_key, raw, backed_up_at, recovered: list.len(), total };
+        (list, Some(report))
+    }
+
+    /// Like `get`, but when the value fails to parse the raw value is backed up and
+    /// every field that still parses is applied on top of the defaults.
+    /// Without a backup the stored value is left as it was.
+    pub fn get_recovering<T: for<'de> Deserialize<'de> + Serialize + Default>(key: &str, label: &str) -> (Option<T>, Option<StorageRecovery>) {
+        let raw = match Self::get_raw(key) {
+            Some(raw) => raw,
+            None => return (None, None),
+        };
+        if let Ok(value) = serde_json::from_str::<T>(&raw) {
+            return (Some(value), None);
+        }
+
+        let backed_up_at = js_sys::Date::now();
+        let backup_key = Self::backup_corrupt(key, &raw, backed_up_at).ok();
+        let stored = match serde_json::from_str::<serde_json::Value>(&raw) {
+            Ok(serde_json::Value::Object(map)) => map,
+            _ => serde_json::Map::new(),
+        };
+        let total = stored.len();
+        let mut recovered = 0;
+        let mut merged = serde_json::to_value(T::default()).unwrap_or(serde_json::Value::Null);
+        for (field, value) in stored {
+            let mut candidate = merged.clone();
+            if let Some(obj) = candidate.as_object_mut() {
+                obj.insert(field, value);
+            }
+            if serde_json::from_value::<T>(candidate.clone()).is_ok() {
+                merged = candidate;
+                recovered += 1;
+            }
+        }
+        let value: T = serde_json::from_value(merged).unwrap_or_default();
+        if backup_key.is_some() {
+            Self::set(key, &value);
+        }
+        let report = StorageRecovery { key: key.to_string(), label: label.to_string(), backup_key, raw, backed_up_at, recovered, total };
+        (Some(value), Some(report))
+    }
+
     pub fn get<T: for<'de> Deserialize<'de>>(key: &str) -> Option<T> {
         let window = window()?;
         let storage = window.local_storage().ok()??;
@@ -11,6 +175,13 @@ impl LocalStorage {
         serde_json::from_str(&json).ok()
     }
 
+    /// Like `get`, but a value that is stored and unreadable is an error
+    pub fn try_get<T: for<'de> Deserialize<'de>>(key: &str) -> Result<Option<T>, String> {
+        Self::get_raw(key)
+            .map(|json| serde_json::from_str(&json).map_err(|e| format!("The data stored under '{}' is unreadable: {}", key, e)))
+            .transpose()
+    }
+
     pub fn set<T: Serialize + ?Sized>(key: &str, value: &T) {
         if let Some(window) = window() {
             if let Ok(Some(storage)) = window.local_storage() {
@@ -21,6 +192,31 @@ impl LocalStorage {
         }
     }
 
+    /// Like `set`, but reports a value the browser refused to store, which
+    /// almost always means storage is full
+    pub fn try_set<T: Serialize + ?Sized>(key: &str, value: &T) -> Result<(), String> {
+        let json = serde_json::to_string(value).map_err(|e| e.to_string())?;
+        Self::try_set_raw(key, &json)
+    }
+
+    /// Size in bytes of every stored entry (key plus value), largest first
+    pub fn entry_sizes() -> Vec<(String, usize)> {
+        let Some(storage) = window().and_then(|w| w.local_storage().ok().flatten()) else {
+            return Vec::new();
+        };
+        let count = storage.length().unwrap_or(0);
+        let mut sizes: Vec<(String, usize)> = (0..count)
+            .filter_map(|i| storage.key(i).ok().flatten())
+            .map(|key| {
+                let value = storage.get_item(&key).ok().flatten().unwrap_or_default();
+                let size = key.len() + value.len();
+                (key, size)
+            })
+            .collect();
+        sizes.sort_by_key(|e| std::cmp::Reverse(e.1));
+        sizes
+    }
+
     pub fn remove(key: &str) {
         if let Some(window) = window() {
             if let Ok(Some(storage)) = window.local_storage() {
@@ -29,25 +225,94 @@ impl LocalStorage {
         }
     }
 
-    pub fn get_vec<T: for<'de> Deserialize<'de> + Default + serde::Serialize>(key: &str) -> Vec<T> {
-        LocalStorage::get(key).unwrap_or_default()
+    /// The list stored under `key`; empty when it is missing, unreadable or
+    /// storage isn't available
+    pub fn get_vec<T: for<'de> Deserialize<'de>>(key: &str) -> Vec<T> {
+        Self::try_get_vec(key).unwrap_or_default()
+    }
+
+    /// Like `get_vec`, but a list that is stored and unreadable is an error, so
+    /// it isn't mistaken for an empty one and overwritten
+    pub fn try_get_vec<T: for<'de> Deserialize<'de>>(key: &str) -> Result<Vec<T>, String> {
+        Self::vec_from_raw(key, Self::get_raw(key).as_deref())
+    }
+
+    /// The list in a raw stored value; empty when there is none
+    fn vec_from_raw<T: for<'de> Deserialize<'de>>(key: &str, raw: Option<&str>) -> Result<Vec<T>, String> {
+        match raw {
+            None => Ok(Vec::new()),
+            Some(json) => serde_json::from_str(json).map_err(|e| format!("The data stored under '{}' is unreadable: {}", key, e)),
+        }
+    }
+
+    /// Adds an item to the end of the list stored under `key`
+    pub fn append<T: Serialize + for<'de> Deserialize<'de>>(key: &str, item: T) -> Result<(), String> {
+        Self::append_all(key, std::iter::once(item))
+    }
+
+    /// Adds items to the end of the list stored under `key`. Fails, leaving the
+    /// list as it was, when the stored list is unreadable or the browser
+    /// refuses the larger list.
+    pub fn append_all<T: Serialize + for<'de> Deserialize<'de>>(key: &str, items: impl IntoIterator<Item = T>) -> Result<(), String> {
+        Self::append_all_in(&LocalStorage, key, items)
     }
 
-    pub fn set_vec<T: Serialize>(key: &str, value: &[T]) {
-        LocalStorage::set(key, value);
+    fn append_all_in<T: Serialize + for<'de> Deserialize<'de>>(store: &impl RawStore, key: &str, items: impl IntoIterator<Item = T>) -> Result<(), String> {
+        let mut vec: Vec<T> = Self::vec_from_raw(key, store.get_raw(key).as_deref())?;
+        vec.extend(items);
+        let json = serde_json::to_string(&vec).map_err(|e| e.to_string())?;
+        store.try_set_raw(key, &json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(raw: Option<&str>) -> Result<Vec<String>, String> {
+        LocalStorage::vec_from_raw("list", raw)
+    }
+
+    #[test]
+    fn missing_list_reads_as_empty() {
+        assert_eq!(read(None), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn stored_list_is_read() {
+        assert_eq!(read(Some("[\"a\", \"b\"]")), Ok(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(read(Some("[]")), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn unreadable_list_is_an_error_not_an_empty_list() {
+        for raw in ["[\"a\", \"b", "{\"a\": 1}", "[1, 2]", ""] {
+            assert!(read(Some(raw)).is_err(), "{:?} read as a list", raw);
+        }
+    }
+
+    #[test]
+    fn append_extends_the_stored_list() {
+        let store = MemoryStore::with(&[("list", "[\"a\"]")]);
+        LocalStorage::append_all_in(&store, "list", ["b".to_string()]).unwrap();
+        assert_eq!(store.get_raw("list").unwrap(), "[\"a\",\"b\"]");
+
+        let store = MemoryStore::default();
+        LocalStorage::append_all_in(&store, "list", ["a".to_string()]).unwrap();
+        assert_eq!(store.get_raw("list").unwrap(), "[\"a\"]");
     }
 
-    pub fn push_vec<T: Serialize + Clone + for<'de> Deserialize<'de> + Default>(key: &str, item: &T) -> Vec<T> {
-        let mut vec: Vec<T> = LocalStorage::get_vec(key);
-        vec.push(item.clone());
-        LocalStorage::set(key, &vec);
-        vec
+    #[test]
+    fn append_leaves_an_unreadable_list_alone() {
+        let store = MemoryStore::with(&[("list", "[\"a\", \"b")]);
+        assert!(LocalStorage::append_all_in(&store, "list", ["c".to_string()]).is_err());
+        assert_eq!(store.get_raw("list").unwrap(), "[\"a\", \"b");
     }
 
-    pub fn remove_from_vec<T: PartialEq + Serialize + Clone + for<'de> Deserialize<'de> + Default>(key: &str, item: &T) -> Vec<T> {
-        let mut vec: Vec<T> = LocalStorage::get_vec(key);
-        vec.retain(|x| x != item);
-        LocalStorage::set(key, &vec);
-        vec
+    #[test]
+    fn append_leaves_the_list_when_storage_is_full() {
+        let store = MemoryStore::with(&[("list", "[\"a\"]")]).refusing("list");
+        assert!(LocalStorage::append_all_in(&store, "list", ["b".to_string()]).is_err());
+        assert_eq!(store.get_raw("list").unwrap(), "[\"a\"]");
     }
 }
@@ -0,0 +1,117 @@
+use std::collections::{BTreeMap, HashMap};
+use gloo_timers::future::TimeoutFuture;
+
+use crate::models::ChatSession;
+use crate::utils::{count_words, format_count, format_date, markdown_to_plain};
+
+/// Messages processed between yields to the browser
+const MESSAGES_PER_SLICE: usize = 200;
+/// Days shown in the heatmap, ending today
+pub const HEATMAP_DAYS: usize = 16 * 7;
+
+const DAY_MS: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+/// Usage statistics computed from the stored chats
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct UsageSummary {
+    pub chats: usize,
+    pub messages: usize,
+    /// Messages per local day, keyed by "YYYY-MM-DD"
+    pub messages_per_day: BTreeMap<String, usize>,
+    pub tokens_generated: usize,
+    pub words_generated: usize,
+    /// Assistant replies per model, most used first
+    pub models: Vec<(String, usize)>,
+    /// Replies saved before the model was recorded
+    pub replies_without_model: usize,
+    /// Responses that came back without any text
+    pub empty_responses: usize,
+    pub average_chat_length: f64,
+}
+
+pub struct UsageService;
+
+impl UsageService {
+    /// Walk every message of every chat, yielding regularly so large histories
+    /// don't freeze the page
+    pub async fn summarize(chats: Vec<ChatSession>) -> UsageSummary {
+        let mut summary = UsageSummary::default();
+        let mut models: HashMap<String, usize> = HashMap::new();
+        let mut chats_with_messages = 0;
+        let mut processed = 0;
+
+        for chat in &chats {
+            summary.chats += 1;
+            summary.empty_responses += chat.empty_responses;
+            let mut chat_messages = 0;
+            for msg in chat.messages.iter().filter(|m| m.role != "system") {
+                chat_messages += 1;
+                let at = msg.created_at.unwrap_or(chat.created_at);
+                *summary.messages_per_day.entry(Self::day_key(at)).or_insert(0) += 1;
+                if msg.role == "assistant" {
+                    summary.tokens_generated += msg.tokens();
+                    summary.words_generated += count_words(&markdown_to_plain(&msg.content));
+                    match &msg.model {
+                        Some(model) => *models.entry(model.clone()).or_insert(0) += 1,
+                        None => summary.replies_without_model += 1,
+                    }
+                }
+
+                processed += 1;
+                if processed % MESSAGES_PER_SLICE == 0 {
+                    TimeoutFuture::new(0).await;
+                }
+            }
+            if chat_messages > 0 {
+                chats_with_messages += 1;
+            }
+            summary.messages += chat_messages;
+        }
+
+        if chats_with_messages > 0 {
+            summary.average_chat_length = summary.messages as f64 / chats_with_messages as f64;
+        }
+        summary.models = models.into_iter().collect();
+        summary.models.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        summary
+    }
+
+    /// Local calendar day of a timestamp, e.g. "2024-03-05"
+    pub fn day_key(ms: f64) -> String {
+        let date = js_sys::Date::new(&ms.into());
+        format!("{:04}-{:02}-{:02}", date.get_full_year(), date.get_month() + 1, date.get_date())
+    }
+
+    /// Timestamps (local noon) of the heatmap days, oldest first. The first day is
+    /// a Sunday so every column of the heatmap is one week.
+    pub fn heatmap_days() -> Vec<f64> {
+        let today = js_sys::Date::new_0();
+        today.set_hours(12);
+        let end = today.get_time();
+        let weekday = today.get_day() as usize;
+        let count = HEATMAP_DAYS - 6 + weekday;
+        (0..count).map(|i| end - (count - 1 - i) as f64 * DAY_MS).collect()
+    }
+
+    /// Shareable Markdown version of the summary
+    pub fn to_markdown(summary: &UsageSummary) -> String {
+        let mut out = String::from("## Chat usage\n\n");
+        out.push_str(&format!("- Chats: {}\n", format_count(summary.chats)));
+        out.push_str(&format!("- Messages: {}\n", format_count(summary.messages)));
+        out.push_str(&format!("- Tokens generated: {}\n", format_count(summary.tokens_generated)));
+        out.push_str(&format!("- Words generated: {}\n", format_count(summary.words_generated)));
+        out.push_str(&format!("- Average chat length: {:.1} messages\n", summary.average_chat_length));
+        if summary.empty_responses > 0 {
+            out.push_str(&format!("- Empty responses: {}\n", format_count(summary.empty_responses)));
+        }
+        if let Some((day, count)) = summary.messages_per_day.iter().max_by_key(|(_, n)| **n) {
+            out.push_str(&format!("- Busiest day: {} ({} messages)\n", day, count));
+        }
+        if !summary.models.is_empty() {
+            let models: Vec<String> = summary.models.iter().take(5).map(|(m, n)| format!("{} ({})", m, n)).collect();
+            out.push_str(&format!("- Most used models: {}\n", models.join(", ")));
+        }
+        out.push_str(&format!("\n_As of {}_\n", format_date(js_sys::Date::now())));
+        out
+    }
+}
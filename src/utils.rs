@@ -1,6 +1,13 @@
-use pulldown_cmark::{Parser, Options, html, Event as MdEvent};
+use pulldown_cmark::{Parser, Options, html, Event as MdEvent, Tag};
 use yew::{Html, AttrValue};
-use std::panic;
+use std::cell::{OnceCell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use tiktoken_rs::{cl100k_base, CoreBPE};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::JsValue;
+
+use crate::models::{new_message_id, ContextStrategy, Message, THINK_CLOSE, THINK_OPEN};
 
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
@@ -13,7 +20,677 @@ pub fn set_panic_hook() {
     console_error_panic_hook::set_once();
 }
 
+/// Index of the first message that is still sent to the model when only the
+/// last `window` user/assistant exchanges are kept. System messages before
+/// that index are always sent regardless.
+pub fn history_window_start(messages: &[Message], window: Option<usize>) -> usize {
+    let first_non_system = messages.iter().position(|m| m.role != "system").unwrap_or(messages.len());
+    let n = match window {
+        Some(n) => n,
+        None => return first_non_system,
+    };
+    if n == 0 {
+        return messages.len();
+    }
+
+    let mut seen = 0;
+    for (i, msg) in messages.iter().enumerate().rev() {
+        if msg.role == "user" {
+            seen += 1;
+            if seen == n {
+                return i.max(first_non_system);
+            }
+        }
+    }
+    first_non_system
+}
+
+thread_local! {
+    // Building the BPE tables is expensive, so it happens once per page load
+    static TOKENIZER: OnceCell<Option<CoreBPE>> = const { OnceCell::new() };
+    // Components whose styles are already in the document head
+    static INJECTED_STYLES: RefCell<HashSet<&'static str>> = RefCell::new(HashSet::new());
+    // Plain text of recently converted markdown, by the markdown itself
+    static PLAIN_TEXT: RefCell<HashMap<Box<str>, Rc<str>>> = RefCell::new(HashMap::new());
+}
+
+/// Entries the plain text cache holds before it starts over
+const PLAIN_TEXT_CACHE_SIZE: usize = 2000;
+
+/// Class of the element wrapping the whole app. Every class the app uses is
+/// prefixed with "swi-", and every rule is scoped to this element, so the app
+/// can share a page with other styles.
+pub const ROOT_CLASS: &str = "swi-root";
+
+/// Adds a component's CSS to the document head the first time it renders,
+/// instead of once per rendered instance
+pub fn inject_styles(name: &'static str, css: &str) {
+    if !INJECTED_STYLES.with(|s| s.borrow_mut().insert(name)) {
+        return;
+    }
+    let Some(document) = web_sys::window().and_then(|w| w.document()) else { return };
+    let (Some(head), Ok(style)) = (document.head(), document.create_element("style")) else { return };
+    let _ = style.set_attribute("data-swi-styles", name);
+    style.set_text_content(Some(&scope_css(css)));
+    let _ = head.append_child(&style);
+}
+
+/// Prefixes every selector with the root class, and puts `:root` rules on the
+/// root element itself. Rules inside `@media` are scoped too; other at-rules
+/// like `@keyframes` are kept as they are.
+fn scope_css(css: &str) -> String {
+    let mut css = css.to_string();
+    while let Some(start) = css.find("/*") {
+        let end = css[start..].find("*/").map(|e| start + e + 2).unwrap_or(css.len());
+        css.replace_range(start..end, "");
+    }
+
+    let mut out = String::new();
+    let mut rest = css.as_str();
+    while let Some(open) = rest.find('{') {
+        let selector = rest[..open].trim();
+        let mut depth = 0;
+        let close = rest[open..]
+            .char_indices()
+            .find(|(_, c)| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })
+            .map(|(i, _)| open + i)
+            .unwrap_or(rest.len() - 1);
+        let block = &rest[open + 1..close];
+        if selector.starts_with("@media") || selector.starts_with("@supports") {
+            out.push_str(&format!("{} {{\n{}}}\n", selector, scope_css(block)));
+        } else if selector.starts_with('@') {
+            out.push_str(&format!("{} {{{}}}\n", selector, block));
+        } else {
+            let root = format!(".{}", ROOT_CLASS);
+            let scoped: Vec<String> = selector
+                .split(',')
+                .map(str::trim)
+                .map(|s| match s.strip_prefix(":root") {
+                    Some(rest) => format!("{}{}", root, rest),
+                    None if s.starts_with(&root) => s.to_string(),
+                    None => format!("{} {}", root, s),
+                })
+                .collect();
+            out.push_str(&format!("{} {{{}}}\n", scoped.join(", "), block));
+        }
+        rest = &rest[close + 1..];
+    }
+    out
+}
+
+/// The messages of `history` to send within `budget` tokens. System messages
+/// and the last message are always kept; the strategy decides which of the
+/// others go first, oldest before newest. Summarize leaves out what
+/// SlidingWindow does, for the caller to summarize.
+pub fn build_request_messages(history: &[Message], strategy: ContextStrategy, budget: usize) -> Vec<Message> {
+    let mut kept = history.to_vec();
+    let mut total: usize = kept.iter().map(Message::tokens).sum();
+    if strategy == ContextStrategy::SendAll {
+        return kept;
+    }
+    while total > budget {
+        let last = kept.len().saturating_sub(1);
+        let Some(oldest) = kept[..last].iter().position(|m| m.role != "system") else { break };
+        // An exchange runs up to the next question
+        let end = match strategy {
+            ContextStrategy::SlidingWindow | ContextStrategy::Summarize => kept[oldest + 1..last]
+                .iter()
+                .position(|m| m.role == "user")
+                .map_or(last, |i| oldest + 1 + i),
+            _ => oldest + 1,
+        };
+        let mut index = 0;
+        kept.retain(|m| {
+            let dropped = (oldest..end).contains(&index) && m.role != "system";
+            index += 1;
+            if dropped {
+                total -= m.tokens();
+            }
+            !dropped
+        });
+    }
+    kept
+}
+
+/// Index of the first message after the one with id `until`, the last a chat
+/// summary covers; None when that message is gone
+pub fn summary_end(history: &[Message], until: &str) -> Option<usize> {
+    history.iter().position(|m| m.id == until).map(|i| i + 1)
+}
+
+/// `history` with the messages up to `until` replaced by `summary`, after the
+/// system messages. A summary whose last message is gone is left out.
+pub fn with_summary(history: &[Message], summary: &str, until: &str) -> Vec<Message> {
+    let Some(end) = summary_end(history, until) else { return history.to_vec() };
+    let mut messages: Vec<Message> = history[..end].iter().filter(|m| m.role == "system").cloned().collect();
+    messages.push(Message {
+        id: new_message_id(),
+        role: "system".to_string(),
+        content: format!("Summary of earlier conversation: {}", summary),
+        ..Default::default()
+    });
+    messages.extend(history[end..].iter().cloned());
+    messages
+}
+
+/// Count tokens with the cl100k_base tokenizer, falling back to a word count
+pub fn count_tokens(text: &str) -> usize {
+    TOKENIZER.with(|cell| match cell.get_or_init(|| cl100k_base().ok()) {
+        Some(tokenizer) => tokenizer.encode(text, HashSet::new()).len(),
+        None => text.split_whitespace().count(),
+    })
+}
+
+/// Sizes of a text in the units models and people count in
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct TextCounts {
+    pub tokens: usize,
+    pub words: usize,
+    pub chars: usize,
+}
+
+impl TextCounts {
+    pub fn of(text: &str) -> Self {
+        Self { tokens: count_tokens(text), words: count_words(text), chars: count_chars(text) }
+    }
+}
+
+/// Words separated by whitespace, except that each CJK character counts as a
+/// word of its own since those scripts don't put spaces between words.
+/// Runs of punctuation alone are not words.
+pub fn count_words(text: &str) -> usize {
+    let mut words = 0;
+    for part in text.split_whitespace() {
+        let mut in_word = false;
+        for c in part.chars() {
+            if is_cjk(c) {
+                words += 1;
+                in_word = false;
+            } else if c.is_alphanumeric() && !in_word {
+                words += 1;
+                in_word = true;
+            }
+        }
+    }
+    words
+}
+
+/// Characters as a reader sees them: combining marks, joiners and variation
+/// selectors belong to the character before them, as does whatever follows a
+/// zero-width joiner, and two regional indicators make one flag
+pub fn count_chars(text: &str) -> usize {
+    let mut count = 0;
+    let mut joined = false;
+    let mut open_flag = false;
+    for c in text.chars() {
+        let code = c as u32;
+        let extends = matches!(code,
+            0x0300..=0x036F | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x20D0..=0x20FF
+            | 0xFE20..=0xFE2F | 0x200D | 0xFE00..=0xFE0F | 0x1F3FB..=0x1F3FF | 0xE0020..=0xE007F | 0xE0100..=0xE01EF);
+        let regional = (0x1F1E6..=0x1F1FF).contains(&code);
+        if !(extends || joined || regional && open_flag) {
+            count += 1;
+        }
+        open_flag = regional && !open_flag;
+        joined = code == 0x200D;
+    }
+    count
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF | 0x31F0..=0x31FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF
+        | 0xF900..=0xFAFF | 0xFF66..=0xFF9F | 0x20000..=0x2FA1F)
+}
+
+/// Formats a count with thousands separators, e.g. 12000 -> "12,000"
+pub fn format_count(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Short form of a count for tight spaces, e.g. "812", "3.2k", "128k", "1.5M".
+/// Rounded before the unit is picked, so 999,999 is "1M", not "1000k".
+pub fn format_compact(n: usize) -> String {
+    // None when the value rounds up into the next unit
+    let short = |value: f64, unit: &str| {
+        let tenths = (value * 10.0).round() / 10.0;
+        if tenths < 10.0 {
+            Some(if tenths.fract() == 0.0 { format!("{:.0}{}", tenths, unit) } else { format!("{:.1}{}", tenths, unit) })
+        } else {
+            (value.round() < 1000.0).then(|| format!("{:.0}{}", value.round(), unit))
+        }
+    };
+    if n < 1000 {
+        return n.to_string();
+    }
+    let millions = n as f64 / 1_000_000.0;
+    short(n as f64 / 1000.0, "k").or_else(|| short(millions, "M")).unwrap_or_else(|| format!("{:.0}M", millions))
+}
+
+/// Moves the item at `from` so it ends up at index `to`; out-of-range indices are ignored
+pub fn move_item<T>(list: &mut Vec<T>, from: usize, to: usize) {
+    if from >= list.len() || to >= list.len() || from == to {
+        return;
+    }
+    let item = list.remove(from);
+    list.insert(to, item);
+}
+
+/// Formats a size in bytes, e.g. 1_900_000 -> "1.8 MB"
+pub fn format_bytes(bytes: usize) -> String {
+    const KB: f64 = 1024.0;
+    let b = bytes as f64;
+    if b >= KB * KB {
+        format!("{:.1} MB", b / (KB * KB))
+    } else if b >= KB {
+        format!("{:.0} KB", b / KB)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+const MINUTE_MS: f64 = 60_000.0;
+const HOUR_MS: f64 = 60.0 * MINUTE_MS;
+const DAY_MS: f64 = 24.0 * HOUR_MS;
+
+/// How far local time is ahead of UTC at a timestamp, in ms. Outside the
+/// browser (tests) local time is UTC.
+#[cfg(target_arch = "wasm32")]
+fn local_offset_ms(ms: f64) -> f64 {
+    -js_sys::Date::new(&ms.into()).get_timezone_offset() * MINUTE_MS
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn local_offset_ms(_ms: f64) -> f64 {
+    0.0
+}
+
+/// Local calendar day of a timestamp, counted from 1970-01-01
+fn local_day(ms: f64) -> i64 {
+    ((ms + local_offset_ms(ms)) / DAY_MS).floor() as i64
+}
+
+/// Calendar days between the local dates of two timestamps (0 = same day)
+fn days_between(earlier: f64, later: f64) -> i64 {
+    local_day(later) - local_day(earlier)
+}
+
+/// Local (year, month, day, hour, minute) of a timestamp
+fn local_parts(ms: f64) -> (i64, u32, u32, u32, u32) {
+    // Days to civil date, after Howard Hinnant's algorithm
+    let z = local_day(ms) + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let minutes = ((ms + local_offset_ms(ms)).rem_euclid(DAY_MS) / MINUTE_MS) as u32;
+    (year, month, day, minutes / 60, minutes % 60)
+}
+
+thread_local! {
+    // Creating an Intl.DateTimeFormat is slow, so one is kept per set of options
+    #[cfg(target_arch = "wasm32")]
+    static DATE_FORMATS: RefCell<HashMap<String, Option<js_sys::Intl::DateTimeFormat>>> = RefCell::new(HashMap::new());
+}
+
+/// Formats with the browser's locale through `Intl.DateTimeFormat`, if it exists
+#[cfg(target_arch = "wasm32")]
+fn intl_format(ms: f64, options: &[(&str, &str)]) -> Option<String> {
+    let key = options.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join(";");
+    let formatter = DATE_FORMATS.with(|formats| {
+        formats
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| {
+                let intl = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("Intl")).ok()?;
+                if intl.is_undefined() {
+                    return None;
+                }
+                let opts = js_sys::Object::new();
+                for (key, value) in options {
+                    js_sys::Reflect::set(&opts, &JsValue::from_str(key), &JsValue::from_str(value)).ok()?;
+                }
+                Some(js_sys::Intl::DateTimeFormat::new(&js_sys::Array::new(), &opts))
+            })
+            .clone()
+    })?;
+    formatter
+        .format()
+        .call1(&formatter, &js_sys::Date::new(&ms.into()))
+        .ok()?
+        .as_string()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn intl_format(_ms: f64, _options: &[(&str, &str)]) -> Option<String> {
+    None
+}
+
+/// Relative description of a timestamp in ms, as seen at `now`: "just now",
+/// "5 min ago", "3 h ago", "yesterday", "4 days ago", "last week", and the
+/// date itself for anything older
+pub fn format_relative_time(ms: f64, now: f64) -> String {
+    let elapsed = (now - ms).max(0.0);
+    if elapsed < MINUTE_MS {
+        return "just now".to_string();
+    }
+    if elapsed < HOUR_MS {
+        return format!("{} min ago", (elapsed / MINUTE_MS) as u64);
+    }
+    match days_between(ms, now) {
+        0 => format!("{} h ago", (elapsed / HOUR_MS) as u64),
+        1 => "yesterday".to_string(),
+        days @ 2..=6 => format!("{} days ago", days),
+        7..=13 => "last week".to_string(),
+        _ => format_date(ms),
+    }
+}
+
+/// Heading for grouping items by day as seen at `now`: "Today", "Yesterday",
+/// "Previous 7 days", "Previous 30 days", then month and year
+pub fn format_date_group(ms: f64, now: f64) -> String {
+    match days_between(ms, now) {
+        i64::MIN..=0 => "Today".to_string(),
+        1 => "Yesterday".to_string(),
+        2..=6 => "Previous 7 days".to_string(),
+        7..=29 => "Previous 30 days".to_string(),
+        _ => intl_format(ms, &[("month", "long"), ("year", "numeric")]).unwrap_or_else(|| {
+            let (year, month, ..) = local_parts(ms);
+            format!("{:04}-{:02}", year, month)
+        }),
+    }
+}
+
+/// Date of a timestamp in ms, e.g. "Mar 5, 2024" (or "2024-03-05" without `Intl`)
+pub fn format_date(ms: f64) -> String {
+    intl_format(ms, &[("dateStyle", "medium")]).unwrap_or_else(|| {
+        let (year, month, day, ..) = local_parts(ms);
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    })
+}
+
+/// Date and time of a timestamp in ms, e.g. "Mar 5, 2024, 2:07 PM"
+/// (or "2024-03-05 14:07" without `Intl`)
+pub fn format_absolute(ms: f64) -> String {
+    intl_format(ms, &[("dateStyle", "medium"), ("timeStyle", "short")]).unwrap_or_else(|| {
+        let (year, month, day, hour, minute) = local_parts(ms);
+        format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, hour, minute)
+    })
+}
+
+/// Heuristic for pastes that should be fenced as code: enough lines, and either
+/// dense punctuation with code-like line shapes or several language keywords.
+/// Ordinary prose and text that is already fenced are left alone.
+pub fn looks_like_code(text: &str) -> bool {
+    if text.contains("```") {
+        return false;
+    }
+    let lines: Vec<&str> = text.lines().filter(|l| !l.trim().is_empty()).collect();
+    if lines.len() < 5 {
+        return false;
+    }
+
+    let visible: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let symbols = visible.iter().filter(|c| "{}[]();=<>&|*/\\#$:".contains(**c)).count();
+    let symbol_density = symbols as f64 / visible.len().max(1) as f64;
+
+    let ratio = |hits: usize| hits as f64 / lines.len() as f64;
+    let indented = ratio(lines.iter().filter(|l| l.starts_with("  ") || l.starts_with('\t')).count());
+    let code_endings = ratio(lines.iter().filter(|l| {
+        let t = l.trim_end();
+        t.ends_with(';') || t.ends_with('{') || t.ends_with('}') || t.ends_with(')') || t.ends_with(':') || t.ends_with(',')
+    }).count());
+    // Prose lines end sentences and are long
+    let prose = ratio(lines.iter().filter(|l| {
+        let t = l.trim_end();
+        (t.ends_with('.') || t.ends_with('?') || t.ends_with('!')) && t.split_whitespace().count() > 8
+    }).count());
+    if prose > 0.5 {
+        return false;
+    }
+
+    const KEYWORDS: &[&str] = &[
+        "fn ", "let ", "def ", "function ", "class ", "import ", "return ", "const ", "#include",
+        "public ", "private ", "func ", "package ", "SELECT ", "=>", "->", "::", "var ", "elif ",
+    ];
+    let keyword_hits = KEYWORDS.iter().filter(|k| text.contains(*k)).count();
+
+    (symbol_density > 0.08 && (indented > 0.3 || code_endings > 0.3)) || (keyword_hits >= 3 && symbol_density > 0.04)
+}
+
+/// Best guess at the language of a code snippet for a Markdown fence, or ""
+pub fn guess_code_language(text: &str) -> &'static str {
+    // A filename in a leading comment ("// src/main.rs") is the strongest hint
+    if let Some(first) = text.lines().next() {
+        let lang = first
+            .split(|c: char| c.is_whitespace() || c == ':')
+            .filter_map(language_for_filename)
+            .next();
+        if let Some(lang) = lang {
+            return lang;
+        }
+    }
+    let trimmed = text.trim_start();
+    let has = |s: &str| text.contains(s);
+    if trimmed.starts_with("#!") && (has("bash") || has("/sh")) {
+        "bash"
+    } else if (trimmed.starts_with('{') || trimmed.starts_with('[')) && serde_json::from_str::<serde_json::Value>(text).is_ok() {
+        "json"
+    } else if has("fn ") && (has("let ") || has("::") || has("-> ")) {
+        "rust"
+    } else if has("package main") || (has("func ") && has(":=")) {
+        "go"
+    } else if has("#include") {
+        if has("std::") || has("class ") { "cpp" } else { "c" }
+    } else if has("public class ") || has("public static void") {
+        "java"
+    } else if has("def ") || (has("import ") && !has(";") && has(":\n")) {
+        "python"
+    } else if has("interface ") && has(": string") {
+        "typescript"
+    } else if has("function ") || has("const ") || has("=> ") || has("console.log") {
+        "javascript"
+    } else if trimmed.starts_with('<') && has("</") {
+        "html"
+    } else if has("SELECT ") || has("CREATE TABLE") || has("INSERT INTO") {
+        "sql"
+    } else {
+        ""
+    }
+}
+
+/// Language for a file name with a known extension, e.g. "main.rs" -> "rust"
+pub fn language_for_filename(name: &str) -> Option<&'static str> {
+    let ext = name.rsplit_once('.')?.1.to_lowercase();
+    let lang = match ext.as_str() {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" | "jsx" => "javascript",
+        "ts" | "tsx" => "typescript",
+        "go" => "go",
+        "c" | "h" => "c",
+        "cpp" | "cc" | "hpp" => "cpp",
+        "java" => "java",
+        "json" => "json",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "sh" | "bash" => "bash",
+        "sql" => "sql",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        _ => return None,
+    };
+    Some(lang)
+}
+
+/// Words per side above which a word diff is too costly to compute
+const DIFF_MAX_WORDS: usize = 2000;
+
+/// A run of text in a word diff
+#[derive(Clone, PartialEq, Debug)]
+pub enum DiffPart {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+/// Word-level diff of two texts, whitespace kept with the word before it.
+/// `None` when the texts differ over more words than is cheap to compare.
+pub fn word_diff(old: &str, new: &str) -> Option<Vec<DiffPart>> {
+    let a = split_words(old);
+    let b = split_words(new);
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    if a_mid.len() > DIFF_MAX_WORDS || b_mid.len() > DIFF_MAX_WORDS {
+        return None;
+    }
+
+    // Longest common subsequence lengths of every pair of suffixes
+    let width = b_mid.len() + 1;
+    let mut lcs = vec![0u16; (a_mid.len() + 1) * width];
+    for i in (0..a_mid.len()).rev() {
+        for j in (0..b_mid.len()).rev() {
+            lcs[i * width + j] = if a_mid[i] == b_mid[j] {
+                lcs[(i + 1) * width + j + 1] + 1
+            } else {
+                lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut parts: Vec<DiffPart> = Vec::new();
+    let mut push = |part: DiffPart| match (parts.last_mut(), part) {
+        (Some(DiffPart::Same(s)), DiffPart::Same(t)) | (Some(DiffPart::Added(s)), DiffPart::Added(t)) | (Some(DiffPart::Removed(s)), DiffPart::Removed(t)) => s.push_str(&t),
+        (_, part) => parts.push(part),
+    };
+    push(DiffPart::Same(a[..prefix].concat()));
+    let (mut i, mut j) = (0, 0);
+    while i < a_mid.len() || j < b_mid.len() {
+        if i < a_mid.len() && j < b_mid.len() && a_mid[i] == b_mid[j] {
+            push(DiffPart::Same(a_mid[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if i < a_mid.len() && (j == b_mid.len() || lcs[(i + 1) * width + j] >= lcs[i * width + j + 1]) {
+            push(DiffPart::Removed(a_mid[i].to_string()));
+            i += 1;
+        } else {
+            push(DiffPart::Added(b_mid[j].to_string()));
+            j += 1;
+        }
+    }
+    push(DiffPart::Same(a[a.len() - suffix..].concat()));
+    parts.retain(|p| !matches!(p, DiffPart::Same(s) if s.is_empty()));
+    Some(parts)
+}
+
+/// Words with their trailing whitespace, so joining them gives the text back
+fn split_words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut in_space = false;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() {
+            in_space = true;
+        } else if in_space {
+            words.push(&text[start..i]);
+            start = i;
+            in_space = false;
+        }
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+/// Text of a markdown string without the markup: code is kept, links and
+/// images are reduced to their text, blocks end up on lines of their own.
+/// Use this wherever a message is read as text (previews, search, counts,
+/// similarity) rather than stripping markup by hand. Results are cached, so
+/// going over the same messages again is cheap.
+pub fn markdown_to_plain(text: &str) -> String {
+    if let Some(plain) = PLAIN_TEXT.with(|cache| cache.borrow().get(text).cloned()) {
+        return plain.to_string();
+    }
+    let plain = convert_markdown_to_plain(text);
+    PLAIN_TEXT.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.len() >= PLAIN_TEXT_CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(Box::from(text), Rc::from(plain.as_str()));
+    });
+    plain
+}
+
+fn convert_markdown_to_plain(text: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TABLES);
+
+    let mut out = String::new();
+    for event in Parser::new_ext(text, options) {
+        match event {
+            MdEvent::Text(t) | MdEvent::Code(t) => out.push_str(&t),
+            MdEvent::SoftBreak => out.push(' '),
+            MdEvent::HardBreak => out.push('\n'),
+            MdEvent::End(Tag::TableCell) => out.push('\t'),
+            // A nested list or a code block inside a list item starts on a line of its own
+            MdEvent::Start(Tag::List(_) | Tag::CodeBlock(_)) if !out.is_empty() && !out.ends_with('\n') => out.push('\n'),
+            MdEvent::End(Tag::Paragraph | Tag::Heading(..) | Tag::Item | Tag::CodeBlock(_) | Tag::TableHead | Tag::TableRow | Tag::BlockQuote)
+                if !out.ends_with('\n') => out.push('\n'),
+            _ => {}
+        }
+    }
+    out.trim_end().to_string()
+}
+
+/// The think block a reasoning model's message starts with, and the answer
+/// after it. The answer is None while the model is still thinking.
+pub fn split_reasoning(content: &str) -> (Option<&str>, Option<&str>) {
+    let Some(thinking) = content.trim_start().strip_prefix(THINK_OPEN) else {
+        return (None, Some(content));
+    };
+    match thinking.find(THINK_CLOSE) {
+        Some(end) => (Some(thinking[..end].trim()), Some(thinking[end + THINK_CLOSE.len()..].trim_start())),
+        None => (Some(thinking.trim()), None),
+    }
+}
+
 pub fn render_markdown(text: &str) -> Html {
+    render_markdown_with_chips(text, &[])
+}
+
+/// Like `render_markdown`, with each `chip_marker(i)` in `text` replaced by
+/// `chips[i]`. Chips are put in after sanitizing, so only markup built by the
+/// app can carry the attributes chip clicks are handled by.
+pub fn render_markdown_with_chips(text: &str, chips: &[String]) -> Html {
+    let styled_html = format!(r#"<div class="swi-markdown-body">{}</div>"#, markdown_html(text, chips));
+    Html::from_html_unchecked(AttrValue::from(styled_html))
+}
+
+/// Sanitized HTML of a markdown string, with the chips put in
+pub fn markdown_html(text: &str, chips: &[String]) -> String {
     let mut options = Options::empty();
     options.insert(Options::ENABLE_STRIKETHROUGH);
     options.insert(Options::ENABLE_TABLES);
@@ -25,7 +702,586 @@ pub fn render_markdown(text: &str) -> Html {
 
     let mut html_output = String::new();
     html::push_html(&mut html_output, parser);
+    // Markdown passes HTML through as it is, and answers echo whatever
+    // documents and pages contained
+    let mut html_output = sanitize_html(&html_output);
+    for (i, chip) in chips.iter().enumerate() {
+        html_output = html_output.replace(&chip_marker(i), chip);
+    }
+    // ChatArea copies the code of the block whose button was clicked
+    html_output.replace("<pre>", r#"<pre><button type="button" class="swi-code-copy" data-copy-code>Copy</button>"#)
+}
 
-    let styled_html = format!(r#"<div class="markdown-body">{}</div>"#, html_output);
-    Html::from_html_unchecked(AttrValue::from(styled_html))
-}
\ No newline at end of file
+/// Private use character that brackets chip markers
+const CHIP_MARK: char = '\u{E000}';
+
+/// Stands in the markdown for the `index`th chip given to `render_markdown_with_chips`
+pub fn chip_marker(index: usize) -> String {
+    format!("{0}{1}{0}", CHIP_MARK, index)
+}
+
+/// `text` without anything that could pass for a chip marker; applied to
+/// untrusted text before markers are put in
+pub fn strip_chip_marks(text: &str) -> String {
+    text.replace(CHIP_MARK, "")
+}
+
+/// Tags rendered Markdown may contain, with the attributes each keeps
+const ALLOWED_TAGS: &[(&str, &[&str])] = &[
+    ("p", &[]),
+    ("br", &[]),
+    ("hr", &[]),
+    ("h1", &[]),
+    ("h2", &[]),
+    ("h3", &[]),
+    ("h4", &[]),
+    ("h5", &[]),
+    ("h6", &[]),
+    ("em", &[]),
+    ("strong", &[]),
+    ("del", &[]),
+    ("code", &["class"]),
+    ("pre", &[]),
+    ("blockquote", &[]),
+    ("ul", &[]),
+    ("ol", &["start"]),
+    ("li", &[]),
+    ("table", &[]),
+    ("thead", &[]),
+    ("tbody", &[]),
+    ("tr", &[]),
+    ("th", &["style"]),
+    ("td", &["style"]),
+    ("a", &["href", "title"]),
+    ("img", &["src", "alt", "title"]),
+];
+
+/// Tags removed together with everything inside them
+const DROPPED_WITH_CONTENT: &[&str] = &["script", "style", "iframe", "object", "embed", "template", "noscript", "textarea", "title", "svg", "math"];
+
+struct HtmlTag {
+    name: String,
+    closing: bool,
+    attributes: Vec<(String, String)>,
+}
+
+/// HTML with only the `ALLOWED_TAGS` and their attributes left; links open in
+/// a new tab. Other tags are removed, their text is kept.
+pub fn sanitize_html(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some((tag, len)) = parse_tag(rest) else {
+            out.push_str("&lt;");
+            rest = &rest[1..];
+            continue;
+        };
+        rest = &rest[len..];
+        if DROPPED_WITH_CONTENT.contains(&tag.name.as_str()) {
+            if !tag.closing {
+                // Lowercasing ASCII keeps byte offsets
+                let close = format!("</{}", tag.name);
+                rest = match rest.to_ascii_lowercase().find(&close) {
+                    Some(end) => rest[end..].find('>').map_or("", |gt| &rest[end + gt + 1..]),
+                    None => "",
+                };
+            }
+        } else if let Some(allowed) = render_allowed_tag(&tag) {
+            out.push_str(&allowed);
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The tag `text` starts with and its length in bytes; None if the `<` doesn't start a tag
+fn parse_tag(text: &str) -> Option<(HtmlTag, usize)> {
+    let bytes = text.as_bytes();
+    let mut i = 1;
+    let closing = bytes.get(i) == Some(&b'/');
+    if closing {
+        i += 1;
+    }
+    let name_start = i;
+    while bytes.get(i).is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'-') {
+        i += 1;
+    }
+    if !bytes.get(name_start).is_some_and(|b| b.is_ascii_alphabetic()) {
+        return None;
+    }
+    let name = text[name_start..i].to_ascii_lowercase();
+    let mut attributes = Vec::new();
+    loop {
+        while bytes.get(i).is_some_and(|b| b.is_ascii_whitespace() || *b == b'/') {
+            i += 1;
+        }
+        match bytes.get(i) {
+            None => return None,
+            Some(b'>') => return Some((HtmlTag { name, closing, attributes }, i + 1)),
+            Some(_) => {}
+        }
+        let attr_start = i;
+        while bytes.get(i).is_some_and(|b| !b.is_ascii_whitespace() && !matches!(b, b'=' | b'>' | b'/')) {
+            i += 1;
+        }
+        let attr = text[attr_start..i].to_ascii_lowercase();
+        while bytes.get(i).is_some_and(|b| b.is_ascii_whitespace()) {
+            i += 1;
+        }
+        let mut value = String::new();
+        if bytes.get(i) == Some(&b'=') {
+            i += 1;
+            while bytes.get(i).is_some_and(|b| b.is_ascii_whitespace()) {
+                i += 1;
+            }
+            match bytes.get(i) {
+                Some(&quote @ (b'"' | b'\'')) => {
+                    let end = i + 1 + text[i + 1..].find(quote as char)?;
+                    value = text[i + 1..end].to_string();
+                    i = end + 1;
+                }
+                _ => {
+                    let value_start = i;
+                    while bytes.get(i).is_some_and(|b| !b.is_ascii_whitespace() && *b != b'>') {
+                        i += 1;
+                    }
+                    value = text[value_start..i].to_string();
+                }
+            }
+        }
+        attributes.push((attr, value));
+    }
+}
+
+fn render_allowed_tag(tag: &HtmlTag) -> Option<String> {
+    let (_, allowed) = ALLOWED_TAGS.iter().find(|(name, _)| *name == tag.name)?;
+    if tag.closing {
+        return Some(format!("</{}>", tag.name));
+    }
+    let mut out = format!("<{}", tag.name);
+    for (name, value) in &tag.attributes {
+        if allowed.contains(&name.as_str()) && is_safe_attribute(name, value) {
+            let value = value.replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;");
+            out.push_str(&format!(r#" {}="{}""#, name, value));
+        }
+    }
+    if tag.name == "a" {
+        out.push_str(r#" rel="noopener noreferrer" target="_blank""#);
+    }
+    out.push('>');
+    Some(out)
+}
+
+/// Whether an attribute value can't run script or reach another site: links
+/// must be relative or web links, images must be embedded, styles may only
+/// align table cells and classes may only name a code block's language, so
+/// model output can't take on the page's own styles
+fn is_safe_attribute(name: &str, value: &str) -> bool {
+    match name {
+        "href" | "src" => {
+            // Character references could spell out a scheme
+            if value.replace("&amp;", "").contains('&') {
+                return false;
+            }
+            // Browsers skip whitespace and control characters in a scheme
+            let url = value.chars().filter(|c| !c.is_ascii_whitespace() && !c.is_control()).collect::<String>().to_ascii_lowercase();
+            if name == "src" {
+                // Loading a remote image would tell its server the chat is being read
+                return url.starts_with("data:image/");
+            }
+            match url.find([':', '/', '?', '#']) {
+                Some(i) if url[i..].starts_with(':') => matches!(&url[..i], "http" | "https" | "mailto"),
+                _ => true,
+            }
+        }
+        "style" => value
+            .trim()
+            .strip_prefix("text-align:")
+            .is_some_and(|align| matches!(align.trim(), "left" | "center" | "right")),
+        "class" => value
+            .strip_prefix("language-")
+            .is_some_and(|lang| !lang.is_empty() && lang.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-'))),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-03-15 12:00 UTC; local time is UTC outside the browser
+    const NOW: f64 = 1_710_504_000_000.0;
+
+    const RUST_SNIPPET: &str = "fn main() {\n    let total = add(2, 3);\n    println!(\"{}\", total);\n}\n\nfn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n";
+    const PYTHON_SNIPPET: &str = "import os\n\ndef list_files(path):\n    for name in os.listdir(path):\n        if name.endswith('.txt'):\n            print(name)\n    return None\n";
+
+    #[test]
+    fn code_pastes_are_detected() {
+        assert!(looks_like_code(RUST_SNIPPET));
+        assert!(looks_like_code(PYTHON_SNIPPET));
+        let json = "{\n  \"name\": \"swi\",\n  \"tags\": [\"a\", \"b\"],\n  \"nested\": {\n    \"on\": true\n  }\n}";
+        assert!(looks_like_code(json));
+    }
+
+    #[test]
+    fn prose_and_short_or_fenced_pastes_are_not_code() {
+        let prose = "I spent the weekend reading about the history of the printing press in Europe.\n\
+                     It is remarkable how quickly the technology spread between the major trading cities.\n\
+                     Within a few decades, presses were running in hundreds of towns across the continent.\n\
+                     Do you know which books were printed most often during that early period of time?\n\
+                     I would also like a short list of further reading on the topic, if you have one.\n";
+        assert!(!looks_like_code(prose));
+        let list = "Shopping list:\n- eggs\n- milk (two bottles)\n- bread\n- apples, pears\n- coffee\n";
+        assert!(!looks_like_code(list));
+        assert!(!looks_like_code("fn main() {\n}\n"));
+        assert!(!looks_like_code(&format!("```rust\n{}```", RUST_SNIPPET)));
+    }
+
+    #[test]
+    fn code_language_is_guessed_from_content_and_filenames() {
+        assert_eq!(guess_code_language(RUST_SNIPPET), "rust");
+        assert_eq!(guess_code_language(PYTHON_SNIPPET), "python");
+        assert_eq!(guess_code_language("{\"a\": [1, 2]}"), "json");
+        assert_eq!(guess_code_language("#!/bin/bash\necho hi\n"), "bash");
+        assert_eq!(guess_code_language("package main\n\nfunc main() {}\n"), "go");
+        assert_eq!(guess_code_language("#include <stdio.h>\nint main() { return 0; }\n"), "c");
+        assert_eq!(guess_code_language("const x = () => 1;\nconsole.log(x());\n"), "javascript");
+        assert_eq!(guess_code_language("SELECT id FROM users WHERE age > 3;"), "sql");
+        assert_eq!(guess_code_language("// src/lib.py\nx = 1\n"), "python");
+        assert_eq!(guess_code_language("just some words"), "");
+    }
+
+    #[test]
+    fn words_are_counted_across_scripts() {
+        assert_eq!(count_words("Hello, world!  It's 2024."), 4);
+        assert_eq!(count_words("-- … --"), 0);
+        assert_eq!(count_words("snake_case and kebab-case"), 3);
+        assert_eq!(count_words("数据库"), 3);
+        assert_eq!(count_words("Rust语言 is fun"), 5);
+        assert_eq!(count_words("ひらがなとカタカナ"), 9);
+        assert_eq!(count_words(""), 0);
+    }
+
+    #[test]
+    fn characters_are_counted_as_readers_see_them() {
+        assert_eq!(count_chars("abc"), 3);
+        assert_eq!(count_chars("e\u{301}te\u{301}"), 3);
+        assert_eq!(count_chars("👍🏽"), 1);
+        assert_eq!(count_chars("❤️"), 1);
+        assert_eq!(count_chars("👨\u{200D}👩\u{200D}👧\u{200D}👦"), 1);
+        assert_eq!(count_chars("🏳️\u{200D}🌈!"), 2);
+        assert_eq!(count_chars("🇩🇪🇫🇷"), 2);
+        assert_eq!(count_chars("🇩🇪🇫"), 2);
+        assert_eq!(count_chars("🏴\u{E0067}\u{E0062}\u{E0073}\u{E0063}\u{E0074}\u{E007F}"), 1);
+    }
+
+    #[test]
+    fn cjk_covers_han_and_kana_only() {
+        assert!(is_cjk('中'));
+        assert!(is_cjk('ひ'));
+        assert!(is_cjk('カ'));
+        assert!(is_cjk('\u{20000}'));
+        assert!(!is_cjk('한'));
+        assert!(!is_cjk('a'));
+        assert!(!is_cjk('。'));
+    }
+
+    #[test]
+    fn scoped_css_prefixes_each_selector() {
+        assert_eq!(scope_css(".swi-a, .swi-b:hover { color: red; }"), ".swi-root .swi-a, .swi-root .swi-b:hover { color: red; }\n");
+        assert_eq!(scope_css(".swi-root.swi-dark { color: red; }"), ".swi-root.swi-dark { color: red; }\n");
+        assert_eq!(scope_css(":root { --swi-gap: 4px; }"), ".swi-root { --swi-gap: 4px; }\n");
+        assert_eq!(scope_css(":root.swi-dark .swi-a { color: red; }"), ".swi-root.swi-dark .swi-a { color: red; }\n");
+    }
+
+    #[test]
+    fn scoped_css_drops_comments() {
+        let css = "/* header { } */ .swi-a { color: red; /* inline */ }\n/* unterminated";
+        assert_eq!(scope_css(css), ".swi-root .swi-a { color: red;  }\n");
+    }
+
+    #[test]
+    fn scoped_css_scopes_media_rules_and_keeps_keyframes() {
+        let css = "@media (max-width: 600px) { .swi-a { display: none; } .swi-b, p { margin: 0; } }\n\
+                   @keyframes swi-spin { from { opacity: 0; } to { opacity: 1; } }";
+        let scoped = scope_css(css);
+        assert!(scoped.starts_with("@media (max-width: 600px) {\n.swi-root .swi-a { display: none; }\n.swi-root .swi-b, .swi-root p { margin: 0; }\n}\n"));
+        assert!(scoped.ends_with("@keyframes swi-spin { from { opacity: 0; } to { opacity: 1; } }\n"));
+        assert!(!scoped.contains(".swi-root from"));
+    }
+
+    #[test]
+    fn compact_counts_round_before_picking_the_unit() {
+        assert_eq!(format_compact(812), "812");
+        assert_eq!(format_compact(1_000), "1k");
+        assert_eq!(format_compact(1_020), "1k");
+        assert_eq!(format_compact(3_240), "3.2k");
+        assert_eq!(format_compact(9_960), "10k");
+        assert_eq!(format_compact(128_000), "128k");
+        assert_eq!(format_compact(999_499), "999k");
+        assert_eq!(format_compact(999_999), "1M");
+        assert_eq!(format_compact(1_500_000), "1.5M");
+        assert_eq!(format_compact(12_345_678), "12M");
+    }
+
+    #[test]
+    fn word_diff_marks_changed_words() {
+        use DiffPart::*;
+        assert_eq!(
+            word_diff("the quick brown fox", "the slow brown dog"),
+            Some(vec![Same("the ".into()), Removed("quick ".into()), Added("slow ".into()), Same("brown ".into()), Removed("fox".into()), Added("dog".into())])
+        );
+        assert_eq!(word_diff("same text", "same text"), Some(vec![Same("same text".into())]));
+        assert_eq!(word_diff("", "new"), Some(vec![Added("new".into())]));
+    }
+
+    #[test]
+    fn word_diff_gives_up_on_large_changes() {
+        let old = "a ".repeat(DIFF_MAX_WORDS + 1);
+        let new = "b ".repeat(DIFF_MAX_WORDS + 1);
+        assert_eq!(word_diff(&old, &new), None);
+        // Shared beginnings and endings don't count towards the limit
+        let edited = format!("{}x {}", old, old);
+        assert!(word_diff(&format!("{}{}", old, old), &edited).is_some());
+    }
+
+    #[test]
+    fn relative_time_boundaries() {
+        let ago = |ms: f64| format_relative_time(NOW - ms, NOW);
+        assert_eq!(ago(0.0), "just now");
+        assert_eq!(ago(MINUTE_MS - 1.0), "just now");
+        assert_eq!(format_relative_time(NOW + 5_000.0, NOW), "just now");
+        assert_eq!(ago(MINUTE_MS), "1 min ago");
+        assert_eq!(ago(HOUR_MS - 1.0), "59 min ago");
+        assert_eq!(ago(HOUR_MS), "1 h ago");
+        assert_eq!(ago(12.0 * HOUR_MS), "12 h ago");
+        // 23:00 the day before
+        assert_eq!(ago(13.0 * HOUR_MS), "yesterday");
+        assert_eq!(ago(2.0 * DAY_MS), "2 days ago");
+        assert_eq!(ago(6.0 * DAY_MS), "6 days ago");
+        assert_eq!(ago(7.0 * DAY_MS), "last week");
+        assert_eq!(ago(13.0 * DAY_MS), "last week");
+        assert_eq!(ago(14.0 * DAY_MS), "2024-03-01");
+    }
+
+    #[test]
+    fn date_group_boundaries() {
+        let group = |days: f64| format_date_group(NOW - days * DAY_MS, NOW);
+        assert_eq!(group(0.0), "Today");
+        assert_eq!(format_date_group(NOW + DAY_MS, NOW), "Today");
+        assert_eq!(group(0.6), "Yesterday");
+        assert_eq!(group(2.0), "Previous 7 days");
+        assert_eq!(group(6.0), "Previous 7 days");
+        assert_eq!(group(7.0), "Previous 30 days");
+        assert_eq!(group(29.0), "Previous 30 days");
+        assert_eq!(group(30.0), "2024-02");
+    }
+
+    #[test]
+    fn absolute_dates_without_intl() {
+        assert_eq!(format_date(NOW), "2024-03-15");
+        assert_eq!(format_absolute(NOW + 7.0 * MINUTE_MS), "2024-03-15 12:07");
+        assert_eq!(format_date(0.0), "1970-01-01");
+        // Leap day
+        assert_eq!(format_date(NOW - 15.0 * DAY_MS), "2024-02-29");
+    }
+
+    #[test]
+    fn plain_text_of_tables() {
+        let md = "| Name | Size |\n|------|-----:|\n| a.txt | 3 |\n| b.txt | 12 |";
+        assert_eq!(markdown_to_plain(md), "Name\tSize\t\na.txt\t3\t\nb.txt\t12");
+    }
+
+    #[test]
+    fn plain_text_of_nested_lists() {
+        let md = "- fruit\n  - apple\n  - pear\n- vegetables\n  1. leek";
+        assert_eq!(markdown_to_plain(md), "fruit\napple\npear\nvegetables\nleek");
+    }
+
+    #[test]
+    fn plain_text_keeps_fenced_code() {
+        let md = "Run this:\n\n```rust\nfn main() {\n    println!(\"**hi**\");\n}\n```\n\nDone.";
+        assert_eq!(markdown_to_plain(md), "Run this:\nfn main() {\n    println!(\"**hi**\");\n}\nDone.");
+    }
+
+    #[test]
+    fn plain_text_of_links_and_images() {
+        let md = "See [the docs](https://example.com) and ![a chart](chart.png), or `cargo doc`.";
+        assert_eq!(markdown_to_plain(md), "See the docs and a chart, or cargo doc.");
+    }
+
+    #[test]
+    fn plain_text_cache_tells_sources_apart() {
+        assert_eq!(markdown_to_plain("**one**"), "one");
+        assert_eq!(markdown_to_plain("**two**"), "two");
+        assert_eq!(markdown_to_plain("**one**"), "one");
+    }
+
+    #[test]
+    fn sanitize_drops_script_style_and_iframe_with_content() {
+        assert_eq!(sanitize_html("a<script>alert(1)</script>b"), "ab");
+        assert_eq!(sanitize_html("a<SCRIPT src=x.js></SCRIPT>b"), "ab");
+        assert_eq!(sanitize_html("a<style>body { display: none }</style>b"), "ab");
+        assert_eq!(sanitize_html(r#"a<iframe src="https://example.com">x</iframe>b"#), "ab");
+    }
+
+    #[test]
+    fn sanitize_drops_event_handlers() {
+        assert_eq!(sanitize_html(r#"<img src=x onerror="alert(1)">"#), "<img>");
+        assert_eq!(sanitize_html(r#"<p onclick="alert(1)">hi</p>"#), "<p>hi</p>");
+    }
+
+    #[test]
+    fn sanitize_drops_script_urls() {
+        let blocked = [
+            "javascript:alert(1)",
+            "JavaScript:alert(1)",
+            " java\tscript:alert(1)",
+            "&#106;avascript:alert(1)",
+            "&#x6A;avascript:alert(1)",
+            "javascript&colon;alert(1)",
+        ];
+        for href in blocked {
+            let html = sanitize_html(&format!(r#"<a href="{}">x</a>"#, href));
+            assert!(!html.contains("href"), "{} kept in {}", href, html);
+        }
+    }
+
+    #[test]
+    fn sanitize_keeps_only_embedded_images() {
+        assert_eq!(sanitize_html(r#"<img src="https://example.com/t.png" alt="t">"#), r#"<img alt="t">"#);
+        assert_eq!(sanitize_html(r#"<img src="//example.com/t.png">"#), "<img>");
+        assert_eq!(sanitize_html(r#"<img src="data:image/png;base64,AAAA">"#), r#"<img src="data:image/png;base64,AAAA">"#);
+    }
+
+    #[test]
+    fn sanitize_keeps_only_language_classes() {
+        assert_eq!(sanitize_html(r#"<code class="language-c++">x</code>"#), r#"<code class="language-c++">x</code>"#);
+        assert_eq!(sanitize_html(r#"<code class="swi-doc-chip">x</code>"#), "<code>x</code>");
+        assert_eq!(sanitize_html(r#"<code class="language-rust swi-overlay">x</code>"#), "<code>x</code>");
+        assert_eq!(sanitize_html(r#"<code class="language-">x</code>"#), "<code>x</code>");
+    }
+
+    #[test]
+    fn sanitize_strips_chip_markup() {
+        let html = sanitize_html(r#"<span class="swi-doc-chip" data-document-id="a1" data-attach-document-id="a1">x</span>"#);
+        assert_eq!(html, "x");
+    }
+
+    #[test]
+    fn sanitize_keeps_formatting() {
+        let html = markdown_html("# Title\n\n**bold**, *em*, ~~gone~~ and [a link](https://example.com)\n\n- one\n\n| a |\n|:-:|\n| 1 |", &[]);
+        for tag in ["<h1>", "<strong>bold</strong>", "<em>em</em>", "<del>gone</del>", "<ul>", "<li>", "<table>", r#"<td style="text-align: center">"#] {
+            assert!(html.contains(tag), "{} missing from {}", tag, html);
+        }
+        assert!(html.contains(r#"<a href="https://example.com" rel="noopener noreferrer" target="_blank">a link</a>"#));
+        assert!(markdown_html("```rust\nlet x = 1;\n```", &[]).contains(r#"<code class="language-rust">"#));
+    }
+
+    #[test]
+    fn chips_are_put_in_after_sanitizing() {
+        let chip = r#"<span data-document-id="a1">notes</span>"#.to_string();
+        let html = markdown_html(&format!("See {}", chip_marker(0)), std::slice::from_ref(&chip));
+        assert!(html.contains(&chip));
+    }
+
+    fn msg(role: &str, id: &str, tokens: usize) -> Message {
+        Message { id: id.to_string(), role: role.to_string(), token_count: Some(tokens), ..Default::default() }
+    }
+
+    /// A system prompt and three questions, the last one unanswered
+    fn history() -> Vec<Message> {
+        vec![
+            msg("system", "sys", 10),
+            msg("user", "q1", 20),
+            msg("assistant", "a1", 30),
+            msg("user", "q2", 20),
+            msg("assistant", "a2", 30),
+            msg("user", "q3", 20),
+        ]
+    }
+
+    fn ids(messages: &[Message]) -> Vec<&str> {
+        messages.iter().map(|m| m.id.as_str()).collect()
+    }
+
+    #[test]
+    fn send_all_ignores_the_budget() {
+        let kept = build_request_messages(&history(), ContextStrategy::SendAll, 0);
+        assert_eq!(ids(&kept), ["sys", "q1", "a1", "q2", "a2", "q3"]);
+    }
+
+    #[test]
+    fn history_within_budget_is_sent_whole() {
+        for strategy in ContextStrategy::ALL {
+            assert_eq!(build_request_messages(&history(), strategy, 130).len(), 6);
+        }
+    }
+
+    #[test]
+    fn sliding_window_drops_whole_exchanges() {
+        let kept = build_request_messages(&history(), ContextStrategy::SlidingWindow, 129);
+        assert_eq!(ids(&kept), ["sys", "q2", "a2", "q3"]);
+        let kept = build_request_messages(&history(), ContextStrategy::SlidingWindow, 79);
+        assert_eq!(ids(&kept), ["sys", "q3"]);
+    }
+
+    #[test]
+    fn truncate_oldest_drops_single_messages() {
+        let kept = build_request_messages(&history(), ContextStrategy::TruncateOldest, 110);
+        assert_eq!(ids(&kept), ["sys", "a1", "q2", "a2", "q3"]);
+        let kept = build_request_messages(&history(), ContextStrategy::TruncateOldest, 80);
+        assert_eq!(ids(&kept), ["sys", "q2", "a2", "q3"]);
+        let kept = build_request_messages(&history(), ContextStrategy::TruncateOldest, 79);
+        assert_eq!(ids(&kept), ["sys", "a2", "q3"]);
+    }
+
+    #[test]
+    fn system_messages_and_the_last_message_are_always_kept() {
+        let mut history = history();
+        history.insert(3, msg("system", "note", 10));
+        for strategy in [ContextStrategy::SlidingWindow, ContextStrategy::TruncateOldest, ContextStrategy::Summarize] {
+            let kept = build_request_messages(&history, strategy, 0);
+            assert_eq!(ids(&kept), ["sys", "note", "q3"], "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn summary_ends_after_the_last_message_it_covers() {
+        assert_eq!(summary_end(&history(), "a1"), Some(3));
+        assert_eq!(summary_end(&history(), "gone"), None);
+    }
+
+    #[test]
+    fn summary_replaces_the_messages_it_covers() {
+        let messages = with_summary(&history(), "They said hello.", "a1");
+        assert_eq!(ids(&messages)[0], "sys");
+        assert_eq!(messages[1].role, "system");
+        assert_eq!(messages[1].content, "Summary of earlier conversation: They said hello.");
+        assert_eq!(ids(&messages)[2..], ["q2", "a2", "q3"]);
+    }
+
+    #[test]
+    fn summary_of_a_removed_message_is_left_out() {
+        assert_eq!(ids(&with_summary(&history(), "They said hello.", "gone")), ids(&history()));
+    }
+
+    #[test]
+    fn split_reasoning_without_think_block() {
+        assert_eq!(split_reasoning("Hello"), (None, Some("Hello")));
+        assert_eq!(split_reasoning("Sure <think>x</think>"), (None, Some("Sure <think>x</think>")));
+    }
+
+    #[test]
+    fn split_reasoning_with_think_block() {
+        assert_eq!(split_reasoning("  <think> plan </think>\n\nHello"), (Some("plan"), Some("Hello")));
+        assert_eq!(split_reasoning("<think>plan"), (Some("plan"), None));
+        assert_eq!(split_reasoning("<think>plan</think>"), (Some("plan"), Some("")));
+    }
+}
@@ -0,0 +1,180 @@
+//! Browser tests, run with `wasm-pack test --headless --firefox` (or `--chrome`).
+//! Without a browser, `cargo test --target wasm32-unknown-unknown --no-run` still checks they build.
+#![cfg(target_arch = "wasm32")]
+
+use gloo_timers::future::TimeoutFuture;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_test::*;
+use web_sys::{Document, Element};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+const PREFIX: &str = "keyed_test_";
+
+fn document() -> Document {
+    web_sys::window().and_then(|w| w.document()).expect("a document")
+}
+
+/// Chats with distinct titles and a message each, so a new chat is always added
+fn seed_chats() {
+    let chats: Vec<String> = ["Alpha", "Beta", "Gamma"]
+        .iter()
+        .enumerate()
+        .map(|(i, title)| {
+            format!(
+                r#"{{"id":"chat-{i}","title":"{title}","created_at":{at},"messages":[{{"role":"user","content":"hi"}}]}}"#,
+                i = i,
+                title = title,
+                at = 1_700_000_000_000u64 - i as u64 * 1000,
+            )
+        })
+        .collect();
+    let settings = r#"{"system_prompt":"","base_url":"http://localhost:1","selected_model":"test","stream_enabled":true,"onboarding_completed":true}"#;
+    let storage = web_sys::window().and_then(|w| w.local_storage().ok().flatten()).expect("localStorage");
+    storage.clear().unwrap();
+    storage.set_item(&format!("{}llm_chats_v3", PREFIX), &format!("[{}]", chats.join(","))).unwrap();
+    storage.set_item(&format!("{}chat_settings_v1", PREFIX), settings).unwrap();
+}
+
+/// One chat with two exchanges, for tests of the message list
+fn seed_conversation() {
+    seed_chats();
+    let messages = [("u1", "user", "First question"), ("a1", "assistant", "First answer"), ("u2", "user", "Second question"), ("a2", "assistant", "Second answer")]
+        .iter()
+        .map(|(id, role, content)| format!(r#"{{"id":"{}","role":"{}","content":"{}"}}"#, id, role, content))
+        .collect::<Vec<_>>()
+        .join(",");
+    let chat = format!(r#"[{{"id":"chat-0","title":"Alpha","created_at":1700000000000,"messages":[{}]}}]"#, messages);
+    let storage = web_sys::window().and_then(|w| w.local_storage().ok().flatten()).expect("localStorage");
+    storage.set_item(&format!("{}llm_chats_v3", PREFIX), &chat).unwrap();
+}
+
+/// Mounts the app into a fresh element of the page
+async fn mount(id: &str) -> (Element, SimpleWebInterface::MountHandle) {
+    let host = document().create_element("div").unwrap();
+    host.set_id(id);
+    document().body().unwrap().append_child(&host).unwrap();
+    let config = js_sys::JSON::parse(&format!(r#"{{"storage_key_prefix":"{}"}}"#, PREFIX)).unwrap();
+    let handle = SimpleWebInterface::mount(&format!("#{}", id), config).unwrap();
+    TimeoutFuture::new(50).await;
+    (host, handle)
+}
+
+fn click(element: &Element) {
+    element.unchecked_ref::<web_sys::HtmlElement>().click();
+}
+
+/// The first button showing `text`
+fn button(root: &Element, text: &str) -> Element {
+    let list = root.query_selector_all("button").unwrap();
+    (0..list.length())
+        .filter_map(|i| list.item(i)?.dyn_into::<Element>().ok())
+        .find(|b| b.text_content().is_some_and(|t| t.trim() == text))
+        .unwrap_or_else(|| panic!("a {:?} button", text))
+}
+
+/// The message rows of the chat by the text of their bubble
+fn message_rows(root: &Element) -> Vec<(String, Element)> {
+    let list = root.query_selector_all(".swi-message-row").unwrap();
+    (0..list.length())
+        .filter_map(|i| list.item(i)?.dyn_into::<Element>().ok())
+        .filter_map(|row| {
+            let text = row.query_selector(".swi-msg-bubble").ok().flatten()?.text_content()?;
+            Some((text.trim().to_string(), row))
+        })
+        .collect()
+}
+
+/// The row showing `text`
+fn row<'a>(rows: &'a [(String, Element)], text: &str) -> &'a Element {
+    &rows.iter().find(|(t, _)| t.contains(text)).unwrap_or_else(|| panic!("a row for {:?}", text)).1
+}
+
+/// The sidebar items by the title they show
+fn chat_items(root: &Element) -> Vec<(String, Element)> {
+    let list = root.query_selector_all(".swi-chat-item").unwrap();
+    (0..list.length())
+        .filter_map(|i| list.item(i)?.dyn_into::<Element>().ok())
+        .map(|item| {
+            let title = item.query_selector(".swi-chat-title").ok().flatten().and_then(|t| t.text_content()).unwrap_or_default();
+            (title, item)
+        })
+        .collect()
+}
+
+#[wasm_bindgen_test]
+async fn sidebar_keeps_chat_nodes_when_a_chat_is_added() {
+    seed_chats();
+    let (host, mut handle) = mount("keyed-host").await;
+
+    let before = chat_items(&host);
+    assert_eq!(before.len(), 3, "seeded chats are listed");
+
+    let new_chat = host.query_selector(".swi-new-chat-btn").unwrap().expect("new chat button");
+    click(&new_chat);
+    TimeoutFuture::new(50).await;
+
+    let after = chat_items(&host);
+    assert_eq!(after.len(), 4, "the new chat is listed");
+    for (title, node) in &before {
+        let (_, same) = after.iter().find(|(t, _)| t == title).expect("chat still listed");
+        // Unkeyed, the new chat would take over the first node and shift every title down by one
+        assert!(same.is_same_node(Some(node)), "{} moved to another node", title);
+    }
+    assert!(!before.iter().any(|(_, node)| after[0].1.is_same_node(Some(node))), "the new chat gets a node of its own");
+
+    handle.unmount();
+    host.remove();
+}
+
+#[wasm_bindgen_test]
+async fn sidebar_keeps_chat_nodes_when_a_chat_is_deleted() {
+    seed_chats();
+    let (host, mut handle) = mount("keyed-delete-host").await;
+
+    let before = chat_items(&host);
+    let (_, beta) = before.iter().find(|(t, _)| t == "Beta").expect("Beta is listed");
+    click(&beta.query_selector(".swi-del-btn").unwrap().expect("delete button"));
+    TimeoutFuture::new(50).await;
+
+    let after = chat_items(&host);
+    assert_eq!(after.iter().map(|(t, _)| t.as_str()).collect::<Vec<_>>(), ["Alpha", "Gamma"]);
+    for (title, node) in &after {
+        let (_, old) = before.iter().find(|(t, _)| t == title).unwrap();
+        // Unkeyed, Gamma would move into Beta's node
+        assert!(node.is_same_node(Some(old)), "{} moved to another node", title);
+    }
+
+    handle.unmount();
+    host.remove();
+}
+
+#[wasm_bindgen_test]
+async fn chat_keeps_message_nodes_when_messages_are_deleted() {
+    seed_conversation();
+    let (host, mut handle) = mount("keyed-messages-host").await;
+
+    let before = message_rows(&host);
+    let (second_question, second_answer) = (row(&before, "Second question").clone(), row(&before, "Second answer").clone());
+
+    click(&button(&host, "Select messages"));
+    TimeoutFuture::new(50).await;
+    for text in ["First question", "First answer"] {
+        click(&row(&message_rows(&host), text).query_selector(".swi-select-box").unwrap().expect("a checkbox"));
+        TimeoutFuture::new(20).await;
+    }
+    // Deleting asks first
+    let confirm = js_sys::Function::new_no_args("return true");
+    js_sys::Reflect::set(&web_sys::window().unwrap(), &"confirm".into(), &confirm).unwrap();
+    click(&button(&host, "Delete selected"));
+    TimeoutFuture::new(50).await;
+
+    let after = message_rows(&host);
+    assert!(!after.iter().any(|(t, _)| t.contains("First")), "the selected messages are gone");
+    // Unkeyed, the remaining messages would be redrawn into the first rows
+    assert!(row(&after, "Second question").is_same_node(Some(&second_question)));
+    assert!(row(&after, "Second answer").is_same_node(Some(&second_answer)));
+
+    handle.unmount();
+    host.remove();
+}